@@ -2,9 +2,6 @@ extern crate pak;
 
 use pak::*;
 
-use std::fs;
-use std::fs::File;
-use std::io::Write;
 use std::path::Path;
 
 fn main() {
@@ -12,8 +9,11 @@ fn main() {
 
     if args.len() != 3 {
         println!("the extractor needs 2 parameters : extract output_location pak_file_location");
-        return 
+        ExitCode::Usage.exit();
     }
 
-    MergeReader::extract(Path::new(&args[1]), Path::new(&args[2]));
+    if let Err(err) = MergeReader::extract(Path::new(&args[1]), Path::new(&args[2])) {
+        eprintln!("{}", err);
+        ExitCode::from_error(&err).exit();
+    }
 }
\ No newline at end of file
@@ -0,0 +1,36 @@
+extern crate pak;
+
+use pak::*;
+
+use std::path::Path;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.len() != 3 {
+        println!("the extractor needs 2 parameters : extract_all content_dir output_location");
+        ExitCode::Usage.exit();
+    }
+
+    let threads = std::thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1);
+    let result = extract_all_with(Path::new(&args[1]), Path::new(&args[2]), threads, |done, total, archive| {
+        match archive.error {
+            Some(ref err) => eprintln!("[{}/{}] {}: {}", done, total, archive.archive.display(), err),
+            None => println!("[{}/{}] {}: {} entries", done, total, archive.archive.display(), archive.entries),
+        }
+    });
+
+    match result {
+        Ok(report) => {
+            println!("{} entries, {} bytes extracted", report.entries(), report.bytes());
+            if let Some(failed) = report.failed().next() {
+                eprintln!("{} archives failed", report.failed().count());
+                ExitCode::from_error(failed.error.as_ref().expect("a failed archive has an error")).exit();
+            }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::from_error(&err).exit();
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use error::Error as PakError;
+use read::MergeReader;
+use repack::ShardGrouping;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::{Error, ErrorKind, Read, Seek};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Access
+///
+/// Decision of an `AccessPolicy` on an entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Access {
+    /// The entry can be read
+    Allow,
+    /// The entry can't be read
+    Deny,
+}
+
+/// AccessPolicy
+///
+/// Hook consulted with the full file name of an entry before its data is
+/// read or extracted, so that an embedding application (a mod sandbox)
+/// denies some entries without keeping a filtered copy of the archive. Set
+/// in `ReadOptions`, it applies to the `MergeReader` and to its
+/// `SharedReader`: a denied entry fails with `Error::AccessDenied` when
+/// read, pinned, decompressed or extracted by name, and is skipped by the
+/// extraction, the search, the repacks, the transforms and the exports of
+/// the whole archive. The entries are still listed.
+///
+/// The policy is advisory: the `MergedChunk`s returned by `iter`, `entry`
+/// and `entries` read their data without consulting it, as do the
+/// integrity checks (`verify`, `manifest`, `sidecar`, the digests of
+/// `diff`). An application handing a reader to untrusted code must only
+/// hand out the filtered paths, or a `SharedReader` or `PakFs`.
+///
+/// Two policies are equal if they are clones of each other.
+#[derive(Clone)]
+pub struct AccessPolicy {
+    check: Arc<dyn Fn(&str) -> Access + Send + Sync>,
+}
+
+impl AccessPolicy {
+    /// Creates a new `AccessPolicy` from its `check` function.
+    pub fn new<F>(check: F) -> Self
+        where F: Fn(&str) -> Access + Send + Sync + 'static
+    {
+        AccessPolicy { check: Arc::new(check) }
+    }
+
+    /// Decision on the entry `full_file_name`.
+    pub fn check(&self, full_file_name: &str) -> Access {
+        (self.check)(full_file_name)
+    }
+
+    /// Returns `true` if the entry `full_file_name` can be read.
+    pub fn allows(&self, full_file_name: &str) -> bool {
+        self.check(full_file_name) == Access::Allow
+    }
+}
+
+impl fmt::Debug for AccessPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("AccessPolicy(..)")
+    }
+}
+
+impl PartialEq for AccessPolicy {
+    fn eq(&self, other: &AccessPolicy) -> bool {
+        Arc::ptr_eq(&self.check, &other.check)
+    }
+}
+
+impl Eq for AccessPolicy {}
+
+/// Fails if `policy` denies the entry `full_file_name`.
+pub(crate) fn check_access(policy: &Option<AccessPolicy>, full_file_name: &str) -> io::Result<()> {
+    match *policy {
+        Some(ref policy) if !policy.allows(full_file_name) => {
+            Err(PakError::AccessDenied { name: full_file_name.to_string() }.into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// AccessLog
+///
+/// Entries read through `MergeReader::read_file` while the recording is
+/// enabled, in the order of their first access, with their read count.
+///
+/// Exported as text, one line per entry: the read count, a tab, then the
+/// full file name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccessLog {
+    order: Vec<String>,
+    counts: HashMap<String, u64>,
+}
+
+impl AccessLog {
+    /// Creates a new empty `AccessLog`.
+    pub fn new() -> Self {
+        AccessLog::default()
+    }
+
+    /// Records a read of the entry `full_file_name`.
+    pub fn record(&mut self, full_file_name: &str) {
+        if let Some(count) = self.counts.get_mut(full_file_name) {
+            *count += 1;
+            return;
+        }
+        self.order.push(full_file_name.to_string());
+        self.counts.insert(full_file_name.to_string(), 1);
+    }
+
+    /// Entries in the order of their first access.
+    pub fn order(&self) -> &[String] {
+        &self.order
+    }
+
+    /// Number of reads of the entry `full_file_name`.
+    pub fn count(&self, full_file_name: &str) -> u64 {
+        self.counts.get(full_file_name).cloned().unwrap_or(0)
+    }
+
+    /// Number of entries read.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Returns `true` if no entry was read.
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Adds the accesses of `other`, its new entries following the ones of
+    /// the log.
+    pub fn merge(&mut self, other: &AccessLog) {
+        for full_file_name in &other.order {
+            let count = other.count(full_file_name);
+            self.record(full_file_name);
+            *self.counts.get_mut(full_file_name).unwrap() += count - 1;
+        }
+    }
+
+    /// Ordering policy packing the entries in the order of their first
+    /// access, so that entries accessed together end up adjacent.
+    pub fn grouping(&self) -> ShardGrouping {
+        ShardGrouping::Access(self.order.clone())
+    }
+
+    /// Text export of the log.
+    pub fn to_text(&self) -> String {
+        self.order.iter()
+            .map(|full_file_name| format!("{}\t{}\n", self.count(full_file_name), full_file_name))
+            .collect()
+    }
+
+    /// Parses a text export of a log.
+    pub fn from_text(text: &str) -> io::Result<Self> {
+        let mut log = AccessLog::new();
+        for line in text.lines().filter(|line| !line.is_empty()) {
+            let mut parts = line.splitn(2, '\t');
+            let count = parts.next().and_then(|count| count.parse::<u64>().ok());
+            match (count, parts.next()) {
+                (Some(count), Some(full_file_name)) if count > 0 => {
+                    log.record(full_file_name);
+                    *log.counts.get_mut(full_file_name).unwrap() += count - 1;
+                }
+                _ => return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("invalid access log line \"{}\"", line)
+                )),
+            }
+        }
+        Ok(log)
+    }
+
+    /// Saves the text export of the log at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    /// Loads a log saved at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        AccessLog::from_text(&fs::read_to_string(path)?)
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Starts (or stops) recording the entries read through `read_file`.
+    /// Stopping discards the recorded log.
+    pub fn record_accesses(&mut self, enabled: bool) {
+        let log = if enabled { Some(AccessLog::new()) } else { None };
+        *self.access_log.borrow_mut() = log;
+    }
+
+    /// Recorded accesses, `None` if the recording isn't enabled.
+    pub fn access_log(&self) -> Option<AccessLog> {
+        self.access_log.borrow().clone()
+    }
+
+    /// Returns the recorded accesses and starts a new log.
+    pub fn take_access_log(&self) -> Option<AccessLog> {
+        self.access_log.borrow_mut().as_mut().map(|log| ::std::mem::replace(log, AccessLog::new()))
+    }
+}
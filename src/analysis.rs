@@ -0,0 +1,239 @@
+use fnv::FnvHasher;
+use hash::{Digest, HashAlgorithm};
+use read::{MergedChunk, MergeReader};
+use std::collections::{HashMap, HashSet};
+use std::hash::Hasher;
+use std::io;
+use std::io::{Read, Seek};
+
+/// DuplicateGroup
+///
+/// Entries sharing exactly the same content.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    /// Digest of the shared content
+    pub digest: Digest,
+    /// Size of the shared content
+    pub size: u64,
+    /// Full file names (sorted) of the entries
+    pub names: Vec<String>,
+    /// Number of distinct copies stored in the fragments
+    pub copies: usize,
+}
+
+impl DuplicateGroup {
+    /// Bytes that would be saved by storing the content only once.
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.copies.saturating_sub(1) as u64)
+    }
+}
+
+/// DuplicateReport
+///
+/// Groups of entries with identical content across all the fragments.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DuplicateReport {
+    /// Groups sorted by decreasing wasted size
+    pub groups: Vec<DuplicateGroup>,
+}
+
+impl DuplicateReport {
+    /// Bytes that would be saved by deduplicating every group.
+    pub fn potential_savings(&self) -> u64 {
+        self.groups.iter().map(DuplicateGroup::wasted).sum()
+    }
+
+    /// Number of entries having at least one duplicate.
+    pub fn duplicate_entries(&self) -> usize {
+        self.groups.iter().map(|group| group.names.len()).sum()
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Groups the entries by identical content.
+    ///
+    /// Only entries sharing their size with another entry are read. Entries
+    /// already pointing at the same stored data count as a single copy.
+    pub fn duplicates(&self, algorithm: HashAlgorithm) -> io::Result<DuplicateReport> {
+        let mut by_size: HashMap<u64, Vec<(&String, &MergedChunk<R>)>> = HashMap::new();
+        for (full_file_name, chunk) in self.iter() {
+            by_size.entry(chunk.size()).or_default().push((full_file_name, chunk));
+        }
+
+        let mut groups = Vec::new();
+        for (size, candidates) in by_size {
+            if candidates.len() < 2 {
+                continue;
+            }
+
+            let mut by_digest: HashMap<Digest, Vec<(&String, &MergedChunk<R>)>> = HashMap::new();
+            for (full_file_name, chunk) in candidates {
+                by_digest.entry(algorithm.digest(&chunk.data()?))
+                    .or_default()
+                    .push((full_file_name, chunk));
+            }
+
+            for (digest, entries) in by_digest {
+                if entries.len() < 2 {
+                    continue;
+                }
+                let copies = entries.iter()
+                    .map(|&(_, chunk)| chunk.location())
+                    .collect::<HashSet<_>>()
+                    .len();
+                let mut names: Vec<String> = entries.into_iter()
+                    .map(|(full_file_name, _)| full_file_name.clone())
+                    .collect();
+                names.sort();
+                groups.push(DuplicateGroup { digest, size, names, copies });
+            }
+        }
+
+        groups.sort_by(|a, b| b.wasted().cmp(&a.wasted()).then_with(|| a.names.cmp(&b.names)));
+        Ok(DuplicateReport { groups })
+    }
+}
+
+/// Number of minimum hashes kept in a `Fingerprint` signature.
+const SIGNATURE_LEN: usize = 32;
+
+/// Width of the rolling window delimiting the pieces of a `Fingerprint`.
+const ROLLING_WINDOW: usize = 32;
+
+/// Average size of the pieces of a `Fingerprint`.
+const PIECE_SIZE: u32 = 256;
+
+/// Fingerprint
+///
+/// Content fingerprint of an entry: the data is cut into pieces at content
+/// defined boundaries (so that an insertion only changes the pieces around
+/// it) and the minimum hashes of the pieces are kept as a MinHash signature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fingerprint {
+    /// Size of the data
+    pub size: u64,
+    signature: Vec<u64>,
+}
+
+/// Hashes `piece` with the seed `seed`.
+fn seeded_hash(seed: u64, piece: &[u8]) -> u64 {
+    let mut hasher = FnvHasher::with_key(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ 0xcbf2_9ce4_8422_2325);
+    hasher.write(piece);
+    hasher.finish()
+}
+
+impl Fingerprint {
+    /// Computes the `Fingerprint` of `data`.
+    pub fn of(data: &[u8]) -> Self {
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        let mut rolling: u32 = 0;
+
+        for i in 0..data.len() {
+            rolling = rolling.wrapping_add(data[i] as u32);
+            if i >= ROLLING_WINDOW {
+                rolling = rolling.wrapping_sub(data[i - ROLLING_WINDOW] as u32);
+            }
+            if rolling.wrapping_mul(0x01000193) % PIECE_SIZE == PIECE_SIZE - 1 {
+                pieces.push(&data[start..i + 1]);
+                start = i + 1;
+            }
+        }
+        if start < data.len() {
+            pieces.push(&data[start..]);
+        }
+
+        let signature = (0..SIGNATURE_LEN as u64).map(|seed| {
+            pieces.iter().map(|piece| seeded_hash(seed, piece)).min().unwrap_or(0)
+        }).collect();
+
+        Fingerprint { size: data.len() as u64, signature }
+    }
+
+    /// Estimated similarity with `other`, from 0 (unrelated) to 1 (same
+    /// pieces).
+    pub fn similarity(&self, other: &Fingerprint) -> f64 {
+        let same = self.signature.iter()
+            .zip(&other.signature)
+            .filter(|&(a, b)| a == b)
+            .count();
+        same as f64 / SIGNATURE_LEN as f64
+    }
+}
+
+/// Cluster
+///
+/// Entries with similar (but not necessarily identical) content.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cluster {
+    /// Full file names (sorted) of the entries
+    pub names: Vec<String>,
+    /// Total size of the entries
+    pub total_size: u64,
+}
+
+/// Finds the representative of `i` in the union-find `parents`.
+fn find_root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Clusters the entries whose fingerprints have a similarity of at least
+    /// `threshold` (between 0 and 1).
+    ///
+    /// Only entries whose sizes are within a factor 2 are compared. Empty
+    /// entries are ignored, clusters are sorted by decreasing total size.
+    pub fn similar_clusters(&self, threshold: f64) -> io::Result<Vec<Cluster>> {
+        let mut fingerprints = Vec::new();
+        for (full_file_name, chunk) in self.iter() {
+            if chunk.size() > 0 {
+                fingerprints.push((full_file_name, Fingerprint::of(&chunk.data()?)));
+            }
+        }
+        fingerprints.sort_by(|a, b| a.1.size.cmp(&b.1.size).then_with(|| a.0.cmp(b.0)));
+
+        let mut parents: Vec<usize> = (0..fingerprints.len()).collect();
+        for i in 0..fingerprints.len() {
+            for j in i + 1..fingerprints.len() {
+                if fingerprints[j].1.size > fingerprints[i].1.size * 2 {
+                    break;
+                }
+                if fingerprints[i].1.similarity(&fingerprints[j].1) >= threshold {
+                    let (a, b) = (find_root(&mut parents, i), find_root(&mut parents, j));
+                    parents[a] = b;
+                }
+            }
+        }
+
+        let mut clusters: HashMap<usize, Cluster> = HashMap::new();
+        for (i, &(full_file_name, ref fingerprint)) in fingerprints.iter().enumerate() {
+            let root = find_root(&mut parents, i);
+            let cluster = clusters.entry(root).or_insert_with(|| Cluster {
+                names: Vec::new(),
+                total_size: 0,
+            });
+            cluster.names.push(full_file_name.clone());
+            cluster.total_size += fingerprint.size;
+        }
+
+        let mut clusters: Vec<Cluster> = clusters.into_values()
+            .map(|mut cluster| {
+                cluster.names.sort();
+                cluster
+            })
+            .filter(|cluster| cluster.names.len() > 1)
+            .collect();
+        clusters.sort_by(|a, b| b.total_size.cmp(&a.total_size).then_with(|| a.names.cmp(&b.names)));
+        Ok(clusters)
+    }
+}
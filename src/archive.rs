@@ -0,0 +1,523 @@
+use error::{entry_exists, entry_not_found};
+use name::{lookup_key, EntryName};
+use hash::HashAlgorithm;
+use history::History;
+use options::{ReadOptions, WriteOptions};
+use properties::check_property_key;
+use read::MergeReader;
+use source::{HashingReader, SourceManifest};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use write::FragmentWriter;
+
+/// Staged content of an entry.
+#[derive(Clone, Debug)]
+pub(crate) enum Staged {
+    /// Data held in memory
+    Bytes(Vec<u8>),
+    /// Data read from a file at commit
+    File(PathBuf),
+    /// Entry removed
+    Removed,
+}
+
+/// Strictness
+///
+/// Level of equality checked by `Archive::equivalent`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Strictness {
+    /// Same entries with the same data and the same properties, whatever the
+    /// order of the data in the fragment
+    Content,
+    /// Same entries with the same data and the same properties, the data
+    /// being stored in the same order
+    Layout,
+    /// Byte-identical committed files
+    Bytes,
+}
+
+/// Returns `true` if `a` and `b` have the same bytes.
+fn same_bytes(a: &Path, b: &Path) -> io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+
+    let mut a = BufReader::new(File::open(a)?);
+    let mut b = BufReader::new(File::open(b)?);
+    let mut buffer_a = [0; 8192];
+    let mut buffer_b = [0; 8192];
+    loop {
+        let read = a.read(&mut buffer_a)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buffer_b[..read])?;
+        if buffer_a[..read] != buffer_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Counter making the temporary files of the commits of a process unique.
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Path of a temporary file used to commit the archive at `path`, unique
+/// to the commit so that concurrent commits never write the same file.
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    let mut temp = OsString::from(path);
+    temp.push(format!(".{}-{}.tmp", process::id(), TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+    PathBuf::from(temp)
+}
+
+/// Archive
+///
+/// A single pak fragment opened for reading and editing. Additions,
+/// replacements and removals are staged in memory, then `commit` rewrites
+/// the fragment atomically (into a temporary file renamed over the
+/// original). The `link` properties are kept as is: linked fragments are
+/// neither read nor modified.
+#[derive(Debug)]
+pub struct Archive {
+    path: PathBuf,
+    /// Reader of the committed fragment, `None` if it doesn't exist yet
+    pub(crate) reader: Option<MergeReader<File>>,
+    pub(crate) properties: HashMap<String, String>,
+    pub(crate) staged: BTreeMap<String, Staged>,
+    pub(crate) properties_changed: bool,
+    read_options: ReadOptions,
+    pub(crate) write_options: WriteOptions,
+    /// Number of commits since the archive was opened
+    pub(crate) generation: u64,
+    /// Staged changes which can be undone
+    pub(crate) history: History,
+}
+
+impl Archive {
+    /// Opens the existing pak fragment at `path` for reading and editing.
+    pub fn open_rw<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Archive::open_rw_with(path, &ReadOptions::default())
+    }
+
+    /// Opens the existing pak fragment at `path` for reading and editing,
+    /// according to `options`.
+    pub fn open_rw_with<P: AsRef<Path>>(path: P, options: &ReadOptions) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let reader = MergeReader::merge_with_options(&path, File::open, false, options)?;
+        Ok(Archive {
+            path,
+            properties: reader.properties.clone(),
+            reader: Some(reader),
+            staged: BTreeMap::new(),
+            properties_changed: false,
+            read_options: options.clone(),
+            write_options: WriteOptions::default(),
+            generation: 0,
+            history: History::default(),
+        })
+    }
+
+    /// Creates a new empty archive, written at `path` on `commit`.
+    pub fn create<P: AsRef<Path>>(path: P) -> Self {
+        Archive {
+            path: path.as_ref().to_path_buf(),
+            reader: None,
+            properties: HashMap::new(),
+            staged: BTreeMap::new(),
+            properties_changed: false,
+            read_options: ReadOptions::default(),
+            write_options: WriteOptions::default(),
+            generation: 0,
+            history: History::default(),
+        }
+    }
+
+    /// Sets the options of the writes of `commit`.
+    pub fn set_write_options(&mut self, options: WriteOptions) {
+        self.write_options = options;
+    }
+
+    /// Path of the archive.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of commits since the archive was opened, to detect outdated
+    /// snapshots.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns `true` if the committed archive contains `full_file_name`.
+    pub(crate) fn committed(&self, full_file_name: &str) -> bool {
+        self.reader.as_ref().is_some_and(|reader| reader.chunk(full_file_name).is_some())
+    }
+
+    /// Returns `true` if the archive, staged changes included, contains the
+    /// entry `full_file_name`.
+    pub fn contains(&self, full_file_name: &str) -> bool {
+        match self.staged.get(&*lookup_key(full_file_name)) {
+            Some(&Staged::Removed) => false,
+            Some(_) => true,
+            None => self.committed(full_file_name),
+        }
+    }
+
+    /// Full file names of the entries, staged changes included, sorted.
+    pub fn file_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.reader.iter()
+            .flat_map(|reader| reader.chunks.keys())
+            .filter(|name| !self.staged.contains_key(*name))
+            .cloned()
+            .collect();
+        names.extend(self.staged.iter()
+            .filter(|&(_, staged)| !matches!(*staged, Staged::Removed))
+            .map(|(name, _)| name.clone()));
+        names.sort();
+        names
+    }
+
+    /// Reads the entry `full_file_name`, staged changes included.
+    pub fn read_file(&self, full_file_name: &str) -> io::Result<Vec<u8>> {
+        match self.staged.get(&*lookup_key(full_file_name)) {
+            Some(Staged::Bytes(data)) => Ok(data.clone()),
+            Some(Staged::File(path)) => fs::read(path),
+            Some(Staged::Removed) => Err(Error::new(
+                ErrorKind::NotFound,
+                format!("`full_file_name` \"{}\" was removed", full_file_name)
+            )),
+            None => match self.reader {
+                Some(ref reader) => reader.read_file(full_file_name),
+                None => Err(entry_not_found(full_file_name)),
+            },
+        }
+    }
+
+    /// Stages the addition of a new entry.
+    pub fn add<S: Into<String>>(&mut self, full_file_name: S, data: Vec<u8>) -> io::Result<()> {
+        let full_file_name = EntryName::new(&full_file_name.into())?.into_string();
+        if self.contains(&full_file_name) {
+            return Err(entry_exists(&full_file_name));
+        }
+        self.stage(full_file_name, Some(Staged::Bytes(data)));
+        Ok(())
+    }
+
+    /// Stages the replacement of the data of an existing entry.
+    pub fn replace(&mut self, full_file_name: &str, data: Vec<u8>) -> io::Result<()> {
+        if !self.contains(full_file_name) {
+            return Err(entry_not_found(full_file_name));
+        }
+        self.stage(EntryName::new(full_file_name)?.into_string(), Some(Staged::Bytes(data)));
+        Ok(())
+    }
+
+    /// Stages the addition or the replacement of an entry.
+    pub fn insert<S: Into<String>>(&mut self, full_file_name: S, data: Vec<u8>) -> io::Result<()> {
+        let full_file_name = EntryName::new(&full_file_name.into())?.into_string();
+        self.stage(full_file_name, Some(Staged::Bytes(data)));
+        Ok(())
+    }
+
+    /// Stages the addition or the replacement of an entry with the content
+    /// of the file at `path`, read at commit.
+    pub fn insert_file<S: Into<String>, P: AsRef<Path>>(&mut self, full_file_name: S, path: P) -> io::Result<()> {
+        let full_file_name = EntryName::new(&full_file_name.into())?.into_string();
+        self.stage(full_file_name, Some(Staged::File(path.as_ref().to_path_buf())));
+        Ok(())
+    }
+
+    /// Stages the removal of an entry.
+    pub fn remove(&mut self, full_file_name: &str) -> io::Result<()> {
+        if !self.contains(full_file_name) {
+            return Err(entry_not_found(full_file_name));
+        }
+        let full_file_name = lookup_key(full_file_name).into_owned();
+        if self.committed(&full_file_name) {
+            self.stage(full_file_name, Some(Staged::Removed));
+        } else {
+            self.stage(full_file_name, None);
+        }
+        Ok(())
+    }
+
+    /// Value of the property `key`.
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(|value| value.as_str())
+    }
+
+    /// Sets the property `key`, failing with `InvalidInput` if it is
+    /// reserved, see `is_reserved_property`.
+    pub fn set_property<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) -> io::Result<()> {
+        let key = key.into();
+        check_property_key(&key)?;
+        self.stage_property(key, Some(value.into()));
+        Ok(())
+    }
+
+    /// Removes the property `key`, returning its value, failing with
+    /// `InvalidInput` if it is reserved, see `is_reserved_property`.
+    pub fn remove_property(&mut self, key: &str) -> io::Result<Option<String>> {
+        check_property_key(key)?;
+        if !self.properties.contains_key(key) {
+            return Ok(None);
+        }
+        Ok(self.stage_property(key.to_string(), None))
+    }
+
+    /// Returns `true` if changes are staged.
+    pub fn has_changes(&self) -> bool {
+        !self.staged.is_empty() || self.properties_changed || self.reader.is_none()
+    }
+
+    /// Discards the staged changes, which can't be undone.
+    pub fn discard(&mut self) {
+        self.history.clear();
+        self.staged.clear();
+        self.properties = self.reader.as_ref()
+            .map_or(HashMap::new(), |reader| reader.properties.clone());
+        self.properties_changed = false;
+    }
+
+    /// Size of the data of the entry `full_file_name`, staged changes
+    /// included.
+    pub(crate) fn entry_size(&self, full_file_name: &str) -> io::Result<u64> {
+        match self.staged.get(full_file_name) {
+            Some(Staged::Bytes(data)) => Ok(data.len() as u64),
+            Some(Staged::File(path)) => fs::metadata(path).map(|metadata| metadata.len()),
+            Some(Staged::Removed) => Ok(0),
+            None => Ok(self.reader.as_ref()
+                .and_then(|reader| reader.chunk(full_file_name))
+                .map_or(0, |chunk| chunk.size())),
+        }
+    }
+
+    /// Checks that the archive with the staged changes can be written: the
+    /// names are valid, the staged files exist and the data fits in the
+    /// offsets of `WriteOptions::offsets`.
+    pub fn validate(&self) -> io::Result<()> {
+        let mut total: u64 = 0;
+        for full_file_name in self.file_names() {
+            EntryName::new(&full_file_name)?;
+            total += self.entry_size(&full_file_name)?;
+        }
+
+        if total > self.write_options.offsets.max() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} bytes of data exceed the capacity of a pak fragment", total)
+            ));
+        }
+        Ok(())
+    }
+
+    /// Full file names of the entries, staged changes included, in the order
+    /// of their data once committed: the committed entries keep their order
+    /// in the fragment, the staged ones follow sorted by name. All of them
+    /// are sorted by name in the `canonical` mode.
+    fn layout(&self) -> Vec<&String> {
+        let mut names: Vec<&String> = Vec::new();
+        if let Some(ref reader) = self.reader {
+            let mut committed: Vec<_> = reader.chunks.iter()
+                .filter(|&(name, _)| !self.staged.contains_key(name))
+                .collect();
+            committed.sort_by_key(|&(_, chunk)| chunk.offset());
+            names.extend(committed.into_iter().map(|(name, _)| name));
+        }
+        names.extend(self.staged.iter()
+            .filter(|&(_, staged)| !matches!(*staged, Staged::Removed))
+            .map(|(name, _)| name));
+        if self.write_options.canonical {
+            names.sort();
+        }
+        names
+    }
+
+    /// Writes the archive with the staged changes into `path`, computing the
+    /// digests of the source files with `algorithm` if specified.
+    fn write_to(&self, path: &Path, algorithm: Option<HashAlgorithm>) -> io::Result<Option<SourceManifest>> {
+        let mut writer = FragmentWriter::with_options(
+            BufWriter::with_capacity(self.write_options.buffer_size, File::create(path)?),
+            &self.write_options
+        )?;
+        let mut sources = algorithm.map(SourceManifest::new);
+
+        for full_file_name in self.layout() {
+            match self.staged.get(full_file_name) {
+                Some(Staged::Bytes(data)) => {
+                    writer.add_bytes(full_file_name, data)?;
+                }
+                Some(Staged::File(source)) => match sources {
+                    Some(ref mut sources) => {
+                        let mut reader = HashingReader::new(File::open(source)?, sources.algorithm());
+                        writer.add(full_file_name, &mut reader)?;
+                        sources.insert(full_file_name.as_str(), reader.finish());
+                    }
+                    None => {
+                        writer.add(full_file_name, &mut File::open(source)?)?;
+                    }
+                },
+                _ => {
+                    // copied as stored, compressed and encrypted as it is,
+                    // whatever the `AccessPolicy` of the reader
+                    let chunk = self.reader.as_ref()
+                        .and_then(|reader| reader.chunk(full_file_name))
+                        .ok_or_else(|| entry_not_found(full_file_name))?;
+                    writer.add_chunk(full_file_name, chunk)?;
+                }
+            }
+        }
+
+        let mut properties: Vec<_> = self.properties.iter().collect();
+        properties.sort();
+        for (key, value) in properties {
+            writer.add_property(key, value);
+        }
+
+        writer.finish()?.into_inner()
+            .map_err(Error::from)?
+            .sync_all()?;
+        Ok(sources)
+    }
+
+    /// Applies the staged changes: the archive is written into a temporary
+    /// file which then replaces the original one. On error, the original
+    /// file is left untouched and the changes stay staged, unless the file
+    /// was replaced but can't be reopened: the changes are then committed
+    /// and the archive must be opened again to be read.
+    pub fn commit(&mut self) -> io::Result<()> {
+        self.commit_checked(None, None).map(|_| ())
+    }
+
+    /// Commits like `commit`, computing the digests of the staged source
+    /// files (the ones added with `insert_file`) while they are packed.
+    pub fn commit_with_digests(&mut self, algorithm: HashAlgorithm) -> io::Result<SourceManifest> {
+        self.commit_checked(Some(algorithm), None)
+            .map(|sources| sources.unwrap_or_else(|| SourceManifest::new(algorithm)))
+    }
+
+    /// Commits like `commit_with_digests`, failing without modifying the
+    /// archive if a source file is missing from `expected` or doesn't match
+    /// its digest, e.g. because it was modified since the manifest was
+    /// generated.
+    pub fn commit_verified(&mut self, expected: &SourceManifest) -> io::Result<SourceManifest> {
+        let algorithm = expected.algorithm();
+        self.commit_checked(Some(algorithm), Some(expected))
+            .map(|sources| sources.unwrap_or_else(|| SourceManifest::new(algorithm)))
+    }
+
+    fn commit_checked(&mut self, algorithm: Option<HashAlgorithm>, expected: Option<&SourceManifest>) -> io::Result<Option<SourceManifest>> {
+        if !self.has_changes() {
+            return Ok(None);
+        }
+        self.validate()?;
+
+        let temp = temp_path(&self.path);
+        let sources = match self.write_to(&temp, algorithm) {
+            Ok(sources) => sources,
+            Err(err) => {
+                let _ = fs::remove_file(&temp);
+                return Err(err);
+            }
+        };
+        if let (Some(expected), Some(sources)) = (expected, sources.as_ref()) {
+            let mismatches = expected.mismatches(sources);
+            if !mismatches.is_empty() {
+                let _ = fs::remove_file(&temp);
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("source files not matching the manifest: {}", mismatches.join(", "))
+                ));
+            }
+        }
+
+        // the original file must be closed before being replaced
+        self.reader = None;
+        let renamed = fs::rename(&temp, &self.path);
+        let reader = self.open_committed();
+        match (renamed, reader) {
+            (Ok(()), Ok(reader)) => {
+                self.properties = reader.properties.clone();
+                self.reader = Some(reader);
+                self.staged.clear();
+                self.properties_changed = false;
+                self.history.clear();
+                self.generation += 1;
+                Ok(sources)
+            }
+            (Err(err), reader) => {
+                let _ = fs::remove_file(&temp);
+                self.reader = reader.ok();
+                Err(err)
+            }
+            (Ok(()), Err(err)) => {
+                // the changes are in the file, staging them again would
+                // apply them twice
+                self.staged.clear();
+                self.properties_changed = false;
+                self.history.clear();
+                self.generation += 1;
+                Err(Error::new(
+                    err.kind(),
+                    format!("the changes were committed but \"{}\" can't be reopened: {}", self.path.display(), err)
+                ))
+            }
+        }
+    }
+
+    /// Opens the committed file, whose names are encoded by the codec of the
+    /// writes.
+    pub(crate) fn open_committed(&self) -> io::Result<MergeReader<File>> {
+        let options = ReadOptions {
+            name_codec: self.write_options.name_codec.clone(),
+            ..self.read_options.clone()
+        };
+        MergeReader::merge_with_options(&self.path, File::open, false, &options)
+    }
+
+    /// Returns `true` if the archive is equivalent to `other` at the level of
+    /// `strictness`.
+    ///
+    /// `Content` and `Layout` compare the archives staged changes included,
+    /// `Bytes` compares the committed files only.
+    pub fn equivalent(&self, other: &Archive, strictness: Strictness) -> io::Result<bool> {
+        if strictness == Strictness::Bytes {
+            return match (self.reader.is_some(), other.reader.is_some()) {
+                (true, true) => same_bytes(&self.path, &other.path),
+                (committed, other_committed) => Ok(committed == other_committed),
+            };
+        }
+
+        if self.properties != other.properties {
+            return Ok(false);
+        }
+
+        let (names, other_names) = match strictness {
+            Strictness::Layout => (self.layout(), other.layout()),
+            _ => {
+                let mut names = self.layout();
+                let mut other_names = other.layout();
+                names.sort();
+                other_names.sort();
+                (names, other_names)
+            }
+        };
+        if names != other_names {
+            return Ok(false);
+        }
+
+        for full_file_name in names {
+            if self.entry_size(full_file_name)? != other.entry_size(full_file_name)?
+                || self.read_file(full_file_name)? != other.read_file(full_file_name)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
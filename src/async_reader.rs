@@ -0,0 +1,165 @@
+use options::ReadOptions;
+use read::MergeReader;
+use shared::SharedReader;
+use std::fs::File;
+use std::future::Future;
+use std::io;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::fs;
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf, Take};
+use tokio::task::JoinHandle;
+
+/// PakFuture
+///
+/// Future of an operation of an `AsyncMergeReader`, run on the blocking
+/// threads of the tokio runtime as `tokio::fs` does, so that the tasks of
+/// the runtime never wait for the disk.
+#[must_use = "futures do nothing unless polled"]
+pub struct PakFuture<T> {
+    handle: JoinHandle<io::Result<T>>,
+}
+
+impl<T: Send + 'static> PakFuture<T> {
+    /// Runs `f` on the blocking threads of the current runtime.
+    fn spawn<F>(f: F) -> Self
+        where F: FnOnce() -> io::Result<T> + Send + 'static
+    {
+        PakFuture { handle: tokio::task::spawn_blocking(f) }
+    }
+}
+
+impl<T> Future for PakFuture<T> {
+    type Output = io::Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<T>> {
+        match Pin::new(&mut self.handle).poll(cx) {
+            Poll::Ready(Ok(result)) => Poll::Ready(result),
+            // the operation panicked or the runtime is shutting down
+            Poll::Ready(Err(err)) => Poll::Ready(Err(Error::other(err))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// EntryStream
+///
+/// Data of an entry read asynchronously, through its own handle on the
+/// fragment.
+pub struct EntryStream {
+    data: Take<fs::File>,
+    size: u64,
+}
+
+impl EntryStream {
+    /// Size of the entry.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Bytes left to read.
+    pub fn remaining(&self) -> u64 {
+        self.data.limit()
+    }
+}
+
+impl AsyncRead for EntryStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.data).poll_read(cx, buf)
+    }
+}
+
+/// AsyncMergeReader
+///
+/// Reader of a merged archive for the tokio runtime: the entries are
+/// looked up in memory, and their data is read on the blocking threads of
+/// the runtime, like the files of `tokio::fs`. The operations returning a
+/// `PakFuture` must be called within a runtime.
+///
+/// Built on a `SharedReader`, it is `Send + Sync` and cheap to clone, so
+/// that concurrent requests share one reader.
+#[derive(Clone, Debug)]
+pub struct AsyncMergeReader {
+    shared: Arc<SharedReader>,
+}
+
+impl AsyncMergeReader {
+    /// Opens the pak file `loc` (and the fragments it links to).
+    pub fn open<P: AsRef<Path>>(loc: P) -> PakFuture<Self> {
+        AsyncMergeReader::open_with(loc, &ReadOptions::default())
+    }
+
+    /// Opens the pak file `loc` according to `options`.
+    pub fn open_with<P: AsRef<Path>>(loc: P, options: &ReadOptions) -> PakFuture<Self> {
+        let loc = loc.as_ref().to_path_buf();
+        let options = options.clone();
+        PakFuture::spawn(move || {
+            let shared = MergeReader::<File>::open_with(loc, &options)?.shared()?;
+            Ok(AsyncMergeReader::from(shared))
+        })
+    }
+
+    /// Returns `true` if the archive contains the entry `full_file_name`.
+    pub fn contains(&self, full_file_name: &str) -> bool {
+        self.shared.contains(full_file_name)
+    }
+
+    /// Full file names of the entries, sorted.
+    pub fn file_names(&self) -> Vec<&str> {
+        self.shared.file_names()
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    /// Returns `true` if the archive contains no entry.
+    pub fn is_empty(&self) -> bool {
+        self.shared.is_empty()
+    }
+
+    /// Reads the entry `full_file_name`.
+    pub fn read_file(&self, full_file_name: &str) -> PakFuture<Vec<u8>> {
+        let shared = self.shared.clone();
+        let full_file_name = full_file_name.to_string();
+        PakFuture::spawn(move || shared.read_file(&full_file_name))
+    }
+
+    /// Opens the entry `full_file_name` to stream its data, without holding
+    /// it in memory. Fails with `Unsupported` if the entry is compressed.
+    pub fn open_entry(&self, full_file_name: &str) -> PakFuture<EntryStream> {
+        let shared = self.shared.clone();
+        let full_file_name = full_file_name.to_string();
+        PakFuture::spawn(move || {
+            let (file, size) = shared.open_entry(&full_file_name)?;
+            Ok(EntryStream {
+                data: fs::File::from_std(file).take(size),
+                size,
+            })
+        })
+    }
+
+    /// Extracts the entry `full_file_name` into `dest`, returning the path
+    /// of the extracted file.
+    pub fn extract_entry<P: AsRef<Path>>(&self, full_file_name: &str, dest: P) -> PakFuture<PathBuf> {
+        let shared = self.shared.clone();
+        let full_file_name = full_file_name.to_string();
+        let dest = dest.as_ref().to_path_buf();
+        PakFuture::spawn(move || shared.extract_entry(&full_file_name, dest))
+    }
+
+    /// The `SharedReader` the reader is built on.
+    pub fn shared(&self) -> &SharedReader {
+        &self.shared
+    }
+}
+
+impl From<SharedReader> for AsyncMergeReader {
+    fn from(shared: SharedReader) -> Self {
+        AsyncMergeReader { shared: Arc::new(shared) }
+    }
+}
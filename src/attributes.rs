@@ -0,0 +1,223 @@
+use archive::Archive;
+use byteorder_extended::{ReadExt, WriteExt};
+use error::entry_not_found;
+use mem::MemArchive;
+use name::lookup_key;
+use read::MergeReader;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::{Error, ErrorKind, Read, Seek};
+use std::str::FromStr;
+
+/// Full file name of the reserved chunk storing the entry attributes.
+///
+/// The chunk is a regular entry, so it is kept by the repacking functions.
+pub const ATTRIBUTES_CHUNK: &str = ".pak/attributes";
+
+/// Attributes
+///
+/// Per-entry metadata (origin, license, version...) as key/value strings,
+/// stored in the `ATTRIBUTES_CHUNK` of an archive.
+///
+/// Attributes chunk format :
+///
+/// ``` text
+///     entries_count                       4 bytes
+///     for 0 to entries_count
+///         name                            2 bytes (length) | string (utf8)
+///         attributes_count                4 bytes
+///         for 0 to attributes_count
+///             key                         2 bytes (length) | string (utf8)
+///             value                       2 bytes (length) | string (utf8)
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Attributes {
+    entries: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Attributes {
+    /// Creates a new empty `Attributes`.
+    pub fn new() -> Self {
+        Attributes::default()
+    }
+
+    /// Reads the content of an attributes chunk.
+    pub fn from_bytes(mut data: &[u8]) -> io::Result<Self> {
+        let mut attributes = Attributes::new();
+        let reader = &mut data;
+
+        let entries_count = reader.read_i32()?;
+        if entries_count < 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "negative attributes entries count"));
+        }
+        for _ in 0..entries_count {
+            let full_file_name = reader.read_string()?;
+            let count = reader.read_i32()?;
+            let map = attributes.entries.entry(full_file_name).or_default();
+            for _ in 0..count {
+                let key = reader.read_string()?;
+                let value = reader.read_string()?;
+                map.insert(key, value);
+            }
+        }
+
+        if !reader.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "trailing bytes in the attributes chunk"));
+        }
+        Ok(attributes)
+    }
+
+    /// Content of the attributes chunk.
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut writer = Vec::new();
+        let entries: Vec<_> = self.entries.iter().filter(|&(_, map)| !map.is_empty()).collect();
+        writer.write_i32(entries.len() as i32)?;
+        for (full_file_name, map) in entries {
+            writer.write_string(full_file_name)?;
+            writer.write_i32(map.len() as i32)?;
+            for (key, value) in map {
+                writer.write_string(key)?;
+                writer.write_string(value)?;
+            }
+        }
+        Ok(writer)
+    }
+
+    /// Returns `true` if no entry has an attribute.
+    pub fn is_empty(&self) -> bool {
+        self.entries.values().all(|map| map.is_empty())
+    }
+
+    /// Attributes of the entry `full_file_name`.
+    pub fn for_entry(&self, full_file_name: &str) -> Option<&BTreeMap<String, String>> {
+        self.entries.get(&*lookup_key(full_file_name))
+    }
+
+    /// Value of the attribute `key` of the entry `full_file_name`.
+    pub fn get(&self, full_file_name: &str, key: &str) -> Option<&str> {
+        self.for_entry(full_file_name)
+            .and_then(|map| map.get(key))
+            .map(|value| value.as_str())
+    }
+
+    /// Value of the attribute `key` of the entry `full_file_name`, parsed.
+    pub fn get_as<T: FromStr>(&self, full_file_name: &str, key: &str) -> Option<Result<T, T::Err>> {
+        self.get(full_file_name, key).map(|value| value.parse())
+    }
+
+    /// Sets the attribute `key` of the entry `full_file_name`.
+    pub fn set<K: Into<String>, V: ToString>(&mut self, full_file_name: &str, key: K, value: V) {
+        self.entries.entry(lookup_key(full_file_name).into_owned())
+            .or_default()
+            .insert(key.into(), value.to_string());
+    }
+
+    /// Removes the attribute `key` of the entry `full_file_name`, returning
+    /// its value.
+    pub fn remove(&mut self, full_file_name: &str, key: &str) -> Option<String> {
+        self.entries.get_mut(&*lookup_key(full_file_name)).and_then(|map| map.remove(key))
+    }
+
+    /// Removes every attribute of the entry `full_file_name`.
+    pub fn remove_entry(&mut self, full_file_name: &str) -> Option<BTreeMap<String, String>> {
+        self.entries.remove(&*lookup_key(full_file_name))
+    }
+
+    /// Iterates over the entries and their attributes.
+    pub fn iter(&self) -> ::std::collections::btree_map::Iter<'_, String, BTreeMap<String, String>> {
+        self.entries.iter()
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Attributes of the entries, empty if the archive has no
+    /// `ATTRIBUTES_CHUNK`.
+    pub fn attributes(&self) -> io::Result<Attributes> {
+        match self.chunk(ATTRIBUTES_CHUNK) {
+            Some(chunk) => Attributes::from_bytes(&chunk.data()?),
+            None => Ok(Attributes::new()),
+        }
+    }
+}
+
+impl Archive {
+    /// Attributes of the entries, staged changes included.
+    pub fn attributes(&self) -> io::Result<Attributes> {
+        if self.contains(ATTRIBUTES_CHUNK) {
+            Attributes::from_bytes(&self.read_file(ATTRIBUTES_CHUNK)?)
+        } else {
+            Ok(Attributes::new())
+        }
+    }
+
+    /// Stages `attributes` as the attributes of the entries, removing the
+    /// `ATTRIBUTES_CHUNK` if they are empty.
+    pub fn set_attributes(&mut self, attributes: &Attributes) -> io::Result<()> {
+        if attributes.is_empty() {
+            if self.contains(ATTRIBUTES_CHUNK) {
+                self.remove(ATTRIBUTES_CHUNK)?;
+            }
+            Ok(())
+        } else {
+            self.insert(ATTRIBUTES_CHUNK, attributes.to_bytes()?)
+        }
+    }
+
+    /// Value of the attribute `key` of the entry `full_file_name`, parsed.
+    pub fn attribute<T: FromStr>(&self, full_file_name: &str, key: &str) -> io::Result<Option<T>> {
+        match self.attributes()?.get_as(full_file_name, key) {
+            Some(Ok(value)) => Ok(Some(value)),
+            Some(Err(_)) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid attribute \"{}\" of `full_file_name` \"{}\"", key, full_file_name)
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Stages the attribute `key` of the existing entry `full_file_name`.
+    pub fn set_attribute<K: Into<String>, V: ToString>(&mut self, full_file_name: &str, key: K, value: V) -> io::Result<()> {
+        if !self.contains(full_file_name) {
+            return Err(entry_not_found(full_file_name));
+        }
+        let mut attributes = self.attributes()?;
+        attributes.set(full_file_name, key, value);
+        self.set_attributes(&attributes)
+    }
+}
+
+impl MemArchive {
+    /// Attributes of the entries.
+    pub fn attributes(&self) -> io::Result<Attributes> {
+        match self.get(ATTRIBUTES_CHUNK) {
+            Some(data) => Attributes::from_bytes(data),
+            None => Ok(Attributes::new()),
+        }
+    }
+
+    /// Sets the attributes of the entries, removing the `ATTRIBUTES_CHUNK`
+    /// if they are empty.
+    pub fn set_attributes(&mut self, attributes: &Attributes) -> io::Result<()> {
+        if attributes.is_empty() {
+            if self.contains(ATTRIBUTES_CHUNK) {
+                self.remove(ATTRIBUTES_CHUNK)?;
+            }
+            Ok(())
+        } else {
+            self.insert(ATTRIBUTES_CHUNK, attributes.to_bytes()?)
+        }
+    }
+
+    /// Sets the attribute `key` of the existing entry `full_file_name`.
+    pub fn set_attribute<K: Into<String>, V: ToString>(&mut self, full_file_name: &str, key: K, value: V) -> io::Result<()> {
+        if !self.contains(full_file_name) {
+            return Err(entry_not_found(full_file_name));
+        }
+        let mut attributes = self.attributes()?;
+        attributes.set(full_file_name, key, value);
+        self.set_attributes(&attributes)
+    }
+}
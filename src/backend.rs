@@ -0,0 +1,150 @@
+use lazy::LazyReader;
+use offline::Local;
+use options::ReadOptions;
+use read::MergeReader;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::{Cursor, Error, ErrorKind, Read, Seek};
+use std::path::Path;
+use std::rc::Rc;
+
+/// ArchiveSource
+///
+/// Provider of the fragments of an archive which aren't files on the disk:
+/// fragments downloaded by a browser tool (`Uint8Array`s on
+/// `wasm32-unknown-unknown`, where there is no file system), unpacked from
+/// another container, or generated. Any `Fn(&str) -> io::Result<R>` is an
+/// `ArchiveSource`, except with the `offline` feature, where only
+/// `MemSource` is.
+///
+/// The initial fragment is opened by the name given to
+/// `MergeReader::open_source`, the linked ones by that name with its file
+/// name replaced by the `link` property, as with the files.
+pub trait ArchiveSource: Local {
+    /// Reader of a fragment.
+    type Reader: Read + Seek;
+
+    /// Opens the fragment `name`, failing with `NotFound` if there is no
+    /// such fragment.
+    fn open_fragment(&self, name: &str) -> io::Result<Self::Reader>;
+}
+
+#[cfg(not(feature = "offline"))]
+impl<F, R> ArchiveSource for F
+where
+    F: Fn(&str) -> io::Result<R>,
+    R: Read + Seek
+{
+    type Reader = R;
+
+    fn open_fragment(&self, name: &str) -> io::Result<R> {
+        self(name)
+    }
+}
+
+/// MemSource
+///
+/// `ArchiveSource` of fragments held in memory. A fragment is looked up by
+/// its name, then by its file name, so the fragments can be inserted under
+/// their file names whatever the name the archive is opened with. The
+/// bytes are shared, not copied, by the readers.
+#[derive(Clone, Default)]
+pub struct MemSource {
+    fragments: HashMap<String, Rc<[u8]>>,
+}
+
+impl MemSource {
+    /// Creates a new `MemSource` without any fragment.
+    pub fn new() -> Self {
+        MemSource::default()
+    }
+
+    /// Adds the fragment `name`, replacing any fragment of that name.
+    pub fn insert<S: Into<String>, B: Into<Vec<u8>>>(&mut self, name: S, bytes: B) -> &mut Self {
+        self.fragments.insert(name.into(), Rc::from(bytes.into()));
+        self
+    }
+
+    /// Returns `true` if the fragment `name` would be found.
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Number of fragments.
+    pub fn len(&self) -> usize {
+        self.fragments.len()
+    }
+
+    /// Returns `true` if there is no fragment.
+    pub fn is_empty(&self) -> bool {
+        self.fragments.is_empty()
+    }
+
+    fn get(&self, name: &str) -> Option<&Rc<[u8]>> {
+        self.fragments.get(name).or_else(|| {
+            let file_name = &name[name.rfind('/').map_or(0, |slash| slash + 1)..];
+            self.fragments.get(file_name)
+        })
+    }
+}
+
+impl ArchiveSource for MemSource {
+    type Reader = Cursor<Rc<[u8]>>;
+
+    fn open_fragment(&self, name: &str) -> io::Result<Cursor<Rc<[u8]>>> {
+        self.get(name).map(|bytes| Cursor::new(bytes.clone())).ok_or_else(|| Error::new(
+            ErrorKind::NotFound,
+            format!("no fragment \"{}\" in memory", name)
+        ))
+    }
+}
+
+impl fmt::Debug for MemSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<&String> = self.fragments.keys().collect();
+        names.sort();
+        f.debug_struct("MemSource")
+            .field("fragments", &names)
+            .finish()
+    }
+}
+
+/// Name of the fragment at `path`, the paths being built from names.
+fn fragment_name(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Opens the archive `initial` (and the fragments it links to) from
+    /// `source`, without touching the file system.
+    pub fn open_source<S: ArchiveSource<Reader = R>>(initial: &str, source: S) -> io::Result<Self> {
+        MergeReader::open_source_with(initial, source, &ReadOptions::default())
+    }
+
+    /// Opens the archive `initial` from `source` according to `options`.
+    pub fn open_source_with<S: ArchiveSource<Reader = R>>(initial: &str, source: S, options: &ReadOptions) -> io::Result<Self> {
+        MergeReader::merge_with_options(
+            initial,
+            |path| source.open_fragment(&fragment_name(&path)),
+            true,
+            options
+        )
+    }
+}
+
+impl<R> LazyReader<R>
+where
+    R: Read + Seek
+{
+    /// Opens the archive `initial` from `source`, reading only its initial
+    /// fragment, see `MergeReader::open_source`.
+    pub fn open_source<S>(initial: &str, source: S, options: &ReadOptions) -> io::Result<Self>
+        where S: ArchiveSource<Reader = R> + 'static
+    {
+        LazyReader::new(initial, move |path| source.open_fragment(&fragment_name(&path)), options)
+    }
+}
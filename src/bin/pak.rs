@@ -0,0 +1,224 @@
+extern crate pak;
+
+use pak::*;
+
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::{IsTerminal, Write};
+use std::thread;
+
+static USAGE: &str = "usage: pak <command> [arguments]
+
+commands:
+    list <pak> [glob]                       lists the entries and their sizes
+    extract <pak> <dest> [glob]             extracts the entries into dest
+    extract-all <content_dir> <dest>        extracts every archive found in
+                                            content_dir into dest
+    pack <src_dir> <pak> [--max-size <n>]   packs a directory, split into
+                                            fragments of n bytes of data
+    verify <pak>                            reads and checks every entry
+    diff <old_pak> <new_pak>                lists the added (+), removed (-)
+                                            and modified (M) entries
+    info <pak>                              describes the fragments, the
+                                            properties, the largest entries
+                                            and the health of the archive";
+
+/// Progress bar drawn on the standard error when it is a terminal.
+struct Progress {
+    label: &'static str,
+    tracker: ProgressTracker,
+    enabled: bool,
+}
+
+impl Progress {
+    fn new(label: &'static str) -> Self {
+        Progress { label, tracker: ProgressTracker::new(), enabled: io::stderr().is_terminal() }
+    }
+
+    /// Counts the event, redrawing the bar once an entry is done.
+    fn update(&mut self, event: &ProgressEvent) {
+        self.tracker.update(event);
+        if !self.enabled {
+            return;
+        }
+        let finished = match *event {
+            ProgressEvent::EntryFinished { .. } => false,
+            ProgressEvent::Finished { .. } => true,
+            _ => return,
+        };
+        let width = 30;
+        let filled = (self.tracker.fraction() * width as f64) as usize;
+        let remaining = self.tracker.remaining().map_or(0, |remaining| remaining.as_secs());
+        let mut stderr = io::stderr();
+        let _ = write!(
+            stderr,
+            "\r{} [{}{}] {}/{} ~{}s ",
+            self.label, "#".repeat(filled), ".".repeat(width - filled),
+            self.tracker.entries_done(), self.tracker.entries(), remaining
+        );
+        if finished {
+            let _ = writeln!(stderr);
+        }
+        let _ = stderr.flush();
+    }
+}
+
+/// Prints the usage and exits.
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    ExitCode::Usage.exit()
+}
+
+/// Full file names and sizes of the entries of `reader` matching `glob`
+/// (every entry if `None`), sorted by name.
+fn matching_entries<'a>(reader: &'a MergeReader<File>, glob: Option<&str>) -> Vec<(&'a String, u64)> {
+    let pattern = glob.map(Pattern::new);
+    let mut entries: Vec<(&String, u64)> = reader.iter()
+        .filter(|&(full_file_name, _)| pattern.as_ref().is_none_or(|pattern| pattern.matches(full_file_name)))
+        .map(|(full_file_name, chunk)| (full_file_name, chunk.size()))
+        .collect();
+    entries.sort();
+    entries
+}
+
+fn list(pak: &str, glob: Option<&str>) -> io::Result<ExitCode> {
+    let reader = MergeReader::open(pak)?;
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for (full_file_name, size) in matching_entries(&reader, glob) {
+        writeln!(stdout, "{:>12}  {}", size, full_file_name)?;
+    }
+    Ok(ExitCode::Success)
+}
+
+fn extract(pak: &str, dest: &str, glob: Option<&str>) -> io::Result<ExitCode> {
+    let shared = SharedReader::open(pak)?;
+    let pattern = glob.map(Pattern::new);
+    let mut progress = Progress::new("extracting");
+    shared.extract_with_progress(dest, pattern.as_ref(), |event| progress.update(event))?;
+    Ok(ExitCode::Success)
+}
+
+fn extract_all(content_dir: &str, dest: &str) -> io::Result<ExitCode> {
+    let threads = thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1);
+    let report = extract_all_with(content_dir, dest, threads, |done, total, archive| {
+        match archive.error {
+            Some(ref err) => eprintln!("[{}/{}] {}: {}", done, total, archive.archive.display(), err),
+            None => println!("[{}/{}] {}: {} entries", done, total, archive.archive.display(), archive.entries),
+        }
+    })?;
+    println!("{} entries, {} bytes extracted", report.entries(), report.bytes());
+    let code = match report.failed().next() {
+        Some(failed) => {
+            eprintln!("{} archives failed", report.failed().count());
+            ExitCode::from_error(failed.error.as_ref().expect("a failed archive has an error"))
+        }
+        None => ExitCode::Success,
+    };
+    Ok(code)
+}
+
+fn pack(src_dir: &str, pak: &str, max_size: Option<u64>) -> io::Result<ExitCode> {
+    let mut progress = Progress::new("packing");
+    match max_size {
+        Some(max_size) => {
+            let fragments = ChainPacker::pack_dir_with_progress(src_dir, pak, max_size, |event| progress.update(event))?;
+            for fragment in fragments {
+                println!("{}", fragment.display());
+            }
+        }
+        None => PakWriter::pack_dir_with_progress(src_dir, pak, |event| progress.update(event))?,
+    }
+    Ok(ExitCode::Success)
+}
+
+fn verify(pak: &str) -> io::Result<ExitCode> {
+    let report = MergeReader::open(pak)?.verify_all();
+    for finding in &report.findings {
+        println!("{}", finding);
+    }
+    Ok(ExitCode::from_report(&report))
+}
+
+fn diff(old: &str, new: &str) -> io::Result<ExitCode> {
+    let diff = pak::diff(&MergeReader::open(old)?, &MergeReader::open(new)?)?;
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for full_file_name in &diff.added {
+        writeln!(stdout, "+ {}", full_file_name)?;
+    }
+    for full_file_name in &diff.removed {
+        writeln!(stdout, "- {}", full_file_name)?;
+    }
+    for full_file_name in &diff.modified {
+        writeln!(stdout, "M {}", full_file_name)?;
+    }
+    Ok(ExitCode::Success)
+}
+
+fn info(pak: &str) -> io::Result<ExitCode> {
+    let chain = FragmentChain::open(pak)?;
+    for fragment in chain.fragments() {
+        println!("{}: {} entries, {} bytes", fragment.path.display(), fragment.entries, fragment.size);
+    }
+    println!("{} fragments, {} entries, {} bytes", chain.len(), chain.entry_count(), chain.total_size());
+
+    let summary = peek(pak)?;
+    let mut properties: Vec<_> = summary.properties.iter().collect();
+    properties.sort();
+    for (key, value) in properties {
+        println!("{} = {}", key, value);
+    }
+
+    let report = chain.validate();
+    if report.is_ok() {
+        let stats = MergeReader::open(pak)?.stats()?;
+        println!(
+            "{} bytes of data, {} bytes wasted, up to {} bytes duplicated",
+            stats.payload, stats.wasted_bytes(), stats.duplicate_bytes_estimate
+        );
+        for entry in &stats.largest {
+            println!("{}: {} bytes", entry.name, entry.size);
+        }
+    }
+
+    // the findings of the health include the ones of the chain
+    let health = chain.health()?;
+    println!("health {}/100, {}% of the size wasted", health.score, health.wasted_percent());
+    for finding in &health.findings {
+        println!("{}", finding);
+    }
+    Ok(ExitCode::from_report(&report))
+}
+
+fn run(args: &[String]) -> io::Result<ExitCode> {
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    match args[..] {
+        ["list", pak] => list(pak, None),
+        ["list", pak, glob] => list(pak, Some(glob)),
+        ["extract", pak, dest] => extract(pak, dest, None),
+        ["extract", pak, dest, glob] => extract(pak, dest, Some(glob)),
+        ["extract-all", content_dir, dest] => extract_all(content_dir, dest),
+        ["pack", src_dir, pak] => pack(src_dir, pak, None),
+        ["pack", src_dir, pak, "--max-size", max_size] => match max_size.parse() {
+            Ok(max_size) => pack(src_dir, pak, Some(max_size)),
+            Err(_) => usage(),
+        },
+        ["verify", pak] => verify(pak),
+        ["diff", old, new] => diff(old, new),
+        ["info", pak] => info(pak),
+        _ => usage(),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(code) => code.exit(),
+        Err(err) => {
+            eprintln!("pak: {}", err);
+            ExitCode::from_error(&err).exit();
+        }
+    }
+}
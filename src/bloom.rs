@@ -0,0 +1,64 @@
+use fnv::FnvHasher;
+use std::hash::Hasher;
+
+/// BloomFilter
+///
+/// Probabilistic set of names: `might_contain` never returns `false` for
+/// an inserted name, and returns `true` for a missing one with a bounded
+/// false positive rate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    /// Creates a `BloomFilter` sized for `capacity` names with the false
+    /// positive rate `rate` (between 0 and 1 exclusive).
+    pub fn new(capacity: usize, rate: f64) -> Self {
+        let capacity = capacity.max(1) as f64;
+        let rate = rate.clamp(1e-9, 0.5);
+        let ln2 = ::std::f64::consts::LN_2;
+
+        let bits = (-capacity * rate.ln() / (ln2 * ln2)).ceil().max(64.0) as usize;
+        let hashes = ((bits as f64 / capacity) * ln2).round().max(1.0) as u32;
+
+        BloomFilter {
+            bits: vec![0; bits.div_ceil(64)],
+            hashes,
+        }
+    }
+
+    /// Number of bits of the filter.
+    fn len(&self) -> u64 {
+        self.bits.len() as u64 * 64
+    }
+
+    /// Bit positions of `name` (double hashing).
+    fn positions<'a>(&'a self, name: &str) -> impl Iterator<Item = u64> + 'a {
+        let mut hasher = FnvHasher::default();
+        hasher.write(name.as_bytes());
+        let first = hasher.finish();
+        let mut hasher = FnvHasher::with_key(first);
+        hasher.write(name.as_bytes());
+        let second = hasher.finish() | 1;
+
+        let len = self.len();
+        (0..self.hashes as u64).map(move |i| first.wrapping_add(i.wrapping_mul(second)) % len)
+    }
+
+    /// Adds `name` to the filter.
+    pub fn insert(&mut self, name: &str) {
+        let positions: Vec<u64> = self.positions(name).collect();
+        for position in positions {
+            self.bits[(position / 64) as usize] |= 1 << (position % 64);
+        }
+    }
+
+    /// Returns `false` if `name` was definitely not inserted.
+    pub fn might_contain(&self, name: &str) -> bool {
+        self.positions(name).all(|position| {
+            self.bits[(position / 64) as usize] & (1 << (position % 64)) != 0
+        })
+    }
+}
@@ -0,0 +1,120 @@
+use catalog::find_heads;
+use read::MergeReader;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// ArchiveExtraction
+///
+/// Result of the extraction of one archive by `extract_all`.
+#[derive(Debug)]
+pub struct ArchiveExtraction {
+    /// First fragment of the archive
+    pub archive: PathBuf,
+    /// Directory the entries were extracted into
+    pub dest: PathBuf,
+    /// Number of entries of the archive
+    pub entries: usize,
+    /// Total size of their data
+    pub bytes: u64,
+    /// Failure which stopped the extraction of the archive, if any
+    pub error: Option<io::Error>,
+}
+
+impl ArchiveExtraction {
+    /// Returns `true` if every entry was extracted.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// ExtractAllReport
+///
+/// Results of `extract_all`, one per archive in the order of their paths.
+#[derive(Debug, Default)]
+pub struct ExtractAllReport {
+    /// Results of the archives
+    pub archives: Vec<ArchiveExtraction>,
+}
+
+impl ExtractAllReport {
+    /// Returns `true` if every archive was extracted.
+    pub fn is_ok(&self) -> bool {
+        self.archives.iter().all(ArchiveExtraction::is_ok)
+    }
+
+    /// Archives whose extraction failed.
+    pub fn failed(&self) -> impl Iterator<Item = &ArchiveExtraction> {
+        self.archives.iter().filter(|archive| !archive.is_ok())
+    }
+
+    /// Number of entries of the archives extracted.
+    pub fn entries(&self) -> usize {
+        self.archives.iter().filter(|archive| archive.is_ok()).map(|archive| archive.entries).sum()
+    }
+
+    /// Total size of the entries of the archives extracted.
+    pub fn bytes(&self) -> u64 {
+        self.archives.iter().filter(|archive| archive.is_ok()).map(|archive| archive.bytes).sum()
+    }
+}
+
+/// Directory the archive `head`, found in `content_dir`, is extracted
+/// into: its path relative to `content_dir` in `dest`, without extension
+/// (`<content_dir>/maps/maps0.d2p` into `<dest>/maps/maps0`).
+fn mirror_dir(content_dir: &Path, head: &Path, dest: &Path) -> PathBuf {
+    let relative = head.strip_prefix(content_dir).unwrap_or(head);
+    dest.join(relative.with_extension(""))
+}
+
+/// Extracts the archive `head` into `dest`, on `threads` threads.
+fn extract_archive(head: &Path, dest: &Path, threads: usize, extraction: &mut ArchiveExtraction) -> io::Result<()> {
+    let reader = MergeReader::<File>::open(head)?;
+    extraction.entries = reader.iter().count();
+    extraction.bytes = reader.iter().map(|(_, chunk)| chunk.size()).sum();
+    reader.shared()?.extract_all_with(dest, threads)
+}
+
+/// Extracts every archive of the game content directory `content_dir`,
+/// into a directory of `dest` mirroring its layout, see
+/// `extract_all_with`.
+pub fn extract_all<P: AsRef<Path>, Q: AsRef<Path>>(content_dir: P, dest: Q) -> io::Result<ExtractAllReport> {
+    let threads = thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1);
+    extract_all_with(content_dir, dest, threads, |_, _, _| {})
+}
+
+/// Extracts every archive of the game content directory `content_dir`: the
+/// chains are discovered like `Catalog::open` does, and the entries of the
+/// chain starting at `<content_dir>/maps/maps0.d2p` are extracted into
+/// `<dest>/maps/maps0`, on `threads` threads.
+///
+/// A failing archive doesn't stop the extraction of the others: its error
+/// is reported in the `ExtractAllReport`. `progress` is called after each
+/// archive with the number of archives done, their total number and the
+/// result of the archive. Fails only if `content_dir` can't be listed.
+pub fn extract_all_with<P, Q, F>(content_dir: P, dest: Q, threads: usize, mut progress: F) -> io::Result<ExtractAllReport>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: FnMut(usize, usize, &ArchiveExtraction)
+{
+    let (content_dir, dest) = (content_dir.as_ref(), dest.as_ref());
+    let heads = find_heads(content_dir)?;
+
+    let mut report = ExtractAllReport { archives: Vec::with_capacity(heads.len()) };
+    for (i, head) in heads.iter().enumerate() {
+        let mut extraction = ArchiveExtraction {
+            archive: head.clone(),
+            dest: mirror_dir(content_dir, head, dest),
+            entries: 0,
+            bytes: 0,
+            error: None,
+        };
+        let archive_dest = extraction.dest.clone();
+        extraction.error = extract_archive(head, &archive_dest, threads, &mut extraction).err();
+        progress(i + 1, heads.len(), &extraction);
+        report.archives.push(extraction);
+    }
+    Ok(report)
+}
@@ -0,0 +1,259 @@
+use options::ReadStrategy;
+use read::{MergedChunk, MergeReader};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+/// Maximum of the bytes `read_files` reads at once, unless a single entry
+/// is larger.
+const MAX_BATCH: u64 = 16 << 20;
+
+/// Data of a chunk as stored: index of its fragment, offset and size.
+type Location = (usize, u64, u64);
+
+/// Cache of the data of the chunks as stored, the least recently read
+/// evicted first, see `ReadStrategy`.
+#[derive(Debug, Default)]
+pub(crate) struct ChunkCache {
+    /// Data by location, with the tick of its last read
+    entries: HashMap<Location, (Vec<u8>, u64)>,
+    /// Locations by tick of their last read, the least recent first
+    recency: BTreeMap<u64, Location>,
+    /// Ticks given so far
+    tick: u64,
+    /// Bytes of data cached
+    size: usize,
+    /// Offsets and sizes of the chunks of every fragment, sorted by offset,
+    /// built on the first read ahead
+    layout: Option<Vec<Vec<(u64, u64)>>>,
+}
+
+impl ChunkCache {
+    /// Bytes of data cached.
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Empties the cache and forgets the layout of the fragments.
+    pub(crate) fn clear(&mut self) {
+        *self = ChunkCache::default();
+    }
+
+    /// Next tick, the most recent.
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Data at `location`, marked as the most recently read.
+    fn get(&mut self, location: Location) -> Option<Vec<u8>> {
+        let tick = self.next_tick();
+        let entry = self.entries.get_mut(&location)?;
+        self.recency.remove(&entry.1);
+        self.recency.insert(tick, location);
+        entry.1 = tick;
+        Some(entry.0.clone())
+    }
+
+    /// Caches `data` at `location` as the most recently read, evicting the
+    /// least recently read data beyond `capacity` bytes. Data larger than
+    /// `capacity` isn't cached.
+    fn insert(&mut self, location: Location, data: Vec<u8>, capacity: usize) {
+        if data.len() > capacity {
+            return;
+        }
+        let tick = self.next_tick();
+        self.size += data.len();
+        if let Some((old, old_tick)) = self.entries.insert(location, (data, tick)) {
+            self.size -= old.len();
+            self.recency.remove(&old_tick);
+        }
+        self.recency.insert(tick, location);
+
+        while self.size > capacity {
+            let (&oldest, _) = self.recency.iter().next().expect("the cache isn't empty");
+            let location = self.recency.remove(&oldest).expect("the tick was listed");
+            if let Some((evicted, _)) = self.entries.remove(&location) {
+                self.size -= evicted.len();
+            }
+        }
+    }
+}
+
+/// Reads at most `len` bytes of the fragment of `chunk` from `start`.
+fn read_span<R: Read + Seek>(chunk: &MergedChunk<R>, start: u64, len: u64) -> io::Result<Vec<u8>> {
+    let mut span = Vec::with_capacity(len.min(MAX_BATCH) as usize);
+    chunk.with_reader(|reader| {
+        reader.seek(SeekFrom::Start(start))?;
+        reader.by_ref().take(len).read_to_end(&mut span)
+    })?;
+    Ok(span)
+}
+
+/// Data of `chunk` in `span`, read from `start` in its fragment, failing
+/// with `Error::Truncated` if the span ends before it.
+fn slice_of<R: Read + Seek>(chunk: &MergedChunk<R>, start: u64, span: &[u8]) -> io::Result<Vec<u8>> {
+    let from = (chunk.offset() - start) as usize;
+    let available = span.len().saturating_sub(from) as u64;
+    chunk.check_copied(available.min(chunk.size()))?;
+    Ok(span[from..from + chunk.size() as usize].to_vec())
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Strategy of the reads, see `ReadStrategy`.
+    pub fn read_strategy(&self) -> ReadStrategy {
+        self.options.read_strategy
+    }
+
+    /// Replaces the strategy of the reads, emptying the cache.
+    pub fn set_read_strategy(&mut self, strategy: ReadStrategy) {
+        self.options.read_strategy = strategy;
+        self.cache.borrow_mut().clear();
+    }
+
+    /// Empties the cache, releasing the data it holds.
+    pub fn clear_cache(&self) {
+        self.cache.borrow_mut().clear();
+    }
+
+    /// Offsets and sizes of the chunks of every fragment which aren't
+    /// memory-mapped, sorted by offset.
+    fn layout(&self) -> Vec<Vec<(u64, u64)>> {
+        let mut layout = vec![Vec::new(); self.fragments.len()];
+        for chunk in self.chunks.values().filter(|chunk| chunk.as_slice().is_none()) {
+            if let Some(chunks) = layout.get_mut(chunk.fragment()) {
+                chunks.push((chunk.offset(), chunk.size()));
+            }
+        }
+        for chunks in &mut layout {
+            chunks.sort_unstable();
+            chunks.dedup();
+        }
+        layout
+    }
+
+    /// Caches `data` at `location` if the memory limit allows it.
+    fn cache_data(&self, location: Location, data: Vec<u8>) {
+        if self.reserve(data.len()).is_ok() {
+            self.cache.borrow_mut().insert(location, data, self.options.read_strategy.cache_size);
+        }
+    }
+
+    /// Caches the data of every chunk of `fragment` entirely in `span`, read
+    /// from `start`.
+    fn cache_span(&self, fragment: usize, start: u64, span: &[u8]) {
+        let within: Vec<(u64, u64)> = {
+            let mut cache = self.cache.borrow_mut();
+            if cache.layout.is_none() {
+                cache.layout = Some(self.layout());
+            }
+            let chunks = match cache.layout.as_ref().and_then(|layout| layout.get(fragment)) {
+                Some(chunks) => chunks,
+                None => return,
+            };
+            let end = start + span.len() as u64;
+            let first = chunks.partition_point(|&(offset, _)| offset < start);
+            chunks[first..].iter()
+                .take_while(|&&(offset, size)| offset + size <= end)
+                .cloned()
+                .collect()
+        };
+        for (offset, size) in within {
+            let from = (offset - start) as usize;
+            self.cache_data((fragment, offset, size), span[from..from + size as usize].to_vec());
+        }
+    }
+
+    /// Data of `chunk` as stored, from the cache if it is there. Otherwise
+    /// it is read along with the `read_ahead` bytes following it, and
+    /// cached with the chunks read ahead.
+    pub(crate) fn cached_stored(&self, chunk: &MergedChunk<R>) -> io::Result<Vec<u8>> {
+        let location = (chunk.fragment(), chunk.offset(), chunk.size());
+        if let Some(data) = self.cache.borrow_mut().get(location) {
+            return Ok(data);
+        }
+
+        let read_ahead = self.options.read_strategy.read_ahead;
+        let span = read_span(chunk, chunk.offset(), chunk.size() + read_ahead)?;
+        let data = slice_of(chunk, chunk.offset(), &span)?;
+        if read_ahead > 0 {
+            self.cache_span(chunk.fragment(), chunk.offset(), &span);
+        }
+        // the chunk read is the most recent, whatever was read ahead
+        self.cache_data(location, data.clone());
+        Ok(data)
+    }
+
+    /// Reads the entries `names`, returning their data in the order of
+    /// `names`. The data is read in the order of the fragments and of the
+    /// offsets, the entries of a fragment separated by at most
+    /// `ReadStrategy::read_ahead` bytes being read at once, and cached if
+    /// the cache is enabled: loading the assets of a level this way saves
+    /// most of the seeks. Fails like `read_file` if an entry is missing or
+    /// denied, before reading any.
+    pub fn read_files<S: AsRef<str>>(&self, names: &[S]) -> io::Result<Vec<Vec<u8>>> {
+        let mut entries = Vec::with_capacity(names.len());
+        for full_file_name in names {
+            entries.push(self.allowed_entry(full_file_name.as_ref())?);
+        }
+        if self.options.timeouts.read.is_some() {
+            return names.iter().map(|full_file_name| self.read_file(full_file_name.as_ref())).collect();
+        }
+        if let Some(ref mut log) = *self.access_log.borrow_mut() {
+            for &(name, _) in &entries {
+                log.record(name);
+            }
+        }
+
+        let strategy = self.options.read_strategy;
+        let mut stored: Vec<Option<Vec<u8>>> = vec![None; entries.len()];
+        let mut pending = Vec::new();
+        for (i, &(_, chunk)) in entries.iter().enumerate() {
+            if let Some(data) = chunk.as_slice() {
+                stored[i] = Some(data.to_vec());
+            } else if let Some(data) = self.cache.borrow_mut().get((chunk.fragment(), chunk.offset(), chunk.size())) {
+                stored[i] = Some(data);
+            } else {
+                pending.push(i);
+            }
+        }
+        pending.sort_by_key(|&i| (entries[i].1.fragment(), entries[i].1.offset()));
+
+        // runs of close chunks of the same fragment, read at once
+        let mut runs: Vec<(u64, u64, Vec<usize>)> = Vec::new();
+        for i in pending {
+            let chunk = entries[i].1;
+            let end = chunk.offset() + chunk.size();
+            if let Some(&mut (start, ref mut run_end, ref mut run)) = runs.last_mut() {
+                let first = entries[run[0]].1;
+                if first.fragment() == chunk.fragment()
+                    && chunk.offset() <= run_end.saturating_add(strategy.read_ahead)
+                    && end.max(*run_end) - start <= MAX_BATCH
+                {
+                    *run_end = end.max(*run_end);
+                    run.push(i);
+                    continue;
+                }
+            }
+            runs.push((chunk.offset(), end, vec![i]));
+        }
+
+        for (start, end, run) in runs {
+            let first = entries[run[0]].1;
+            let span = read_span(first, start, end - start)?;
+            for &i in &run {
+                stored[i] = Some(slice_of(entries[i].1, start, &span)?);
+            }
+            if strategy.is_cached() {
+                self.cache_span(first.fragment(), start, &span);
+            }
+        }
+
+        entries.iter().zip(stored)
+            .map(|(&(_, chunk), data)| chunk.decode(data.expect("every entry is read")))
+            .collect()
+    }
+}
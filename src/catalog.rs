@@ -0,0 +1,269 @@
+use bloom::BloomFilter;
+use glob::Pattern;
+use name::lookup_key;
+use raw::{Info, Property, read_header};
+use read::{MergedChunk, MergeReader, set_file_name};
+use search::TextMatch;
+use sidecar::FragmentStamp;
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// False positive rate of the name filter of each archive.
+const BLOOM_RATE: f64 = 0.01;
+
+/// Extension of the pak files.
+static PAK_EXTENSION: &str = "d2p";
+
+/// Reads the `link` properties of the single fragment at `path`.
+pub(crate) fn read_links(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    read_header(&mut reader)?;
+    let info = Info::from(&mut reader)?;
+    let properties = Property::read(&mut reader, &info)?;
+
+    Ok(properties.values()
+        .filter(|property| property.key == "link")
+        .filter_map(|property| set_file_name(path, &property.value))
+        .collect())
+}
+
+/// Lists recursively the pak files of `dir`, sorted.
+fn find_paks(dir: &Path, paks: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_paks(&path, paks)?;
+        } else if path.extension().is_some_and(|extension| extension == PAK_EXTENSION) {
+            paks.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Lists the first fragment of every chain of `dir`: the pak files no other
+/// pak file links to.
+pub(crate) fn find_heads(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paks = Vec::new();
+    find_paks(dir, &mut paks)?;
+    paks.sort();
+
+    let mut linked = HashSet::new();
+    for pak in &paks {
+        // an unreadable pak file is kept as its own chain and reported
+        // when opened
+        if let Ok(links) = read_links(pak) {
+            linked.extend(links);
+        }
+    }
+
+    Ok(paks.into_iter().filter(|pak| !linked.contains(pak)).collect())
+}
+
+/// CatalogArchive
+struct CatalogArchive {
+    path: PathBuf,
+    stamps: Vec<FragmentStamp>,
+    reader: MergeReader<File>,
+    /// Sorted full file names
+    names: Vec<String>,
+    /// Filter over `names`
+    bloom: BloomFilter,
+}
+
+impl CatalogArchive {
+    fn open(path: PathBuf) -> io::Result<Self> {
+        let reader = MergeReader::open(&path)?;
+        let stamps = reader.fragments().iter()
+            .map(FragmentStamp::of)
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut names: Vec<String> = reader.iter().map(|(name, _)| name.clone()).collect();
+        names.sort();
+
+        let mut bloom = BloomFilter::new(names.len(), BLOOM_RATE);
+        for name in &names {
+            bloom.insert(name);
+        }
+
+        Ok(CatalogArchive { path, stamps, reader, names, bloom })
+    }
+
+    fn is_fresh(&self) -> bool {
+        self.reader.fragments().len() == self.stamps.len()
+            && self.reader.fragments().iter().zip(&self.stamps).all(|(path, stamp)| {
+                FragmentStamp::of(path).ok().as_ref() == Some(stamp)
+            })
+    }
+
+    /// Names starting with `prefix`.
+    fn names_with_prefix(&self, prefix: &str) -> &[String] {
+        let start = match self.names.binary_search_by(|name| name.as_str().cmp(prefix)) {
+            Ok(i) | Err(i) => i,
+        };
+        let len = self.names[start..].iter()
+            .take_while(|name| name.starts_with(prefix))
+            .count();
+        &self.names[start..start + len]
+    }
+}
+
+/// CatalogMatch
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CatalogMatch {
+    /// First fragment of the archive containing the entry
+    pub archive: PathBuf,
+    /// Full file name of the entry
+    pub full_file_name: String,
+}
+
+/// CatalogChanges
+///
+/// Archives whose index changed during a `Catalog::refresh`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CatalogChanges {
+    /// New archives
+    pub added: Vec<PathBuf>,
+    /// Archives no longer present
+    pub removed: Vec<PathBuf>,
+    /// Archives with at least one modified fragment
+    pub updated: Vec<PathBuf>,
+}
+
+impl CatalogChanges {
+    /// Returns `true` if nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.updated.is_empty()
+    }
+}
+
+/// Catalog
+///
+/// Every archive chain of a content directory, opened and indexed together.
+pub struct Catalog {
+    root: PathBuf,
+    archives: Vec<CatalogArchive>,
+}
+
+impl Catalog {
+    /// Discovers and opens every archive chain of the directory `root`.
+    pub fn open_dir<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let archives = find_heads(&root)?
+            .into_iter()
+            .map(CatalogArchive::open)
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(Catalog { root, archives })
+    }
+
+    /// Content directory of the `Catalog`.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// First fragment of every archive, sorted.
+    pub fn archives(&self) -> impl Iterator<Item = &Path> {
+        self.archives.iter().map(|archive| archive.path.as_path())
+    }
+
+    /// Reader of the archive whose first fragment is `path`.
+    pub fn reader<P: AsRef<Path>>(&self, path: P) -> Option<&MergeReader<File>> {
+        self.archives.iter()
+            .find(|archive| archive.path == path.as_ref())
+            .map(|archive| &archive.reader)
+    }
+
+    /// Finds the entry `full_file_name` in the first archive containing it.
+    pub fn find(&self, full_file_name: &str) -> Option<(&Path, &MergedChunk<File>)> {
+        let full_file_name = lookup_key(full_file_name);
+        self.archives.iter()
+            .filter(|archive| archive.bloom.might_contain(&full_file_name))
+            .filter_map(|archive| {
+                archive.reader.chunks.get(&*full_file_name).map(|chunk| (archive.path.as_path(), chunk))
+            })
+            .next()
+    }
+
+    /// First archive containing the entry `full_file_name`.
+    ///
+    /// Archives are filtered with a bloom filter over their names, so only
+    /// the archives likely to contain the entry are probed.
+    pub fn which_archive_contains(&self, full_file_name: &str) -> Option<&Path> {
+        self.find(full_file_name).map(|(path, _)| path)
+    }
+
+    /// Every archive containing the entry `full_file_name`.
+    pub fn archives_containing(&self, full_file_name: &str) -> Vec<&Path> {
+        let full_file_name = lookup_key(full_file_name);
+        self.archives.iter()
+            .filter(|archive| archive.bloom.might_contain(&full_file_name))
+            .filter(|archive| archive.reader.chunks.contains_key(&*full_file_name))
+            .map(|archive| archive.path.as_path())
+            .collect()
+    }
+
+    /// Finds the entries matching the glob `pattern` in every archive.
+    pub fn search_names(&self, pattern: &Pattern) -> Vec<CatalogMatch> {
+        let prefix = pattern.literal_prefix();
+        self.archives.iter().flat_map(|archive| {
+            archive.names_with_prefix(prefix).iter()
+                .filter(|name| pattern.matches(name))
+                .map(move |name| CatalogMatch {
+                    archive: archive.path.clone(),
+                    full_file_name: name.clone(),
+                })
+        }).collect()
+    }
+
+    /// Finds every occurrence of `needle` in the text entries of every
+    /// archive.
+    pub fn search_content(&self, needle: &str) -> io::Result<Vec<(PathBuf, TextMatch)>> {
+        let mut matches = Vec::new();
+        for archive in &self.archives {
+            for found in archive.reader.search_text(needle)? {
+                matches.push((archive.path.clone(), found));
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Rediscovers the archives of the content directory, reopening only
+    /// the new ones and the ones with a modified fragment.
+    pub fn refresh(&mut self) -> io::Result<CatalogChanges> {
+        let heads = find_heads(&self.root)?;
+        let mut changes = CatalogChanges::default();
+
+        // open everything first so that the catalog is left untouched on error
+        let mut reopened = Vec::new();
+        for head in &heads {
+            match self.archives.iter().find(|archive| &archive.path == head) {
+                Some(archive) if archive.is_fresh() => continue,
+                Some(_) => changes.updated.push(head.clone()),
+                None => changes.added.push(head.clone()),
+            }
+            reopened.push(CatalogArchive::open(head.clone())?);
+        }
+
+        let mut previous: Vec<CatalogArchive> = self.archives.drain(..).collect();
+        for head in heads {
+            let archive = match reopened.iter().position(|archive| archive.path == head) {
+                Some(i) => reopened.swap_remove(i),
+                None => {
+                    let i = previous.iter().position(|archive| archive.path == head).unwrap();
+                    previous.swap_remove(i)
+                }
+            };
+            if let Some(i) = previous.iter().position(|archive| archive.path == head) {
+                previous.swap_remove(i);
+            }
+            self.archives.push(archive);
+        }
+
+        changes.removed = previous.into_iter().map(|archive| archive.path).collect();
+        changes.removed.sort();
+        Ok(changes)
+    }
+}
@@ -0,0 +1,338 @@
+use archive::Archive;
+use catalog::read_links;
+use error::Error as PakError;
+use hash::{Digest, HashBackend};
+use properties::LINK_PROPERTY;
+use raw::{Info, Property, read_header};
+use read::{MergeReader, set_file_name};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use verify::{Finding, Report, Severity};
+
+/// ChainFragment
+///
+/// A fragment of a `FragmentChain`, described by its `Info` and properties
+/// only: the chunk table isn't read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainFragment {
+    /// Path of the fragment
+    pub path: PathBuf,
+    /// Size of the fragment file
+    pub size: u64,
+    /// Number of entries, as declared by the `Info`
+    pub entries: usize,
+    /// Size of the data area, as declared by the `Info`
+    pub data_size: u64,
+    /// Properties, `link` included
+    pub properties: HashMap<String, String>,
+}
+
+impl ChainFragment {
+    /// Reads the tables of the fragment at `path`.
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path)?;
+        let size = file.metadata()?.len();
+
+        let mut reader = BufReader::new(file);
+        read_header(&mut reader)?;
+        let info = Info::from(&mut reader)?;
+        let entries = info.chunks_count.max(0) as usize;
+        let data_size = info.size.max(0) as u64;
+        let properties = Property::read(&mut reader, &info)?
+            .into_iter()
+            .map(|(key, property)| (key, property.value))
+            .collect();
+
+        Ok(ChainFragment { path, size, entries, data_size, properties })
+    }
+
+    /// Value of the `link` property: file name of the next fragment.
+    pub fn link(&self) -> Option<&str> {
+        self.properties.get("link").map(|link| link.as_str())
+    }
+
+    /// Path of the next fragment.
+    pub fn next_path(&self) -> Option<PathBuf> {
+        self.link().and_then(|link| set_file_name(&self.path, link))
+    }
+}
+
+/// FragmentChain
+///
+/// The fragments of a pak file in the order of their links, read without
+/// loading the entries. A chain whose last link points to a missing
+/// fragment, or back to one of its fragments, is kept as is and reported by
+/// `validate`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FragmentChain {
+    fragments: Vec<ChainFragment>,
+    /// Linked fragment which doesn't exist
+    dangling: Option<PathBuf>,
+    /// Linked fragment already in the chain
+    cycle: Option<PathBuf>,
+}
+
+impl FragmentChain {
+    /// Reads the chain starting at the fragment `head`.
+    pub fn open<P: AsRef<Path>>(head: P) -> io::Result<Self> {
+        let mut chain = FragmentChain {
+            fragments: vec![ChainFragment::read(head)?],
+            dangling: None,
+            cycle: None,
+        };
+
+        while let Some(next) = chain.fragments.last().and_then(|fragment| fragment.next_path()) {
+            if chain.fragments.iter().any(|fragment| fragment.path == next) {
+                chain.cycle = Some(next);
+                break;
+            }
+            match ChainFragment::read(&next) {
+                Ok(fragment) => chain.fragments.push(fragment),
+                Err(ref err) if err.kind() == ErrorKind::NotFound => {
+                    chain.dangling = Some(next);
+                    break;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(chain)
+    }
+
+    /// Path of the first fragment.
+    pub fn head(&self) -> &Path {
+        &self.fragments[0].path
+    }
+
+    /// Fragments, in the order of the links.
+    pub fn fragments(&self) -> &[ChainFragment] {
+        &self.fragments
+    }
+
+    /// Number of fragments.
+    pub fn len(&self) -> usize {
+        self.fragments.len()
+    }
+
+    /// Returns `true` if the chain has no fragment, which never happens:
+    /// a chain has at least its head.
+    pub fn is_empty(&self) -> bool {
+        self.fragments.is_empty()
+    }
+
+    /// Total size of the fragment files.
+    pub fn total_size(&self) -> u64 {
+        self.fragments.iter().map(|fragment| fragment.size).sum()
+    }
+
+    /// Total number of entries, the ones overridden by a later fragment
+    /// included.
+    pub fn entry_count(&self) -> usize {
+        self.fragments.iter().map(|fragment| fragment.entries).sum()
+    }
+
+    /// Checks that the links form a complete chain.
+    pub fn validate(&self) -> Report {
+        let mut report = Report::new();
+
+        if let Some(ref dangling) = self.dangling {
+            report.push(Finding::new(
+                Severity::Error,
+                format!("the last fragment links to \"{}\" which doesn't exist", dangling.display()))
+                .suggestion("restore the missing fragment or truncate the chain"));
+        }
+        if let Some(ref cycle) = self.cycle {
+            report.push(Finding::new(
+                Severity::Error,
+                format!("the last fragment links back to \"{}\"", cycle.display()))
+                .suggestion("re-link the chain"));
+        }
+        for fragment in &self.fragments {
+            if fragment.entries == 0 {
+                report.push(Finding::new(
+                    Severity::Warning,
+                    format!("the fragment \"{}\" has no entry", fragment.path.display())));
+            }
+        }
+
+        report
+    }
+
+    /// Opens the chain with a `MergeReader`.
+    pub fn reader(&self) -> io::Result<MergeReader<File>> {
+        MergeReader::open(self.head())
+    }
+
+    /// Canonical digest of the content of the chain, see
+    /// `MergeReader::chain_digest`.
+    pub fn chain_digest<H: HashBackend + ?Sized>(&self, backend: &H) -> io::Result<Digest> {
+        self.reader()?.chain_digest(backend)
+    }
+
+    /// Sets (or removes, if `next` is `None`) the link of the fragment
+    /// `index`, rewriting it.
+    fn set_link(&mut self, index: usize, next: Option<&Path>) -> io::Result<()> {
+        let link = match next {
+            Some(next) => Some(next.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .ok_or_else(|| Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid fragment path \"{}\"", next.display())
+                ))?),
+            None => None,
+        };
+        if self.fragments[index].properties.get(LINK_PROPERTY) == link.as_ref() {
+            return Ok(());
+        }
+
+        // the link is reserved to the chain, which checks it
+        let mut archive = Archive::open_rw(&self.fragments[index].path)?;
+        archive.stage_property(LINK_PROPERTY.to_string(), link);
+        archive.commit()?;
+
+        self.fragments[index] = ChainFragment::read(&self.fragments[index].path)?;
+        Ok(())
+    }
+
+    /// Keeps only the first `len` fragments (at least 1), removing the link
+    /// of the new last fragment. Returns the paths of the fragments detached
+    /// from the chain, which are left on disk.
+    pub fn truncate(&mut self, len: usize) -> io::Result<Vec<PathBuf>> {
+        let len = len.max(1);
+        if len >= self.fragments.len() && self.dangling.is_none() && self.cycle.is_none() {
+            return Ok(Vec::new());
+        }
+
+        let len = len.min(self.fragments.len());
+        self.set_link(len - 1, None)?;
+        self.dangling = None;
+        self.cycle = None;
+        Ok(self.fragments.drain(len..).map(|fragment| fragment.path).collect())
+    }
+
+    /// Re-links the fragments in the given `order` (indices in the current
+    /// chain), rewriting the fragments whose link changes. The fragments
+    /// left out are detached from the chain and their paths returned.
+    pub fn relink(&mut self, order: &[usize]) -> io::Result<Vec<PathBuf>> {
+        let mut seen = vec![false; self.fragments.len()];
+        for &index in order {
+            if index >= self.fragments.len() || seen[index] {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid fragment order {:?}", order)
+                ));
+            }
+            seen[index] = true;
+        }
+        if order.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "empty fragment order"));
+        }
+
+        for i in 0..order.len() {
+            let next = order.get(i + 1).map(|&next| self.fragments[next].path.clone());
+            self.set_link(order[i], next.as_deref())?;
+        }
+
+        let mut fragments: Vec<Option<ChainFragment>> = self.fragments.drain(..).map(Some).collect();
+        self.fragments = order.iter().filter_map(|&index| fragments[index].take()).collect();
+        self.dangling = None;
+        self.cycle = None;
+        Ok(fragments.into_iter().filter_map(|fragment| fragment.map(|fragment| fragment.path)).collect())
+    }
+}
+
+/// Value of the `link` property chaining the fragment `fragment` to the
+/// fragment `next`: the file name of `next`, which must be in the same
+/// directory (or be a bare file name) and differ from `fragment`.
+pub fn link_value<P: AsRef<Path>, Q: AsRef<Path>>(fragment: P, next: Q) -> io::Result<String> {
+    let (fragment, next) = (fragment.as_ref(), next.as_ref());
+    let same_dir = match next.parent() {
+        Some(parent) => parent.as_os_str().is_empty() || Some(parent) == fragment.parent(),
+        None => false,
+    };
+    match next.file_name().and_then(|name| name.to_str()) {
+        Some(link) if same_dir && next.file_name() != fragment.file_name() => Ok(link.to_string()),
+        _ => Err(PakError::InvalidLink { link: next.display().to_string() }.into()),
+    }
+}
+
+/// Checks the `link` value of the fragment written at `fragment` before
+/// its tables are written: it must name another file of the same
+/// directory, an existing fragment whose chain is complete and doesn't lead
+/// back to `fragment`. Returns the path of the linked fragment.
+pub(crate) fn check_link(fragment: &Path, link: &str) -> io::Result<PathBuf> {
+    let invalid = || Error::from(PakError::InvalidLink { link: link.to_string() });
+    if link.is_empty() || link == "." || link == ".." || link.contains(&['/', '\\'][..]) {
+        return Err(invalid());
+    }
+    let next = set_file_name(fragment, link).ok_or_else(invalid)?;
+
+    let mut visited = HashSet::new();
+    let mut linked_from = fragment.to_path_buf();
+    let mut current = next.clone();
+    loop {
+        if current.file_name() == fragment.file_name() || !visited.insert(current.clone()) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("the link \"{}\" of \"{}\" forms a cycle", link, fragment.display())
+            ));
+        }
+        if !current.is_file() {
+            return Err(PakError::MissingFragment { path: current, linked_from }.into());
+        }
+        match read_links(&current)?.into_iter().next() {
+            Some(following) => {
+                linked_from = current;
+                current = following;
+            }
+            None => return Ok(next),
+        }
+    }
+}
+
+/// ArchiveSummary
+///
+/// Summary of a pak file returned by `peek`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveSummary {
+    /// Paths of the fragments, in the order of the links
+    pub fragments: Vec<PathBuf>,
+    /// Number of entries of all the fragments, the ones overridden by a
+    /// later fragment included
+    pub entry_count: usize,
+    /// Total size of the fragment files
+    pub total_size: u64,
+    /// Merged properties, the `link` ones excluded
+    pub properties: HashMap<String, String>,
+}
+
+/// Summarizes the pak file `path` reading only the `Info` and the properties
+/// of its fragments, without building the index of the entries. Fast enough
+/// to describe archives in a file browser.
+pub fn peek<P: AsRef<Path>>(path: P) -> io::Result<ArchiveSummary> {
+    let chain = FragmentChain::open(path)?;
+    if let Some(ref dangling) = chain.dangling {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("missing fragment \"{}\"", dangling.display())
+        ));
+    }
+
+    let mut properties = HashMap::new();
+    for fragment in &chain.fragments {
+        properties.extend(fragment.properties.iter()
+            .filter(|&(key, _)| key != "link")
+            .map(|(key, value)| (key.clone(), value.clone())));
+    }
+
+    Ok(ArchiveSummary {
+        entry_count: chain.entry_count(),
+        total_size: chain.total_size(),
+        fragments: chain.fragments.into_iter().map(|fragment| fragment.path).collect(),
+        properties,
+    })
+}
@@ -0,0 +1,152 @@
+use error::Error as PakError;
+use hash::{Digest, HashAlgorithm, HashBackend};
+use raw::Property;
+use read::{MergedChunk, MergeReader};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::{Read, Seek};
+use verify::{Finding, Report, Severity};
+
+/// Prefix of the keys of the properties holding the checksums of the chunks
+/// of a fragment, followed by the name of the chunk as stored in the chunk
+/// table.
+pub(crate) const CHECKSUM_PREFIX: &str = "checksum:";
+
+/// Size of the blocks in which the data is checked.
+const BLOCK_SIZE: usize = 1 << 16;
+
+/// Checksum
+///
+/// Checksum of the data of a chunk, stored by the writers in the properties
+/// of its fragment when `WriteOptions::checksums` is set: the key is
+/// `checksum:` followed by the name of the chunk, the value the algorithm
+/// and the hexadecimal digest (`crc32:1c291ca3`). The readers attach the
+/// checksums to the chunks of their fragment, without listing them among
+/// the properties.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Checksum {
+    /// Algorithm of the digest
+    pub algorithm: HashAlgorithm,
+    /// Digest of the data
+    pub digest: Digest,
+}
+
+impl Checksum {
+    /// Computes the checksum of `data` with `algorithm`.
+    pub fn of(algorithm: HashAlgorithm, data: &[u8]) -> Self {
+        Checksum { algorithm, digest: algorithm.digest(data) }
+    }
+
+    /// Parses the value of a checksum property, `None` if the algorithm is
+    /// unknown (or disabled) or the digest isn't hexadecimal.
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.splitn(2, ':');
+        let algorithm = parts.next().and_then(HashAlgorithm::from_name)?;
+        let digest = parts.next().and_then(Digest::from_hex)?;
+        Some(Checksum { algorithm, digest })
+    }
+
+    /// Property storing the checksum of the chunk `stored_name`.
+    pub(crate) fn to_property(&self, stored_name: &str) -> Property {
+        Property::new(format!("{}{}", CHECKSUM_PREFIX, stored_name), self.to_string())
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.digest)
+    }
+}
+
+/// Removes the checksum properties from the properties of a fragment,
+/// returning the checksums by stored chunk name. The unsupported ones are
+/// reported in `warnings`.
+pub(crate) fn take_checksums(properties: &mut HashMap<String, Property>, warnings: &mut Vec<Finding>) -> HashMap<String, Checksum> {
+    let keys: Vec<String> = properties.keys()
+        .filter(|key| key.starts_with(CHECKSUM_PREFIX))
+        .cloned()
+        .collect();
+    let mut checksums = HashMap::with_capacity(keys.len());
+    for key in keys {
+        let property = properties.remove(&key).expect("the key was listed");
+        let stored_name = &key[CHECKSUM_PREFIX.len()..];
+        match Checksum::parse(&property.value) {
+            Some(checksum) => {
+                checksums.insert(stored_name.to_string(), checksum);
+            }
+            None => warnings.push(Finding::new(
+                Severity::Warning,
+                format!("unsupported checksum \"{}\", not verified", property.value))
+                .entry(stored_name)),
+        }
+    }
+    checksums
+}
+
+impl<R> MergedChunk<R>
+where
+    R: Read + Seek
+{
+    /// Checksum stored for the data, if any.
+    pub fn checksum(&self) -> Option<&Checksum> {
+        self.checksum.as_ref()
+    }
+
+    /// Reads the data in blocks and compares it with its checksum, failing
+    /// with `Error::ChecksumMismatch` if they differ. Succeeds without
+    /// reading anything if no checksum is stored.
+    pub fn verify_checksum(&self) -> io::Result<()> {
+        let expected = match self.checksum {
+            Some(ref checksum) => checksum,
+            None => return Ok(()),
+        };
+        let mut state = expected.algorithm.start();
+        for block in self.stored_iter(BLOCK_SIZE) {
+            state.update(&block?);
+        }
+        let actual = state.finish();
+        if actual != expected.digest {
+            return Err(PakError::ChecksumMismatch { expected: expected.clone(), actual }.into());
+        }
+        Ok(())
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Verifies the archive like `verify`, then reads every entry, checking
+    /// its data against its checksum when one is stored, see `Checksum`.
+    pub fn verify_all(&self) -> Report {
+        let mut report = self.verify();
+        let mut entries: Vec<_> = self.iter()
+            .filter(|&(full_file_name, _)| {
+                report.for_entry(full_file_name).all(|finding| finding.severity < Severity::Error)
+            })
+            .collect();
+        // sequential reads
+        entries.sort_by_key(|&(full_file_name, chunk)| (chunk.fragment(), chunk.offset(), full_file_name.clone()));
+
+        let mut unchecked = 0;
+        for (full_file_name, chunk) in entries {
+            let result = match chunk.checksum {
+                Some(_) => chunk.verify_checksum(),
+                None => {
+                    unchecked += 1;
+                    chunk.stored_iter(BLOCK_SIZE).try_for_each(|block| block.map(|_| ()))
+                }
+            };
+            if let Err(err) = result {
+                report.push(Finding::new(Severity::Error, err.to_string())
+                    .entry(full_file_name.as_str())
+                    .suggestion("repack the archive from an intact copy"));
+            }
+        }
+        if unchecked > 0 {
+            report.push(Finding::new(Severity::Info, format!("{} entries without checksum, only read", unchecked)));
+        }
+        report
+    }
+}
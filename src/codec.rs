@@ -0,0 +1,70 @@
+use std::fmt;
+use std::io;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+
+/// Function encoding or decoding a name.
+type NameFn = Arc<dyn Fn(&str) -> io::Result<String> + Send + Sync>;
+
+/// NameCodec
+///
+/// Transformation of the entry names as stored in the chunk tables, for
+/// the archives whose names are obfuscated. Set in `ReadOptions`, the names
+/// are decoded before being normalized; set in `WriteOptions`, the
+/// normalized names are encoded when the chunk table is written. The data
+/// and the properties are left as is.
+///
+/// Two codecs are equal if they are clones of each other.
+#[derive(Clone)]
+pub struct NameCodec {
+    decode: NameFn,
+    encode: NameFn,
+}
+
+impl NameCodec {
+    /// Creates a new `NameCodec` from its `decode` and `encode` functions.
+    pub fn new<D, E>(decode: D, encode: E) -> Self
+        where D: Fn(&str) -> io::Result<String> + Send + Sync + 'static,
+              E: Fn(&str) -> io::Result<String> + Send + Sync + 'static
+    {
+        NameCodec {
+            decode: Arc::new(decode),
+            encode: Arc::new(encode),
+        }
+    }
+
+    /// Creates a new `NameCodec` which can only decode, enough to read the
+    /// obfuscated archives.
+    pub fn decoder<D>(decode: D) -> Self
+        where D: Fn(&str) -> io::Result<String> + Send + Sync + 'static
+    {
+        NameCodec::new(decode, |full_file_name: &str| Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("can't encode `full_file_name` \"{}\" with a decoder", full_file_name)
+        )))
+    }
+
+    /// Decodes the name `stored` in a chunk table.
+    pub fn decode(&self, stored: &str) -> io::Result<String> {
+        (self.decode)(stored)
+    }
+
+    /// Encodes `full_file_name` to store it in a chunk table.
+    pub fn encode(&self, full_file_name: &str) -> io::Result<String> {
+        (self.encode)(full_file_name)
+    }
+}
+
+impl fmt::Debug for NameCodec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("NameCodec(..)")
+    }
+}
+
+impl PartialEq for NameCodec {
+    fn eq(&self, other: &NameCodec) -> bool {
+        Arc::ptr_eq(&self.decode, &other.decode) && Arc::ptr_eq(&self.encode, &other.encode)
+    }
+}
+
+impl Eq for NameCodec {}
@@ -0,0 +1,87 @@
+use read::MergeReader;
+use std::io::{Read, Seek};
+
+/// NameIndex
+///
+/// Entry names sorted in a single array, on which the names sharing a
+/// prefix form a contiguous range found by binary search. It backs the
+/// completion of names in shells and browsers, where the same index serves
+/// every keystroke.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NameIndex {
+    names: Vec<String>,
+}
+
+impl NameIndex {
+    /// Indexes `names`.
+    pub fn new<I, S>(names: I) -> Self
+        where I: IntoIterator<Item = S>,
+              S: Into<String>
+    {
+        let mut names: Vec<String> = names.into_iter().map(Into::into).collect();
+        names.sort();
+        names.dedup();
+        NameIndex { names }
+    }
+
+    /// Number of names.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns `true` if the index contains no name.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Names starting with `prefix`, sorted.
+    pub fn with_prefix(&self, prefix: &str) -> &[String] {
+        let start = self.names.partition_point(|name| name.as_str() < prefix);
+        let len = self.names[start..].partition_point(|name| name.starts_with(prefix));
+        &self.names[start..start + len]
+    }
+
+    /// Completions of `prefix`, sorted: the full names of the matching
+    /// entries, and the matching directories (ending with `/`) instead of
+    /// their content.
+    ///
+    /// `complete("gfx/mon")` gives `["gfx/monkey.swf", "gfx/monsters/"]`
+    /// for the entries `gfx/monsters/1.swf`, `gfx/monsters/2.swf` and
+    /// `gfx/monkey.swf`.
+    pub fn complete(&self, prefix: &str) -> Vec<String> {
+        let mut names = self.with_prefix(prefix);
+        let mut completions = Vec::new();
+        while let Some(name) = names.first() {
+            match name[prefix.len()..].find('/') {
+                Some(slash) => {
+                    let dir = &name[..prefix.len() + slash + 1];
+                    // the content of the directory is skipped at once
+                    names = &names[names.partition_point(|name| name.starts_with(dir))..];
+                    completions.push(dir.to_string());
+                }
+                None => {
+                    names = &names[1..];
+                    completions.push(name.clone());
+                }
+            }
+        }
+        completions
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Indexes the entry names, see `NameIndex`.
+    pub fn name_index(&self) -> NameIndex {
+        NameIndex::new(self.chunks.keys().map(|name| name.as_str()))
+    }
+
+    /// Completions of `prefix` among the entry names, see
+    /// `NameIndex::complete`. The names are indexed on each call: keep a
+    /// `name_index` to complete repeatedly.
+    pub fn complete(&self, prefix: &str) -> Vec<String> {
+        self.name_index().complete(prefix)
+    }
+}
@@ -0,0 +1,186 @@
+use raw::Property;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::{Error, ErrorKind, Read, Write};
+use verify::{Finding, Severity};
+
+/// Prefix of the keys of the properties recording the compression of the
+/// chunks of a fragment, followed by the name of the chunk as stored in the
+/// chunk table.
+pub(crate) const COMPRESSION_PREFIX: &str = "compression:";
+
+/// Compression
+///
+/// Compression of the data of the chunks, applied by the writers when
+/// `WriteOptions::compression` is set and reverted by the readers. The
+/// compression of a chunk is recorded in the properties of its fragment:
+/// the key is `compression:` followed by the name of the chunk, the value
+/// the compression and the size of the uncompressed data (`zlib:1024`).
+/// The chunks without such a property are stored as is, so the archives
+/// written without compression stay readable by any pak reader.
+///
+/// The compressions are enabled by the `zlib` and `zstd` features.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Compression {
+    /// zlib stream (RFC 1950)
+    #[cfg(feature = "zlib")]
+    Zlib,
+    /// Zstandard frame
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// Name of the compression, as recorded in the properties.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "zlib")]
+            Compression::Zlib => "zlib",
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// Compression named `name`, `None` if it is unknown or disabled.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            #[cfg(feature = "zlib")]
+            "zlib" => Some(Compression::Zlib),
+            #[cfg(feature = "zstd")]
+            "zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Compresses `input` into `output`, returning the number of bytes
+    /// read from `input`.
+    // nothing to match without any compression enabled
+    #[cfg_attr(not(any(feature = "zlib", feature = "zstd")), allow(unused_variables, unreachable_code))]
+    pub(crate) fn compress<R: Read, W: Write>(&self, input: &mut R, output: &mut W) -> io::Result<u64> {
+        match *self {
+            #[cfg(feature = "zlib")]
+            Compression::Zlib => {
+                let mut encoder = ::flate2::write::ZlibEncoder::new(output, ::flate2::Compression::default());
+                let read = io::copy(input, &mut encoder)?;
+                encoder.finish()?;
+                Ok(read)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                let mut encoder = ::zstd::stream::write::Encoder::new(output, 0)?;
+                let read = io::copy(input, &mut encoder)?;
+                encoder.finish()?;
+                Ok(read)
+            }
+        }
+    }
+
+    /// Decoder of the data compressed in `input`.
+    // nothing to match without any compression enabled
+    #[cfg_attr(not(any(feature = "zlib", feature = "zstd")), allow(unused_variables, unreachable_code))]
+    pub(crate) fn decoder<'a, R: Read + 'a>(&self, input: R) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match *self {
+            #[cfg(feature = "zlib")]
+            Compression::Zlib => Box::new(::flate2::read::ZlibDecoder::new(input)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Box::new(::zstd::stream::read::Decoder::new(input)?),
+        })
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Compression of a stored chunk, as recorded in the properties of its
+/// fragment. The name is kept as is so that the chunks compressed with a
+/// disabled compression are still copied along with it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ChunkCompression {
+    /// Name of the compression
+    pub(crate) name: String,
+    /// Size of the uncompressed data
+    pub(crate) size: u64,
+}
+
+impl ChunkCompression {
+    /// Parses the value of a compression property, `None` if it is
+    /// malformed.
+    fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.splitn(2, ':');
+        let name = parts.next()?.to_string();
+        let size = parts.next()?.parse().ok()?;
+        Some(ChunkCompression { name, size })
+    }
+
+    /// Property recording the compression of the chunk `stored_name`.
+    pub(crate) fn to_property(&self, stored_name: &str) -> Property {
+        Property::new(format!("{}{}", COMPRESSION_PREFIX, stored_name), format!("{}:{}", self.name, self.size))
+    }
+
+    /// Compression of the chunk, failing with `Unsupported` if it is
+    /// disabled.
+    fn compression(&self) -> io::Result<Compression> {
+        Compression::from_name(&self.name).ok_or_else(|| Error::new(
+            ErrorKind::Unsupported,
+            format!("the data is compressed with \"{}\", which isn't enabled", self.name)
+        ))
+    }
+
+    /// Decoder of the data compressed in `stored`, failing with
+    /// `Unsupported` if the compression is disabled. The number of bytes
+    /// read from it must be checked with `check_size`.
+    pub(crate) fn decoder<'a, R: Read + 'a>(&self, stored: R) -> io::Result<Box<dyn Read + 'a>> {
+        // the recorded size comes from the file, don't read past it
+        Ok(Box::new(self.compression()?.decoder(stored)?.take(self.size + 1)))
+    }
+
+    /// Checks that the data decompressed has the recorded size, failing with
+    /// `InvalidData` otherwise.
+    pub(crate) fn check_size(&self, decoded: u64) -> io::Result<u64> {
+        if decoded != self.size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("the data decompressed with {} has {} bytes instead of {}", self.name, decoded, self.size)
+            ));
+        }
+        Ok(decoded)
+    }
+
+    /// Decompresses the data compressed in `stored`.
+    pub(crate) fn decode(&self, stored: &[u8]) -> io::Result<Vec<u8>> {
+        // nor trust it for the allocation
+        let mut data = Vec::with_capacity(self.size.min(stored.len() as u64 * 4) as usize);
+        self.decoder(stored)?.read_to_end(&mut data)?;
+        self.check_size(data.len() as u64)?;
+        Ok(data)
+    }
+}
+
+/// Removes the compression properties from the properties of a fragment,
+/// returning the compressions by stored chunk name. The malformed ones are
+/// reported in `warnings`.
+pub(crate) fn take_compressions(properties: &mut HashMap<String, Property>, warnings: &mut Vec<Finding>) -> HashMap<String, ChunkCompression> {
+    let keys: Vec<String> = properties.keys()
+        .filter(|key| key.starts_with(COMPRESSION_PREFIX))
+        .cloned()
+        .collect();
+    let mut compressions = HashMap::with_capacity(keys.len());
+    for key in keys {
+        let property = properties.remove(&key).expect("the key was listed");
+        let stored_name = &key[COMPRESSION_PREFIX.len()..];
+        match ChunkCompression::parse(&property.value) {
+            Some(compression) => {
+                compressions.insert(stored_name.to_string(), compression);
+            }
+            None => warnings.push(Finding::new(
+                Severity::Error,
+                format!("malformed compression \"{}\", the data is read as stored", property.value))
+                .entry(stored_name)),
+        }
+    }
+    compressions
+}
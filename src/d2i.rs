@@ -0,0 +1,307 @@
+//! Reading and writing of the `.d2i` translation files.
+//!
+//! A translation file holds the texts of one language: the texts referred to
+//! by an id (the `I18n` fields of the d2o databases) and the named texts of
+//! the user interface. The whole file is read in memory so that it can be
+//! edited and written back.
+//!
+//! D2I file format :
+//!
+//! ``` text
+//!     1. Header : From Start 0
+//!         +----------------------+----------------+-----------------------+
+//!         |   index_offset       |    4 bytes     |   offset of the index |
+//!         +----------------------+----------------+-----------------------+
+//!
+//!     2. Texts : From Start 4
+//!         +---------------------+------------------------------------+
+//!         |   text              |  2 bytes (length) | string (utf8)  |
+//!         +---------------------+------------------------------------+
+//!
+//!     3. Index : From Start index_offset
+//!         +----------------------+----------------+-----------------------+
+//!         |   ids_len            |    4 bytes     |   length in bytes     |
+//!         +----------------------+----------------+-----------------------+
+//!         for each text with an id
+//!             +---------------------+------------------------------------+
+//!             |   id                |  4 bytes                           |
+//!             |   undiacritical     |  1 byte (has an undiacritical text)|
+//!             |   offset            |  4 bytes                           |
+//!             |   undiacritical     |  4 bytes offset, if any            |
+//!             +---------------------+------------------------------------+
+//!         +----------------------+----------------+-----------------------+
+//!         |   names_len          |    4 bytes     |   length in bytes     |
+//!         +----------------------+----------------+-----------------------+
+//!         for each named text
+//!             +---------------------+------------------------------------+
+//!             |   name              |  2 bytes (length) | string (utf8)  |
+//!             |   offset            |  4 bytes                           |
+//!             +---------------------+------------------------------------+
+//!         +----------------------+----------------+-----------------------+
+//!         |   sort_len           |    4 bytes     |   length in bytes     |
+//!         +----------------------+----------------+-----------------------+
+//!         for each text with an id, in the alphabetical order of the texts
+//!             +---------------------+------------------------------------+
+//!             |   id                |  4 bytes                           |
+//!             +---------------------+------------------------------------+
+//! ```
+
+use byteorder_extended::{ReadExt, WriteExt};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+fn invalid_data<S: Into<String>>(message: S) -> Error {
+    Error::new(ErrorKind::InvalidData, message.into())
+}
+
+/// Reads the string at `offset`.
+fn read_text_at<R: Read + Seek>(reader: &mut R, offset: i32) -> io::Result<String> {
+    if offset < 0 {
+        return Err(invalid_data("invalid d2i text offset"));
+    }
+    reader.seek(SeekFrom::Start(offset as u64))?;
+    reader.read_string()
+}
+
+/// Converts a position of the file being written to an offset of the index.
+fn offset_of(position: u64) -> io::Result<i32> {
+    if position > i32::MAX as u64 {
+        return Err(Error::new(ErrorKind::InvalidInput, "the d2i file would exceed 2 GiB"));
+    }
+    Ok(position as i32)
+}
+
+/// I18nText
+///
+/// Text of a translation file referred to by an id.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct I18nText {
+    /// Text
+    pub text: String,
+    /// Text without diacritics (`é` as `e`...) used by the searches of the
+    /// game, `None` if it is the text itself
+    pub undiacritical: Option<String>,
+}
+
+/// D2iFile
+///
+/// The texts of a translation file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct D2iFile {
+    /// Texts, by id
+    texts: BTreeMap<i32, I18nText>,
+    /// Named texts, by name
+    named: BTreeMap<String, String>,
+    /// Ids in the alphabetical order of the texts, as read, `None` once the
+    /// texts are edited
+    sort_order: Option<Vec<i32>>,
+}
+
+impl D2iFile {
+    /// Creates an empty `D2iFile`.
+    pub fn new() -> Self {
+        D2iFile::default()
+    }
+
+    /// Reads the translation file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        D2iFile::from(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Reads a `D2iFile` from the specified reader.
+    pub fn from<R: Read + Seek>(reader: &mut R) -> io::Result<Self> {
+        let index_offset = reader.read_i32()?;
+        if index_offset < 4 {
+            return Err(invalid_data("invalid d2i index offset"));
+        }
+        reader.seek(SeekFrom::Start(index_offset as u64))?;
+
+        let ids_len = reader.read_i32()?;
+        let mut ids = Vec::new();
+        let mut read = 0;
+        while read < ids_len {
+            let id = reader.read_i32()?;
+            let has_undiacritical = reader.read_u8()? != 0;
+            let offset = reader.read_i32()?;
+            let undiacritical = if has_undiacritical {
+                read += 4;
+                Some(reader.read_i32()?)
+            } else {
+                None
+            };
+            ids.push((id, offset, undiacritical));
+            read += 9;
+        }
+
+        let names_len = reader.read_i32()?;
+        let mut names = Vec::new();
+        let mut read = 0;
+        while read < names_len {
+            let name = reader.read_string()?;
+            read += name.len() as i32 + 6;
+            names.push((name, reader.read_i32()?));
+        }
+
+        let sort_len = reader.read_i32()?;
+        let mut sort_order = Vec::new();
+        for _ in 0..sort_len.max(0) / 4 {
+            sort_order.push(reader.read_i32()?);
+        }
+
+        let mut texts = BTreeMap::new();
+        for (id, offset, undiacritical) in ids {
+            let text = read_text_at(reader, offset)?;
+            let undiacritical = match undiacritical {
+                Some(offset) => Some(read_text_at(reader, offset)?),
+                None => None,
+            };
+            texts.insert(id, I18nText { text, undiacritical });
+        }
+        let mut named = BTreeMap::new();
+        for (name, offset) in names {
+            let text = read_text_at(reader, offset)?;
+            named.insert(name, text);
+        }
+
+        Ok(D2iFile { texts, named, sort_order: Some(sort_order) })
+    }
+
+    /// Text `id`.
+    pub fn text(&self, id: i32) -> Option<&str> {
+        self.texts.get(&id).map(|text| text.text.as_str())
+    }
+
+    /// Text `id` with its undiacritical variant.
+    pub fn entry(&self, id: i32) -> Option<&I18nText> {
+        self.texts.get(&id)
+    }
+
+    /// Named text `name`.
+    pub fn named(&self, name: &str) -> Option<&str> {
+        self.named.get(name).map(String::as_str)
+    }
+
+    /// Texts, in the order of their ids.
+    pub fn texts(&self) -> &BTreeMap<i32, I18nText> {
+        &self.texts
+    }
+
+    /// Named texts, in the order of their names.
+    pub fn named_texts(&self) -> &BTreeMap<String, String> {
+        &self.named
+    }
+
+    /// Sets the text `id`, without undiacritical variant, returning the
+    /// previous one.
+    pub fn set_text<S: Into<String>>(&mut self, id: i32, text: S) -> Option<I18nText> {
+        self.set_entry(id, I18nText { text: text.into(), undiacritical: None })
+    }
+
+    /// Sets the text `id` with its undiacritical variant, returning the
+    /// previous one.
+    pub fn set_entry(&mut self, id: i32, text: I18nText) -> Option<I18nText> {
+        self.sort_order = None;
+        self.texts.insert(id, text)
+    }
+
+    /// Removes the text `id`, returning it.
+    pub fn remove_text(&mut self, id: i32) -> Option<I18nText> {
+        let removed = self.texts.remove(&id);
+        if removed.is_some() {
+            self.sort_order = None;
+        }
+        removed
+    }
+
+    /// Sets the named text `name`, returning the previous one.
+    pub fn set_named<K: Into<String>, S: Into<String>>(&mut self, name: K, text: S) -> Option<String> {
+        self.named.insert(name.into(), text.into())
+    }
+
+    /// Removes the named text `name`, returning it.
+    pub fn remove_named(&mut self, name: &str) -> Option<String> {
+        self.named.remove(name)
+    }
+
+    /// Ids in the alphabetical order of the texts: the order of the file read
+    /// if the texts weren't edited since, otherwise the order of their
+    /// lowercase undiacritical variant.
+    pub fn sort_order(&self) -> Vec<i32> {
+        if let Some(ref sort_order) = self.sort_order {
+            return sort_order.clone();
+        }
+        let mut ids: Vec<(String, i32)> = self.texts.iter()
+            .map(|(&id, text)| (text.undiacritical.as_ref().unwrap_or(&text.text).to_lowercase(), id))
+            .collect();
+        ids.sort();
+        ids.into_iter().map(|(_, id)| id).collect()
+    }
+
+    /// Writes the `D2iFile` in the specified writer: the texts in the order
+    /// of their ids, then the named texts, then the index.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut position = 4u64;
+        let mut write_text = |writer: &mut W, text: &str| -> io::Result<i32> {
+            let offset = offset_of(position)?;
+            writer.write_string(text)?;
+            position += 2 + text.len() as u64;
+            Ok(offset)
+        };
+
+        // the index offset is known once the texts are laid out
+        let index_offset = 4 + self.texts.values()
+            .map(|text| 2 + text.text.len() as u64 + text.undiacritical.as_ref().map_or(0, |text| 2 + text.len() as u64))
+            .sum::<u64>()
+            + self.named.values().map(|text| 2 + text.len() as u64).sum::<u64>();
+        writer.write_i32(offset_of(index_offset)?)?;
+
+        let mut ids = Vec::with_capacity(self.texts.len());
+        for (&id, text) in &self.texts {
+            let offset = write_text(writer, &text.text)?;
+            let undiacritical = match text.undiacritical {
+                Some(ref undiacritical) => Some(write_text(writer, undiacritical)?),
+                None => None,
+            };
+            ids.push((id, offset, undiacritical));
+        }
+        let mut names = Vec::with_capacity(self.named.len());
+        for (name, text) in &self.named {
+            names.push((name, write_text(writer, text)?));
+        }
+
+        let ids_len: usize = ids.iter().map(|&(_, _, undiacritical)| if undiacritical.is_some() { 13 } else { 9 }).sum();
+        writer.write_i32(offset_of(ids_len as u64)?)?;
+        for (id, offset, undiacritical) in ids {
+            writer.write_i32(id)?;
+            writer.write_u8(undiacritical.is_some() as u8)?;
+            writer.write_i32(offset)?;
+            if let Some(offset) = undiacritical {
+                writer.write_i32(offset)?;
+            }
+        }
+
+        let names_len: usize = names.iter().map(|&(name, _)| name.len() + 6).sum();
+        writer.write_i32(offset_of(names_len as u64)?)?;
+        for (name, offset) in names {
+            writer.write_string(name)?;
+            writer.write_i32(offset)?;
+        }
+
+        let sort_order = self.sort_order();
+        writer.write_i32(offset_of(sort_order.len() as u64 * 4)?)?;
+        for id in sort_order {
+            writer.write_i32(id)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the `D2iFile` at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.into_inner().map_err(Error::from)?.sync_all()
+    }
+}
@@ -0,0 +1,464 @@
+//! Reading of the `.d2o` game object databases, and of their signed
+//! `.d2os` variant.
+//!
+//! A database holds the objects of one module (items, monsters...) and the
+//! definitions of their classes. Only the class definitions and the index of
+//! the objects are read when the database is opened: each object is read
+//! when it is requested.
+//!
+//! D2O file format :
+//!
+//! ``` text
+//!     1. Header : From Start 0 (after the signature of a d2os file)
+//!         +----------------------+----------------+-----------------------+
+//!         |   header             |    3 bytes     |   value expected D2O  |
+//!         |   index_offset       |    4 bytes     |   from the header     |
+//!         +----------------------+----------------+-----------------------+
+//!
+//!     2. Objects : From Start 7
+//!         for each object
+//!             +---------------------+------------------------------------+
+//!             |   class_id          |  4 bytes                           |
+//!             |   fields            |  values of the fields of the class |
+//!             +---------------------+------------------------------------+
+//!
+//!     3. Index : From Start index_offset
+//!         +----------------------+----------------+-----------------------+
+//!         |   index_len          |    4 bytes     |   8 bytes per object  |
+//!         +----------------------+----------------+-----------------------+
+//!         for 0 to index_len / 8
+//!             +---------------------+------------------------------------+
+//!             |   id                |  4 bytes                           |
+//!             |   offset            |  4 bytes (from the header)         |
+//!             +---------------------+------------------------------------+
+//!
+//!     4. Classes : After the index
+//!         +----------------------+----------------+-----------------------+
+//!         |   classes_count      |    4 bytes     |   _                   |
+//!         +----------------------+----------------+-----------------------+
+//!         for 0 to classes_count
+//!             +---------------------+------------------------------------+
+//!             |   class_id          |  4 bytes                           |
+//!             |   name              |  2 bytes (length) | string (utf8)  |
+//!             |   package           |  2 bytes (length) | string (utf8)  |
+//!             |   fields_count      |  4 bytes                           |
+//!             |   fields            |  name (utf8) and type, see below   |
+//!             +---------------------+------------------------------------+
+//! ```
+//!
+//! A field type is a 4 bytes code, see `FieldType`; the vector code is
+//! followed by the name and the type of the elements. A d2os file starts
+//! with the signature: the `AKSF` string, a 2 bytes version and the 4 bytes
+//! length of the signature data which follows.
+
+use byteorder_extended::ReadExt;
+use raw::read_u64;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Error, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Header of the database.
+const D2O_HEADER: &[u8; 3] = b"D2O";
+
+/// Header of the signature of a d2os file.
+const SIGNATURE_HEADER: &str = "AKSF";
+
+/// Class id of a missing object.
+const NULL_OBJECT: i32 = -1_431_655_766;
+
+/// Maximum depth of the objects held by other objects.
+const MAX_DEPTH: usize = 64;
+
+fn invalid_data<S: Into<String>>(message: S) -> Error {
+    Error::new(ErrorKind::InvalidData, message.into())
+}
+
+/// FieldType
+///
+/// Type of a field of a class.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FieldType {
+    /// 4 bytes signed integer, code -1
+    Int,
+    /// Boolean on 1 byte, code -2
+    Bool,
+    /// UTF-8 string, code -3
+    String,
+    /// 8 bytes float, code -4
+    Number,
+    /// Id of a text of the d2i translations, code -5
+    I18n,
+    /// 4 bytes unsigned integer, code -6
+    UInt,
+    /// Vector of elements of the type, code -99
+    Vector {
+        /// Name of the type of the elements, like `Vector.<int>`
+        name: String,
+        /// Type of the elements
+        element: Box<FieldType>,
+    },
+    /// Object of one of the classes of the database, code > 0
+    Object(i32),
+}
+
+impl FieldType {
+    /// Reads a field type from the specified reader.
+    fn from<R: Read>(reader: &mut R, depth: usize) -> io::Result<Self> {
+        Ok(match reader.read_i32()? {
+            -1 => FieldType::Int,
+            -2 => FieldType::Bool,
+            -3 => FieldType::String,
+            -4 => FieldType::Number,
+            -5 => FieldType::I18n,
+            -6 => FieldType::UInt,
+            -99 if depth < MAX_DEPTH => FieldType::Vector {
+                name: reader.read_string()?,
+                element: Box::new(FieldType::from(reader, depth + 1)?),
+            },
+            -99 => return Err(invalid_data("d2o vector types nested too deeply")),
+            class_id if class_id > 0 => FieldType::Object(class_id),
+            code => return Err(invalid_data(format!("unknown d2o field type {}", code))),
+        })
+    }
+}
+
+/// Field
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    /// Name
+    pub name: String,
+    /// Type
+    pub field_type: FieldType,
+}
+
+/// ClassDefinition
+///
+/// Class of the objects of a database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassDefinition {
+    /// Id, referred to by the objects
+    pub id: i32,
+    /// Name, like `Item`
+    pub name: String,
+    /// Package, like `com.ankamagames.dofus.datacenter.items`
+    pub package: String,
+    /// Fields, in the order of their values
+    pub fields: Vec<Field>,
+}
+
+/// Value
+///
+/// Value of a field of an object.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// `FieldType::Int`
+    Int(i32),
+    /// `FieldType::Bool`
+    Bool(bool),
+    /// `FieldType::String`
+    String(String),
+    /// `FieldType::Number`
+    Number(f64),
+    /// `FieldType::I18n`
+    I18n(i32),
+    /// `FieldType::UInt`
+    UInt(u32),
+    /// `FieldType::Vector`
+    Vector(Vec<Value>),
+    /// `FieldType::Object`, `None` for a missing object
+    Object(Option<Box<GameObject>>),
+}
+
+impl Value {
+    /// The integer of an `Int` value.
+    pub fn as_int(&self) -> Option<i32> {
+        match *self {
+            Value::Int(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The boolean of a `Bool` value.
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The string of a `String` value.
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The float of a `Number` value.
+    pub fn as_number(&self) -> Option<f64> {
+        match *self {
+            Value::Number(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The text id of an `I18n` value.
+    pub fn as_i18n(&self) -> Option<i32> {
+        match *self {
+            Value::I18n(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The integer of a `UInt` value.
+    pub fn as_uint(&self) -> Option<u32> {
+        match *self {
+            Value::UInt(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The elements of a `Vector` value.
+    pub fn as_slice(&self) -> Option<&[Value]> {
+        match *self {
+            Value::Vector(ref values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// The object of an `Object` value, `None` for a missing object too.
+    pub fn as_object(&self) -> Option<&GameObject> {
+        match *self {
+            Value::Object(Some(ref object)) => Some(object),
+            _ => None,
+        }
+    }
+}
+
+/// GameObject
+///
+/// Object of a database, with the values of the fields of its class.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameObject {
+    /// Id of the class
+    pub class_id: i32,
+    /// Name of the class
+    pub class_name: String,
+    /// Names and values of the fields, in the order of the class
+    pub fields: Vec<(String, Value)>,
+}
+
+impl GameObject {
+    /// Value of the field `name`.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.fields.iter().find(|(field, _)| field == name).map(|(_, value)| value)
+    }
+
+    /// Value of the field `name`, failing with `InvalidData` if the class
+    /// has no such field. Meant for the implementations of `GameData`.
+    pub fn field(&self, name: &str) -> io::Result<&Value> {
+        self.get(name).ok_or_else(|| invalid_data(format!("{} has no field \"{}\"", self.class_name, name)))
+    }
+}
+
+/// Typed access to the objects of a database, see `D2oFile::get`.
+pub trait GameData: Sized {
+    /// Converts an object of the database, failing with `InvalidData` if it
+    /// doesn't have the expected fields.
+    fn from_object(object: &GameObject) -> io::Result<Self>;
+}
+
+/// D2oFile
+///
+/// A game object database, its objects being read on request.
+#[derive(Debug)]
+pub struct D2oFile<R> {
+    /// Reader of the file
+    reader: RefCell<R>,
+    /// Position of the header, after the signature of a d2os file
+    base: u64,
+    /// Offsets of the objects from the header, by id
+    index: BTreeMap<i32, u64>,
+    /// Classes, by id
+    classes: BTreeMap<i32, ClassDefinition>,
+}
+
+impl D2oFile<BufReader<File>> {
+    /// Opens the `.d2o` or `.d2os` file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        D2oFile::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> D2oFile<R> {
+    /// Reads the class definitions and the index of the database from the
+    /// specified reader.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let base = skip_signature(&mut reader)?;
+
+        let index_offset = reader.read_i32()?;
+        if index_offset < 0 {
+            return Err(invalid_data("invalid d2o index offset"));
+        }
+        reader.seek(SeekFrom::Start(base + index_offset as u64))?;
+        let index_len = reader.read_i32()?;
+        if index_len < 0 || index_len % 8 != 0 {
+            return Err(invalid_data("invalid d2o index length"));
+        }
+        let mut index = BTreeMap::new();
+        for _ in 0..index_len / 8 {
+            let id = reader.read_i32()?;
+            let offset = reader.read_i32()?;
+            if offset < 0 {
+                return Err(invalid_data(format!("invalid d2o offset of the object {}", id)));
+            }
+            index.insert(id, offset as u64);
+        }
+
+        let classes_count = reader.read_i32()?;
+        let mut classes = BTreeMap::new();
+        for _ in 0..classes_count {
+            let id = reader.read_i32()?;
+            let name = reader.read_string()?;
+            let package = reader.read_string()?;
+            let fields_count = reader.read_i32()?;
+            let mut fields = Vec::new();
+            for _ in 0..fields_count {
+                let name = reader.read_string()?;
+                let field_type = FieldType::from(&mut reader, 0)?;
+                fields.push(Field { name, field_type });
+            }
+            classes.insert(id, ClassDefinition { id, name, package, fields });
+        }
+
+        Ok(D2oFile { reader: RefCell::new(reader), base, index, classes })
+    }
+
+    /// Class definitions, by id.
+    pub fn classes(&self) -> &BTreeMap<i32, ClassDefinition> {
+        &self.classes
+    }
+
+    /// Class definition `id`.
+    pub fn class(&self, id: i32) -> Option<&ClassDefinition> {
+        self.classes.get(&id)
+    }
+
+    /// Number of objects.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns `true` if the database holds no object.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Ids of the objects, in increasing order.
+    pub fn ids<'a>(&'a self) -> impl Iterator<Item = i32> + 'a {
+        self.index.keys().cloned()
+    }
+
+    /// Returns `true` if the database holds the object `id`.
+    pub fn contains(&self, id: i32) -> bool {
+        self.index.contains_key(&id)
+    }
+
+    /// Reads the object `id`, `None` if the database doesn't hold it.
+    pub fn object(&self, id: i32) -> io::Result<Option<GameObject>> {
+        let offset = match self.index.get(&id) {
+            Some(&offset) => offset,
+            None => return Ok(None),
+        };
+        let mut reader = self.reader.borrow_mut();
+        reader.seek(SeekFrom::Start(self.base + offset))?;
+        let class_id = reader.read_i32()?;
+        self.read_object(&mut *reader, class_id, 0).map(Some)
+    }
+
+    /// Reads the object `id` as a `T`, `None` if the database doesn't hold
+    /// it.
+    pub fn get<T: GameData>(&self, id: i32) -> io::Result<Option<T>> {
+        match self.object(id)? {
+            Some(object) => T::from_object(&object).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the objects in the order of their ids, one by one.
+    pub fn objects<'a>(&'a self) -> impl Iterator<Item = io::Result<(i32, GameObject)>> + 'a {
+        self.index.keys().map(move |&id| {
+            self.object(id).map(|object| (id, object.expect("the id comes from the index")))
+        })
+    }
+
+    /// Reads the values of the fields of the class `class_id`.
+    fn read_object(&self, reader: &mut R, class_id: i32, depth: usize) -> io::Result<GameObject> {
+        let class = self.classes.get(&class_id)
+            .ok_or_else(|| invalid_data(format!("unknown d2o class {}", class_id)))?;
+        let mut fields = Vec::with_capacity(class.fields.len());
+        for field in &class.fields {
+            fields.push((field.name.clone(), self.read_value(reader, &field.field_type, depth)?));
+        }
+        Ok(GameObject { class_id, class_name: class.name.clone(), fields })
+    }
+
+    /// Reads a value of the type `field_type`.
+    fn read_value(&self, reader: &mut R, field_type: &FieldType, depth: usize) -> io::Result<Value> {
+        Ok(match *field_type {
+            FieldType::Int => Value::Int(reader.read_i32()?),
+            FieldType::Bool => Value::Bool(reader.read_u8()? != 0),
+            FieldType::String => Value::String(reader.read_string()?),
+            FieldType::Number => Value::Number(f64::from_bits(read_u64(reader)?)),
+            FieldType::I18n => Value::I18n(reader.read_i32()?),
+            FieldType::UInt => Value::UInt(reader.read_i32()? as u32),
+            FieldType::Vector { ref element, .. } => {
+                let len = reader.read_i32()?;
+                if len < 0 {
+                    return Err(invalid_data("invalid d2o vector length"));
+                }
+                // the length comes from the file, don't trust it for the allocation
+                let mut values = Vec::with_capacity((len as usize).min(1024));
+                for _ in 0..len {
+                    values.push(self.read_value(reader, element, depth)?);
+                }
+                Value::Vector(values)
+            }
+            FieldType::Object(_) => match reader.read_i32()? {
+                NULL_OBJECT => Value::Object(None),
+                _ if depth >= MAX_DEPTH => return Err(invalid_data("d2o objects nested too deeply")),
+                class_id => Value::Object(Some(Box::new(self.read_object(reader, class_id, depth + 1)?))),
+            },
+        })
+    }
+}
+
+/// Skips the signature of a d2os file and the `D2O` header, returning the
+/// position of the header.
+fn skip_signature<R: Read + Seek>(reader: &mut R) -> io::Result<u64> {
+    let mut header = [0; 3];
+    reader.read_exact(&mut header)?;
+    if &header == D2O_HEADER {
+        return Ok(0);
+    }
+
+    reader.seek(SeekFrom::Start(0))?;
+    if reader.read_string().ok().as_deref() != Some(SIGNATURE_HEADER) {
+        return Err(invalid_data("Corrupted d2o header"));
+    }
+    let mut version = [0; 2];
+    reader.read_exact(&mut version)?;
+    let signature_len = reader.read_i32()?;
+    if signature_len < 0 {
+        return Err(invalid_data("invalid d2os signature length"));
+    }
+    let base = reader.seek(SeekFrom::Current(signature_len as i64))?;
+    reader.read_exact(&mut header)?;
+    if &header != D2O_HEADER {
+        return Err(invalid_data("Corrupted d2o header"));
+    }
+    Ok(base)
+}
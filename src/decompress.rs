@@ -0,0 +1,133 @@
+use read::MergeReader;
+use std::fmt;
+use std::io;
+use std::io::{BufReader, Error, Read, Seek, Write};
+#[cfg(feature = "lzma")]
+use std::io::ErrorKind;
+
+/// PayloadCodec
+///
+/// Compression of the payload of an entry, decompressed by
+/// `MergeReader::read_file_decompressed`. The codecs are enabled by the
+/// `zlib` and `lzma` features.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PayloadCodec {
+    /// zlib stream (RFC 1950), e.g. the DLM maps
+    #[cfg(feature = "zlib")]
+    Zlib,
+    /// Raw deflate stream (RFC 1951)
+    #[cfg(feature = "zlib")]
+    Deflate,
+    /// gzip file (RFC 1952)
+    #[cfg(feature = "zlib")]
+    Gzip,
+    /// LZMA stream (`.lzma`, "LZMA alone")
+    #[cfg(feature = "lzma")]
+    Lzma,
+    /// xz file
+    #[cfg(feature = "lzma")]
+    Xz,
+}
+
+impl PayloadCodec {
+    /// Name of the codec.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "zlib")]
+            PayloadCodec::Zlib => "zlib",
+            #[cfg(feature = "zlib")]
+            PayloadCodec::Deflate => "deflate",
+            #[cfg(feature = "zlib")]
+            PayloadCodec::Gzip => "gzip",
+            #[cfg(feature = "lzma")]
+            PayloadCodec::Lzma => "lzma",
+            #[cfg(feature = "lzma")]
+            PayloadCodec::Xz => "xz",
+        }
+    }
+
+    /// Decompresses `input` into `output`, returning the number of bytes
+    /// written.
+    pub fn decompress<R: Read, W: Write>(&self, input: R, output: &mut W) -> io::Result<u64> {
+        match *self {
+            #[cfg(feature = "zlib")]
+            PayloadCodec::Zlib => io::copy(&mut ::flate2::read::ZlibDecoder::new(input), output),
+            #[cfg(feature = "zlib")]
+            PayloadCodec::Deflate => io::copy(&mut ::flate2::read::DeflateDecoder::new(input), output),
+            #[cfg(feature = "zlib")]
+            PayloadCodec::Gzip => io::copy(&mut ::flate2::read::MultiGzDecoder::new(input), output),
+            #[cfg(feature = "lzma")]
+            PayloadCodec::Lzma => lzma_decompress(input, output, false),
+            #[cfg(feature = "lzma")]
+            PayloadCodec::Xz => lzma_decompress(input, output, true),
+        }
+    }
+}
+
+impl fmt::Display for PayloadCodec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Writer counting the bytes written into `inner`.
+#[cfg(feature = "lzma")]
+struct CountingWriter<'a, W: 'a> {
+    inner: &'a mut W,
+    count: u64,
+}
+
+#[cfg(feature = "lzma")]
+impl<'a, W: Write> Write for CountingWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decompresses an LZMA stream, or an xz file if `xz` is set, with
+/// `lzma-rs`.
+#[cfg(feature = "lzma")]
+fn lzma_decompress<R: Read, W: Write>(input: R, output: &mut W, xz: bool) -> io::Result<u64> {
+    let mut input = BufReader::new(input);
+    let mut output = CountingWriter { inner: output, count: 0 };
+    let result = if xz {
+        ::lzma_rs::xz_decompress(&mut input, &mut output)
+    } else {
+        ::lzma_rs::lzma_decompress(&mut input, &mut output)
+    };
+    match result {
+        Ok(()) => Ok(output.count),
+        Err(::lzma_rs::error::Error::IoError(err)) => Err(err),
+        Err(err) => Err(Error::new(ErrorKind::InvalidData, err.to_string())),
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Streams the entry `full_file_name` decompressed with `codec` into
+    /// `output`, returning the number of bytes written. Fails with
+    /// `Error::AccessDenied` if the `AccessPolicy` denies the entry.
+    pub fn decompress_to<W: Write>(&self, full_file_name: &str, codec: PayloadCodec, output: &mut W) -> io::Result<u64> {
+        let chunk = self.allowed_chunk(full_file_name)?;
+        let input = BufReader::with_capacity(self.options.copy_buffer_size.max(1), chunk.decoded_reader()?);
+        codec.decompress(input, output).map_err(|err| Error::new(
+            err.kind(),
+            format!("can't decompress `full_file_name` \"{}\" as {}: {}", full_file_name, codec, err)
+        ))
+    }
+
+    /// Reads the entry `full_file_name` decompressed with `codec`.
+    pub fn read_file_decompressed(&self, full_file_name: &str, codec: PayloadCodec) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.decompress_to(full_file_name, codec, &mut data)?;
+        Ok(data)
+    }
+}
@@ -0,0 +1,228 @@
+use hash::{Digest, HashAlgorithm};
+use read::{MergedChunk, MergeReader};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+/// Size of the blocks compared by `same_data`.
+const BLOCK_SIZE: usize = 1 << 16;
+
+/// Rename
+///
+/// An entry whose content didn't change but whose name did.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rename {
+    /// Full file name in the old archive
+    pub from: String,
+    /// Full file name in the new archive
+    pub to: String,
+}
+
+/// File name of `full_file_name`, without its directories.
+fn base_name(full_file_name: &str) -> &str {
+    full_file_name.rsplit('/').next().unwrap_or(full_file_name)
+}
+
+/// Digests of the entries of `reader` named in `names`, skipping the ones
+/// whose size is not in `sizes`.
+fn digests<R>(
+    reader: &MergeReader<R>,
+    names: &[&String],
+    sizes: &HashSet<u64>,
+    algorithm: HashAlgorithm
+) -> io::Result<HashMap<Digest, Vec<String>>>
+where
+    R: Read + Seek
+{
+    let mut digests: HashMap<Digest, Vec<String>> = HashMap::new();
+    for &name in names {
+        let chunk = &reader.chunks[name];
+        if sizes.contains(&chunk.size()) {
+            digests.entry(algorithm.digest(&chunk.data()?))
+                .or_default()
+                .push(name.clone());
+        }
+    }
+    Ok(digests)
+}
+
+/// Detects the entries of `old` renamed in `new`: an entry only present in
+/// `old` and an entry only present in `new` with the same content.
+///
+/// When several entries share the same content, entries keeping their file
+/// name (moved to another directory) are paired first, then the remaining
+/// ones in name order.
+pub fn detect_renames<A, B>(
+    old: &MergeReader<A>,
+    new: &MergeReader<B>,
+    algorithm: HashAlgorithm
+) -> io::Result<Vec<Rename>>
+where
+    A: Read + Seek,
+    B: Read + Seek
+{
+    let removed: Vec<&String> = old.chunks.keys()
+        .filter(|name| !new.chunks.contains_key(*name))
+        .collect();
+    let added: Vec<&String> = new.chunks.keys()
+        .filter(|name| !old.chunks.contains_key(*name))
+        .collect();
+
+    // only entries whose size exists on both sides can be renames
+    let removed_sizes: HashSet<u64> = removed.iter()
+        .map(|name| old.chunks[*name].size())
+        .collect();
+    let sizes: HashSet<u64> = added.iter()
+        .map(|name| new.chunks[*name].size())
+        .filter(|size| removed_sizes.contains(size))
+        .collect();
+
+    let removed = digests(old, &removed, &sizes, algorithm)?;
+    let mut added = digests(new, &added, &sizes, algorithm)?;
+
+    let mut renames = Vec::new();
+    for (digest, mut from) in removed {
+        let mut to = match added.remove(&digest) {
+            Some(to) => to,
+            None => continue,
+        };
+        from.sort();
+        to.sort();
+
+        from.retain(|old_name| {
+            match to.iter().position(|new_name| base_name(new_name) == base_name(old_name)) {
+                Some(i) => {
+                    renames.push(Rename { from: old_name.clone(), to: to.remove(i) });
+                    false
+                }
+                None => true,
+            }
+        });
+
+        for (old_name, new_name) in from.into_iter().zip(to) {
+            renames.push(Rename { from: old_name, to: new_name });
+        }
+    }
+
+    renames.sort();
+    Ok(renames)
+}
+
+/// Returns `true` if the chunks `a` and `b` have the same data, compared
+/// block by block.
+fn same_data<A, B>(a: &MergedChunk<A>, b: &MergedChunk<B>) -> io::Result<bool>
+where
+    A: Read + Seek,
+    B: Read + Seek
+{
+    if a.size() != b.size() {
+        return Ok(false);
+    }
+    for (block_a, block_b) in a.stored_iter(BLOCK_SIZE).zip(b.stored_iter(BLOCK_SIZE)) {
+        if block_a? != block_b? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Returns `true` if the content of the chunk `b` differs from the one of
+/// `a`: by size, then by checksum if both store one of the same algorithm,
+/// and byte by byte otherwise.
+fn differs<A, B>(a: &MergedChunk<A>, b: &MergedChunk<B>) -> io::Result<bool>
+where
+    A: Read + Seek,
+    B: Read + Seek
+{
+    if a.size() != b.size() {
+        return Ok(true);
+    }
+    match (a.checksum(), b.checksum()) {
+        (Some(sum_a), Some(sum_b)) if sum_a.algorithm == sum_b.algorithm => Ok(sum_a.digest != sum_b.digest),
+        _ => same_data(a, b).map(|same| !same),
+    }
+}
+
+/// ArchiveDiff
+///
+/// Entries added, removed and modified between two archives, see `diff`.
+/// The names are sorted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ArchiveDiff {
+    /// Entries only in the new archive
+    pub added: Vec<String>,
+    /// Entries only in the old archive
+    pub removed: Vec<String>,
+    /// Entries in both archives whose content differs
+    pub modified: Vec<String>,
+}
+
+impl ArchiveDiff {
+    /// Returns `true` if the archives have the same entries, with the same
+    /// contents.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Compares the entries of `old` and `new` without extracting them. The
+/// entries of the same size are compared by their checksums when both
+/// archives store them (see `WriteOptions::checksums`), else by their
+/// contents, read block by block; the ones of different sizes are never
+/// read.
+pub fn diff<A, B>(old: &MergeReader<A>, new: &MergeReader<B>) -> io::Result<ArchiveDiff>
+where
+    A: Read + Seek,
+    B: Read + Seek
+{
+    let mut diff = ArchiveDiff::default();
+    for (full_file_name, chunk) in new.iter() {
+        match old.chunks.get(full_file_name) {
+            Some(old_chunk) => {
+                if differs(old_chunk, chunk)? {
+                    diff.modified.push(full_file_name.clone());
+                }
+            }
+            None => diff.added.push(full_file_name.clone()),
+        }
+    }
+    diff.removed = old.chunks.keys()
+        .filter(|name| !new.chunks.contains_key(*name))
+        .cloned()
+        .collect();
+
+    diff.added.sort();
+    diff.removed.sort();
+    diff.modified.sort();
+    Ok(diff)
+}
+
+/// Extracts into `dest` the entries of `new` which are not in `old` or
+/// whose content differs, and returns their names, sorted. The entries
+/// removed from `old` are ignored.
+///
+/// The contents are compared like `diff` does.
+pub fn extract_changed<A, B, P>(old: &MergeReader<A>, new: &MergeReader<B>, dest: P) -> io::Result<Vec<String>>
+where
+    A: Read + Seek,
+    B: Read + Seek,
+    P: AsRef<Path>
+{
+    let mut changed = Vec::new();
+    for (full_file_name, chunk) in new.iter() {
+        let changed_entry = match old.chunks.get(full_file_name) {
+            Some(old_chunk) => differs(old_chunk, chunk)?,
+            None => true,
+        };
+        if changed_entry {
+            changed.push(full_file_name.clone());
+        }
+    }
+    changed.sort();
+
+    for full_file_name in &changed {
+        new.extract_entry(full_file_name, dest.as_ref())?;
+    }
+    Ok(changed)
+}
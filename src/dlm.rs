@@ -0,0 +1,541 @@
+//! Decoding of the `.dlm` maps stored in the `maps*.d2p` archives.
+//!
+//! A map is a zlib stream of the map data: its properties, the fixtures of
+//! its background and foreground, its layers of graphical and sound
+//! elements, and the data of its `MAP_CELLS_COUNT` cells. The fields read
+//! depend on the version of the map. Since version 7 the data following the
+//! header may be encrypted with the key of the game configuration, see
+//! `Map::from_bytes_with_key`.
+//!
+//! ``` no_run
+//! # fn main() -> std::io::Result<()> {
+//! let reader = pak::MergeReader::open("content/maps/maps0.d2p")?;
+//! let map = pak::dlm::Map::from_bytes(&reader.read_file("0/1000.dlm")?)?;
+//! println!("{}: {} layers", map.id, map.layers.len());
+//! # Ok(())
+//! # }
+//! ```
+
+use byteorder_extended::ReadExt;
+use decompress::PayloadCodec;
+use read::MergeReader;
+use std::io;
+use std::io::{Cursor, Error, ErrorKind, Read, Seek};
+
+/// Number of cells of a map.
+pub const MAP_CELLS_COUNT: usize = 560;
+
+/// First byte of the map data, once inflated.
+const MAP_HEADER: u8 = 77;
+
+/// Floor of the cells whose other data is left out.
+const NO_FLOOR: i16 = -1280;
+
+fn invalid_data<S: Into<String>>(message: S) -> Error {
+    Error::new(ErrorKind::InvalidData, message.into())
+}
+
+fn read_i8<R: Read>(reader: &mut R) -> io::Result<i8> {
+    Ok(reader.read_u8()? as i8)
+}
+
+fn read_bool<R: Read>(reader: &mut R) -> io::Result<bool> {
+    Ok(reader.read_u8()? != 0)
+}
+
+fn read_i16<R: Read>(reader: &mut R) -> io::Result<i16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(i16::from_be_bytes(bytes))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    Ok(reader.read_i32()? as u32)
+}
+
+/// Fixture
+///
+/// Picture of the background or the foreground of a map.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fixture {
+    /// Id of the picture
+    pub fixture_id: i32,
+    /// Horizontal offset, in pixels
+    pub offset_x: i16,
+    /// Vertical offset, in pixels
+    pub offset_y: i16,
+    /// Rotation, in hundredths of degree
+    pub rotation: i16,
+    /// Horizontal scale, in thousandths
+    pub x_scale: i16,
+    /// Vertical scale, in thousandths
+    pub y_scale: i16,
+    /// Red multiplier
+    pub red_multiplier: i8,
+    /// Green multiplier
+    pub green_multiplier: i8,
+    /// Blue multiplier
+    pub blue_multiplier: i8,
+    /// Opacity
+    pub alpha: u8,
+}
+
+impl Fixture {
+    fn from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Fixture {
+            fixture_id: reader.read_i32()?,
+            offset_x: read_i16(reader)?,
+            offset_y: read_i16(reader)?,
+            rotation: read_i16(reader)?,
+            x_scale: read_i16(reader)?,
+            y_scale: read_i16(reader)?,
+            red_multiplier: read_i8(reader)?,
+            green_multiplier: read_i8(reader)?,
+            blue_multiplier: read_i8(reader)?,
+            alpha: reader.read_u8()?,
+        })
+    }
+}
+
+/// GraphicalElement
+///
+/// Picture placed on a cell.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphicalElement {
+    /// Id of the element of the `elements.ele` file
+    pub element_id: u32,
+    /// Red, green and blue hue
+    pub hue: [i8; 3],
+    /// Red, green and blue shadow
+    pub shadow: [i8; 3],
+    /// Horizontal offset, in pixels
+    pub offset_x: f32,
+    /// Vertical offset, in pixels
+    pub offset_y: f32,
+    /// Altitude
+    pub altitude: i8,
+    /// Identifier used by the interactive elements
+    pub identifier: u32,
+}
+
+impl GraphicalElement {
+    fn from<R: Read>(reader: &mut R, version: u8) -> io::Result<Self> {
+        let element_id = read_u32(reader)?;
+        let hue = [read_i8(reader)?, read_i8(reader)?, read_i8(reader)?];
+        let shadow = [read_i8(reader)?, read_i8(reader)?, read_i8(reader)?];
+        // the older maps give the offset in cells
+        let (offset_x, offset_y) = if version <= 4 {
+            (read_i8(reader)? as f32 * 43.0, read_i8(reader)? as f32 * 21.5)
+        } else {
+            (read_i16(reader)? as f32, read_i16(reader)? as f32)
+        };
+        Ok(GraphicalElement {
+            element_id,
+            hue,
+            shadow,
+            offset_x,
+            offset_y,
+            altitude: read_i8(reader)?,
+            identifier: read_u32(reader)?,
+        })
+    }
+}
+
+/// SoundElement
+///
+/// Ambient sound played around a cell.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SoundElement {
+    /// Id of the sound
+    pub sound_id: i32,
+    /// Volume
+    pub base_volume: i16,
+    /// Distance up to which the sound is played at full volume
+    pub full_volume_distance: i32,
+    /// Distance from which the sound isn't heard anymore
+    pub null_volume_distance: i32,
+    /// Minimum delay between two loops, in milliseconds
+    pub min_delay_between_loops: i16,
+    /// Maximum delay between two loops, in milliseconds
+    pub max_delay_between_loops: i16,
+}
+
+impl SoundElement {
+    fn from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(SoundElement {
+            sound_id: reader.read_i32()?,
+            base_volume: read_i16(reader)?,
+            full_volume_distance: reader.read_i32()?,
+            null_volume_distance: reader.read_i32()?,
+            min_delay_between_loops: read_i16(reader)?,
+            max_delay_between_loops: read_i16(reader)?,
+        })
+    }
+}
+
+/// Element
+///
+/// Element of a cell of a layer.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Element {
+    /// Element of type 2
+    Graphical(GraphicalElement),
+    /// Element of type 33
+    Sound(SoundElement),
+}
+
+impl Element {
+    fn from<R: Read>(reader: &mut R, version: u8) -> io::Result<Self> {
+        match reader.read_u8()? {
+            2 => GraphicalElement::from(reader, version).map(Element::Graphical),
+            33 => SoundElement::from(reader).map(Element::Sound),
+            element_type => Err(invalid_data(format!("unknown dlm element type {}", element_type))),
+        }
+    }
+}
+
+/// Cell
+///
+/// Elements of a layer on a cell.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cell {
+    /// Id of the cell
+    pub cell_id: i16,
+    /// Elements, from the bottom up
+    pub elements: Vec<Element>,
+}
+
+impl Cell {
+    fn from<R: Read>(reader: &mut R, version: u8) -> io::Result<Self> {
+        let cell_id = read_i16(reader)?;
+        let count = read_i16(reader)?;
+        let mut elements = Vec::with_capacity(count.max(0) as usize);
+        for _ in 0..count {
+            elements.push(Element::from(reader, version)?);
+        }
+        Ok(Cell { cell_id, elements })
+    }
+}
+
+/// Layer
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    /// Id of the layer: 0 for the ground, 1 for the additional ground, 2
+    /// for the decor
+    pub layer_id: i32,
+    /// Cells holding elements
+    pub cells: Vec<Cell>,
+}
+
+impl Layer {
+    fn from<R: Read>(reader: &mut R, version: u8) -> io::Result<Self> {
+        let layer_id = if version >= 9 { read_i8(reader)? as i32 } else { reader.read_i32()? };
+        let count = read_i16(reader)?;
+        let mut cells = Vec::with_capacity(count.max(0) as usize);
+        for _ in 0..count {
+            cells.push(Cell::from(reader, version)?);
+        }
+        Ok(Layer { layer_id, cells })
+    }
+}
+
+/// CellData
+///
+/// Properties of a cell of a map.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CellData {
+    /// Altitude. The other fields of a cell of floor -1280 are left out
+    /// of the map and keep their default values
+    pub floor: i16,
+    /// The cell can be walked on
+    pub mov: bool,
+    /// The cell doesn't block the line of sight
+    pub los: bool,
+    /// The cell can't be walked on during a fight
+    pub non_walkable_during_fight: bool,
+    /// The cell can't be walked on out of the fights
+    pub non_walkable_during_rp: bool,
+    /// Starting cell of the blue team
+    pub blue: bool,
+    /// Starting cell of the red team
+    pub red: bool,
+    /// The cell is displayed
+    pub visible: bool,
+    /// The cell belongs to a farm
+    pub farm_cell: bool,
+    /// The cell belongs to a haven bag
+    pub havenbag_cell: bool,
+    /// Arrows of the map changes, one bit per direction
+    pub arrow: u8,
+    /// Speed modifier of the movements
+    pub speed: i8,
+    /// Directions in which the map can be left, one bit per direction
+    pub map_change_data: u8,
+    /// Zone the cell belongs to
+    pub move_zone: u8,
+    /// Linked zones: out of the fights in the high 4 bits, during the
+    /// fights in the low 4 bits
+    pub linked_zone: u8,
+}
+
+impl CellData {
+    fn from<R: Read>(reader: &mut R, version: u8) -> io::Result<Self> {
+        let mut cell = CellData { floor: read_i8(reader)? as i16 * 10, ..CellData::default() };
+        if cell.floor == NO_FLOOR {
+            return Ok(cell);
+        }
+
+        if version >= 9 {
+            let bits = read_i16(reader)? as u16;
+            cell.mov = bits & 1 == 0;
+            cell.non_walkable_during_fight = bits & 2 != 0;
+            cell.non_walkable_during_rp = bits & 4 != 0;
+            cell.los = bits & 8 == 0;
+            cell.blue = bits & 16 != 0;
+            cell.red = bits & 32 != 0;
+            cell.visible = bits & 64 != 0;
+            cell.farm_cell = bits & 128 != 0;
+            if version >= 10 {
+                cell.havenbag_cell = bits & 256 != 0;
+                cell.arrow = (bits >> 9 & 15) as u8;
+            } else {
+                cell.arrow = (bits >> 8 & 15) as u8;
+            }
+        } else {
+            let bits = reader.read_u8()?;
+            cell.mov = bits & 1 != 0;
+            cell.los = bits & 2 != 0;
+            cell.non_walkable_during_fight = bits & 4 != 0;
+            cell.red = bits & 8 != 0;
+            cell.blue = bits & 16 != 0;
+            cell.farm_cell = bits & 32 != 0;
+            cell.visible = bits & 64 != 0;
+            cell.non_walkable_during_rp = bits & 128 != 0;
+        }
+
+        cell.speed = read_i8(reader)?;
+        cell.map_change_data = reader.read_u8()?;
+        if version > 5 {
+            cell.move_zone = reader.read_u8()?;
+        }
+        if version > 10 && cell.has_linked_zone() {
+            cell.linked_zone = reader.read_u8()?;
+        }
+        if version > 7 && version < 9 {
+            cell.arrow = reader.read_u8()? & 15;
+        }
+        Ok(cell)
+    }
+
+    /// Returns `true` if the cell belongs to a linked zone, out of the
+    /// fights or during them.
+    fn has_linked_zone(&self) -> bool {
+        self.mov && !self.farm_cell
+    }
+}
+
+/// Map
+///
+/// A map decoded from a `.dlm` payload.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Map {
+    /// Version of the map format
+    pub version: u8,
+    /// Id
+    pub id: u32,
+    /// Id relative to the world map
+    pub relative_id: u32,
+    /// Type: 0 outdoor, 1 indoor
+    pub map_type: u8,
+    /// Id of the subarea
+    pub sub_area_id: i32,
+    /// Id of the map above
+    pub top_neighbour_id: i32,
+    /// Id of the map below
+    pub bottom_neighbour_id: i32,
+    /// Id of the map on the left
+    pub left_neighbour_id: i32,
+    /// Id of the map on the right
+    pub right_neighbour_id: i32,
+    /// Shadow bonus of the entities
+    pub shadow_bonus_on_entities: u32,
+    /// Background color, ARGB
+    pub background_color: u32,
+    /// Color of the grid, ARGB
+    pub grid_color: u32,
+    /// Zoom, in hundredths
+    pub zoom_scale: u16,
+    /// Horizontal offset of the zoom
+    pub zoom_offset_x: i16,
+    /// Vertical offset of the zoom
+    pub zoom_offset_y: i16,
+    /// Id of the template of the tactical mode, 0 if none
+    pub tactical_mode_template_id: i32,
+    /// The sounds are played through a low pass filter
+    pub use_low_pass_filter: bool,
+    /// The sounds are played with reverb
+    pub use_reverb: bool,
+    /// Id of the reverb preset, -1 without reverb
+    pub preset_id: i32,
+    /// Fixtures behind the map
+    pub backgrounds: Vec<Fixture>,
+    /// Fixtures in front of the map
+    pub foregrounds: Vec<Fixture>,
+    /// CRC of the ground
+    pub ground_crc: i32,
+    /// Layers, from the bottom up
+    pub layers: Vec<Layer>,
+    /// Cells, `MAP_CELLS_COUNT` of them
+    pub cells: Vec<CellData>,
+}
+
+impl Map {
+    /// Decodes a map from a `.dlm` payload, as returned by
+    /// `MergeReader::read_file`, or from the map data already inflated.
+    /// Fails with `InvalidData` on an encrypted map, see
+    /// `Map::from_bytes_with_key`.
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        Map::decode(data, None)
+    }
+
+    /// Decodes a map like `from_bytes`, decrypting its data with `key` if it
+    /// is encrypted: the key of the game configuration, as bytes.
+    pub fn from_bytes_with_key(data: &[u8], key: &[u8]) -> io::Result<Self> {
+        Map::decode(data, Some(key))
+    }
+
+    fn decode(data: &[u8], key: Option<&[u8]>) -> io::Result<Self> {
+        if data.first() == Some(&MAP_HEADER) {
+            return Map::parse(&mut Cursor::new(data), key);
+        }
+        let mut inflated = Vec::new();
+        PayloadCodec::Zlib.decompress(data, &mut inflated)
+            .map_err(|err| invalid_data(format!("can't inflate the dlm map: {}", err)))?;
+        if inflated.first() != Some(&MAP_HEADER) {
+            return Err(invalid_data("Corrupted dlm header"));
+        }
+        Map::parse(&mut Cursor::new(&inflated[..]), key)
+    }
+
+    fn parse(reader: &mut Cursor<&[u8]>, key: Option<&[u8]>) -> io::Result<Self> {
+        reader.read_u8()?;
+        let version = reader.read_u8()?;
+        let id = read_u32(reader)?;
+
+        if version >= 7 {
+            let encrypted = read_bool(reader)?;
+            let _encryption_version = reader.read_u8()?;
+            let len = reader.read_i32()?;
+            if encrypted {
+                let key = match key {
+                    Some(key) if !key.is_empty() => key,
+                    _ => return Err(invalid_data(format!("the dlm map {} is encrypted", id))),
+                };
+                let start = reader.position() as usize;
+                let end = start.checked_add(len.max(0) as usize)
+                    .filter(|&end| end <= reader.get_ref().len())
+                    .ok_or_else(|| invalid_data("invalid dlm encrypted data length"))?;
+                let data: Vec<u8> = reader.get_ref()[start..end].iter()
+                    .zip(key.iter().cycle())
+                    .map(|(byte, key)| byte ^ key)
+                    .collect();
+                return Map::parse_body(&mut Cursor::new(&data[..]), version, id);
+            }
+        }
+        Map::parse_body(reader, version, id)
+    }
+
+    fn parse_body<R: Read + Seek>(reader: &mut R, version: u8, id: u32) -> io::Result<Self> {
+        let relative_id = read_u32(reader)?;
+        let map_type = reader.read_u8()?;
+        let sub_area_id = reader.read_i32()?;
+        let top_neighbour_id = reader.read_i32()?;
+        let bottom_neighbour_id = reader.read_i32()?;
+        let left_neighbour_id = reader.read_i32()?;
+        let right_neighbour_id = reader.read_i32()?;
+        let shadow_bonus_on_entities = read_u32(reader)?;
+
+        let (background_color, grid_color) = if version >= 9 {
+            (read_u32(reader)?, read_u32(reader)?)
+        } else if version >= 3 {
+            let rgb = [reader.read_u8()?, reader.read_u8()?, reader.read_u8()?];
+            (0xff00_0000 | (rgb[0] as u32) << 16 | (rgb[1] as u32) << 8 | rgb[2] as u32, 0)
+        } else {
+            (0, 0)
+        };
+
+        let (zoom_scale, zoom_offset_x, zoom_offset_y) = if version >= 4 {
+            (read_i16(reader)? as u16, read_i16(reader)?, read_i16(reader)?)
+        } else {
+            (100, 0, 0)
+        };
+        let tactical_mode_template_id = if version > 10 { reader.read_i32()? } else { 0 };
+
+        let use_low_pass_filter = read_bool(reader)?;
+        let use_reverb = read_bool(reader)?;
+        let preset_id = if use_reverb { reader.read_i32()? } else { -1 };
+
+        let mut backgrounds = Vec::new();
+        for _ in 0..reader.read_u8()? {
+            backgrounds.push(Fixture::from(reader)?);
+        }
+        let mut foregrounds = Vec::new();
+        for _ in 0..reader.read_u8()? {
+            foregrounds.push(Fixture::from(reader)?);
+        }
+
+        // count of the cells, always MAP_CELLS_COUNT
+        reader.read_i32()?;
+        let ground_crc = reader.read_i32()?;
+
+        let mut layers = Vec::new();
+        for _ in 0..reader.read_u8()? {
+            layers.push(Layer::from(reader, version)?);
+        }
+        let mut cells = Vec::with_capacity(MAP_CELLS_COUNT);
+        for _ in 0..MAP_CELLS_COUNT {
+            cells.push(CellData::from(reader, version)?);
+        }
+
+        Ok(Map {
+            version,
+            id,
+            relative_id,
+            map_type,
+            sub_area_id,
+            top_neighbour_id,
+            bottom_neighbour_id,
+            left_neighbour_id,
+            right_neighbour_id,
+            shadow_bonus_on_entities,
+            background_color,
+            grid_color,
+            zoom_scale,
+            zoom_offset_x,
+            zoom_offset_y,
+            tactical_mode_template_id,
+            use_low_pass_filter,
+            use_reverb,
+            preset_id,
+            backgrounds,
+            foregrounds,
+            ground_crc,
+            layers,
+            cells,
+        })
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Reads the entry `full_file_name` as a `.dlm` map, see
+    /// `Map::from_bytes`.
+    pub fn read_map(&self, full_file_name: &str) -> io::Result<Map> {
+        Map::from_bytes(&self.read_file(full_file_name)?).map_err(|err| Error::new(
+            err.kind(),
+            format!("can't decode `full_file_name` \"{}\" as a dlm map: {}", full_file_name, err)
+        ))
+    }
+}
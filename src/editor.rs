@@ -0,0 +1,386 @@
+use archive::{Archive, Staged};
+use checksum::Checksum;
+use encryption::ChunkEncryption;
+use options::{ReadOptions, WriteOptions};
+use raw::{Chunk, Info, Property, read_header};
+use source::HashingReader;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use write::encode;
+
+impl Archive {
+    /// Applies the staged changes by appending the new data after the data
+    /// of the committed file and rewriting its tables, see `PakEditor`. If
+    /// `append` is set, the new data follows the end of the file instead, so
+    /// that the previous tables and `Info` are kept, see
+    /// `PakEditor::commit_append`.
+    pub(crate) fn patch(&mut self, append: bool) -> io::Result<()> {
+        self.validate()?;
+
+        let mut file = OpenOptions::new().read(true).write(true).open(self.path())?;
+        read_header(&mut file)?;
+        let mut info = Info::from(&mut file)?;
+        let len = file.seek(SeekFrom::End(0))?;
+
+        // the kept entries, their data stays where it is
+        let mut chunks = Vec::new();
+        if let Some(ref reader) = self.reader {
+            let mut kept: Vec<_> = reader.chunks.iter()
+                .filter(|&(name, _)| !self.staged.contains_key(name))
+                .collect();
+            kept.sort_by_key(|&(_, chunk)| chunk.offset());
+            for (full_file_name, chunk) in kept {
+                chunks.push((full_file_name.clone(), chunk.offset() - info.offset, chunk.size(), chunk.checksum.clone(), chunk.compression.clone(), chunk.encryption.as_ref().map(ChunkEncryption::stored)));
+            }
+        }
+
+        // the staged data is sized, and encoded in memory if enabled, so that
+        // nothing is written if it doesn't fit in the offsets of the fragment
+        let options = &self.write_options;
+        let encoded = options.compression.is_some() || options.encryption.is_some();
+        let mut sized = Vec::new();
+        let mut added = 0;
+        for (full_file_name, staged) in &self.staged {
+            let (expected, encoded) = match *staged {
+                Staged::Bytes(ref data) => (data.len() as u64, if encoded {
+                    Some(encode(full_file_name, &mut &data[..], options.compression, options.encryption, options.keys.as_ref())?)
+                } else {
+                    None
+                }),
+                Staged::File(ref source) => {
+                    let expected = fs::metadata(source)?.len();
+                    (expected, if encoded {
+                        let mut data = File::open(source)?.take(expected);
+                        Some(encode(full_file_name, &mut data, options.compression, options.encryption, options.keys.as_ref())?)
+                    } else {
+                        None
+                    })
+                }
+                Staged::Removed => continue,
+            };
+            if let Some(ref encoded) = encoded {
+                if encoded.read != expected {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("\"{}\" changed while being committed", full_file_name)
+                    ));
+                }
+            }
+            added += encoded.as_ref().map_or(expected, |encoded| encoded.stored.len() as u64);
+            sized.push((full_file_name, staged, expected, encoded));
+        }
+
+        // the new data follows the data of the file, over the slack and the
+        // old tables if needed, or the whole file when appending: the old
+        // tables are then part of the data, as wasted space
+        let mut position = if append { len - info.offset } else { info.size as u64 };
+        // the fragment keeps the width of its offsets
+        if info.offset + position + added > info.offsets.max() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} bytes of data exceed the capacity of a pak fragment, repack the archive", position + added)
+            ));
+        }
+        file.seek(SeekFrom::Start(info.offset + position))?;
+        {
+            let mut writer = BufWriter::with_capacity(options.buffer_size, &mut file);
+            let algorithm = options.checksums;
+            for (full_file_name, staged, expected, encoded) in sized {
+                if let Some(encoded) = encoded {
+                    writer.write_all(&encoded.stored)?;
+                    let size = encoded.stored.len() as u64;
+                    let checksum = algorithm.map(|algorithm| Checksum::of(algorithm, &encoded.stored));
+                    chunks.push((full_file_name.clone(), position, size, checksum, encoded.compression, encoded.encryption));
+                    position += size;
+                    continue;
+                }
+
+                let checksum = match *staged {
+                    Staged::Bytes(ref data) => {
+                        writer.write_all(data)?;
+                        algorithm.map(|algorithm| Checksum::of(algorithm, data))
+                    }
+                    Staged::File(ref source) => {
+                        let mut data = File::open(source)?.take(expected);
+                        let (copied, checksum) = match algorithm {
+                            Some(algorithm) => {
+                                let mut reader = HashingReader::new(data, algorithm);
+                                let copied = io::copy(&mut reader, &mut writer)?;
+                                (copied, Some(Checksum { algorithm, digest: reader.finish() }))
+                            }
+                            None => (io::copy(&mut data, &mut writer)?, None),
+                        };
+                        if copied != expected {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!("\"{}\" changed while being committed", source.display())
+                            ));
+                        }
+                        checksum
+                    }
+                    Staged::Removed => continue,
+                };
+                chunks.push((full_file_name.clone(), position, expected, checksum, None, None));
+                position += expected;
+            }
+            writer.flush()?;
+        }
+
+        if self.write_options.canonical {
+            chunks.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+        let mut tables = Vec::new();
+        let mut chunk_properties = Vec::new();
+        for &(ref full_file_name, offset, size, ref checksum, ref compression, ref encryption) in &chunks {
+            let stored = match self.write_options.name_codec {
+                Some(ref codec) => codec.encode(full_file_name)?,
+                None => full_file_name.clone(),
+            };
+            if let Some(ref checksum) = *checksum {
+                chunk_properties.push(checksum.to_property(&stored));
+            }
+            if let Some(ref compression) = *compression {
+                chunk_properties.push(compression.to_property(&stored));
+            }
+            if let Some(ref encryption) = *encryption {
+                chunk_properties.push(encryption.to_property(&stored));
+            }
+            Chunk::new(stored, offset as i64, size as i64).write(&mut tables, info.offsets)?;
+        }
+        // the remaining slack is kept
+        info.chunks_offset = if append {
+            info.offset + position
+        } else {
+            info.chunks_offset.max(info.offset + position)
+        };
+        info.properties_offset = info.chunks_offset + tables.len() as u64;
+        let mut properties: Vec<_> = self.properties.iter().collect();
+        properties.sort();
+        for (key, value) in &properties {
+            Property::new(key.to_string(), value.to_string()).write(&mut tables)?;
+        }
+        for property in &chunk_properties {
+            property.write(&mut tables)?;
+        }
+        info.size = position as i64;
+        info.chunks_count = chunks.len() as i32;
+        info.properties_count = (properties.len() + chunk_properties.len()) as i32;
+
+        file.seek(SeekFrom::Start(info.chunks_offset))?;
+        file.write_all(&tables)?;
+        info.write(&mut file)?;
+        let end = file.stream_position()?;
+        file.set_len(end)?;
+        file.sync_all()?;
+        drop(file);
+
+        let reader = self.open_committed()?;
+        self.properties = reader.properties.clone();
+        self.reader = Some(reader);
+        self.staged.clear();
+        self.properties_changed = false;
+        self.history.clear();
+        self.generation += 1;
+        Ok(())
+    }
+}
+
+/// Rollback
+///
+/// Previous version of a fragment kept by `PakEditor::commit_append`: its
+/// tables and its `Info` still end the first `len` bytes of the fragment,
+/// the later commits only having appended to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rollback {
+    path: PathBuf,
+    len: u64,
+}
+
+impl Rollback {
+    /// Path of the fragment.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Length of the fragment in its previous version.
+    pub fn previous_len(&self) -> u64 {
+        self.len
+    }
+
+    /// Restores the previous version by truncating the fragment, the
+    /// entries and the properties appended since being lost. Fails with
+    /// `InvalidData` if the fragment no longer ends its previous version
+    /// at `len`, when it was rewritten or already truncated. The readers
+    /// and the editors of the fragment must be opened again.
+    pub fn apply(&self) -> io::Result<()> {
+        let mut file = OpenOptions::new().read(true).write(true).open(&self.path)?;
+        let offsets = read_header(&mut file)?;
+        let info_len = offsets.info_len() as u64;
+        let current_len = file.seek(SeekFrom::End(0))?;
+        let ends_version = self.len >= info_len && self.len <= current_len && {
+            let mut trailer = vec![0; info_len as usize];
+            file.seek(SeekFrom::Start(self.len - info_len))?;
+            file.read_exact(&mut trailer)?;
+            Info::parse_trailer(&trailer, offsets).and_then(|info| info.check(self.len)).is_ok()
+        };
+        if !ends_version {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("\"{}\" has no previous version of {} bytes to roll back to", self.path.display(), self.len)
+            ));
+        }
+        file.set_len(self.len)?;
+        file.sync_all()
+    }
+}
+
+/// PakEditor
+///
+/// An existing pak fragment opened for patching: entries and properties
+/// are staged like with `Archive`, then `commit` writes only the new data,
+/// after the data of the fragment, and regenerates the chunk table and the
+/// `Info`. The data of the replaced and removed entries is left in place
+/// as wasted space (see `health`), reclaimed by rewriting the archive with
+/// `Archive::commit` or a repack.
+///
+/// Unlike `Archive::commit`, an interruption while committing leaves the
+/// file corrupted. `commit_append` doesn't, it never overwrites a byte of
+/// the fragment.
+#[derive(Debug)]
+pub struct PakEditor {
+    archive: Archive,
+}
+
+impl PakEditor {
+    /// Opens the existing pak fragment at `path` for patching.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Archive::open_rw(path).map(|archive| PakEditor { archive })
+    }
+
+    /// Opens the existing pak fragment at `path` for patching, according to
+    /// `options`.
+    pub fn open_with<P: AsRef<Path>>(path: P, options: &ReadOptions) -> io::Result<Self> {
+        Archive::open_rw_with(path, options).map(|archive| PakEditor { archive })
+    }
+
+    /// Sets the options of the writes of `commit`: the new entries are
+    /// compressed and encrypted as `PakWriter` would, the kept ones stay
+    /// as they are stored.
+    pub fn set_write_options(&mut self, options: WriteOptions) {
+        self.archive.set_write_options(options);
+    }
+
+    /// Path of the fragment.
+    pub fn path(&self) -> &Path {
+        self.archive.path()
+    }
+
+    /// Returns `true` if the fragment, staged changes included, contains the
+    /// entry `full_file_name`.
+    pub fn contains(&self, full_file_name: &str) -> bool {
+        self.archive.contains(full_file_name)
+    }
+
+    /// Full file names of the entries, staged changes included, sorted.
+    pub fn file_names(&self) -> Vec<String> {
+        self.archive.file_names()
+    }
+
+    /// Reads the entry `full_file_name`, staged changes included.
+    pub fn read_file(&self, full_file_name: &str) -> io::Result<Vec<u8>> {
+        self.archive.read_file(full_file_name)
+    }
+
+    /// Stages the addition of a new entry.
+    pub fn add<S: Into<String>>(&mut self, full_file_name: S, data: Vec<u8>) -> io::Result<()> {
+        self.archive.add(full_file_name, data)
+    }
+
+    /// Stages the replacement of the data of an existing entry.
+    pub fn replace(&mut self, full_file_name: &str, data: Vec<u8>) -> io::Result<()> {
+        self.archive.replace(full_file_name, data)
+    }
+
+    /// Stages the addition or the replacement of an entry.
+    pub fn insert<S: Into<String>>(&mut self, full_file_name: S, data: Vec<u8>) -> io::Result<()> {
+        self.archive.insert(full_file_name, data)
+    }
+
+    /// Stages the addition or the replacement of an entry with the content
+    /// of the file at `path`, read at commit.
+    pub fn insert_file<S: Into<String>, P: AsRef<Path>>(&mut self, full_file_name: S, path: P) -> io::Result<()> {
+        self.archive.insert_file(full_file_name, path)
+    }
+
+    /// Stages the removal of an entry.
+    pub fn remove(&mut self, full_file_name: &str) -> io::Result<()> {
+        self.archive.remove(full_file_name)
+    }
+
+    /// Value of the property `key`.
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.archive.property(key)
+    }
+
+    /// Sets the property `key`, failing with `InvalidInput` if it is
+    /// reserved, see `is_reserved_property`.
+    pub fn set_property<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) -> io::Result<()> {
+        self.archive.set_property(key, value)
+    }
+
+    /// Removes the property `key`, returning its value, failing with
+    /// `InvalidInput` if it is reserved.
+    pub fn remove_property(&mut self, key: &str) -> io::Result<Option<String>> {
+        self.archive.remove_property(key)
+    }
+
+    /// Returns `true` if changes are staged.
+    pub fn has_changes(&self) -> bool {
+        self.archive.has_changes()
+    }
+
+    /// Discards the staged changes.
+    pub fn discard(&mut self) {
+        self.archive.discard()
+    }
+
+    /// Applies the staged changes, writing only the new data and the
+    /// tables. On error, the changes stay staged.
+    pub fn commit(&mut self) -> io::Result<()> {
+        if !self.archive.has_changes() {
+            return Ok(());
+        }
+        self.archive.patch(false)
+    }
+
+    /// Applies the staged changes like `commit`, but appends the new data
+    /// and the tables at the end of the fragment, so that the previous
+    /// version stays intact before them, the previous tables becoming
+    /// wasted space. Only the new data is written. Returns the `Rollback`
+    /// restoring the previous version. On error, the changes stay staged,
+    /// and the `rollback_point` taken before restores the fragment.
+    pub fn commit_append(&mut self) -> io::Result<Rollback> {
+        let rollback = self.rollback_point()?;
+        if self.archive.has_changes() {
+            self.archive.patch(true)?;
+        }
+        Ok(rollback)
+    }
+
+    /// `Rollback` restoring the current version of the fragment after
+    /// `commit_append`.
+    pub fn rollback_point(&self) -> io::Result<Rollback> {
+        Ok(Rollback {
+            path: self.path().to_path_buf(),
+            len: fs::metadata(self.path())?.len(),
+        })
+    }
+
+    /// Archive being patched.
+    pub fn into_archive(self) -> Archive {
+        self.archive
+    }
+}
@@ -0,0 +1,245 @@
+#[cfg(feature = "crypto")]
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+#[cfg(feature = "crypto")]
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use error::Error as PakError;
+use key::{Key, KeyProvider, Keys};
+use raw::Property;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::{Error, ErrorKind};
+use verify::{Finding, Severity};
+
+/// Prefix of the keys of the properties recording the encryption of the
+/// chunks of a fragment, followed by the name of the chunk as stored in the
+/// chunk table.
+pub(crate) const ENCRYPTION_PREFIX: &str = "encryption:";
+
+/// Length of the nonce of `XChaCha20Poly1305`, stored before the
+/// ciphertext.
+#[cfg(feature = "crypto")]
+const XNONCE_LEN: usize = 24;
+
+/// Data authenticated along with the data of the entry `full_file_name`,
+/// of `size` bytes before it was encrypted: the name followed by the size,
+/// little-endian.
+#[cfg(feature = "crypto")]
+fn associated_data(full_file_name: &str, size: u64) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(full_file_name.len() + 8);
+    aad.extend_from_slice(full_file_name.as_bytes());
+    aad.extend_from_slice(&size.to_le_bytes());
+    aad
+}
+
+/// Cipher of `key`. The key is borrowed, the only copy of its bytes being
+/// the one of the cipher, zeroed when the cipher is dropped.
+#[cfg(feature = "crypto")]
+fn cipher(key: &Key) -> XChaCha20Poly1305 {
+    XChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key.as_bytes()))
+}
+
+/// Encryption
+///
+/// Authenticated encryption of the data of the chunks, applied by the
+/// writers when `WriteOptions::encryption` is set, with the key given for
+/// every entry by `WriteOptions::keys`, and reverted by the readers given
+/// the keys in `ReadOptions::keys`. The encryption of a chunk is recorded in
+/// the properties of its fragment: the key is `encryption:` followed by the
+/// name of the chunk, the value the encryption and the size of the data
+/// before it was encrypted (`xchacha20poly1305:1024`). The data is
+/// compressed, if enabled, then encrypted. The full file name of the entry
+/// and the size are authenticated along with the data: an encrypted chunk
+/// can't be moved to another entry, nor its recorded size altered.
+///
+/// Reading an encrypted entry without its key fails with
+/// `Error::Encrypted`, with the wrong key or tampered data with
+/// `Error::DecryptionFailed`. The names of the entries and the properties
+/// aren't encrypted.
+///
+/// The encryptions are enabled by the `crypto` feature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Encryption {
+    /// XChaCha20-Poly1305, with a random nonce per chunk stored before the
+    /// ciphertext
+    #[cfg(feature = "crypto")]
+    XChaCha20Poly1305,
+}
+
+impl Encryption {
+    /// Name of the encryption, as recorded in the properties.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "crypto")]
+            Encryption::XChaCha20Poly1305 => "xchacha20poly1305",
+        }
+    }
+
+    /// Encryption named `name`, `None` if it is unknown or disabled.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            #[cfg(feature = "crypto")]
+            "xchacha20poly1305" => Some(Encryption::XChaCha20Poly1305),
+            _ => None,
+        }
+    }
+
+    /// Encrypts `data`, the data of the entry `full_file_name`, with `key`.
+    // nothing to match without any encryption enabled
+    #[cfg_attr(not(feature = "crypto"), allow(unused_variables, unreachable_code))]
+    pub(crate) fn encrypt(&self, key: &Key, full_file_name: &str, data: &[u8]) -> io::Result<Vec<u8>> {
+        match *self {
+            #[cfg(feature = "crypto")]
+            Encryption::XChaCha20Poly1305 => {
+                let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+                let aad = associated_data(full_file_name, data.len() as u64);
+                let payload = Payload { msg: data, aad: &aad };
+                let ciphertext = cipher(key).encrypt(&nonce, payload).map_err(|_| Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{} bytes can't be encrypted", data.len())
+                ))?;
+                let mut stored = Vec::with_capacity(XNONCE_LEN + ciphertext.len());
+                stored.extend_from_slice(&nonce);
+                stored.extend_from_slice(&ciphertext);
+                Ok(stored)
+            }
+        }
+    }
+
+    /// Decrypts `stored`, the data of the entry `full_file_name` of `size`
+    /// bytes before it was encrypted, with `key`. `None` if the key is
+    /// wrong, or the data, the name or the size tampered with.
+    // nothing to match without any encryption enabled
+    #[cfg_attr(not(feature = "crypto"), allow(unused_variables, unreachable_code))]
+    fn decrypt(&self, key: &Key, full_file_name: &str, size: u64, stored: &[u8]) -> Option<Vec<u8>> {
+        match *self {
+            #[cfg(feature = "crypto")]
+            Encryption::XChaCha20Poly1305 => {
+                if stored.len() < XNONCE_LEN {
+                    return None;
+                }
+                let (nonce, ciphertext) = stored.split_at(XNONCE_LEN);
+                let aad = associated_data(full_file_name, size);
+                cipher(key)
+                    .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad })
+                    .ok()
+            }
+        }
+    }
+}
+
+impl fmt::Display for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Encryption of a stored chunk, as recorded in the properties of its
+/// fragment, with the keys to decrypt it. The name is kept as is so that
+/// the chunks encrypted with a disabled encryption are still copied along
+/// with it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ChunkEncryption {
+    /// Name of the encryption
+    pub(crate) name: String,
+    /// Size of the data before it was encrypted
+    pub(crate) size: u64,
+    /// Full file name of the entry, its key being asked for by that name
+    pub(crate) entry: String,
+    /// Keys of the reader, if any
+    pub(crate) keys: Option<Keys>,
+}
+
+impl ChunkEncryption {
+    /// Encryption with `encryption` of a chunk of `size` bytes before it
+    /// was encrypted.
+    pub(crate) fn new(encryption: Encryption, size: u64) -> Self {
+        ChunkEncryption {
+            name: encryption.name().to_string(),
+            size,
+            entry: String::new(),
+            keys: None,
+        }
+    }
+
+    /// Parses the value of an encryption property, `None` if it is
+    /// malformed.
+    fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.splitn(2, ':');
+        let name = parts.next()?.to_string();
+        let size = parts.next()?.parse().ok()?;
+        Some(ChunkEncryption { name, size, entry: String::new(), keys: None })
+    }
+
+    /// Encryption as stored, without the entry and the keys of a reader.
+    pub(crate) fn stored(&self) -> Self {
+        ChunkEncryption { name: self.name.clone(), size: self.size, entry: String::new(), keys: None }
+    }
+
+    /// Encryption of the chunk of the entry `entry`, decrypted with `keys`.
+    pub(crate) fn of(mut self, entry: &str, keys: Option<&Keys>) -> Self {
+        self.entry = entry.to_string();
+        self.keys = keys.cloned();
+        self
+    }
+
+    /// Property recording the encryption of the chunk `stored_name`.
+    pub(crate) fn to_property(&self, stored_name: &str) -> Property {
+        Property::new(format!("{}{}", ENCRYPTION_PREFIX, stored_name), format!("{}:{}", self.name, self.size))
+    }
+
+    /// Decrypts the data encrypted in `stored`, failing with `Unsupported`
+    /// if the encryption is disabled, with `Error::Encrypted` without keys
+    /// and with `Error::DecryptionFailed` if the key is wrong.
+    pub(crate) fn decrypt(&self, stored: &[u8]) -> io::Result<Vec<u8>> {
+        let encryption = Encryption::from_name(&self.name).ok_or_else(|| Error::new(
+            ErrorKind::Unsupported,
+            format!("the data is encrypted with \"{}\", which isn't enabled", self.name)
+        ))?;
+        let keys = match self.keys {
+            Some(ref keys) => keys,
+            None => return Err(PakError::Encrypted { name: self.entry.clone() }.into()),
+        };
+        let data = encryption.decrypt(&keys.key_for(&self.entry)?, &self.entry, self.size, stored)
+            .ok_or_else(|| Error::from(PakError::DecryptionFailed { name: self.entry.clone() }))?;
+        self.check_size(data.len() as u64)?;
+        Ok(data)
+    }
+
+    /// Checks that the data decrypted has the recorded size, failing with
+    /// `InvalidData` otherwise.
+    pub(crate) fn check_size(&self, decrypted: u64) -> io::Result<u64> {
+        if decrypted != self.size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("the data decrypted with {} has {} bytes instead of {}", self.name, decrypted, self.size)
+            ));
+        }
+        Ok(decrypted)
+    }
+}
+
+/// Removes the encryption properties from the properties of a fragment,
+/// returning the encryptions by stored chunk name. The malformed ones are
+/// reported in `warnings`.
+pub(crate) fn take_encryptions(properties: &mut HashMap<String, Property>, warnings: &mut Vec<Finding>) -> HashMap<String, ChunkEncryption> {
+    let keys: Vec<String> = properties.keys()
+        .filter(|key| key.starts_with(ENCRYPTION_PREFIX))
+        .cloned()
+        .collect();
+    let mut encryptions = HashMap::with_capacity(keys.len());
+    for key in keys {
+        let property = properties.remove(&key).expect("the key was listed");
+        let stored_name = &key[ENCRYPTION_PREFIX.len()..];
+        match ChunkEncryption::parse(&property.value) {
+            Some(encryption) => {
+                encryptions.insert(stored_name.to_string(), encryption);
+            }
+            None => warnings.push(Finding::new(
+                Severity::Error,
+                format!("malformed encryption \"{}\", the data is read as stored", property.value))
+                .entry(stored_name)),
+        }
+    }
+    encryptions
+}
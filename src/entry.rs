@@ -0,0 +1,160 @@
+use read::{EntryMeta, MergedChunk, MergeReader};
+use std::collections::hash_map;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+
+/// Entry
+///
+/// An entry borrowed from its `MergeReader`: the name and the chunk are
+/// references into the reader, so that scanning the entries allocates
+/// nothing. `meta` detaches it as an owned `EntryMeta`.
+#[derive(Debug)]
+pub struct Entry<'a, R: 'a> {
+    name: &'a str,
+    chunk: &'a MergedChunk<R>,
+    fragment_path: &'a Path,
+}
+
+impl<'a, R> Clone for Entry<'a, R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, R> Copy for Entry<'a, R> {}
+
+impl<'a, R> Entry<'a, R>
+where
+    R: Read + Seek
+{
+    /// Full file name.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Chunk of the data.
+    pub fn chunk(&self) -> &'a MergedChunk<R> {
+        self.chunk
+    }
+
+    /// Size of the data.
+    pub fn size(&self) -> u64 {
+        self.chunk.size()
+    }
+
+    /// Index of the fragment containing the data.
+    pub fn fragment(&self) -> usize {
+        self.chunk.fragment()
+    }
+
+    /// Path of the fragment containing the data.
+    pub fn fragment_path(&self) -> &'a Path {
+        self.fragment_path
+    }
+
+    /// Offset of the data in its fragment.
+    pub fn offset(&self) -> u64 {
+        self.chunk.offset()
+    }
+
+    /// Owned metadata of the entry.
+    pub fn meta(&self) -> EntryMeta {
+        self.chunk.meta(self.name)
+    }
+}
+
+/// Entries
+///
+/// Iterator over the borrowed entries of a `MergeReader`, in no particular
+/// order.
+#[derive(Debug)]
+pub struct Entries<'a, R: 'a> {
+    chunks: hash_map::Iter<'a, String, MergedChunk<R>>,
+    fragments: &'a [PathBuf],
+}
+
+impl<'a, R> Iterator for Entries<'a, R>
+where
+    R: Read + Seek
+{
+    type Item = Entry<'a, R>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let fragments = self.fragments;
+        self.chunks.next().map(|(name, chunk)| Entry {
+            name,
+            chunk,
+            fragment_path: &fragments[chunk.fragment()],
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks.size_hint()
+    }
+}
+
+impl<'a, R> ExactSizeIterator for Entries<'a, R> where R: Read + Seek {}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Iterates over the entries without allocating.
+    pub fn entries(&self) -> Entries<'_, R> {
+        Entries {
+            chunks: self.chunks.iter(),
+            fragments: &self.fragments,
+        }
+    }
+
+    /// Borrowed entry `full_file_name`.
+    pub fn entry(&self, full_file_name: &str) -> Option<Entry<'_, R>> {
+        let (name, chunk) = self.lookup(full_file_name)?;
+        Some(Entry {
+            name,
+            chunk,
+            fragment_path: &self.fragments[chunk.fragment()],
+        })
+    }
+
+    /// Full file names of the entries, sorted.
+    pub fn file_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.chunks.keys().map(|name| name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    /// Owned metadata of the entries, sorted by name, to index the archive
+    /// without borrowing the reader.
+    pub fn entry_metas(&self) -> Vec<EntryMeta> {
+        let mut metas: Vec<EntryMeta> = self.entries().map(|entry| entry.meta()).collect();
+        metas.sort_by(|a, b| a.name.cmp(&b.name));
+        metas
+    }
+}
+
+impl<'a, R> IntoIterator for &'a MergeReader<R>
+where
+    R: Read + Seek
+{
+    type Item = Entry<'a, R>;
+    type IntoIter = Entries<'a, R>;
+
+    fn into_iter(self) -> Entries<'a, R> {
+        self.entries()
+    }
+}
+
+/// Consumes the reader into its entries, in no particular order. Every
+/// chunk keeps its fragment open, so the data stays readable.
+impl<R> IntoIterator for MergeReader<R>
+where
+    R: Read + Seek
+{
+    type Item = (String, MergedChunk<R>);
+    type IntoIter = hash_map::IntoIter<String, MergedChunk<R>>;
+
+    fn into_iter(self) -> hash_map::IntoIter<String, MergedChunk<R>> {
+        self.chunks.into_iter()
+    }
+}
@@ -0,0 +1,221 @@
+use checksum::Checksum;
+use hash::Digest;
+use raw::ParseError;
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Error
+///
+/// Typed cause of a failure. The functions of the crate keep returning
+/// `io::Error`, which carries the `Error` when the cause is known:
+/// `Error::from` recovers it from the `io::Error`, falling back to
+/// `Error::Io`, and `Error::from_io` borrows it.
+#[derive(Debug)]
+pub enum Error {
+    /// The header of a fragment isn't the pak header
+    InvalidHeader {
+        /// Fragment, if known
+        path: Option<PathBuf>,
+    },
+    /// The `Info` or the tables of a fragment are corrupted
+    Corrupt {
+        /// Fragment, if known
+        path: Option<PathBuf>,
+        /// Offset of the structure which can't be parsed, if known
+        offset: Option<u64>,
+        /// What is wrong with the structure
+        cause: ParseError,
+    },
+    /// The data of an entry ends before the size of its chunk
+    Truncated {
+        /// Offset of the data in its fragment
+        offset: u64,
+        /// Size of the chunk
+        expected: u64,
+        /// Bytes actually read
+        read: u64,
+    },
+    /// A `link` property points to a fragment which doesn't exist
+    MissingFragment {
+        /// Linked fragment
+        path: PathBuf,
+        /// Fragment holding the link
+        linked_from: PathBuf,
+    },
+    /// A `link` property can't be resolved to a path
+    InvalidLink {
+        /// Value of the property
+        link: String,
+    },
+    /// No entry has the name
+    EntryNotFound {
+        /// Full file name looked up
+        name: String,
+    },
+    /// An entry already has the name
+    EntryExists {
+        /// Full file name added
+        name: String,
+    },
+    /// The data of an entry doesn't match its checksum
+    ChecksumMismatch {
+        /// Checksum stored for the entry
+        expected: Checksum,
+        /// Digest of the data read
+        actual: Digest,
+    },
+    /// The `AccessPolicy` denies the entry
+    AccessDenied {
+        /// Full file name read
+        name: String,
+    },
+    /// The entry is encrypted and the reader has no keys, see
+    /// `ReadOptions::keys`
+    Encrypted {
+        /// Full file name read
+        name: String,
+    },
+    /// The data of an encrypted entry can't be decrypted: wrong key, or
+    /// tampered data
+    DecryptionFailed {
+        /// Full file name read
+        name: String,
+    },
+    /// Any other failure
+    Io(io::Error),
+}
+
+impl Error {
+    /// Returns the `Error` carried by `err`, if any.
+    pub fn from_io(err: &io::Error) -> Option<&Error> {
+        err.get_ref().and_then(|inner| inner.downcast_ref::<Error>())
+    }
+
+    /// Kind of the `io::Error` carrying the error.
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            Error::InvalidHeader { .. } | Error::InvalidLink { .. } => ErrorKind::InvalidInput,
+            Error::Corrupt { cause: ParseError::UnexpectedEnd, .. } | Error::Truncated { .. } => ErrorKind::UnexpectedEof,
+            Error::Corrupt { .. } | Error::ChecksumMismatch { .. } | Error::DecryptionFailed { .. } => ErrorKind::InvalidData,
+            Error::MissingFragment { .. } | Error::EntryNotFound { .. } => ErrorKind::NotFound,
+            Error::EntryExists { .. } => ErrorKind::AlreadyExists,
+            Error::AccessDenied { .. } | Error::Encrypted { .. } => ErrorKind::PermissionDenied,
+            Error::Io(ref err) => err.kind(),
+        }
+    }
+
+    /// Fragment the error is about, if known.
+    pub fn path(&self) -> Option<&Path> {
+        match *self {
+            Error::InvalidHeader { ref path } | Error::Corrupt { ref path, .. } => path.as_ref().map(|path| path.as_path()),
+            Error::MissingFragment { ref path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Fills the fragment and the offset of a parsing error of its tables,
+    /// read at `offset` of `path`. Unexpected ends of the underlying reader
+    /// become `Corrupt` errors.
+    pub(crate) fn in_fragment(err: io::Error, fragment: &Path, at: Option<u64>) -> io::Error {
+        let typed = match Error::from(err) {
+            Error::InvalidHeader { path } => Error::InvalidHeader {
+                path: path.or_else(|| Some(fragment.to_path_buf())),
+            },
+            Error::Corrupt { path, offset, cause } => Error::Corrupt {
+                path: path.or_else(|| Some(fragment.to_path_buf())),
+                offset: offset.or(at),
+                cause,
+            },
+            Error::Io(ref err) if err.kind() == ErrorKind::UnexpectedEof => Error::Corrupt {
+                path: Some(fragment.to_path_buf()),
+                offset: at,
+                cause: ParseError::UnexpectedEnd,
+            },
+            typed => typed,
+        };
+        typed.into()
+    }
+}
+
+/// Error of the lookup of the entry `full_file_name`.
+pub(crate) fn entry_not_found(full_file_name: &str) -> io::Error {
+    Error::EntryNotFound { name: full_file_name.to_string() }.into()
+}
+
+/// Error of the addition of the entry `full_file_name`.
+pub(crate) fn entry_exists(full_file_name: &str) -> io::Error {
+    Error::EntryExists { name: full_file_name.to_string() }.into()
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidHeader { path: Some(ref path) } => write!(f, "\"{}\": {}", path.display(), ParseError::InvalidHeader),
+            Error::InvalidHeader { path: None } => write!(f, "{}", ParseError::InvalidHeader),
+            Error::Corrupt { ref path, offset, cause } => {
+                if let Some(ref path) = *path {
+                    write!(f, "\"{}\": ", path.display())?;
+                }
+                write!(f, "{}", cause)?;
+                match offset {
+                    Some(offset) => write!(f, " at offset {}", offset),
+                    None => Ok(()),
+                }
+            }
+            Error::Truncated { offset, expected, read } => {
+                write!(f, "chunk data truncated: {} bytes out of {} at offset {}", read, expected, offset)
+            }
+            Error::MissingFragment { ref path, ref linked_from } => {
+                write!(f, "\"{}\" links to \"{}\" which doesn't exist", linked_from.display(), path.display())
+            }
+            Error::InvalidLink { ref link } => write!(f, "invalid link \"{}\"", link),
+            Error::EntryNotFound { ref name } => write!(f, "`full_file_name` \"{}\" doesn't exist", name),
+            Error::EntryExists { ref name } => write!(f, "`full_file_name` \"{}\" already exists", name),
+            Error::ChecksumMismatch { ref expected, ref actual } => {
+                write!(f, "checksum mismatch: {} expected, {} read", expected, actual)
+            }
+            Error::AccessDenied { ref name } => write!(f, "access to `full_file_name` \"{}\" denied", name),
+            Error::Encrypted { ref name } => {
+                write!(f, "`full_file_name` \"{}\" is encrypted, its key must be given in `ReadOptions::keys`", name)
+            }
+            Error::DecryptionFailed { ref name } => {
+                write!(f, "`full_file_name` \"{}\" can't be decrypted: wrong key or tampered data", name)
+            }
+            Error::Io(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Corrupt { ref cause, .. } => Some(cause),
+            Error::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        if Error::from_io(&err).is_none() {
+            return Error::Io(err);
+        }
+        match err.into_inner().map(|inner| inner.downcast::<Error>()) {
+            Some(Ok(typed)) => *typed,
+            _ => unreachable!("the payload was checked"),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        match err {
+            Error::Io(err) => err,
+            typed => io::Error::new(typed.kind(), typed),
+        }
+    }
+}
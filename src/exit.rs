@@ -0,0 +1,101 @@
+use error::Error;
+use retry::{classify, ErrorClass};
+use std::io;
+use std::io::ErrorKind;
+use std::process;
+use timeout::TimedOut;
+use verify::Report;
+
+/// ExitCode
+///
+/// Stable exit codes of the command line tools, so that scripts can branch
+/// on the class of a failure. The values never change once released.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExitCode {
+    /// Success (0)
+    Success,
+    /// Failure not covered by another code (1)
+    Failure,
+    /// Invalid command line arguments (2)
+    Usage,
+    /// A file, a linked fragment or an entry doesn't exist (3)
+    NotFound,
+    /// A pak file is malformed: header, tables or data (4)
+    Corruption,
+    /// The verification found errors in a readable archive (5)
+    VerificationFailed,
+    /// The system failed to read or write: permissions, full disk, device
+    /// errors, timeouts (6)
+    Io,
+}
+
+impl ExitCode {
+    /// Numeric value of the exit code.
+    pub fn code(self) -> i32 {
+        match self {
+            ExitCode::Success => 0,
+            ExitCode::Failure => 1,
+            ExitCode::Usage => 2,
+            ExitCode::NotFound => 3,
+            ExitCode::Corruption => 4,
+            ExitCode::VerificationFailed => 5,
+            ExitCode::Io => 6,
+        }
+    }
+
+    /// Exit code of the failure `err`, from its typed `Error` when it has
+    /// one and from its kind otherwise.
+    pub fn from_error(err: &io::Error) -> Self {
+        match Error::from_io(err) {
+            Some(Error::InvalidHeader { .. })
+            | Some(Error::Corrupt { .. })
+            | Some(Error::Truncated { .. })
+            | Some(Error::InvalidLink { .. })
+            | Some(Error::ChecksumMismatch { .. }) => return ExitCode::Corruption,
+            Some(Error::MissingFragment { .. })
+            | Some(Error::EntryNotFound { .. }) => return ExitCode::NotFound,
+            Some(Error::EntryExists { .. })
+            | Some(Error::AccessDenied { .. })
+            | Some(Error::Encrypted { .. })
+            | Some(Error::DecryptionFailed { .. }) => return ExitCode::Failure,
+            Some(Error::Io(err)) => return ExitCode::from_error(err),
+            None => {}
+        }
+        if TimedOut::from_error(err).is_some() {
+            return ExitCode::Io;
+        }
+        match classify(err) {
+            ErrorClass::Transient => ExitCode::Io,
+            ErrorClass::Corruption => ExitCode::Corruption,
+            ErrorClass::Other => match err.kind() {
+                ErrorKind::NotFound => ExitCode::NotFound,
+                ErrorKind::PermissionDenied
+                | ErrorKind::OutOfMemory
+                | ErrorKind::WriteZero
+                | ErrorKind::StorageFull => ExitCode::Io,
+                _ => ExitCode::Failure,
+            },
+        }
+    }
+
+    /// Exit code of a verification: `VerificationFailed` if `report` has
+    /// errors, `Success` otherwise (warnings included).
+    pub fn from_report(report: &Report) -> Self {
+        if report.is_ok() {
+            ExitCode::Success
+        } else {
+            ExitCode::VerificationFailed
+        }
+    }
+
+    /// Exits the process with the exit code.
+    pub fn exit(self) -> ! {
+        process::exit(self.code())
+    }
+}
+
+impl From<ExitCode> for process::ExitCode {
+    fn from(code: ExitCode) -> process::ExitCode {
+        process::ExitCode::from(code.code() as u8)
+    }
+}
@@ -0,0 +1,257 @@
+use chain::FragmentChain;
+use hash::{Digest, HashAlgorithm, HashBackend};
+use std::ffi::OsString;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Error, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Magic first line of a blob index.
+static INDEX_MAGIC: &str = "pak-blobs 1";
+
+/// BlobFragment
+///
+/// A fragment file stored as a sequence of blobs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlobFragment {
+    /// File name of the fragment
+    pub file_name: String,
+    /// Size of the fragment file
+    pub size: u64,
+    /// Digests of the blocks of the file, in order
+    pub blobs: Vec<Digest>,
+}
+
+/// BlobIndex
+///
+/// Index of an archive chain exported as fixed-size content-addressed blobs:
+/// every fragment file is cut into blocks of `block_size` bytes, each block
+/// being stored once in the blob store under its digest. Exporting a new
+/// version of the archive into the same store only writes the blocks which
+/// changed, so a backup of the store only uploads those.
+///
+/// Index format (text) :
+///
+/// ``` text
+///     pak-blobs 1
+///     block_size <bytes>
+///     algorithm <name>
+///     for every fragment
+///         fragment <size> <file name>
+///         for every block
+///             <digest (hex)>
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlobIndex {
+    /// Size of the blocks (the last block of a fragment may be shorter)
+    pub block_size: u64,
+    /// Algorithm of the digests
+    pub algorithm: HashAlgorithm,
+    /// Fragments, in the order of the chain
+    pub fragments: Vec<BlobFragment>,
+}
+
+/// BlobExport
+///
+/// Outcome of `export_blobs`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlobExport {
+    /// Index of the exported chain
+    pub index: BlobIndex,
+    /// Number of blobs written into the store
+    pub written: usize,
+    /// Number of blocks already present in the store
+    pub reused: usize,
+}
+
+/// Path of the blob `digest` in the `store`: `<store>/<2 first hex digits>/<hex>`.
+fn blob_path(store: &Path, digest: &Digest) -> PathBuf {
+    let hex = digest.to_hex();
+    store.join(&hex[..2.min(hex.len())]).join(&hex)
+}
+
+/// Writes `data` at `path` through a temporary file, so that an interrupted
+/// export never leaves a partial blob.
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut temp = OsString::from(path);
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+
+    let mut file = File::create(&temp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&temp, path)
+}
+
+/// Reads up to `buffer.len()` bytes, less only at the end of `reader`.
+fn read_block<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+/// Exports the archive chain starting at `head` into the blob `store`, cut
+/// into blocks of `block_size` bytes identified by their `algorithm` digest.
+///
+/// Use a cryptographic algorithm (`Blake3` or `Sha256`) when the store is
+/// shared by untrusted archives: two blocks with the same digest are
+/// considered identical.
+pub fn export_blobs<P, Q>(head: P, store: Q, block_size: u64, algorithm: HashAlgorithm) -> io::Result<BlobExport>
+    where P: AsRef<Path>,
+          Q: AsRef<Path>
+{
+    if block_size == 0 {
+        return Err(Error::new(ErrorKind::InvalidInput, "the block size must not be 0"));
+    }
+    let store = store.as_ref();
+    let chain = FragmentChain::open(head)?;
+    if !chain.validate().is_ok() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("the chain of \"{}\" is broken", chain.head().display())
+        ));
+    }
+
+    let mut export = BlobExport {
+        index: BlobIndex { block_size, algorithm, fragments: Vec::new() },
+        written: 0,
+        reused: 0,
+    };
+    let mut buffer = vec![0; block_size as usize];
+
+    for fragment in chain.fragments() {
+        let file_name = fragment.path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut reader = BufReader::new(File::open(&fragment.path)?);
+        let mut blobs = Vec::new();
+        let mut size = 0;
+
+        loop {
+            let len = read_block(&mut reader, &mut buffer)?;
+            if len == 0 {
+                break;
+            }
+            size += len as u64;
+
+            let digest = algorithm.digest(&buffer[..len]);
+            let path = blob_path(store, &digest);
+            if path.exists() {
+                export.reused += 1;
+            } else {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                write_atomic(&path, &buffer[..len])?;
+                export.written += 1;
+            }
+            blobs.push(digest);
+        }
+
+        export.index.fragments.push(BlobFragment { file_name, size, blobs });
+    }
+
+    Ok(export)
+}
+
+impl BlobIndex {
+    /// Text representation of the index.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\nblock_size {}\nalgorithm {}\n", INDEX_MAGIC, self.block_size, self.algorithm.name());
+        for fragment in &self.fragments {
+            text.push_str(&format!("fragment {} {}\n", fragment.size, fragment.file_name));
+            for digest in &fragment.blobs {
+                text.push_str(&digest.to_hex());
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    /// Parses the text representation of an index.
+    pub fn from_text(text: &str) -> io::Result<Self> {
+        let invalid = |line: &str| Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid blob index line \"{}\"", line)
+        );
+
+        let mut lines = text.lines();
+        if lines.next() != Some(INDEX_MAGIC) {
+            return Err(Error::new(ErrorKind::InvalidData, "not a blob index"));
+        }
+        let line = lines.next().unwrap_or("");
+        let block_size = line.strip_prefix("block_size ")
+            .and_then(|block_size| block_size.parse().ok())
+            .ok_or_else(|| invalid(line))?;
+        let line = lines.next().unwrap_or("");
+        let algorithm = line.strip_prefix("algorithm ")
+            .and_then(HashAlgorithm::from_name)
+            .ok_or_else(|| invalid(line))?;
+
+        let mut index = BlobIndex { block_size, algorithm, fragments: Vec::new() };
+        for line in lines.filter(|line| !line.is_empty()) {
+            if let Some(fragment) = line.strip_prefix("fragment ") {
+                let mut parts = fragment.splitn(2, ' ');
+                let size = parts.next().and_then(|size| size.parse().ok()).ok_or_else(|| invalid(line))?;
+                let file_name = parts.next().ok_or_else(|| invalid(line))?.to_string();
+                index.fragments.push(BlobFragment { file_name, size, blobs: Vec::new() });
+            } else {
+                let digest = Digest::from_hex(line).ok_or_else(|| invalid(line))?;
+                index.fragments.last_mut().ok_or_else(|| invalid(line))?.blobs.push(digest);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Saves the index at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        write_atomic(path.as_ref(), self.to_text().as_bytes())
+    }
+
+    /// Loads the index saved at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        BlobIndex::from_text(&fs::read_to_string(path)?)
+    }
+
+    /// Rebuilds the fragment files into `dest` from the blobs of `store`,
+    /// checking their digests, and returns their paths.
+    pub fn restore<P, Q>(&self, store: P, dest: Q) -> io::Result<Vec<PathBuf>>
+        where P: AsRef<Path>,
+              Q: AsRef<Path>
+    {
+        let mut paths = Vec::with_capacity(self.fragments.len());
+        for fragment in &self.fragments {
+            let path = dest.as_ref().join(&fragment.file_name);
+            let mut file = File::create(&path)?;
+            let mut size = 0;
+
+            for digest in &fragment.blobs {
+                let data = fs::read(blob_path(store.as_ref(), digest))?;
+                if &self.algorithm.digest(&data) != digest {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("the blob {} is corrupted", digest)
+                    ));
+                }
+                file.write_all(&data)?;
+                size += data.len() as u64;
+            }
+
+            if size != fragment.size {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("\"{}\" restored with {} bytes instead of {}", fragment.file_name, size, fragment.size)
+                ));
+            }
+            file.sync_all()?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
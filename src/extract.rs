@@ -0,0 +1,201 @@
+use name::EntryName;
+use options::{ExistingFiles, ExtractOptions};
+use read::{MergedChunk, MergeReader};
+use std::env;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Error, ErrorKind, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use timeout::Deadline;
+
+/// Counter making the temporary directories of a process unique.
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Temporary directory removed with its content when dropped, panics
+/// included.
+struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates a new empty directory in the temporary directory of the OS.
+    fn new() -> io::Result<Self> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.subsec_nanos()).unwrap_or(0);
+        for _ in 0..16 {
+            let path = env::temp_dir().join(format!(
+                "pak-{}-{}-{}",
+                process::id(),
+                TEMP_COUNTER.fetch_add(1, Ordering::Relaxed),
+                nanos
+            ));
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(TempDir { path }),
+                Err(ref err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Err(Error::new(ErrorKind::AlreadyExists, "can't create a unique temporary directory"))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// ExtractSummary
+///
+/// Entries written and skipped by `MergeReader::extract_with`, by full file
+/// name, in the order they were extracted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExtractSummary {
+    /// Entries written, or which would be written by a dry run
+    pub written: Vec<String>,
+    /// Entries written over an existing file, also listed in `written`
+    pub overwritten: Vec<String>,
+    /// Entries left out because their file exists
+    pub skipped: Vec<String>,
+    /// Size of the data of the entries written, decompressed
+    pub bytes: u64,
+}
+
+/// Fails with `PermissionDenied` if `path`, or one of its directories
+/// under `dest`, is a symbolic link.
+fn check_symlinks(dest: &Path, path: &Path) -> io::Result<()> {
+    let relative = match path.strip_prefix(dest) {
+        Ok(relative) => relative,
+        Err(_) => return Ok(()),
+    };
+    let mut checked = dest.to_path_buf();
+    for component in relative.components() {
+        checked.push(component);
+        match fs::symlink_metadata(&checked) {
+            Ok(ref metadata) if metadata.file_type().is_symlink() => return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!("\"{}\" is a symbolic link, see `ExtractOptions::follow_symlinks`", checked.display())
+            )),
+            Ok(_) => {}
+            // the paths under it don't exist either
+            Err(ref err) if err.kind() == ErrorKind::NotFound => break,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Extracts the entry `full_file_name` into `dest`, returning the path
+    /// of the extracted file.
+    pub(crate) fn extract_entry(&self, full_file_name: &str, dest: &Path) -> io::Result<PathBuf> {
+        let chunk = self.allowed_chunk(full_file_name)?;
+        let output = EntryName::new(full_file_name)?.output_path(dest);
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        self.write_entry(full_file_name, chunk, &mut File::create(&output)?)?;
+        Ok(output)
+    }
+
+    /// Writes the data of the entry `full_file_name` to `file`, within the
+    /// extraction timeout.
+    fn write_entry(&self, full_file_name: &str, chunk: &MergedChunk<R>, file: &mut File) -> io::Result<()> {
+        if self.options.timeouts.extract.is_none() {
+            chunk.decode_to(file, self.options.copy_buffer_size)?;
+        } else {
+            let deadline = Deadline::start(format!("extract \"{}\"", full_file_name), self.options.timeouts.extract);
+            let copied = io::copy(&mut deadline.reader(chunk.decoded_reader()?), file)?;
+            chunk.check_decoded(copied)?;
+        }
+        Ok(())
+    }
+
+    /// Extracts the entries into `dest` according to `options`, in the
+    /// order of their data, returning the entries written and skipped. The
+    /// entries denied by the `AccessPolicy` are left out.
+    pub fn extract_with<P: AsRef<Path>>(&self, dest: P, options: &ExtractOptions) -> io::Result<ExtractSummary> {
+        let dest = dest.as_ref();
+        let mut entries: Vec<_> = self.iter()
+            .filter(|&(full_file_name, _)| self.allows(full_file_name))
+            .collect();
+        // sequential reads
+        entries.sort_by_key(|&(full_file_name, chunk)| (chunk.fragment(), chunk.offset(), full_file_name.clone()));
+
+        let mut summary = ExtractSummary::default();
+        for (full_file_name, chunk) in entries {
+            let name = EntryName::new(full_file_name)?;
+            let path = name.to_path(dest);
+            if !options.filter.as_ref().is_none_or(|pattern| pattern.matches(full_file_name)) {
+                match path.parent() {
+                    Some(parent) if options.preserve_empty_dirs && !options.dry_run => {
+                        if !options.follow_symlinks {
+                            check_symlinks(dest, parent)?;
+                        }
+                        fs::create_dir_all(parent)?;
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            if !options.follow_symlinks {
+                check_symlinks(dest, &path)?;
+            }
+            if fs::symlink_metadata(&path).is_ok() {
+                match options.existing {
+                    ExistingFiles::Overwrite => summary.overwritten.push(full_file_name.clone()),
+                    ExistingFiles::Skip => {
+                        summary.skipped.push(full_file_name.clone());
+                        continue;
+                    }
+                    ExistingFiles::Fail => return Err(Error::new(
+                        ErrorKind::AlreadyExists,
+                        format!("\"{}\" already exists", path.display())
+                    )),
+                }
+            }
+            summary.written.push(full_file_name.clone());
+            summary.bytes += chunk.uncompressed_size();
+            if options.dry_run {
+                continue;
+            }
+
+            let output = name.output_path(dest);
+            if let Some(parent) = output.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = match options.existing {
+                // a file created meanwhile isn't overwritten either
+                ExistingFiles::Fail => OpenOptions::new().write(true).create_new(true).open(&output)?,
+                ExistingFiles::Overwrite | ExistingFiles::Skip => File::create(&output)?,
+            };
+            self.write_entry(full_file_name, chunk, &mut file)?;
+        }
+        Ok(summary)
+    }
+
+    /// Extracts the entries `names` into a new temporary directory, runs `f`
+    /// with the path of the directory, then removes the directory, even if
+    /// `f` panics. Useful to hand real files to external programs.
+    ///
+    /// The entries keep their directories: `a/b.swf` is extracted at
+    /// `<dir>/a/b.swf`.
+    pub fn with_extracted<S, F, T>(&self, names: &[S], f: F) -> io::Result<T>
+        where S: AsRef<str>,
+              F: FnOnce(&Path) -> T
+    {
+        let dir = TempDir::new()?;
+        for full_file_name in names {
+            self.extract_entry(full_file_name.as_ref(), &dir.path)?;
+        }
+        Ok(f(&dir.path))
+    }
+}
@@ -0,0 +1,30 @@
+use glob::Pattern;
+use read::{MergeReader, MergedChunk};
+use std::collections::BTreeMap;
+use std::io;
+use std::io::{Read, Seek};
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Iterates over the entries whose full file name matches the glob
+    /// `pattern` (`maps/0/*.dlm`), in no particular order.
+    pub fn iter_matching<'a>(&'a self, pattern: &'a Pattern) -> impl Iterator<Item = (&'a String, &'a MergedChunk<R>)> + 'a {
+        let prefix = pattern.literal_prefix();
+        self.iter().filter(move |&(full_file_name, _)| {
+            full_file_name.starts_with(prefix) && pattern.matches(full_file_name)
+        })
+    }
+
+    /// Reads every entry whose full file name starts with `prefix`, by
+    /// name. An empty `prefix` reads the whole archive. The entries denied
+    /// by the `AccessPolicy` are skipped.
+    pub fn read_files_with_prefix(&self, prefix: &str) -> io::Result<BTreeMap<String, Vec<u8>>> {
+        let mut files = BTreeMap::new();
+        for full_file_name in self.chunks.keys().filter(|name| name.starts_with(prefix) && self.allows(name)) {
+            files.insert(full_file_name.clone(), self.read_file(full_file_name)?);
+        }
+        Ok(files)
+    }
+}
@@ -0,0 +1,176 @@
+use name::EntryName;
+use raw::{parse_header, Chunk, Info, Offsets, Property, EXTENDED_INFO_LEN, HEADER, INFO_LEN};
+use read::set_file_name;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use verify::{Finding, Report, Severity};
+
+/// Checks the structure of the fragment `path`, returning the path of the
+/// fragment it links to.
+fn check_fragment(path: &Path, initial: &Path, report: &mut Report) -> io::Result<Option<PathBuf>> {
+    let fragment = path.display();
+    let mut file = File::open(path)?;
+    let len = file.seek(SeekFrom::End(0))?;
+    let min_len = (HEADER.len() + INFO_LEN) as u64;
+    if len < min_len {
+        report.push(Finding::new(
+            Severity::Error,
+            format!("\"{}\": {} bytes, too short to hold a header and an `Info`", fragment, len)));
+        return Ok(None);
+    }
+
+    let mut header = [0; 2];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut header)?;
+    let offsets = match parse_header(&header) {
+        Ok(offsets) => offsets,
+        Err(err) => {
+            report.push(Finding::new(Severity::Error, format!("\"{}\": {}", fragment, err))
+                .suggestion("check that the file is a pak file"));
+            Offsets::Classic
+        }
+    };
+    let info_len = offsets.info_len() as u64;
+    if len < HEADER.len() as u64 + info_len {
+        report.push(Finding::new(
+            Severity::Error,
+            format!("\"{}\": {} bytes, too short to hold a header and an `Info`", fragment, len)));
+        return Ok(None);
+    }
+
+    let mut trailer = [0; EXTENDED_INFO_LEN];
+    file.seek(SeekFrom::Start(len - info_len))?;
+    file.read_exact(&mut trailer[..info_len as usize])?;
+    let info = match Info::parse_trailer(&trailer[..info_len as usize], offsets) {
+        Ok(info) => info,
+        Err(err) => {
+            report.push(Finding::new(Severity::Error, format!("\"{}\": `Info`: {}", fragment, err)));
+            return Ok(None);
+        }
+    };
+
+    // the layout: header, data, slack, chunk table, properties, `Info`
+    let tables_end = len - info_len;
+    let data_end = info.offset.checked_add(info.size as u64);
+    let mut bounds = Vec::new();
+    if info.offset < HEADER.len() as u64 || info.offset > tables_end {
+        bounds.push(format!("data offset {}", info.offset));
+    }
+    if info.size < 0 || data_end.is_none_or(|end| end > info.chunks_offset) {
+        bounds.push(format!("data size {}", info.size));
+    }
+    if info.chunks_offset > tables_end || info.chunks_count < 0 {
+        bounds.push(format!("chunk table {}x{}", info.chunks_offset, info.chunks_count));
+    }
+    if info.properties_offset < info.chunks_offset || info.properties_offset > tables_end || info.properties_count < 0 {
+        bounds.push(format!("properties {}x{}", info.properties_offset, info.properties_count));
+    }
+    if !bounds.is_empty() {
+        report.push(Finding::new(
+            Severity::Error,
+            format!("\"{}\": `Info` out of the fragment of {} bytes: {}", fragment, len, bounds.join(", ")))
+            .suggestion("restore the fragment from an intact copy"));
+        return Ok(None);
+    }
+
+    // the tables are parsed from memory, their offsets rebased
+    let mut tables = vec![0; (tables_end - info.chunks_offset) as usize];
+    file.seek(SeekFrom::Start(info.chunks_offset))?;
+    file.read_exact(&mut tables)?;
+    let rebased = Info {
+        chunks_offset: 0,
+        properties_offset: info.properties_offset - info.chunks_offset,
+        ..info.clone()
+    };
+
+    match Chunk::parse_all(&tables, &rebased) {
+        Ok(chunks) => {
+            let data_end = info.offset + info.size as u64;
+            let mut names = HashSet::with_capacity(chunks.len());
+            for chunk in chunks {
+                let name = chunk.full_file_name.as_str();
+                if let Err(err) = EntryName::new(name) {
+                    report.push(Finding::new(Severity::Warning, format!("\"{}\": {}", fragment, err))
+                        .entry(name));
+                }
+                if !names.insert(chunk.full_file_name.clone()) {
+                    report.push(Finding::new(Severity::Warning, format!("\"{}\": duplicated in the fragment", fragment))
+                        .entry(name));
+                }
+
+                let end = info.offset.checked_add(chunk.offset as u64)
+                    .and_then(|start| start.checked_add(chunk.size as u64))
+                    .filter(|_| chunk.offset >= 0 && chunk.size >= 0);
+                let problem = match end {
+                    Some(end) if end <= data_end => continue,
+                    Some(end) if end <= info.chunks_offset => format!("exceeds the data size {}", info.size),
+                    Some(end) if end <= len => "overlaps the tables".to_string(),
+                    _ => format!("exceeds the fragment size {}", len),
+                };
+                report.push(Finding::new(
+                    Severity::Error,
+                    format!("\"{}\": data range {}+{} {}", fragment, chunk.offset, chunk.size, problem))
+                    .entry(name)
+                    .suggestion("repack the archive from an intact copy"));
+            }
+        }
+        Err(err) => {
+            report.push(Finding::new(Severity::Error, format!("\"{}\": chunk table: {}", fragment, err))
+                .suggestion("restore the fragment from an intact copy"));
+        }
+    }
+
+    match Property::parse_all(&tables, &rebased) {
+        Ok(properties) => {
+            let link = properties.into_iter().find(|property| property.key == "link");
+            Ok(link.and_then(|link| match set_file_name(initial, &link.value) {
+                Some(next) => Some(next),
+                None => {
+                    report.push(Finding::new(Severity::Error, format!("\"{}\": invalid link \"{}\"", fragment, link.value)));
+                    None
+                }
+            }))
+        }
+        Err(err) => {
+            report.push(Finding::new(Severity::Error, format!("\"{}\": properties: {}", fragment, err))
+                .suggestion("restore the fragment from an intact copy"));
+            Ok(None)
+        }
+    }
+}
+
+/// Checks the structure of the pak file `path` and of the fragments it
+/// links to, without opening it as a `MergeReader`: a corrupted archive is
+/// reported instead of failing to open. Checks the header, that the `Info`
+/// and the chunk ranges are within the fragment and don't overlap the
+/// tables, that the tables and the names can be parsed, and that every
+/// linked fragment exists without cycle.
+///
+/// Fails only if `path` itself can't be read. The data isn't read, see
+/// `MergeReader::verify_with`.
+pub fn fsck<P: AsRef<Path>>(path: P) -> io::Result<Report> {
+    let initial = path.as_ref();
+    let mut report = Report::new();
+    let mut visited = HashSet::new();
+    let mut next = Some(initial.to_path_buf());
+    while let Some(path) = next.take() {
+        if !visited.insert(path.clone()) {
+            report.push(Finding::new(Severity::Error, format!("the last fragment links back to \"{}\"", path.display()))
+                .suggestion("re-link the chain"));
+            break;
+        }
+        next = match check_fragment(&path, initial, &mut report) {
+            Ok(next) => next,
+            Err(err) if path.as_path() == initial => return Err(err),
+            Err(err) => {
+                report.push(Finding::new(Severity::Error, format!("can't read the linked fragment \"{}\": {}", path.display(), err))
+                    .suggestion("restore the missing fragment or truncate the chain"));
+                None
+            }
+        };
+    }
+    Ok(report)
+}
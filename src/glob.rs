@@ -0,0 +1,70 @@
+/// Pattern
+///
+/// Glob pattern matched against full file names:
+///
+/// - `?` matches any character but `/`
+/// - `*` matches any sequence of characters without `/`
+/// - `**` matches any sequence of characters, `a/**/b` also matches `a/b`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Pattern {
+    pattern: String,
+    chars: Vec<char>,
+}
+
+impl Pattern {
+    /// Creates a new `Pattern`.
+    pub fn new(pattern: &str) -> Self {
+        Pattern {
+            pattern: pattern.to_string(),
+            chars: pattern.chars().collect(),
+        }
+    }
+
+    /// The pattern as given.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Leading part of the pattern without any wildcard: every matching
+    /// name starts with it.
+    pub fn literal_prefix(&self) -> &str {
+        let end = self.pattern.find(['*', '?']).unwrap_or(self.pattern.len());
+        &self.pattern[..end]
+    }
+
+    /// Returns `true` if `name` matches the pattern.
+    pub fn matches(&self, name: &str) -> bool {
+        let name: Vec<char> = name.chars().collect();
+        match_from(&self.chars, &name)
+    }
+}
+
+fn match_from(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some(&'*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            if rest.first() == Some(&'/') && match_from(&rest[1..], name) {
+                return true;
+            }
+            (0..name.len() + 1).any(|i| match_from(rest, &name[i..]))
+        }
+        Some(&'*') => {
+            for i in 0..name.len() + 1 {
+                if match_from(&pattern[1..], &name[i..]) {
+                    return true;
+                }
+                if i < name.len() && name[i] == '/' {
+                    break;
+                }
+            }
+            false
+        }
+        Some(&'?') => {
+            !name.is_empty() && name[0] != '/' && match_from(&pattern[1..], &name[1..])
+        }
+        Some(&c) => {
+            !name.is_empty() && name[0] == c && match_from(&pattern[1..], &name[1..])
+        }
+    }
+}
@@ -0,0 +1,372 @@
+use fnv::FnvHasher;
+use read::{MergedChunk, MergeReader};
+use std::collections::HashMap;
+use std::collections::hash_map::Iter;
+use std::fmt;
+use std::hash::Hasher;
+use std::io;
+use std::io::{Read, Seek};
+
+/// Digest
+///
+/// Digest of a content, its length depends on the `HashAlgorithm` used.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Digest {
+    bytes: Vec<u8>,
+}
+
+impl Digest {
+    /// Creates a new `Digest` from its raw bytes.
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Digest { bytes }
+    }
+
+    /// Parses a `Digest` from its hexadecimal representation.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+            return None;
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()
+            .map(Digest::new)
+    }
+
+    /// Raw bytes of the `Digest`.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Hexadecimal representation of the `Digest`.
+    pub fn to_hex(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in &self.bytes {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// HashState
+///
+/// Digest being computed incrementally.
+pub trait HashState {
+    /// Feeds `data`.
+    fn update(&mut self, data: &[u8]);
+
+    /// Computes the `Digest` of the data fed so far.
+    fn finish(self: Box<Self>) -> Digest;
+}
+
+/// HashBackend
+///
+/// Digest algorithm used by the verification and deduplication features.
+/// Implemented by `HashAlgorithm`, and by custom backends.
+pub trait HashBackend {
+    /// Name of the algorithm, e.g. `"sha256"`.
+    fn name(&self) -> &str;
+
+    /// Starts computing a digest.
+    fn start(&self) -> Box<dyn HashState>;
+
+    /// Returns `true` if the digests resist to collision attacks.
+    fn is_cryptographic(&self) -> bool {
+        false
+    }
+
+    /// Computes the `Digest` of `data`.
+    fn digest(&self, data: &[u8]) -> Digest {
+        let mut state = self.start();
+        state.update(data);
+        state.finish()
+    }
+}
+
+/// HashAlgorithm
+///
+/// The built-in `HashBackend`s. `Xxh3`, `Blake3` and `Sha256` are available
+/// with the `xxh3`, `blake3` and `sha256` features respectively.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    /// CRC-32 (IEEE), 4 bytes
+    Crc32,
+    /// FNV-1a, 8 bytes
+    Fnv64,
+    /// XXH3, 8 bytes
+    #[cfg(feature = "xxh3")]
+    Xxh3,
+    /// BLAKE3, 32 bytes
+    #[cfg(feature = "blake3")]
+    Blake3,
+    /// SHA-256, 32 bytes
+    #[cfg(feature = "sha256")]
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Computes the `Digest` of `data`.
+    pub fn digest(&self, data: &[u8]) -> Digest {
+        HashBackend::digest(self, data)
+    }
+
+    /// Finds the algorithm named `name`, as returned by `HashBackend::name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "crc32" => Some(HashAlgorithm::Crc32),
+            "fnv64" => Some(HashAlgorithm::Fnv64),
+            #[cfg(feature = "xxh3")]
+            "xxh3" => Some(HashAlgorithm::Xxh3),
+            #[cfg(feature = "blake3")]
+            "blake3" => Some(HashAlgorithm::Blake3),
+            #[cfg(feature = "sha256")]
+            "sha256" => Some(HashAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+}
+
+impl HashBackend for HashAlgorithm {
+    fn name(&self) -> &str {
+        match *self {
+            HashAlgorithm::Crc32 => "crc32",
+            HashAlgorithm::Fnv64 => "fnv64",
+            #[cfg(feature = "xxh3")]
+            HashAlgorithm::Xxh3 => "xxh3",
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => "blake3",
+            #[cfg(feature = "sha256")]
+            HashAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    fn start(&self) -> Box<dyn HashState> {
+        match *self {
+            HashAlgorithm::Crc32 => Box::new(Crc32State::new()),
+            HashAlgorithm::Fnv64 => Box::new(FnvHasher::default()),
+            #[cfg(feature = "xxh3")]
+            HashAlgorithm::Xxh3 => Box::new(::xxhash_rust::xxh3::Xxh3::new()),
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => Box::new(::blake3::Hasher::new()),
+            #[cfg(feature = "sha256")]
+            HashAlgorithm::Sha256 => {
+                use sha2::Digest;
+                Box::new(::sha2::Sha256::new())
+            }
+        }
+    }
+
+    fn is_cryptographic(&self) -> bool {
+        match *self {
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => true,
+            #[cfg(feature = "sha256")]
+            HashAlgorithm::Sha256 => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Incremental CRC-32 (IEEE) checksum.
+struct Crc32State {
+    table: [u32; 256],
+    crc: u32,
+}
+
+impl Crc32State {
+    fn new() -> Self {
+        let mut table = [0u32; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut crc = i as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            }
+            *entry = crc;
+        }
+        Crc32State { table, crc: !0 }
+    }
+}
+
+impl HashState for Crc32State {
+    fn update(&mut self, data: &[u8]) {
+        let table = &self.table;
+        self.crc = data.iter().fold(self.crc, |crc, &byte| {
+            table[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+        });
+    }
+
+    fn finish(self: Box<Self>) -> Digest {
+        Digest::new((!self.crc).to_be_bytes().to_vec())
+    }
+}
+
+impl HashState for FnvHasher {
+    fn update(&mut self, data: &[u8]) {
+        self.write(data);
+    }
+
+    fn finish(self: Box<Self>) -> Digest {
+        Digest::new(Hasher::finish(&*self).to_be_bytes().to_vec())
+    }
+}
+
+#[cfg(feature = "xxh3")]
+impl HashState for ::xxhash_rust::xxh3::Xxh3 {
+    fn update(&mut self, data: &[u8]) {
+        ::xxhash_rust::xxh3::Xxh3::update(self, data);
+    }
+
+    fn finish(self: Box<Self>) -> Digest {
+        Digest::new(self.digest().to_be_bytes().to_vec())
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl HashState for ::blake3::Hasher {
+    fn update(&mut self, data: &[u8]) {
+        ::blake3::Hasher::update(self, data);
+    }
+
+    fn finish(self: Box<Self>) -> Digest {
+        Digest::new(self.finalize().as_bytes().to_vec())
+    }
+}
+
+#[cfg(feature = "sha256")]
+impl HashState for ::sha2::Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        ::sha2::Digest::update(self, data);
+    }
+
+    fn finish(self: Box<Self>) -> Digest {
+        Digest::new(::sha2::Digest::finalize(*self).to_vec())
+    }
+}
+
+/// HashIndex
+///
+/// Index of the entries of an archive by the digest of their content.
+#[derive(Clone, Debug)]
+pub struct HashIndex {
+    algorithm: HashAlgorithm,
+    names: HashMap<Digest, Vec<String>>,
+}
+
+impl HashIndex {
+    /// Creates a new `HashIndex`, sorting the names of each digest.
+    pub(crate) fn new(algorithm: HashAlgorithm, mut names: HashMap<Digest, Vec<String>>) -> Self {
+        for names in names.values_mut() {
+            names.sort();
+        }
+        HashIndex { algorithm, names }
+    }
+
+    /// Algorithm used to compute the digests.
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Full file names (sorted) of the entries whose content has the
+    /// specified digest.
+    pub fn find_by_hash(&self, digest: &Digest) -> &[String] {
+        self.names.get(digest).map_or(&[], |names| names.as_slice())
+    }
+
+    /// Full file names of the entries having the same content as `data`.
+    pub fn find_by_content(&self, data: &[u8]) -> &[String] {
+        self.find_by_hash(&self.algorithm.digest(data))
+    }
+
+    /// Number of distinct digests.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns `true` if the index contains no digest.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Iterates over the digests and their full file names.
+    pub fn iter(&self) -> Iter<'_, Digest, Vec<String>> {
+        self.names.iter()
+    }
+}
+
+/// Size of the blocks in which the data is hashed.
+const BLOCK_SIZE: usize = 1 << 16;
+
+impl<R> MergedChunk<R>
+where
+    R: Read + Seek
+{
+    /// Computes the `Digest` of the data as stored with `backend`, reading
+    /// it in blocks.
+    pub fn digest<H: HashBackend + ?Sized>(&self, backend: &H) -> io::Result<Digest> {
+        let mut state = backend.start();
+        for block in self.stored_iter(BLOCK_SIZE) {
+            state.update(&block?);
+        }
+        Ok(state.finish())
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Reads every entry and indexes it by the digest of its content.
+    pub fn build_hash_index(&self, algorithm: HashAlgorithm) -> io::Result<HashIndex> {
+        let mut names: HashMap<Digest, Vec<String>> = HashMap::new();
+
+        for (full_file_name, chunk) in self.iter() {
+            names.entry(algorithm.digest(&chunk.data()?))
+                .or_default()
+                .push(full_file_name.clone());
+        }
+
+        Ok(HashIndex::new(algorithm, names))
+    }
+
+    /// Computes a canonical digest of the logical content of the archive:
+    /// the names and the data of the entries in the byte order of their
+    /// names, independently of the fragments, their order and the layout of
+    /// the data. Two archives with the same entries have the same digest.
+    ///
+    /// Every entry is hashed as:
+    ///
+    /// ``` text
+    ///     name length        8 bytes (big-endian)
+    ///     name               utf8
+    ///     data length        8 bytes (big-endian)
+    ///     data
+    /// ```
+    ///
+    /// Use a cryptographic algorithm (`Blake3` or `Sha256`) to sign releases.
+    pub fn chain_digest<H: HashBackend + ?Sized>(&self, backend: &H) -> io::Result<Digest> {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut state = backend.start();
+        for (full_file_name, chunk) in entries {
+            state.update(&(full_file_name.len() as u64).to_be_bytes());
+            state.update(full_file_name.as_bytes());
+            state.update(&chunk.size().to_be_bytes());
+            for block in chunk.stored_iter(BLOCK_SIZE) {
+                state.update(&block?);
+            }
+        }
+        Ok(state.finish())
+    }
+}
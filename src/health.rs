@@ -0,0 +1,102 @@
+use chain::FragmentChain;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+use verify::{Finding, Severity};
+
+/// Share of wasted data area from which a repack is suggested, in percent.
+const WASTE_THRESHOLD: u64 = 10;
+
+/// Health
+///
+/// Summary of the state of an archive returned by `health`: a score and the
+/// findings of the chain checks, the verification of the entries and the
+/// analysis of the wasted space, most severe first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Health {
+    /// Score from 0 (unreadable) to 100 (nothing to fix)
+    pub score: u8,
+    /// Findings, sorted by decreasing severity
+    pub findings: Vec<Finding>,
+    /// Bytes of the data areas no entry refers to: overridden entries and
+    /// gaps left by the edits
+    pub wasted: u64,
+    /// Total size of the fragment files
+    pub total_size: u64,
+}
+
+impl Health {
+    /// Returns `true` if no finding is an error.
+    pub fn is_ok(&self) -> bool {
+        self.findings.iter().all(|finding| finding.severity < Severity::Error)
+    }
+
+    /// Share of the total size wasted, in percent.
+    pub fn wasted_percent(&self) -> u64 {
+        (self.wasted * 100).checked_div(self.total_size).unwrap_or(0)
+    }
+}
+
+/// Checks the archive `path`: its chain of fragments, its entries (without
+/// reading their data) and the space wasted in its fragments.
+///
+/// The score starts at 100 and loses 40 points per error, 10 per warning,
+/// and half the percentage of wasted space.
+pub fn health<P: AsRef<Path>>(path: P) -> io::Result<Health> {
+    FragmentChain::open(path)?.health()
+}
+
+impl FragmentChain {
+    /// Checks the archive of the chain, see `health`.
+    pub fn health(&self) -> io::Result<Health> {
+        let report = self.validate();
+        let linked = report.is_ok();
+        let mut findings = report.findings;
+
+        let mut wasted = 0;
+        if linked {
+            let reader = self.reader()?;
+            findings.extend(reader.verify().findings);
+
+            // the entries sharing their data are only counted once
+            let mut used = HashSet::new();
+            let live: u64 = reader.iter()
+                .filter(|&(_, chunk)| used.insert((chunk.fragment(), chunk.offset(), chunk.size())))
+                .map(|(_, chunk)| chunk.size())
+                .sum();
+            let data_size: u64 = self.fragments().iter().map(|fragment| fragment.data_size).sum();
+            wasted = data_size.saturating_sub(live);
+        }
+
+        let mut health = Health {
+            score: 0,
+            findings,
+            wasted,
+            total_size: self.total_size(),
+        };
+
+        let wasted_percent = health.wasted_percent();
+        if wasted_percent >= WASTE_THRESHOLD {
+            health.findings.push(Finding::new(
+                Severity::Warning,
+                format!("{}% wasted space ({} bytes)", wasted_percent, health.wasted))
+                .suggestion("run repack"));
+        }
+
+        let mut score = 100i64 - (wasted_percent / 2) as i64;
+        for finding in &health.findings {
+            score -= match finding.severity {
+                Severity::Error => 40,
+                Severity::Warning => 10,
+                Severity::Info => 0,
+            };
+        }
+        health.score = score.max(0) as u8;
+        // stable: the findings of a severity keep their order
+        health.findings.sort_by_key(|finding| Reverse(finding.severity));
+
+        Ok(health)
+    }
+}
@@ -0,0 +1,119 @@
+use archive::{Archive, Staged};
+
+/// Change of the staged state of an `Archive`, holding what it replaced.
+#[derive(Clone, Debug)]
+pub(crate) enum Edit {
+    /// Staged content of an entry, `None` if it wasn't staged
+    Entry {
+        name: String,
+        previous: Option<Staged>,
+    },
+    /// Value of a property, `None` if it wasn't set
+    Property {
+        key: String,
+        previous: Option<String>,
+        properties_changed: bool,
+    },
+}
+
+/// Staged changes which can be undone and redone.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl History {
+    /// Forgets every change, after a commit or a discard.
+    pub(crate) fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+impl Archive {
+    /// Stages `staged` as the content of the entry `name` (unstages it if
+    /// `None`), recording the change.
+    pub(crate) fn stage(&mut self, name: String, staged: Option<Staged>) {
+        let edit = self.apply_edit(Edit::Entry { name, previous: staged });
+        self.history.undo.push(edit);
+        self.history.redo.clear();
+    }
+
+    /// Sets the property `key` (removes it if `None`), recording the change
+    /// and returning the previous value.
+    pub(crate) fn stage_property(&mut self, key: String, value: Option<String>) -> Option<String> {
+        let edit = self.apply_edit(Edit::Property {
+            key,
+            previous: value,
+            properties_changed: true,
+        });
+        let previous = match edit {
+            Edit::Property { ref previous, .. } => previous.clone(),
+            Edit::Entry { .. } => None,
+        };
+        self.history.undo.push(edit);
+        self.history.redo.clear();
+        previous
+    }
+
+    /// Restores the state held by `edit`, returning the edit restoring the
+    /// current state.
+    fn apply_edit(&mut self, edit: Edit) -> Edit {
+        match edit {
+            Edit::Entry { name, previous } => {
+                let current = match previous {
+                    Some(staged) => self.staged.insert(name.clone(), staged),
+                    None => self.staged.remove(&name),
+                };
+                Edit::Entry { name, previous: current }
+            }
+            Edit::Property { key, previous, properties_changed } => {
+                let current = match previous {
+                    Some(value) => self.properties.insert(key.clone(), value),
+                    None => self.properties.remove(&key),
+                };
+                let changed = self.properties_changed;
+                self.properties_changed = properties_changed;
+                Edit::Property { key, previous: current, properties_changed: changed }
+            }
+        }
+    }
+
+    /// Undoes the last staged change (addition, replacement, removal or
+    /// property change) since the archive was opened, committed or
+    /// discarded. Returns `false` if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        match self.history.undo.pop() {
+            Some(edit) => {
+                let edit = self.apply_edit(edit);
+                self.history.redo.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Redoes the last undone change. Returns `false` if there is nothing to
+    /// redo: staging a new change forgets the undone ones.
+    pub fn redo(&mut self) -> bool {
+        match self.history.redo.pop() {
+            Some(edit) => {
+                let edit = self.apply_edit(edit);
+                self.history.undo.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `true` if a change can be undone.
+    pub fn can_undo(&self) -> bool {
+        !self.history.undo.is_empty()
+    }
+
+    /// Returns `true` if a change can be redone.
+    pub fn can_redo(&self) -> bool {
+        !self.history.redo.is_empty()
+    }
+}
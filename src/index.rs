@@ -0,0 +1,362 @@
+use byteorder_extended::{ReadExt, WriteExt};
+use catalog::{find_heads, CatalogChanges};
+use name::lookup_key;
+use raw::{read_u64, write_u64};
+use read::{MergeReader, set_file_name};
+use sidecar::FragmentStamp;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Magic bytes starting a saved `PakIndex`.
+static INDEX_MAGIC: &[u8; 4] = b"PAKX";
+
+/// Version of the `PakIndex` format.
+const INDEX_VERSION: u8 = 1;
+
+/// IndexedArchive
+///
+/// Archive of a `PakIndex`, with the state of its fragments when it was
+/// indexed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexedArchive {
+    /// First fragment of the archive
+    pub path: PathBuf,
+    /// Fragments in the order they are merged
+    pub fragments: Vec<FragmentStamp>,
+}
+
+impl IndexedArchive {
+    /// Indexes the archive `path`, returning it with its entries sorted by
+    /// full file name.
+    fn open(path: PathBuf, archive: usize) -> io::Result<(Self, Vec<IndexEntry>)> {
+        let reader = MergeReader::open(&path)?;
+        let fragments = reader.fragments().iter()
+            .map(FragmentStamp::of)
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut entries: Vec<IndexEntry> = reader.iter()
+            .map(|(full_file_name, chunk)| IndexEntry {
+                full_file_name: full_file_name.clone(),
+                archive,
+                fragment: chunk.fragment(),
+                offset: chunk.offset(),
+                size: chunk.size(),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.full_file_name.cmp(&b.full_file_name));
+        Ok((IndexedArchive { path, fragments }, entries))
+    }
+
+    /// Returns `true` if no fragment changed since the archive was indexed.
+    pub fn is_fresh(&self) -> bool {
+        !self.fragments.is_empty() && self.fragments.iter().enumerate().all(|(i, stamp)| {
+            let path = if i == 0 {
+                Some(self.path.clone())
+            } else {
+                set_file_name(&self.path, &stamp.file_name)
+            };
+            path.is_some_and(|path| FragmentStamp::of(path).ok().as_ref() == Some(stamp))
+        })
+    }
+}
+
+/// IndexEntry
+///
+/// Entry of a `PakIndex`: where its data is, without opening the archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Full file name
+    pub full_file_name: String,
+    /// Index of the archive, see `PakIndex::archives`
+    pub archive: usize,
+    /// Index of the fragment containing the data in the archive
+    pub fragment: usize,
+    /// Offset of the data in its fragment
+    pub offset: u64,
+    /// Size of the data, as stored
+    pub size: u64,
+}
+
+/// PakIndex
+///
+/// Index of the entries of many archives, typically of a whole game
+/// installation, telling which archive holds an entry without opening any.
+/// It is built once from the archives (`PakIndex::build_dir`), saved to
+/// disk, then loaded by the next runs; `refresh` indexes again the archives
+/// modified meanwhile only.
+///
+/// An entry present in several archives is listed once per archive, in the
+/// order the archives were added.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PakIndex {
+    /// Directory the archives were discovered in, if built from one
+    root: Option<PathBuf>,
+    archives: Vec<IndexedArchive>,
+    /// Sorted by full file name, then by archive
+    entries: Vec<IndexEntry>,
+}
+
+impl PakIndex {
+    /// Creates a new empty `PakIndex`.
+    pub fn new() -> Self {
+        PakIndex::default()
+    }
+
+    /// Indexes every archive chain of the directory `root`, recursively.
+    pub fn build_dir<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        let mut index = PakIndex {
+            root: Some(root.as_ref().to_path_buf()),
+            ..PakIndex::default()
+        };
+        for head in find_heads(root.as_ref())? {
+            index.add_archive(head)?;
+        }
+        Ok(index)
+    }
+
+    /// Indexes the archive `loc` (and the fragments it links to), indexing
+    /// it again if it already is.
+    pub fn add_archive<P: AsRef<Path>>(&mut self, loc: P) -> io::Result<&mut Self> {
+        let loc = loc.as_ref();
+        let archive = self.archives.iter().position(|archive| archive.path == loc).unwrap_or(self.archives.len());
+        let (indexed, entries) = IndexedArchive::open(loc.to_path_buf(), archive)?;
+        if archive == self.archives.len() {
+            self.archives.push(indexed);
+        } else {
+            self.archives[archive] = indexed;
+            self.entries.retain(|entry| entry.archive != archive);
+        }
+        self.entries.extend(entries);
+        self.sort();
+        Ok(self)
+    }
+
+    fn sort(&mut self) {
+        self.entries.sort_by(|a, b| a.full_file_name.cmp(&b.full_file_name).then(a.archive.cmp(&b.archive)));
+    }
+
+    /// Directory the archives were discovered in, `None` if they were added
+    /// one by one.
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// Archives indexed, in the order they were added.
+    pub fn archives(&self) -> &[IndexedArchive] {
+        &self.archives
+    }
+
+    /// Every entry, sorted by full file name.
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if no entry is indexed.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries named `full_file_name`, one per archive containing it.
+    fn named(&self, full_file_name: &str) -> &[IndexEntry] {
+        let full_file_name = lookup_key(full_file_name);
+        let start = self.entries.partition_point(|entry| entry.full_file_name.as_str() < &*full_file_name);
+        let len = self.entries[start..].iter()
+            .take_while(|entry| entry.full_file_name == *full_file_name)
+            .count();
+        &self.entries[start..start + len]
+    }
+
+    /// Finds the entry `full_file_name` in the first archive containing it.
+    pub fn lookup(&self, full_file_name: &str) -> Option<(&Path, &IndexEntry)> {
+        self.named(full_file_name).first()
+            .map(|entry| (self.archives[entry.archive].path.as_path(), entry))
+    }
+
+    /// Every archive containing the entry `full_file_name`.
+    pub fn archives_containing(&self, full_file_name: &str) -> Vec<&Path> {
+        self.named(full_file_name).iter()
+            .map(|entry| self.archives[entry.archive].path.as_path())
+            .collect()
+    }
+
+    /// Entries whose full file name starts with `prefix`, sorted by full
+    /// file name. `gfx/items/` lists a directory.
+    pub fn with_prefix(&self, prefix: &str) -> Vec<(&Path, &IndexEntry)> {
+        let start = self.entries.partition_point(|entry| entry.full_file_name.as_str() < prefix);
+        self.entries[start..].iter()
+            .take_while(|entry| entry.full_file_name.starts_with(prefix))
+            .map(|entry| (self.archives[entry.archive].path.as_path(), entry))
+            .collect()
+    }
+
+    /// Opens the archive holding the entry `full_file_name` and reads it.
+    pub fn read_file(&self, full_file_name: &str) -> io::Result<Vec<u8>> {
+        let (path, entry) = self.lookup(full_file_name).ok_or_else(|| Error::new(
+            ErrorKind::NotFound,
+            format!("\"{}\" is in no indexed archive", full_file_name)
+        ))?;
+        MergeReader::open(path)?.read_file(&entry.full_file_name)
+    }
+
+    /// Returns `true` if no archive changed since it was indexed. The new
+    /// archives of the `root` aren't looked for.
+    pub fn is_fresh(&self) -> bool {
+        self.archives.iter().all(IndexedArchive::is_fresh)
+    }
+
+    /// Indexes again the archives modified since they were indexed, drops
+    /// the ones no longer present and, if the index was built from a
+    /// directory, indexes its new archives.
+    pub fn refresh(&mut self) -> io::Result<CatalogChanges> {
+        let mut changes = CatalogChanges::default();
+        let heads = match self.root {
+            Some(ref root) => find_heads(root)?,
+            None => self.archives.iter()
+                .filter(|archive| archive.path.is_file())
+                .map(|archive| archive.path.clone())
+                .collect(),
+        };
+
+        // index everything first so that the index is left untouched on error
+        let mut archives = Vec::with_capacity(heads.len());
+        let mut entries = Vec::new();
+        for head in heads {
+            let previous = self.archives.iter().position(|archive| archive.path == head);
+            match previous {
+                Some(i) if self.archives[i].is_fresh() => {
+                    archives.push(self.archives[i].clone());
+                    entries.extend(self.entries.iter()
+                        .filter(|entry| entry.archive == i)
+                        .map(|entry| IndexEntry { archive: archives.len() - 1, ..entry.clone() }));
+                    continue;
+                }
+                Some(_) => changes.updated.push(head.clone()),
+                None => changes.added.push(head.clone()),
+            }
+            let (indexed, indexed_entries) = IndexedArchive::open(head, archives.len())?;
+            archives.push(indexed);
+            entries.extend(indexed_entries);
+        }
+
+        changes.removed = self.archives.iter()
+            .filter(|previous| !archives.iter().any(|archive| archive.path == previous.path))
+            .map(|previous| previous.path.clone())
+            .collect();
+        changes.removed.sort();
+        self.archives = archives;
+        self.entries = entries;
+        self.sort();
+        Ok(changes)
+    }
+
+    /// Reads a `PakIndex` from the specified reader.
+    pub fn from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != INDEX_MAGIC || reader.read_u8()? != INDEX_VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, "Corrupted pak index header"));
+        }
+        let root = match reader.read_u8()? {
+            0 => None,
+            _ => Some(PathBuf::from(reader.read_string()?)),
+        };
+
+        let archives_count = reader.read_i32()?;
+        let mut archives = Vec::new();
+        for _ in 0..archives_count {
+            let path = PathBuf::from(reader.read_string()?);
+            let fragments_count = reader.read_i32()?;
+            let mut fragments = Vec::new();
+            for _ in 0..fragments_count {
+                let file_name = reader.read_string()?;
+                let size = read_u64(reader)?;
+                let modified = read_u64(reader)?;
+                fragments.push(FragmentStamp { file_name, size, modified });
+            }
+            archives.push(IndexedArchive { path, fragments });
+        }
+
+        let entries_count = reader.read_i32()?;
+        let mut entries = Vec::new();
+        for _ in 0..entries_count {
+            let full_file_name = reader.read_string()?;
+            let archive = reader.read_i32()? as usize;
+            let fragment = reader.read_i32()? as usize;
+            let offset = read_u64(reader)?;
+            let size = read_u64(reader)?;
+            if archive >= archives.len() || fragment >= archives[archive].fragments.len() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("pak index entry \"{}\" refers to an unknown fragment", full_file_name)
+                ));
+            }
+            entries.push(IndexEntry { full_file_name, archive, fragment, offset, size });
+        }
+
+        let mut index = PakIndex { root, archives, entries };
+        index.sort();
+        Ok(index)
+    }
+
+    /// Writes the `PakIndex` in the specified writer. The paths must be
+    /// valid UTF-8.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(INDEX_MAGIC)?;
+        writer.write_u8(INDEX_VERSION)?;
+        match self.root {
+            Some(ref root) => {
+                writer.write_u8(1)?;
+                writer.write_string(path_str(root)?)?;
+            }
+            None => writer.write_u8(0)?,
+        }
+
+        writer.write_i32(self.archives.len() as i32)?;
+        for archive in &self.archives {
+            writer.write_string(path_str(&archive.path)?)?;
+            writer.write_i32(archive.fragments.len() as i32)?;
+            for fragment in &archive.fragments {
+                writer.write_string(&fragment.file_name)?;
+                write_u64(writer, fragment.size)?;
+                write_u64(writer, fragment.modified)?;
+            }
+        }
+
+        writer.write_i32(self.entries.len() as i32)?;
+        for entry in &self.entries {
+            writer.write_string(&entry.full_file_name)?;
+            writer.write_i32(entry.archive as i32)?;
+            writer.write_i32(entry.fragment as i32)?;
+            write_u64(writer, entry.offset)?;
+            write_u64(writer, entry.size)?;
+        }
+
+        Ok(())
+    }
+
+    /// Loads a `PakIndex` from the file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        PakIndex::from(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Saves the `PakIndex` to the file at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+}
+
+/// `path` as a string, failing if it isn't valid UTF-8.
+fn path_str(path: &Path) -> io::Result<&str> {
+    path.to_str().ok_or_else(|| Error::new(
+        ErrorKind::InvalidInput,
+        format!("the path \"{}\" isn't valid UTF-8", path.display())
+    ))
+}
@@ -0,0 +1,526 @@
+//! Conversions between pak archives and the tar and zip formats.
+//!
+//! Available with the `tar` and `zip` features, so that standard tools
+//! inspect the content of an archive, and zip-based pipelines produce
+//! archives. The entries are written sorted by name, without timestamps,
+//! so that converting the same archive twice gives the same bytes. Only
+//! regular files are imported: the directories, links and other special
+//! members are skipped.
+
+#[cfg(feature = "zip")]
+use flate2::Compression;
+#[cfg(feature = "zip")]
+use flate2::read::DeflateDecoder;
+#[cfg(feature = "zip")]
+use flate2::write::DeflateEncoder;
+#[cfg(feature = "zip")]
+use hash::HashAlgorithm;
+use read::{MergedChunk, MergeReader};
+#[cfg(feature = "zip")]
+use source::HashingReader;
+use std::io;
+use std::io::{Error, ErrorKind, Read, Seek, Write};
+#[cfg(feature = "zip")]
+use std::io::SeekFrom;
+use std::path::Path;
+use write::PakWriter;
+
+/// Entries of `reader` to export, sorted by name, the ones denied by the
+/// `AccessPolicy` excluded.
+fn exported<R: Read + Seek>(reader: &MergeReader<R>) -> Vec<(&String, &MergedChunk<R>)> {
+    let mut entries: Vec<_> = reader.iter()
+        .filter(|&(full_file_name, _)| reader.allows(full_file_name))
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+/// Adds to `writer` the entry `full_file_name` of `size` bytes read from
+/// `data`, failing if `data` ends early.
+fn import<R: Read>(writer: &mut PakWriter, full_file_name: &str, data: R, size: u64) -> io::Result<()> {
+    let added = writer.add_reader(full_file_name, &mut data.take(size))?;
+    if added != size {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            format!("member \"{}\" truncated: {} bytes out of {}", full_file_name, added, size)
+        ));
+    }
+    Ok(())
+}
+
+/// Size of a tar block.
+#[cfg(feature = "tar")]
+const TAR_BLOCK: u64 = 512;
+
+/// Writes `value` in the octal field `field`, NUL-terminated.
+#[cfg(feature = "tar")]
+fn write_octal(field: &mut [u8], value: u64) {
+    let len = field.len() - 1;
+    let digits = format!("{:0width$o}", value, width = len);
+    field[..len].copy_from_slice(digits.as_bytes());
+}
+
+/// Reads the numeric field `field`, octal or base-256.
+#[cfg(feature = "tar")]
+fn read_number(field: &[u8]) -> io::Result<u64> {
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..].iter().fold(u64::from(field[0] & 0x7f), |value, &byte| value << 8 | u64::from(byte)));
+    }
+    let digits: Vec<u8> = field.iter()
+        .cloned()
+        .skip_while(|&byte| byte == b' ')
+        .take_while(|&byte| byte != 0 && byte != b' ')
+        .collect();
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    String::from_utf8(digits).ok()
+        .and_then(|digits| u64::from_str_radix(&digits, 8).ok())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid numeric field in a tar header"))
+}
+
+/// Reads the NUL-terminated string field `field`.
+#[cfg(feature = "tar")]
+fn read_field(field: &[u8]) -> io::Result<String> {
+    let len = field.iter().position(|&byte| byte == 0).unwrap_or(field.len());
+    String::from_utf8(field[..len].to_vec())
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "tar member name isn't valid UTF-8"))
+}
+
+/// Sum of the bytes of `header`, its checksum field counted as spaces.
+#[cfg(feature = "tar")]
+fn tar_checksum(header: &[u8; 512]) -> u64 {
+    header.iter().enumerate()
+        .map(|(i, &byte)| if (148..156).contains(&i) { u64::from(b' ') } else { u64::from(byte) })
+        .sum()
+}
+
+/// Writes the ustar header of a member of type `kind`.
+#[cfg(feature = "tar")]
+fn write_tar_header<W: Write>(out: &mut W, name: &str, prefix: &str, size: u64, kind: u8) -> io::Result<()> {
+    let mut header = [0u8; 512];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    write_octal(&mut header[100..108], 0o644);
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    write_octal(&mut header[124..136], size);
+    write_octal(&mut header[136..148], 0);
+    header[156] = kind;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+    let checksum = format!("{:06o}\0 ", tar_checksum(&header));
+    header[148..156].copy_from_slice(checksum.as_bytes());
+    out.write_all(&header)
+}
+
+/// Writes the zeros padding `size` bytes of data to a whole block.
+#[cfg(feature = "tar")]
+fn write_tar_padding<W: Write>(out: &mut W, size: u64) -> io::Result<()> {
+    let padding = (TAR_BLOCK - size % TAR_BLOCK) % TAR_BLOCK;
+    out.write_all(&[0; TAR_BLOCK as usize][..padding as usize])
+}
+
+/// Splits `full_file_name` into the name and prefix fields of a ustar
+/// header, `None` if it doesn't fit.
+#[cfg(feature = "tar")]
+fn split_ustar_name(full_file_name: &str) -> Option<(&str, &str)> {
+    if full_file_name.len() <= 100 {
+        return Some((full_file_name, ""));
+    }
+    full_file_name.match_indices('/')
+        .map(|(i, _)| (&full_file_name[i + 1..], &full_file_name[..i]))
+        .find(|&(name, prefix)| !name.is_empty() && name.len() <= 100 && prefix.len() <= 155)
+}
+
+/// PAX record `<len> path=<full_file_name>\n`, its length counting itself.
+#[cfg(feature = "tar")]
+fn pax_path_record(full_file_name: &str) -> String {
+    let body = format!(" path={}\n", full_file_name);
+    let mut len = body.len() + 1;
+    while len.to_string().len() + body.len() != len {
+        len = len.to_string().len() + body.len();
+    }
+    format!("{}{}", len, body)
+}
+
+/// Writes the entries of `reader` into the tar stream `out`, sorted by
+/// name. The names too long for a ustar header are stored in PAX headers.
+#[cfg(feature = "tar")]
+pub fn to_tar<R, W>(reader: &MergeReader<R>, mut out: W) -> io::Result<()>
+where
+    R: Read + Seek,
+    W: Write
+{
+    for (full_file_name, chunk) in exported(reader) {
+        let (name, prefix) = match split_ustar_name(full_file_name) {
+            Some(fields) => fields,
+            None => {
+                let record = pax_path_record(full_file_name);
+                write_tar_header(&mut out, "PaxHeader", "", record.len() as u64, b'x')?;
+                out.write_all(record.as_bytes())?;
+                write_tar_padding(&mut out, record.len() as u64)?;
+                ("", "")
+            }
+        };
+        write_tar_header(&mut out, name, prefix, chunk.uncompressed_size(), b'0')?;
+        let copied = io::copy(&mut chunk.decoded_reader()?, &mut out)?;
+        chunk.check_decoded(copied)?;
+        write_tar_padding(&mut out, copied)?;
+    }
+    // end of archive
+    out.write_all(&[0; 2 * TAR_BLOCK as usize])?;
+    out.flush()
+}
+
+/// Reads the `size` bytes of a member followed by their padding.
+#[cfg(feature = "tar")]
+fn read_tar_data<R: Read>(input: &mut R, size: u64) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    input.take(size).read_to_end(&mut data)?;
+    if data.len() as u64 != size {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "tar member truncated"));
+    }
+    skip(input, (TAR_BLOCK - size % TAR_BLOCK) % TAR_BLOCK)?;
+    Ok(data)
+}
+
+/// Skips `len` bytes of `input`.
+#[cfg(feature = "tar")]
+fn skip<R: Read>(input: &mut R, len: u64) -> io::Result<()> {
+    if io::copy(&mut input.take(len), &mut io::sink())? != len {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "tar stream truncated"));
+    }
+    Ok(())
+}
+
+/// Packs the regular files of the tar stream `input` into the new pak file
+/// `dest_pak`, returning the number of entries. The ustar, PAX and GNU
+/// long names are supported.
+#[cfg(feature = "tar")]
+pub fn from_tar<R: Read, P: AsRef<Path>>(mut input: R, dest_pak: P) -> io::Result<usize> {
+    let mut writer = PakWriter::create(dest_pak)?;
+    let mut entries = 0;
+    // name set by a PAX or GNU header for the next member
+    let mut long_name: Option<String> = None;
+
+    loop {
+        let mut header = [0u8; 512];
+        input.read_exact(&mut header)?;
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+        if read_number(&header[148..156])? != tar_checksum(&header) {
+            return Err(Error::new(ErrorKind::InvalidData, "tar header checksum mismatch"));
+        }
+
+        let size = read_number(&header[124..136])?;
+        match header[156] {
+            b'0' | 0 | b'7' => {
+                let full_file_name = match long_name.take() {
+                    Some(name) => name,
+                    None => {
+                        let name = read_field(&header[..100])?;
+                        let prefix = if &header[257..262] == b"ustar" { read_field(&header[345..500])? } else { String::new() };
+                        if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) }
+                    }
+                };
+                import(&mut writer, &full_file_name, &mut input, size)?;
+                skip(&mut input, (TAR_BLOCK - size % TAR_BLOCK) % TAR_BLOCK)?;
+                entries += 1;
+            }
+            b'x' => {
+                let records = read_tar_data(&mut input, size)?;
+                let records = String::from_utf8_lossy(&records);
+                for record in records.split('\n') {
+                    if let Some(path) = record.split_once(' ').and_then(|(_, record)| record.strip_prefix("path=")) {
+                        long_name = Some(path.to_string());
+                    }
+                }
+            }
+            b'L' => {
+                let name = read_tar_data(&mut input, size)?;
+                long_name = Some(read_field(&name)?);
+            }
+            _ => {
+                // directories, links, global headers...
+                skip(&mut input, size + (TAR_BLOCK - size % TAR_BLOCK) % TAR_BLOCK)?;
+                long_name = None;
+            }
+        }
+    }
+
+    writer.finish()?;
+    Ok(entries)
+}
+
+/// Signature of a zip local file header.
+#[cfg(feature = "zip")]
+const ZIP_LOCAL_HEADER: u32 = 0x0403_4b50;
+
+/// Signature of a zip data descriptor.
+#[cfg(feature = "zip")]
+const ZIP_DATA_DESCRIPTOR: u32 = 0x0807_4b50;
+
+/// Signature of a zip central directory header.
+#[cfg(feature = "zip")]
+const ZIP_CENTRAL_HEADER: u32 = 0x0201_4b50;
+
+/// Signature of the zip end of central directory record.
+#[cfg(feature = "zip")]
+const ZIP_END: u32 = 0x0605_4b50;
+
+/// Flags of the written members: sizes in a data descriptor, UTF-8 names.
+#[cfg(feature = "zip")]
+const ZIP_FLAGS: u16 = 0x0808;
+
+/// DOS date of the written members, 1980-01-01.
+#[cfg(feature = "zip")]
+const ZIP_DATE: u16 = 0x0021;
+
+/// Writer counting the bytes written into `inner`.
+#[cfg(feature = "zip")]
+struct Counting<W> {
+    inner: W,
+    count: u64,
+}
+
+#[cfg(feature = "zip")]
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Central directory record of a written member.
+#[cfg(feature = "zip")]
+struct ZipMember<'a> {
+    name: &'a str,
+    crc: u32,
+    compressed: u64,
+    size: u64,
+    offset: u64,
+}
+
+/// Value of a CRC32 digest.
+#[cfg(feature = "zip")]
+fn crc_value(digest: &[u8]) -> u32 {
+    digest.iter().fold(0, |crc, &byte| crc << 8 | u32::from(byte))
+}
+
+/// Adds to `writer` the member `name` of `size` bytes read from `data`,
+/// checking its CRC32.
+#[cfg(feature = "zip")]
+fn import_checked<D: Read>(writer: &mut PakWriter, name: &str, data: D, size: u64, crc: u32) -> io::Result<()> {
+    let mut data = HashingReader::new(data, HashAlgorithm::Crc32);
+    import(writer, name, &mut data, size)?;
+    if crc_value(data.finish().as_bytes()) != crc {
+        return Err(Error::new(ErrorKind::InvalidData, format!("zip member \"{}\" fails its CRC32 check", name)));
+    }
+    Ok(())
+}
+
+/// Fails if `value` doesn't fit in the 32 bits of a zip field, which would
+/// need zip64.
+#[cfg(feature = "zip")]
+fn zip_u32(value: u64) -> io::Result<u32> {
+    if value >= u64::from(u32::MAX) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "the archive is too large for a zip file without zip64"
+        ));
+    }
+    Ok(value as u32)
+}
+
+/// Writes the entries of `reader` into the zip file `out`, sorted by name
+/// and compressed with deflate. The sizes are written after the data, so
+/// `out` doesn't need to be seekable.
+#[cfg(feature = "zip")]
+pub fn to_zip<R, W>(reader: &MergeReader<R>, out: W) -> io::Result<()>
+where
+    R: Read + Seek,
+    W: Write
+{
+    let mut out = Counting { inner: out, count: 0 };
+    let entries = exported(reader);
+    if entries.len() >= usize::from(u16::MAX) {
+        return Err(Error::new(ErrorKind::InvalidInput, "too many entries for a zip file without zip64"));
+    }
+
+    let mut members = Vec::with_capacity(entries.len());
+    for (full_file_name, chunk) in entries {
+        let offset = out.count;
+        out.write_all(&ZIP_LOCAL_HEADER.to_le_bytes())?;
+        out.write_all(&20u16.to_le_bytes())?;
+        out.write_all(&ZIP_FLAGS.to_le_bytes())?;
+        out.write_all(&8u16.to_le_bytes())?;
+        out.write_all(&0u16.to_le_bytes())?;
+        out.write_all(&ZIP_DATE.to_le_bytes())?;
+        // crc and sizes in the data descriptor
+        out.write_all(&[0; 12])?;
+        out.write_all(&(full_file_name.len() as u16).to_le_bytes())?;
+        out.write_all(&0u16.to_le_bytes())?;
+        out.write_all(full_file_name.as_bytes())?;
+
+        let start = out.count;
+        let mut data = HashingReader::new(chunk.decoded_reader()?, HashAlgorithm::Crc32);
+        let mut encoder = DeflateEncoder::new(&mut out, Compression::default());
+        let size = io::copy(&mut data, &mut encoder)?;
+        encoder.finish()?;
+        chunk.check_decoded(size)?;
+        let crc = crc_value(data.finish().as_bytes());
+        let compressed = out.count - start;
+
+        out.write_all(&ZIP_DATA_DESCRIPTOR.to_le_bytes())?;
+        out.write_all(&crc.to_le_bytes())?;
+        out.write_all(&zip_u32(compressed)?.to_le_bytes())?;
+        out.write_all(&zip_u32(size)?.to_le_bytes())?;
+        members.push(ZipMember { name: full_file_name, crc, compressed, size, offset });
+    }
+
+    let directory_offset = out.count;
+    for member in &members {
+        out.write_all(&ZIP_CENTRAL_HEADER.to_le_bytes())?;
+        // made by: UNIX, version 2.0
+        out.write_all(&0x0314u16.to_le_bytes())?;
+        out.write_all(&20u16.to_le_bytes())?;
+        out.write_all(&ZIP_FLAGS.to_le_bytes())?;
+        out.write_all(&8u16.to_le_bytes())?;
+        out.write_all(&0u16.to_le_bytes())?;
+        out.write_all(&ZIP_DATE.to_le_bytes())?;
+        out.write_all(&member.crc.to_le_bytes())?;
+        out.write_all(&zip_u32(member.compressed)?.to_le_bytes())?;
+        out.write_all(&zip_u32(member.size)?.to_le_bytes())?;
+        out.write_all(&(member.name.len() as u16).to_le_bytes())?;
+        // extra field, comment, disk, internal attributes
+        out.write_all(&[0; 8])?;
+        // external attributes: regular file, rw-r--r--
+        out.write_all(&(0o100_644u32 << 16).to_le_bytes())?;
+        out.write_all(&zip_u32(member.offset)?.to_le_bytes())?;
+        out.write_all(member.name.as_bytes())?;
+    }
+    let directory_size = out.count - directory_offset;
+
+    out.write_all(&ZIP_END.to_le_bytes())?;
+    out.write_all(&[0; 4])?;
+    out.write_all(&(members.len() as u16).to_le_bytes())?;
+    out.write_all(&(members.len() as u16).to_le_bytes())?;
+    out.write_all(&zip_u32(directory_size)?.to_le_bytes())?;
+    out.write_all(&zip_u32(directory_offset)?.to_le_bytes())?;
+    out.write_all(&0u16.to_le_bytes())?;
+    out.flush()
+}
+
+/// Little-endian `u16` at `offset` of `bytes`.
+#[cfg(feature = "zip")]
+fn le_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+/// Little-endian `u32` at `offset` of `bytes`.
+#[cfg(feature = "zip")]
+fn le_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+/// Packs the files of the zip file `input` into the new pak file
+/// `dest_pak`, returning the number of entries. The stored and deflated
+/// members are supported, and their CRC32 is checked; the encrypted and
+/// zip64 files are refused.
+#[cfg(feature = "zip")]
+pub fn from_zip<R: Read + Seek, P: AsRef<Path>>(mut input: R, dest_pak: P) -> io::Result<usize> {
+    let invalid = |message: &str| Error::new(ErrorKind::InvalidData, format!("invalid zip file: {}", message));
+
+    // the end record is followed by a comment of at most 64 KiB
+    let len = input.seek(SeekFrom::End(0))?;
+    let tail_len = len.min(22 + 0xffff);
+    input.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = Vec::with_capacity(tail_len as usize);
+    input.by_ref().take(tail_len).read_to_end(&mut tail)?;
+    let end = tail.windows(4)
+        .rposition(|window| window == ZIP_END.to_le_bytes())
+        .filter(|&end| end + 22 <= tail.len())
+        .ok_or_else(|| invalid("no end of central directory"))?;
+    let count = le_u16(&tail, end + 10);
+    let directory_size = le_u32(&tail, end + 12);
+    let directory_offset = le_u32(&tail, end + 16);
+    if count == u16::MAX || directory_offset == u32::MAX {
+        return Err(Error::new(ErrorKind::InvalidData, "zip64 files aren't supported"));
+    }
+
+    // the sizes read are checked against the input before any allocation
+    if u64::from(directory_offset) + u64::from(directory_size) > len {
+        return Err(invalid("truncated central directory"));
+    }
+    input.seek(SeekFrom::Start(u64::from(directory_offset)))?;
+    let mut directory = Vec::with_capacity(directory_size as usize);
+    input.by_ref().take(u64::from(directory_size)).read_to_end(&mut directory)?;
+
+    // the whole directory is parsed before the pak file is created
+    let mut members = Vec::new();
+    let mut position = 0;
+    for _ in 0..count {
+        if position + 46 > directory.len() || le_u32(&directory, position) != ZIP_CENTRAL_HEADER {
+            return Err(invalid("truncated central directory"));
+        }
+        let header = &directory[position..];
+        let flags = le_u16(header, 8);
+        let method = le_u16(header, 10);
+        let crc = le_u32(header, 16);
+        let compressed = u64::from(le_u32(header, 20));
+        let size = u64::from(le_u32(header, 24));
+        let name_len = usize::from(le_u16(header, 28));
+        let skipped = usize::from(le_u16(header, 30)) + usize::from(le_u16(header, 32));
+        let offset = u64::from(le_u32(header, 42));
+        if 46 + name_len > header.len() {
+            return Err(invalid("truncated central directory"));
+        }
+        let name = String::from_utf8(header[46..46 + name_len].to_vec())
+            .map_err(|_| invalid("member name isn't valid UTF-8"))?;
+        position += 46 + name_len + skipped;
+
+        if name.ends_with('/') {
+            continue;
+        }
+        if flags & 1 != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("zip member \"{}\" is encrypted", name)));
+        }
+        if method != 0 && method != 8 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("zip member \"{}\" uses the unsupported compression method {}", name, method)
+            ));
+        }
+
+        let mut local = [0u8; 30];
+        input.seek(SeekFrom::Start(offset))?;
+        input.read_exact(&mut local)?;
+        if le_u32(&local, 0) != ZIP_LOCAL_HEADER {
+            return Err(invalid("bad local header"));
+        }
+        let data_offset = offset + 30 + u64::from(le_u16(&local, 26)) + u64::from(le_u16(&local, 28));
+        members.push((name, method, crc, compressed, size, data_offset));
+    }
+
+    let mut writer = PakWriter::create(dest_pak)?;
+    for &(ref name, method, crc, compressed, size, data_offset) in &members {
+        input.seek(SeekFrom::Start(data_offset))?;
+        let data = input.by_ref().take(compressed);
+        if method == 0 {
+            import_checked(&mut writer, name, data, size, crc)?;
+        } else {
+            import_checked(&mut writer, name, DeflateDecoder::new(data), size, crc)?;
+        }
+    }
+
+    writer.finish()?;
+    Ok(members.len())
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::{Error, ErrorKind};
+use std::ptr;
+use std::sync::Arc;
+
+/// Length of a `Key`.
+pub const KEY_LEN: usize = 32;
+
+/// Key
+///
+/// 256-bit encryption key of the chunks. Its bytes are never printed and
+/// are zeroed when it is dropped.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Key {
+    bytes: [u8; KEY_LEN],
+}
+
+impl Key {
+    /// Creates a new `Key` from its raw bytes.
+    pub fn new(bytes: [u8; KEY_LEN]) -> Self {
+        Key { bytes }
+    }
+
+    /// Creates a new `Key` from a slice of `KEY_LEN` bytes.
+    pub fn from_slice(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() != KEY_LEN {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("a key is {} bytes long, not {}", KEY_LEN, bytes.len())
+            ));
+        }
+        let mut key = Key::new([0; KEY_LEN]);
+        key.bytes.copy_from_slice(bytes);
+        Ok(key)
+    }
+
+    /// Raw bytes of the key, only handed to the cipher.
+    #[cfg_attr(not(feature = "crypto"), allow(dead_code))]
+    pub(crate) fn as_bytes(&self) -> &[u8; KEY_LEN] {
+        &self.bytes
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+impl Drop for Key {
+    fn drop(&mut self) {
+        for byte in self.bytes.iter_mut() {
+            // volatile so that the zeroing isn't optimized away
+            unsafe { ptr::write_volatile(byte, 0) };
+        }
+    }
+}
+
+/// KeyProvider
+///
+/// Source of the keys of the encrypted chunks, so that the application
+/// never has to pass the keys around itself. Any
+/// `Fn(&str) -> io::Result<Key>` is a `KeyProvider`, which makes it easy to
+/// call a KMS or a key agent.
+pub trait KeyProvider {
+    /// Key of the entry `full_file_name`.
+    fn key_for(&self, full_file_name: &str) -> io::Result<Key>;
+}
+
+impl<F> KeyProvider for F
+where
+    F: Fn(&str) -> io::Result<Key>
+{
+    fn key_for(&self, full_file_name: &str) -> io::Result<Key> {
+        self(full_file_name)
+    }
+}
+
+/// StaticKey
+///
+/// `KeyProvider` of the same key for every entry.
+#[derive(Clone, Debug)]
+pub struct StaticKey {
+    key: Key,
+}
+
+impl StaticKey {
+    /// Creates a new `StaticKey`.
+    pub fn new(key: Key) -> Self {
+        StaticKey { key }
+    }
+}
+
+impl KeyProvider for StaticKey {
+    fn key_for(&self, _full_file_name: &str) -> io::Result<Key> {
+        Ok(self.key.clone())
+    }
+}
+
+/// EntryKeys
+///
+/// `KeyProvider` of a key per entry, with an optional default key for the
+/// other entries.
+#[derive(Clone, Debug, Default)]
+pub struct EntryKeys {
+    keys: HashMap<String, Key>,
+    default: Option<Key>,
+}
+
+impl EntryKeys {
+    /// Creates a new empty `EntryKeys`.
+    pub fn new() -> Self {
+        EntryKeys::default()
+    }
+
+    /// Sets the key of the entry `full_file_name`.
+    pub fn insert<S: Into<String>>(&mut self, full_file_name: S, key: Key) -> &mut Self {
+        self.keys.insert(full_file_name.into(), key);
+        self
+    }
+
+    /// Sets the key of the entries without a key of their own.
+    pub fn default_key(&mut self, key: Key) -> &mut Self {
+        self.default = Some(key);
+        self
+    }
+}
+
+impl KeyProvider for EntryKeys {
+    fn key_for(&self, full_file_name: &str) -> io::Result<Key> {
+        self.keys.get(full_file_name)
+            .or(self.default.as_ref())
+            .cloned()
+            .ok_or_else(|| Error::new(
+                ErrorKind::NotFound,
+                format!("no key for `full_file_name` \"{}\"", full_file_name)
+            ))
+    }
+}
+
+/// Keys
+///
+/// `KeyProvider` shared by the options of the readers and the writers, see
+/// `Encryption`. It is `Send + Sync`, so that the `SharedReader` decrypts
+/// the entries from any thread.
+///
+/// Two `Keys` are equal if they are clones of each other.
+#[derive(Clone)]
+pub struct Keys {
+    provider: Arc<dyn KeyProvider + Send + Sync>,
+}
+
+impl Keys {
+    /// Creates new `Keys` from `provider`.
+    pub fn new<P>(provider: P) -> Self
+        where P: KeyProvider + Send + Sync + 'static
+    {
+        Keys { provider: Arc::new(provider) }
+    }
+}
+
+impl KeyProvider for Keys {
+    fn key_for(&self, full_file_name: &str) -> io::Result<Key> {
+        self.provider.key_for(full_file_name)
+    }
+}
+
+impl fmt::Debug for Keys {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Keys(..)")
+    }
+}
+
+impl PartialEq for Keys {
+    fn eq(&self, other: &Keys) -> bool {
+        Arc::ptr_eq(&self.provider, &other.provider)
+    }
+}
+
+impl Eq for Keys {}
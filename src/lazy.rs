@@ -0,0 +1,263 @@
+use error::{entry_not_found, Error as PakError};
+use options::ReadOptions;
+use properties::LINK_PROPERTY;
+use read::{limit_exceeded, set_file_name, EntryMeta, MergedChunk, MergeReader};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{Error, ErrorKind, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::vec;
+
+/// LazyReader
+///
+/// Reader of a linked archive opening its fragments on demand: only the
+/// initial fragment is read by `open`, the next ones when a lookup misses
+/// in the fragments loaded so far or when `entries` crosses into them. The
+/// chunk table of a fragment is read once, then kept.
+///
+/// Unlike `MergeReader`, which keeps the entry of the last fragment when a
+/// name is repeated across the fragments, a lookup returns the entry of the
+/// first fragment containing it, so that the fragments after it are never
+/// opened. The archives written by this crate never repeat a name.
+///
+/// The `max_fragments` of the `ReadLimits` bounds the chain, the other
+/// limits apply to every fragment on its own.
+pub struct LazyReader<R> {
+    make_reader: Box<dyn Fn(PathBuf) -> io::Result<R>>,
+    initial: PathBuf,
+    options: ReadOptions,
+    /// Fragments loaded so far, each merged on its own
+    loaded: Vec<MergeReader<R>>,
+    /// Fragment linked by the last one loaded, `None` at the end of the
+    /// chain
+    next: Option<PathBuf>,
+}
+
+impl LazyReader<File> {
+    /// Opens the pak file `loc`, reading only its initial fragment.
+    pub fn open<P: AsRef<Path>>(loc: P) -> io::Result<Self> {
+        LazyReader::open_with(loc, &ReadOptions::default())
+    }
+
+    /// Opens the pak file `loc` according to `options`, reading only its
+    /// initial fragment.
+    pub fn open_with<P: AsRef<Path>>(loc: P, options: &ReadOptions) -> io::Result<Self> {
+        LazyReader::new(loc.as_ref(), File::open, options)
+    }
+}
+
+impl<R> LazyReader<R>
+where
+    R: Read + Seek
+{
+    /// Reads the fragment `initial`, the linked ones being read on demand
+    /// through `make_reader`.
+    pub(crate) fn new<P, F>(initial: P, make_reader: F, options: &ReadOptions) -> io::Result<Self>
+        where P: Into<PathBuf>,
+              F: Fn(PathBuf) -> io::Result<R> + 'static
+    {
+        let initial = initial.into();
+        let mut lazy = LazyReader {
+            make_reader: Box::new(make_reader),
+            initial: initial.clone(),
+            options: options.clone(),
+            loaded: Vec::new(),
+            next: Some(initial),
+        };
+        lazy.load_next()?;
+        Ok(lazy)
+    }
+
+    /// Reads the next fragment of the chain, returning `false` at its end.
+    /// A fragment which can't be read is tried again by the next call.
+    fn load_next(&mut self) -> io::Result<bool> {
+        let path = match self.next {
+            Some(ref path) => path.clone(),
+            None => return Ok(false),
+        };
+        if self.loaded.iter().any(|fragment| fragment.fragments[0] == path) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("the fragments link back to \"{}\"", path.display())
+            ));
+        }
+        if let Some(max) = self.options.limits.max_fragments {
+            if self.loaded.len() >= max {
+                return Err(limit_exceeded(&path, format!("more than {} fragments", max), "max_fragments"));
+            }
+        }
+
+        let make_reader = &self.make_reader;
+        let fragment = match MergeReader::merge_with_options(path.clone(), make_reader, false, &self.options) {
+            Err(ref err) if err.kind() == ErrorKind::NotFound && !self.loaded.is_empty() => {
+                let linked_from = self.loaded[self.loaded.len() - 1].fragments[0].clone();
+                return Err(PakError::MissingFragment { path, linked_from }.into());
+            }
+            fragment => fragment?,
+        };
+        self.next = match fragment.properties.get(LINK_PROPERTY) {
+            Some(link) => Some(set_file_name(&self.initial, link).ok_or_else(|| {
+                Error::from(PakError::InvalidLink { link: link.clone() })
+            })?),
+            None => None,
+        };
+        self.loaded.push(fragment);
+        Ok(true)
+    }
+
+    /// Index of the first fragment containing the entry `full_file_name`,
+    /// reading the next fragments until one does.
+    fn find(&mut self, full_file_name: &str) -> io::Result<Option<usize>> {
+        let mut index = 0;
+        loop {
+            while index < self.loaded.len() {
+                if self.loaded[index].lookup(full_file_name).is_some() {
+                    return Ok(Some(index));
+                }
+                index += 1;
+            }
+            if !self.load_next()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Chunk of the entry `full_file_name`, reading the next fragments until
+    /// one contains it.
+    pub fn chunk(&mut self, full_file_name: &str) -> io::Result<Option<&MergedChunk<R>>> {
+        Ok(match self.find(full_file_name)? {
+            Some(index) => self.loaded[index].chunk(full_file_name),
+            None => None,
+        })
+    }
+
+    /// Returns `true` if the archive contains the entry `full_file_name`,
+    /// reading the next fragments until one does.
+    pub fn contains(&mut self, full_file_name: &str) -> io::Result<bool> {
+        self.find(full_file_name).map(|index| index.is_some())
+    }
+
+    /// Reads the entry `full_file_name`, reading the next fragments until
+    /// one contains it.
+    pub fn read_file(&mut self, full_file_name: &str) -> io::Result<Vec<u8>> {
+        match self.find(full_file_name)? {
+            Some(index) => self.loaded[index].read_file(full_file_name),
+            None => Err(entry_not_found(full_file_name)),
+        }
+    }
+
+    /// Fragment `index` of the chain merged on its own, reading the
+    /// fragments up to it, `None` past the last one.
+    pub fn fragment(&mut self, index: usize) -> io::Result<Option<&MergeReader<R>>> {
+        while self.loaded.len() <= index {
+            if !self.load_next()? {
+                return Ok(None);
+            }
+        }
+        Ok(Some(&self.loaded[index]))
+    }
+
+    /// Iterates over the entries of the archive, fragment by fragment,
+    /// reading every fragment when the iteration crosses into it. The
+    /// entries found in an earlier fragment are skipped.
+    pub fn entries(&mut self) -> LazyEntries<'_, R> {
+        LazyEntries {
+            reader: self,
+            fragment: Some(0),
+            pending: Vec::new().into_iter(),
+        }
+    }
+
+    /// Number of fragments read so far.
+    pub fn loaded(&self) -> usize {
+        self.loaded.len()
+    }
+
+    /// Returns `true` if every fragment of the chain was read.
+    pub fn is_complete(&self) -> bool {
+        self.next.is_none()
+    }
+
+    /// Paths of the fragments read so far, the first one being the initial
+    /// pak file.
+    pub fn fragments(&self) -> Vec<&Path> {
+        self.loaded.iter().map(|fragment| fragment.fragments[0].as_path()).collect()
+    }
+
+    /// Reads the remaining fragments and merges them into a `MergeReader`,
+    /// the entries of the last fragments overriding the ones of the first.
+    pub fn into_merged(mut self) -> io::Result<MergeReader<R>> {
+        while self.load_next()? {}
+        let mut fragments = self.loaded.into_iter();
+        let mut merged = fragments.next().expect("the initial fragment is read by `new`");
+        for fragment in fragments {
+            merged.absorb(fragment)?;
+        }
+        Ok(merged)
+    }
+}
+
+impl<R> fmt::Debug for LazyReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LazyReader")
+            .field("initial", &self.initial)
+            .field("loaded", &self.loaded.len())
+            .field("next", &self.next)
+            .finish()
+    }
+}
+
+/// LazyEntries
+///
+/// Iterator over the metadata of the entries of a `LazyReader`, returned by
+/// `LazyReader::entries`. A fragment which can't be read ends the iteration
+/// with its error.
+#[derive(Debug)]
+pub struct LazyEntries<'a, R: 'a> {
+    reader: &'a mut LazyReader<R>,
+    /// Next fragment to iterate over, `None` once the iteration ended
+    fragment: Option<usize>,
+    /// LazyEntries of the current fragment left to yield
+    pending: vec::IntoIter<EntryMeta>,
+}
+
+impl<'a, R> Iterator for LazyEntries<'a, R>
+where
+    R: Read + Seek
+{
+    type Item = io::Result<EntryMeta>;
+
+    fn next(&mut self) -> Option<io::Result<EntryMeta>> {
+        loop {
+            if let Some(meta) = self.pending.next() {
+                return Some(Ok(meta));
+            }
+
+            let index = self.fragment?;
+            match self.reader.fragment(index) {
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    self.fragment = None;
+                    return None;
+                }
+                Err(err) => {
+                    // a failed read ends the iteration
+                    self.fragment = None;
+                    return Some(Err(err));
+                }
+            }
+            let earlier: HashSet<&String> = self.reader.loaded[..index].iter()
+                .flat_map(|fragment| fragment.chunks.keys())
+                .collect();
+            let mut metas: Vec<EntryMeta> = self.reader.loaded[index].iter()
+                .filter(|&(full_file_name, _)| !earlier.contains(full_file_name))
+                .map(|(full_file_name, chunk)| EntryMeta { fragment: index, ..chunk.meta(full_file_name) })
+                .collect();
+            metas.sort_by(|a, b| a.name.cmp(&b.name));
+            self.pending = metas.into_iter();
+            self.fragment = Some(index + 1);
+        }
+    }
+}
@@ -4,9 +4,12 @@
 //!
 //! _NB : Historically named Pak Protocol 2 with the file extensions `pak`, `pak2`
 //! or `d2p`. Since the Pak Protocol (`d2pOld` extension) is not used anymore, 
-//! Pak Protocol 2 becomes Pak Protocol._
+//! Pak Protocol 2 becomes Pak Protocol. `MergeReader` still reads the legacy
+//! archives, see `raw::legacy`._
 //!
-//! A pak file is an archive file without compression. The file extension is `d2p`.
+//! A pak file is an archive file, without compression unless written with a
+//! `Compression`, nor encryption unless written with an `Encryption`. The
+//! file extension is `d2p`.
 //! A pak file can be split in several files. A file segment contains the path of
 //! the next segment to read.
 //!
@@ -50,12 +53,344 @@
 //!
 //! The data described by a chunk can be load starting from the `Info.offset` + 
 //! `Chunk.offset`.
+//!
+//! The offsets and sizes above limit a fragment to 2 GiB. The fragments
+//! written with `Offsets::Extended` start with the header `2, 2` instead,
+//! and store every offset and size of the `Info` (then 40 bytes long) and
+//! of the chunk table on 8 bytes.
+//!
+//! The functions return `io::Error`s. When the cause of a failure is known,
+//! the `io::Error` carries a typed `Error`, borrowed with `Error::from_io`:
+//!
+//! ``` no_run
+//! # use pak::{Error, MergeReader};
+//! match MergeReader::open("data.d2p") {
+//!     Ok(reader) => println!("version {:?}", reader.property("version")),
+//!     Err(err) => match Error::from_io(&err) {
+//!         Some(&Error::MissingFragment { ref path, .. }) => println!("{} is missing", path.display()),
+//!         Some(typed) => println!("{}", typed),
+//!         None => println!("{}", err),
+//!     },
+//! }
+//! ```
+//!
+//! Without the default `std` feature, the crate is `no_std` (with `alloc`)
+//! and only provides the `raw` module, which parses fragments held in memory.
+//!
+//! On `wasm32-unknown-unknown`, which has neither a file system nor a clock,
+//! the archives are opened from memory with `MergeReader::open_source` and a
+//! `MemSource`, without any timeout.
+//!
+//! The crate only reads and writes local files: no source or feature
+//! reaches the network. The `offline` feature enforces it in a build: the
+//! sources the crate opens the data from by itself (an `ArchiveSource`, the
+//! layers of a `PakFs`) are sealed to its own, files and memory, so a
+//! network-capable source doesn't compile, and `OFFLINE` lets a dependent
+//! assert the feature at compile time.
 
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate core;
+#[cfg(feature = "blake3")]
+extern crate blake3;
+#[cfg(feature = "std")]
 extern crate byteorder_extended;
+#[cfg(feature = "crypto")]
+extern crate chacha20poly1305;
+#[cfg(feature = "std")]
+extern crate fnv;
+#[cfg(any(feature = "zlib", feature = "zip"))]
+extern crate flate2;
+#[cfg(all(unix, feature = "std"))]
+extern crate libc;
+#[cfg(feature = "lzma")]
+extern crate lzma_rs;
+#[cfg(feature = "regex")]
+extern crate regex;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "sha256")]
+extern crate sha2;
+#[cfg(feature = "async")]
+extern crate tokio;
+#[cfg(feature = "xxh3")]
+extern crate xxhash_rust;
+#[cfg(feature = "zstd")]
+extern crate zstd;
 
+#[cfg(feature = "std")]
+pub mod d2i;
+#[cfg(feature = "std")]
+pub mod d2o;
+#[cfg(feature = "zlib")]
+pub mod dlm;
+#[cfg(any(feature = "tar", feature = "zip"))]
+pub mod interop;
 pub mod raw;
+#[cfg(feature = "std")]
+pub mod swl;
+#[cfg(feature = "testkit")]
+pub mod testkit;
 
+#[cfg(feature = "std")]
+mod access;
+#[cfg(feature = "std")]
+mod analysis;
+#[cfg(feature = "std")]
+mod archive;
+#[cfg(feature = "async")]
+mod async_reader;
+#[cfg(feature = "std")]
+mod attributes;
+#[cfg(feature = "std")]
+mod backend;
+#[cfg(feature = "std")]
+mod bloom;
+#[cfg(feature = "std")]
+mod bulk;
+#[cfg(feature = "std")]
+mod cache;
+#[cfg(feature = "std")]
+mod catalog;
+#[cfg(feature = "std")]
+mod chain;
+#[cfg(feature = "std")]
+mod checksum;
+#[cfg(feature = "std")]
+mod codec;
+#[cfg(feature = "std")]
+mod complete;
+#[cfg(feature = "std")]
+mod compression;
+#[cfg(any(feature = "zlib", feature = "lzma"))]
+mod decompress;
+#[cfg(feature = "std")]
+mod diff;
+#[cfg(feature = "std")]
+mod editor;
+#[cfg(feature = "std")]
+mod encryption;
+#[cfg(feature = "std")]
+mod entry;
+#[cfg(feature = "std")]
+mod error;
+#[cfg(feature = "std")]
+mod exit;
+#[cfg(feature = "std")]
+mod export;
+#[cfg(feature = "std")]
+mod extract;
+#[cfg(feature = "std")]
+mod filter;
+#[cfg(feature = "std")]
+mod fsck;
+#[cfg(feature = "std")]
+mod glob;
+#[cfg(feature = "std")]
+mod hash;
+#[cfg(feature = "std")]
+mod health;
+#[cfg(feature = "std")]
+mod history;
+#[cfg(feature = "std")]
+mod index;
+#[cfg(feature = "std")]
+mod key;
+#[cfg(feature = "std")]
+mod lazy;
+#[cfg(feature = "std")]
+mod localized;
+#[cfg(feature = "std")]
+mod manifest;
+#[cfg(feature = "std")]
+mod mem;
+#[cfg(feature = "std")]
+mod memory;
+#[cfg(all(unix, feature = "mmap"))]
+mod mmap;
+#[cfg(feature = "std")]
+mod name;
+#[cfg(feature = "std")]
+mod offline;
+#[cfg(feature = "std")]
+mod options;
+#[cfg(feature = "std")]
+mod pack;
+#[cfg(feature = "std")]
+mod patch;
+#[cfg(feature = "std")]
+mod pin;
+#[cfg(feature = "std")]
+mod probe;
+#[cfg(feature = "std")]
+mod progress;
+#[cfg(feature = "std")]
+mod properties;
+#[cfg(feature = "std")]
+mod query;
+#[cfg(feature = "std")]
 mod read;
+#[cfg(feature = "std")]
+mod repack;
+#[cfg(feature = "std")]
+mod retry;
+#[cfg(all(target_os = "linux", feature = "std"))]
+mod sandbox;
+#[cfg(feature = "std")]
+mod search;
+#[cfg(feature = "std")]
+mod session;
+#[cfg(feature = "std")]
+mod shared;
+#[cfg(feature = "std")]
+mod sidecar;
+#[cfg(feature = "std")]
+mod slack;
+#[cfg(feature = "std")]
+mod snapshot;
+#[cfg(feature = "std")]
+mod source;
+#[cfg(feature = "std")]
+mod stats;
+#[cfg(feature = "std")]
+mod timeout;
+#[cfg(feature = "std")]
+mod transform;
+#[cfg(feature = "std")]
+mod tree;
+#[cfg(feature = "std")]
+mod verify;
+#[cfg(feature = "std")]
+mod vfs;
+#[cfg(feature = "std")]
+mod volumes;
+#[cfg(feature = "std")]
 mod write;
 
-pub use read::{MergedChunk, MergeReader};
\ No newline at end of file
+#[cfg(feature = "std")]
+pub use access::{Access, AccessLog, AccessPolicy};
+#[cfg(feature = "std")]
+pub use analysis::{Cluster, DuplicateGroup, DuplicateReport, Fingerprint};
+#[cfg(feature = "std")]
+pub use archive::{Archive, Strictness};
+#[cfg(feature = "async")]
+pub use async_reader::{AsyncMergeReader, EntryStream, PakFuture};
+#[cfg(feature = "std")]
+pub use attributes::{Attributes, ATTRIBUTES_CHUNK};
+#[cfg(feature = "std")]
+pub use backend::{ArchiveSource, MemSource};
+#[cfg(feature = "std")]
+pub use bloom::BloomFilter;
+#[cfg(feature = "std")]
+pub use bulk::{extract_all, extract_all_with, ArchiveExtraction, ExtractAllReport};
+#[cfg(feature = "std")]
+pub use catalog::{Catalog, CatalogChanges, CatalogMatch};
+#[cfg(feature = "std")]
+pub use chain::{link_value, peek, ArchiveSummary, ChainFragment, FragmentChain};
+#[cfg(feature = "std")]
+pub use checksum::Checksum;
+#[cfg(feature = "std")]
+pub use codec::NameCodec;
+#[cfg(feature = "std")]
+pub use complete::NameIndex;
+#[cfg(feature = "std")]
+pub use compression::Compression;
+#[cfg(any(feature = "zlib", feature = "lzma"))]
+pub use decompress::PayloadCodec;
+#[cfg(feature = "std")]
+pub use diff::{detect_renames, diff, extract_changed, ArchiveDiff, Rename};
+#[cfg(feature = "std")]
+pub use editor::{PakEditor, Rollback};
+#[cfg(feature = "std")]
+pub use encryption::Encryption;
+#[cfg(feature = "std")]
+pub use entry::{Entries, Entry};
+#[cfg(feature = "std")]
+pub use error::Error;
+#[cfg(feature = "std")]
+pub use exit::ExitCode;
+#[cfg(feature = "std")]
+pub use export::{export_blobs, BlobExport, BlobFragment, BlobIndex};
+#[cfg(feature = "std")]
+pub use extract::ExtractSummary;
+#[cfg(feature = "std")]
+pub use fsck::fsck;
+#[cfg(feature = "std")]
+pub use glob::Pattern;
+#[cfg(feature = "std")]
+pub use hash::{Digest, HashAlgorithm, HashBackend, HashIndex, HashState};
+#[cfg(feature = "std")]
+pub use health::{health, Health};
+#[cfg(feature = "std")]
+pub use index::{IndexEntry, IndexedArchive, PakIndex};
+#[cfg(feature = "std")]
+pub use key::{EntryKeys, Key, KeyProvider, Keys, StaticKey, KEY_LEN};
+#[cfg(feature = "std")]
+pub use lazy::{LazyEntries, LazyReader};
+#[cfg(feature = "std")]
+pub use manifest::{Manifest, ManifestEntry};
+#[cfg(feature = "std")]
+pub use mem::MemArchive;
+#[cfg(feature = "std")]
+pub use memory::MemoryUsage;
+#[cfg(all(unix, feature = "mmap"))]
+pub use mmap::MmapReader;
+#[cfg(feature = "std")]
+pub use name::EntryName;
+#[cfg(feature = "std")]
+pub use options::{ExistingFiles, ExtractOptions, InvalidNames, ReadLimits, ReadOptions, ReadStrategy, WriteOptions, DEFAULT_MAX_FRAGMENTS};
+#[cfg(feature = "std")]
+pub use pack::ChainPacker;
+#[cfg(feature = "std")]
+pub use patch::{apply_patch, Patch, PatchEntry};
+#[cfg(feature = "std")]
+pub use pin::PinnedBytes;
+#[cfg(feature = "std")]
+pub use probe::{ContentRegistry, KindStats};
+#[cfg(feature = "std")]
+pub use progress::{ProgressEvent, ProgressTracker};
+#[cfg(feature = "std")]
+pub use properties::is_reserved_property;
+#[cfg(feature = "std")]
+pub use read::{ChunkReader, DataIter, EntryMeta, MergedChunk, MergeReader};
+#[cfg(feature = "std")]
+pub use repack::{ShardGrouping, ShardPolicy};
+#[cfg(feature = "std")]
+pub use retry::{classify, is_transient, ErrorClass, RetryExhausted, RetryPolicy, RetryReader};
+#[cfg(all(target_os = "linux", feature = "std"))]
+pub use sandbox::SandboxDir;
+#[cfg(feature = "std")]
+pub use search::{detect_text, TextIndex, TextKind, TextMatch};
+#[cfg(feature = "std")]
+pub use session::Session;
+#[cfg(feature = "std")]
+pub use shared::SharedReader;
+#[cfg(feature = "std")]
+pub use sidecar::{FragmentStamp, Sidecar, SidecarEntry};
+#[cfg(feature = "std")]
+pub use snapshot::Snapshot;
+#[cfg(feature = "std")]
+pub use source::SourceManifest;
+#[cfg(feature = "std")]
+pub use stats::{ArchiveStats, FragmentStats, WastedRegion};
+#[cfg(feature = "std")]
+pub use timeout::{TimedOut, Timeouts};
+#[cfg(feature = "std")]
+pub use transform::{transform, RewriteFn, Transform, Transformed};
+#[cfg(feature = "std")]
+pub use tree::{Dir, Node};
+#[cfg(feature = "std")]
+pub use verify::{Finding, Report, Severity, VerifyOptions};
+#[cfg(feature = "std")]
+pub use vfs::{FsEntry, LooseDir, Overlay, PakFs};
+#[cfg(feature = "std")]
+pub use write::PakWriter;
+
+/// `true` when the crate is built with the `offline` feature: every source
+/// is a file or memory, a dependent asserts it with
+/// `const _: () = assert!(pak::OFFLINE);`.
+pub const OFFLINE: bool = cfg!(feature = "offline");
@@ -0,0 +1,59 @@
+use error::entry_not_found;
+use read::MergeReader;
+use std::io;
+use std::io::{Read, Seek};
+
+/// Name of the variant of `base_name` for the language `lang`: the
+/// language suffixed to the file name, before the extension
+/// (`gfx/items/12.png`, `fr` → `gfx/items/12_fr.png`).
+fn localized_name(base_name: &str, lang: &str) -> String {
+    let file_start = base_name.rfind('/').map_or(0, |slash| slash + 1);
+    match base_name[file_start..].rfind('.') {
+        // a leading dot isn't an extension
+        Some(dot) if dot > 0 => {
+            let dot = file_start + dot;
+            format!("{}_{}{}", &base_name[..dot], lang, &base_name[dot..])
+        }
+        _ => format!("{}_{}", base_name, lang),
+    }
+}
+
+/// Suffixes tried for `lang`, most specific first: `fr-CA` gives `fr_ca`
+/// then `fr`.
+fn language_suffixes(lang: &str) -> Vec<String> {
+    let lang = lang.trim().to_lowercase().replace('-', "_");
+    if lang.is_empty() {
+        return Vec::new();
+    }
+    let mut suffixes = vec![lang.clone()];
+    if let Some(underscore) = lang.find('_') {
+        suffixes.push(lang[..underscore].to_string());
+    }
+    suffixes
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Name of the entry to read for the asset `base_name` in the language
+    /// `lang`, following the naming of the game archives: the variant
+    /// suffixed with the language (`12_fr.png`, or `12_fr_ca.png` then
+    /// `12_fr.png` for `fr-CA`) when present, `base_name` itself otherwise.
+    /// `None` if neither exists.
+    pub fn resolve_localized_asset(&self, base_name: &str, lang: &str) -> Option<String> {
+        language_suffixes(lang).iter()
+            .map(|suffix| localized_name(base_name, suffix))
+            .chain(Some(base_name.to_string()))
+            .find(|name| self.chunk(name).is_some())
+    }
+
+    /// Reads the asset `base_name` in the language `lang`, see
+    /// `resolve_localized_asset`.
+    pub fn read_localized_asset(&self, base_name: &str, lang: &str) -> io::Result<Vec<u8>> {
+        match self.resolve_localized_asset(base_name, lang) {
+            Some(full_file_name) => self.read_file(&full_file_name),
+            None => Err(entry_not_found(base_name)),
+        }
+    }
+}
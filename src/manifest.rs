@@ -0,0 +1,140 @@
+use hash::{HashAlgorithm, HashBackend};
+use properties::LINK_PROPERTY;
+use read::{MergedChunk, MergeReader};
+use std::collections::BTreeMap;
+use std::io;
+use std::io::{Error, ErrorKind, Read, Seek};
+use verify::{Finding, Report, Severity};
+
+/// Size of the blocks in which the data is hashed.
+const BLOCK_SIZE: usize = 1 << 16;
+
+/// ManifestEntry
+///
+/// Entry of a `Manifest`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ManifestEntry {
+    /// Size of the data, decompressed
+    pub size: u64,
+    /// Digest of the data, decompressed, in hexadecimal
+    pub digest: String,
+}
+
+/// Manifest
+///
+/// Record of the content of an archive returned by `MergeReader::manifest`:
+/// the entries with the size and the digest of their data, and the
+/// properties. It describes the logical content only, so an archive rebuilt
+/// with another layout, other fragments or another compression matches it,
+/// see `MergeReader::check_manifest`. With the `serde` feature, it
+/// serializes to JSON or TOML for the build pipelines to keep it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Manifest {
+    /// Name of the algorithm of the digests, see `HashAlgorithm::from_name`
+    pub algorithm: String,
+    /// Entries by full file name
+    pub entries: BTreeMap<String, ManifestEntry>,
+    /// Properties of the archive, without the `link` chaining the fragments
+    pub properties: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Algorithm of the digests, failing with `Unsupported` if it is unknown
+    /// or disabled.
+    pub fn hash_algorithm(&self) -> io::Result<HashAlgorithm> {
+        HashAlgorithm::from_name(&self.algorithm).ok_or_else(|| Error::new(
+            ErrorKind::Unsupported,
+            format!("the manifest is hashed with \"{}\", which isn't enabled", self.algorithm)
+        ))
+    }
+
+    /// Differences of `actual` from the manifest: missing, unexpected and
+    /// modified entries are errors, modified properties warnings. The
+    /// digests are only compared if both manifests use the same algorithm.
+    pub fn compare(&self, actual: &Manifest) -> Report {
+        let mut report = Report::new();
+        let same_algorithm = self.algorithm == actual.algorithm;
+        for (full_file_name, expected) in &self.entries {
+            match actual.entries.get(full_file_name) {
+                None => report.push(Finding::new(Severity::Error, "missing").entry(full_file_name.as_str())),
+                Some(entry) if entry.size != expected.size => report.push(Finding::new(
+                    Severity::Error,
+                    format!("{} bytes instead of {}", entry.size, expected.size))
+                    .entry(full_file_name.as_str())),
+                Some(entry) if same_algorithm && entry.digest != expected.digest => report.push(Finding::new(
+                    Severity::Error,
+                    format!("{} digest {} instead of {}", self.algorithm, entry.digest, expected.digest))
+                    .entry(full_file_name.as_str())),
+                Some(_) => {}
+            }
+        }
+        for full_file_name in actual.entries.keys().filter(|name| !self.entries.contains_key(*name)) {
+            report.push(Finding::new(Severity::Error, "not in the manifest").entry(full_file_name.as_str()));
+        }
+
+        for (key, expected) in &self.properties {
+            match actual.properties.get(key) {
+                Some(value) if value == expected => {}
+                Some(value) => report.push(Finding::new(
+                    Severity::Warning,
+                    format!("the property \"{}\" is \"{}\" instead of \"{}\"", key, value, expected))),
+                None => report.push(Finding::new(
+                    Severity::Warning,
+                    format!("the property \"{}\" is missing", key))),
+            }
+        }
+        for key in actual.properties.keys().filter(|key| !self.properties.contains_key(*key)) {
+            report.push(Finding::new(Severity::Warning, format!("the property \"{}\" is not in the manifest", key)));
+        }
+        report
+    }
+}
+
+/// Size and digest of the decompressed data of `chunk`.
+fn describe<R: Read + Seek>(chunk: &MergedChunk<R>, algorithm: HashAlgorithm) -> io::Result<ManifestEntry> {
+    let mut state = algorithm.start();
+    let mut decoded = chunk.decoded_reader()?;
+    let mut buffer = vec![0; BLOCK_SIZE];
+    let mut size = 0;
+    loop {
+        let read = decoded.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        state.update(&buffer[..read]);
+        size += read as u64;
+    }
+    chunk.check_decoded(size)?;
+    Ok(ManifestEntry { size, digest: state.finish().to_hex() })
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Reads every entry to record the content of the archive in a
+    /// `Manifest`, the data being hashed with `algorithm`.
+    pub fn manifest(&self, algorithm: HashAlgorithm) -> io::Result<Manifest> {
+        let mut entries = BTreeMap::new();
+        for (full_file_name, chunk) in self.iter() {
+            entries.insert(full_file_name.clone(), describe(chunk, algorithm)?);
+        }
+        let properties = self.properties.iter()
+            .filter(|&(key, _)| key != LINK_PROPERTY)
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        Ok(Manifest {
+            algorithm: algorithm.name().to_string(),
+            entries,
+            properties,
+        })
+    }
+
+    /// Checks the archive against `expected`, typically the manifest of the
+    /// archive it was rebuilt from, see `Manifest::compare`.
+    pub fn check_manifest(&self, expected: &Manifest) -> io::Result<Report> {
+        Ok(expected.compare(&self.manifest(expected.hash_algorithm()?)?))
+    }
+}
@@ -0,0 +1,174 @@
+use error::{entry_exists, entry_not_found};
+use name::{lookup_key, EntryName};
+use options::WriteOptions;
+use properties::check_property_key;
+use read::MergeReader;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Error, Read, Seek, Write};
+use std::path::Path;
+use write::FragmentWriter;
+
+/// MemArchive
+///
+/// An archive held entirely in memory, with the same entry API as
+/// `Archive`. It is only serialized on `save`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemArchive {
+    entries: HashMap<String, Vec<u8>>,
+    properties: HashMap<String, String>,
+}
+
+impl MemArchive {
+    /// Creates a new empty `MemArchive`.
+    pub fn new() -> Self {
+        MemArchive::default()
+    }
+
+    /// Loads every entry and property of the pak file `loc` (and of the
+    /// fragments it links to).
+    pub fn load<P: AsRef<Path>>(loc: P) -> io::Result<Self> {
+        MemArchive::from_reader(&MergeReader::open(loc)?)
+    }
+
+    /// Loads every entry and property of `reader`. The entries denied by
+    /// the `AccessPolicy` are skipped.
+    pub fn from_reader<R: Read + Seek>(reader: &MergeReader<R>) -> io::Result<Self> {
+        let mut entries = HashMap::with_capacity(reader.chunks.len());
+        for (full_file_name, chunk) in reader.iter().filter(|&(full_file_name, _)| reader.allows(full_file_name)) {
+            entries.insert(full_file_name.clone(), chunk.data()?);
+        }
+
+        let mut properties = reader.properties.clone();
+        // the fragments are merged, the links are meaningless
+        properties.remove("link");
+
+        Ok(MemArchive { entries, properties })
+    }
+
+    /// Writes the archive as a single pak fragment in `writer`, entries
+    /// sorted by full file name.
+    pub fn write<W: Write>(&self, writer: W) -> io::Result<W> {
+        self.write_with(writer, &WriteOptions::default())
+    }
+
+    /// Writes the archive in `writer` according to `options`.
+    fn write_with<W: Write>(&self, writer: W, options: &WriteOptions) -> io::Result<W> {
+        let mut writer = FragmentWriter::with_options(writer, options)?;
+
+        let mut names: Vec<&String> = self.entries.keys().collect();
+        names.sort();
+        for full_file_name in names {
+            writer.add_bytes(full_file_name, &self.entries[full_file_name])?;
+        }
+
+        let mut properties: Vec<_> = self.properties.iter().collect();
+        properties.sort();
+        for (key, value) in properties {
+            writer.add_property(key, value);
+        }
+
+        writer.finish()
+    }
+
+    /// Saves the archive as a single pak fragment at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.save_with(path, &WriteOptions::default())
+    }
+
+    /// Saves the archive as a single pak fragment at `path` according to
+    /// `options`.
+    pub fn save_with<P: AsRef<Path>>(&self, path: P, options: &WriteOptions) -> io::Result<()> {
+        self.write_with(BufWriter::with_capacity(options.buffer_size, File::create(path)?), options)?
+            .into_inner()
+            .map_err(Error::from)?
+            .sync_all()
+    }
+
+    /// Returns `true` if the archive contains the entry `full_file_name`.
+    pub fn contains(&self, full_file_name: &str) -> bool {
+        self.entries.contains_key(&*lookup_key(full_file_name))
+    }
+
+    /// Full file names of the entries, sorted.
+    pub fn file_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.entries.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the archive contains no entry.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Data of the entry `full_file_name`.
+    pub fn get(&self, full_file_name: &str) -> Option<&[u8]> {
+        self.entries.get(&*lookup_key(full_file_name)).map(|data| data.as_slice())
+    }
+
+    /// Reads the entry `full_file_name`.
+    pub fn read_file(&self, full_file_name: &str) -> io::Result<Vec<u8>> {
+        self.get(full_file_name).map(|data| data.to_vec()).ok_or_else(|| entry_not_found(full_file_name))
+    }
+
+    /// Adds a new entry.
+    pub fn add<S: Into<String>>(&mut self, full_file_name: S, data: Vec<u8>) -> io::Result<()> {
+        let full_file_name = EntryName::new(&full_file_name.into())?.into_string();
+        if self.contains(&full_file_name) {
+            return Err(entry_exists(&full_file_name));
+        }
+        self.entries.insert(full_file_name, data);
+        Ok(())
+    }
+
+    /// Replaces the data of an existing entry.
+    pub fn replace(&mut self, full_file_name: &str, data: Vec<u8>) -> io::Result<()> {
+        match self.entries.get_mut(&*lookup_key(full_file_name)) {
+            Some(entry) => {
+                *entry = data;
+                Ok(())
+            }
+            None => Err(entry_not_found(full_file_name)),
+        }
+    }
+
+    /// Adds or replaces an entry.
+    pub fn insert<S: Into<String>>(&mut self, full_file_name: S, data: Vec<u8>) -> io::Result<()> {
+        let full_file_name = EntryName::new(&full_file_name.into())?.into_string();
+        self.entries.insert(full_file_name, data);
+        Ok(())
+    }
+
+    /// Removes an entry.
+    pub fn remove(&mut self, full_file_name: &str) -> io::Result<()> {
+        self.entries.remove(&*lookup_key(full_file_name)).map(|_| ()).ok_or_else(|| entry_not_found(full_file_name))
+    }
+
+    /// Value of the property `key`.
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(|value| value.as_str())
+    }
+
+    /// Sets the property `key`, failing with `InvalidInput` if it is
+    /// reserved, see `is_reserved_property`.
+    pub fn set_property<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) -> io::Result<()> {
+        let key = key.into();
+        check_property_key(&key)?;
+        self.properties.insert(key, value.into());
+        Ok(())
+    }
+
+    /// Removes the property `key`, returning its value, failing with
+    /// `InvalidInput` if it is reserved.
+    pub fn remove_property(&mut self, key: &str) -> io::Result<Option<String>> {
+        check_property_key(key)?;
+        Ok(self.properties.remove(key))
+    }
+}
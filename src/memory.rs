@@ -0,0 +1,112 @@
+use read::{MergedChunk, MergeReader};
+use std::io;
+use std::io::{Error, ErrorKind, Read, Seek};
+use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// MemoryUsage
+///
+/// Estimated bytes held by a `MergeReader`, see `MergeReader::memory_usage`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Index of the entries, properties and fragment paths
+    pub index: usize,
+    /// Data of the `PinnedBytes` still alive, even if they outlive the
+    /// reader
+    pub pinned: usize,
+    /// Data of the entries in the cache, see `ReadStrategy`
+    pub cached: usize,
+}
+
+impl MemoryUsage {
+    /// Total of the bytes held.
+    pub fn total(&self) -> usize {
+        self.index + self.pinned + self.cached
+    }
+}
+
+/// Bytes charged to the account of a reader, released when dropped.
+#[derive(Debug)]
+pub(crate) struct Charge {
+    counter: Arc<AtomicUsize>,
+    bytes: usize,
+}
+
+impl Drop for Charge {
+    fn drop(&mut self) {
+        self.counter.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+/// Memory account of a reader: the estimated size of its index and the
+/// bytes of its allocations which may outlive it.
+#[derive(Debug, Default)]
+pub(crate) struct MemoryAccount {
+    index: usize,
+    pinned: Arc<AtomicUsize>,
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Estimates the size of the index, checking it against the memory
+    /// limit. Called once the reader is built.
+    pub(crate) fn account_index(&mut self) -> io::Result<()> {
+        let entry_size = mem::size_of::<String>() + mem::size_of::<MergedChunk<R>>();
+        let mut index = 0;
+        for full_file_name in self.chunks.keys() {
+            index += entry_size + full_file_name.capacity();
+        }
+        for (key, name) in self.folded.iter().flatten() {
+            index += 2 * mem::size_of::<String>() + key.capacity() + name.capacity();
+        }
+        for (key, value) in &self.properties {
+            index += 2 * mem::size_of::<String>() + key.capacity() + value.capacity();
+        }
+        for path in &self.fragments {
+            index += mem::size_of_val(path) + path.as_os_str().len();
+        }
+        self.memory.index = index;
+        self.reserve(0)
+    }
+
+    /// Checks that `bytes` more fit in the memory limit.
+    pub(crate) fn reserve(&self, bytes: usize) -> io::Result<()> {
+        let limit = match self.options.memory_limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let usage = self.memory_usage().total().saturating_add(bytes);
+        if usage > limit {
+            return Err(Error::new(
+                ErrorKind::OutOfMemory,
+                format!("memory limit of {} bytes exceeded ({} bytes)", limit, usage)
+            ));
+        }
+        Ok(())
+    }
+
+    /// Charges `bytes` of data about to be pinned, failing if they exceed
+    /// the memory limit.
+    pub(crate) fn charge(&self, bytes: usize) -> io::Result<Charge> {
+        self.reserve(bytes)?;
+        self.memory.pinned.fetch_add(bytes, Ordering::Relaxed);
+        Ok(Charge {
+            counter: self.memory.pinned.clone(),
+            bytes,
+        })
+    }
+
+    /// Estimated bytes held by the reader. The `memory_limit` of the
+    /// `ReadOptions` caps it: opening an archive whose index exceeds it, or
+    /// pinning data beyond it, fails, and the data beyond it isn't cached.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            index: self.memory.index,
+            pinned: self.memory.pinned.load(Ordering::Relaxed),
+            cached: self.cache.borrow().size(),
+        }
+    }
+}
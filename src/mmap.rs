@@ -0,0 +1,136 @@
+use pin::PinnedBytes;
+use read::MergeReader;
+use std::fs::File;
+use std::io;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+use std::slice;
+use std::sync::Arc;
+
+/// Read-only memory map of a whole file, unmapped when dropped.
+#[derive(Debug)]
+pub(crate) struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// the mapping is read-only and never moves
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    /// Maps `file`.
+    fn new(file: &File) -> io::Result<Self> {
+        let len = file.metadata()?.len();
+        if len > usize::MAX as u64 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} bytes can't be mapped in memory", len)
+            ));
+        }
+        let len = len as usize;
+        // a zero-length mapping is invalid
+        if len == 0 {
+            return Ok(Mapping { ptr: ptr::null_mut(), len });
+        }
+
+        let ptr = unsafe {
+            libc::mmap(ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0)
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(Error::last_os_error());
+        }
+        Ok(Mapping { ptr, len })
+    }
+
+    /// Mapped bytes.
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+/// MmapReader
+///
+/// Reader of a fragment mapped in memory, backing `MergeReader::open_mmap`.
+/// The file must not be truncated while it is mapped.
+#[derive(Clone, Debug)]
+pub struct MmapReader {
+    map: PinnedBytes,
+    position: u64,
+}
+
+impl MmapReader {
+    /// Maps the file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mapping = Mapping::new(&File::open(path)?)?;
+        Ok(MmapReader {
+            map: PinnedBytes::mapped(Arc::new(mapping)),
+            position: 0,
+        })
+    }
+
+    /// Mapped bytes.
+    pub fn as_slice(&self) -> &[u8] {
+        self.map.as_slice()
+    }
+}
+
+impl Read for MmapReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.map.as_slice();
+        let start = self.position.min(data.len() as u64) as usize;
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for MmapReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => (self.map.len() as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position"
+            )),
+        }
+    }
+}
+
+impl MergeReader<MmapReader> {
+    /// Opens the pak file mapping every fragment in memory: `read_file_cow`
+    /// borrows the data and `pin` shares it, without copying it.
+    pub fn open_mmap<P: AsRef<Path>>(loc: P) -> io::Result<Self> {
+        let mut merge = MergeReader::merge(loc.as_ref(), MmapReader::open)?;
+        // the compressed or encrypted data can't be borrowed
+        for chunk in merge.chunks.values_mut().filter(|chunk| !chunk.is_compressed() && !chunk.is_encrypted()) {
+            let mapped = chunk.with_reader(|reader| Ok(reader.map.slice(chunk.offset() as usize, chunk.size() as usize)))?;
+            chunk.mapped = mapped;
+        }
+        Ok(merge)
+    }
+}
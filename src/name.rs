@@ -0,0 +1,246 @@
+use std::borrow::{Borrow, Cow};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::io;
+use std::io::{Error, ErrorKind};
+use std::iter;
+use std::path;
+use std::path::{Component, Path, PathBuf, Prefix};
+
+/// EntryName
+///
+/// Normalized full file name of an entry: segments separated by `/` (`\` is
+/// accepted as a separator), without leading or trailing separator, without
+/// empty or `.` segments, `..` segments being resolved. A name escaping the
+/// root of the archive, or with a segment made of whitespaces only, is
+/// rejected.
+///
+/// The reader, the writers, the lookups and the extraction all go through
+/// `EntryName`, so `dir\file.xml`, `/dir/file.xml` and `dir/./file.xml` name
+/// the same entry.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntryName(String);
+
+impl EntryName {
+    /// Creates a new `EntryName` by normalizing `full_file_name`.
+    pub fn new(full_file_name: &str) -> io::Result<Self> {
+        let mut segments: Vec<&str> = Vec::new();
+        for segment in full_file_name.split(&['/', '\\'][..]) {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    if segments.pop().is_none() {
+                        return Err(Error::new(
+                            ErrorKind::InvalidInput,
+                            format!("`full_file_name` \"{}\" escapes the archive", full_file_name)
+                        ));
+                    }
+                }
+                segment if segment.trim().is_empty() => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("`full_file_name` \"{}\" has a blank segment", full_file_name)
+                    ));
+                }
+                segment => segments.push(segment),
+            }
+        }
+
+        if segments.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("`full_file_name` \"{}\" is empty", full_file_name)
+            ));
+        }
+        Ok(EntryName(segments.join("/")))
+    }
+
+    /// Normalized name.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Converts into the normalized `String`.
+    pub fn into_string(self) -> String {
+        self.0
+    }
+
+    /// Segments of the name, the last one being the file name.
+    pub fn segments(&self) -> ::std::str::Split<'_, char> {
+        self.0.split('/')
+    }
+
+    /// File name, without the directories.
+    pub fn file_name(&self) -> &str {
+        self.0.rsplit('/').next().unwrap_or(&self.0)
+    }
+
+    /// Path of the entry once extracted into `dest`, built segment by segment
+    /// so that it always stays inside `dest`. On Windows, the segments are
+    /// made valid file names, see `windows_file_name`.
+    pub fn to_path<P: AsRef<Path>>(&self, dest: P) -> PathBuf {
+        let mut path = dest.as_ref().to_path_buf();
+        for segment in self.segments() {
+            if cfg!(windows) {
+                path.push(&*windows_file_name(segment));
+            } else {
+                path.push(segment);
+            }
+        }
+        path
+    }
+
+    /// Path of the file the entry is extracted to in `dest`: `to_path`,
+    /// with the `\\?\` prefix on Windows when it exceeds `MAX_PATH`.
+    pub(crate) fn output_path<P: AsRef<Path>>(&self, dest: P) -> PathBuf {
+        let path = self.to_path(dest);
+        if cfg!(windows) {
+            long_path(path)
+        } else {
+            path
+        }
+    }
+}
+
+/// Names of the devices of Windows, reserved whatever their extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// `segment` made a valid file name on Windows: the characters Windows
+/// forbids are replaced with `_`, so are the trailing dots and spaces it
+/// would drop, and the device names (`CON`, `nul.txt`) are prefixed with
+/// `_`.
+fn windows_file_name(segment: &str) -> Cow<'_, str> {
+    let mut name: String = segment.chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            c if c < ' ' => '_',
+            c => c,
+        })
+        .collect();
+    let kept = name.trim_end_matches(&['.', ' '][..]).len();
+    let dropped = name.len() - kept;
+    name.truncate(kept);
+    name.extend(iter::repeat_n('_', dropped));
+
+    let stem = name.split('.').next().unwrap_or("").trim_end_matches(' ');
+    if RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem)) {
+        name.insert(0, '_');
+    }
+
+    if name == segment {
+        Cow::Borrowed(segment)
+    } else {
+        Cow::Owned(name)
+    }
+}
+
+/// `path` in the extended-length form of Windows (`\\?\C:\...`,
+/// `\\?\UNC\server\share\...`) if it exceeds `MAX_PATH`, as is
+/// otherwise or if it can't be made absolute.
+fn long_path(path: PathBuf) -> PathBuf {
+    const MAX_PATH: usize = 260;
+    if path.as_os_str().len() < MAX_PATH {
+        return path;
+    }
+    let absolute = match path::absolute(&path) {
+        Ok(absolute) => absolute,
+        Err(_) => return path,
+    };
+
+    let mut components = absolute.components();
+    let mut long = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(letter) => PathBuf::from(format!("\\\\?\\{}:\\", letter as char)),
+            Prefix::UNC(server, share) => {
+                let mut long = OsString::from("\\\\?\\UNC\\");
+                long.push(server);
+                long.push("\\");
+                long.push(share);
+                long.push("\\");
+                PathBuf::from(long)
+            }
+            // already verbatim, or a device
+            _ => return absolute,
+        },
+        _ => return absolute,
+    };
+    // the extended-length form isn't normalized by Windows
+    long.extend(components.filter_map(|component| match component {
+        Component::Normal(segment) => Some(segment),
+        _ => None,
+    }));
+    long
+}
+
+/// Key under which `full_file_name` is looked up: its normalized form, or
+/// the name as is if it can't be normalized.
+pub(crate) fn lookup_key(full_file_name: &str) -> Cow<'_, str> {
+    match EntryName::new(full_file_name) {
+        Ok(ref name) if name.as_str() == full_file_name => Cow::Borrowed(full_file_name),
+        Ok(name) => Cow::Owned(name.into_string()),
+        Err(_) => Cow::Borrowed(full_file_name),
+    }
+}
+
+/// Key under which `full_file_name` is looked up by the case-insensitive
+/// lookups: its lookup key in lowercase.
+pub(crate) fn folded_key(full_file_name: &str) -> String {
+    lookup_key(full_file_name).to_lowercase()
+}
+
+/// Index of the case-insensitive lookups: the names by folded key. When
+/// several names differ only by their case, the first one in sorted order
+/// is found by the lookups not matching any exactly.
+pub(crate) fn fold_names<'a, I: Iterator<Item = &'a String>>(names: I) -> HashMap<String, String> {
+    let mut folded: HashMap<String, String> = HashMap::new();
+    for name in names {
+        let key = folded_key(name);
+        if folded.get(&key).is_none_or(|first| name < first) {
+            folded.insert(key, name.clone());
+        }
+    }
+    folded
+}
+
+/// Entry `full_file_name` of `entries`, looked up by its normalized name,
+/// then by its folded key in `folded` if the lookups are case-insensitive.
+pub(crate) fn find_entry<'a, V>(
+    entries: &'a HashMap<String, V>,
+    folded: Option<&HashMap<String, String>>,
+    full_file_name: &str
+) -> Option<(&'a String, &'a V)> {
+    let key = lookup_key(full_file_name);
+    entries.get_key_value(&*key).or_else(|| {
+        let name = folded?.get(&key.to_lowercase())?;
+        entries.get_key_value(name)
+    })
+}
+
+impl fmt::Display for EntryName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for EntryName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for EntryName {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<EntryName> for String {
+    fn from(name: EntryName) -> String {
+        name.0
+    }
+}
@@ -0,0 +1,35 @@
+#[cfg(feature = "offline")]
+use backend::MemSource;
+#[cfg(feature = "offline")]
+use mem::MemArchive;
+#[cfg(feature = "offline")]
+use read::MergeReader;
+#[cfg(feature = "offline")]
+use vfs::{LooseDir, Overlay};
+
+/// Local
+///
+/// Bound of the sources the crate opens the data from by itself: the
+/// `ArchiveSource` of the fragments and the layers of a `PakFs`. Every type
+/// is `Local`, except with the `offline` feature, where only the sources
+/// listed below (files on the disk and memory) are: a dependent can't plug
+/// a network-capable source in, it doesn't compile.
+pub trait Local {}
+
+#[cfg(not(feature = "offline"))]
+impl<T: ?Sized> Local for T {}
+
+#[cfg(feature = "offline")]
+impl Local for MemSource {}
+
+#[cfg(feature = "offline")]
+impl<R> Local for MergeReader<R> {}
+
+#[cfg(feature = "offline")]
+impl Local for MemArchive {}
+
+#[cfg(feature = "offline")]
+impl Local for LooseDir {}
+
+#[cfg(feature = "offline")]
+impl Local for Overlay {}
@@ -0,0 +1,336 @@
+use access::AccessPolicy;
+use codec::NameCodec;
+use compression::Compression;
+use encryption::Encryption;
+use glob::Pattern;
+use hash::HashAlgorithm;
+use key::Keys;
+use raw::Offsets;
+use timeout::Timeouts;
+
+/// InvalidNames
+///
+/// Handling of the entries whose name can't be normalized into an
+/// `EntryName`: empty, made of whitespaces only, or escaping the archive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum InvalidNames {
+    /// Fail to open the archive
+    Reject,
+    /// Leave the entries out, with a warning
+    Skip,
+    /// Rename the entries `unnamed/<fragment>-<index>` (index of the
+    /// fragment in the chain, index of the entry in the chunk table), with a
+    /// warning (default)
+    #[default]
+    Synthesize,
+}
+
+/// Maximum number of fragments of a chain by default.
+pub const DEFAULT_MAX_FRAGMENTS: usize = 1024;
+
+/// ReadLimits
+///
+/// Bounds of the archives accepted by the readers, checked while the tables
+/// are read so that an untrusted archive can't make them allocate or read
+/// without bound. `ReadLimits::default()` only bounds the chains to
+/// `DEFAULT_MAX_FRAGMENTS` fragments, the `Info` of every fragment being
+/// checked against its size; `ReadLimits::untrusted()` suits the archives
+/// received by a server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ReadLimits {
+    /// Maximum number of fragments of a chain
+    pub max_fragments: Option<usize>,
+    /// Maximum number of chunks, all fragments included
+    pub max_chunks: Option<usize>,
+    /// Maximum number of properties, all fragments included
+    pub max_properties: Option<usize>,
+    /// Maximum size of an entry, stored or decompressed
+    pub max_entry_size: Option<u64>,
+    /// Rejects the chunks whose offset or size is negative or whose data is
+    /// out of the fragment when the archive is opened, instead of failing
+    /// when they are read (and of reporting them in `MergeReader::verify`)
+    pub check_chunks: bool,
+}
+
+impl Default for ReadLimits {
+    fn default() -> Self {
+        ReadLimits {
+            max_fragments: Some(DEFAULT_MAX_FRAGMENTS),
+            max_chunks: None,
+            max_properties: None,
+            max_entry_size: None,
+            check_chunks: false,
+        }
+    }
+}
+
+impl ReadLimits {
+    /// Limits of the archives from an untrusted source: 64 fragments, a
+    /// million chunks, 10,000 properties, entries of 1 GiB and the chunks
+    /// checked at opening.
+    pub fn untrusted() -> Self {
+        ReadLimits {
+            max_fragments: Some(64),
+            max_chunks: Some(1_000_000),
+            max_properties: Some(10_000),
+            max_entry_size: Some(1 << 30),
+            check_chunks: true,
+        }
+    }
+}
+
+/// Default size of the buffers.
+const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// ReadStrategy
+///
+/// Reads of the data of the entries by `MergeReader::read_file` and
+/// `MergeReader::read_files`. By default every read seeks and reads the
+/// fragment directly, which suits the SSDs and the entries read once; the
+/// interleaved reads of many small entries from a HDD or a network share
+/// gain from a cache and from reading ahead.
+///
+/// The data is cached as stored, so the compressed and encrypted entries
+/// are still decoded on every read. The memory-mapped fragments and the
+/// reads under a `Timeouts::read` bypass the cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ReadStrategy {
+    /// Maximum of the bytes of data kept in the cache, the least recently
+    /// read evicted first; no cache if 0. The cached data is counted in
+    /// the `memory_limit`, the data beyond it isn't cached
+    pub cache_size: usize,
+    /// Bytes read past the end of an entry missing from the cache, the
+    /// entries of the same fragment entirely read along with it being
+    /// cached too. `read_files` also reads the entries separated by at
+    /// most as many bytes at once
+    pub read_ahead: u64,
+}
+
+impl ReadStrategy {
+    /// Creates a new `ReadStrategy`, reading the fragments directly.
+    pub fn new() -> Self {
+        ReadStrategy::default()
+    }
+
+    /// Caches at most `bytes` of data, see `cache_size`.
+    pub fn cache_size(mut self, bytes: usize) -> Self {
+        self.cache_size = bytes;
+        self
+    }
+
+    /// Reads `bytes` ahead of the entries, see `read_ahead`.
+    pub fn read_ahead(mut self, bytes: u64) -> Self {
+        self.read_ahead = bytes;
+        self
+    }
+
+    /// Returns `true` if the data is cached.
+    pub fn is_cached(&self) -> bool {
+        self.cache_size > 0
+    }
+}
+
+/// ReadOptions
+///
+/// Options of `MergeReader::open_with`.
+///
+/// The best buffer sizes depend on the storage: small ones suit NVMe
+/// drives, large ones limit the round trips to HDDs and network shares.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReadOptions {
+    /// Handling of the invalid entry names
+    pub invalid_names: InvalidNames,
+    /// Size of the buffer reading the `Info`, the chunk table and the
+    /// properties of each fragment
+    pub table_buffer_size: usize,
+    /// Size of the buffer copying the data out of the fragments, when
+    /// extracting or repacking entries
+    pub copy_buffer_size: usize,
+    /// Decoder of the obfuscated entry names
+    pub name_codec: Option<NameCodec>,
+    /// Maximum of the bytes held by the reader (index and pinned data),
+    /// unlimited if `None`
+    pub memory_limit: Option<usize>,
+    /// Timeouts of the opening, the reads and the extractions
+    pub timeouts: Timeouts,
+    /// Hook denying the reads of some entries, see `AccessPolicy`
+    pub access_policy: Option<AccessPolicy>,
+    /// Bounds of the archives accepted, see `ReadLimits`
+    pub limits: ReadLimits,
+    /// Looks the entries up ignoring the case of their names, through an
+    /// index of the lowercase names built when the archive is opened. An
+    /// exact match is preferred; among the names differing only by their
+    /// case, the first in sorted order is found
+    pub case_insensitive: bool,
+    /// Keys of the encrypted entries, asked for by full file name when an
+    /// entry is read. Without keys, the encrypted entries fail with
+    /// `Error::Encrypted`
+    pub keys: Option<Keys>,
+    /// Cache and read-ahead of the data, see `ReadStrategy`
+    pub read_strategy: ReadStrategy,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            invalid_names: InvalidNames::default(),
+            table_buffer_size: DEFAULT_BUFFER_SIZE,
+            copy_buffer_size: DEFAULT_BUFFER_SIZE,
+            name_codec: None,
+            memory_limit: None,
+            timeouts: Timeouts::default(),
+            access_policy: None,
+            limits: ReadLimits::default(),
+            case_insensitive: false,
+            keys: None,
+            read_strategy: ReadStrategy::default(),
+        }
+    }
+}
+
+/// WriteOptions
+///
+/// Options of the writes of `Archive`, `MemArchive` and `PakWriter`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Size of the buffer staging the data before it is written to the file
+    pub buffer_size: usize,
+    /// Encoder of the entry names
+    pub name_codec: Option<NameCodec>,
+    /// Bytes reserved between the data and the tables, so that entries can
+    /// later be added without moving the tables, see
+    /// `Archive::commit_in_place`
+    pub slack: u64,
+    /// Algorithm of the checksums stored for the chunks, none if `None`,
+    /// see `Checksum`
+    pub checksums: Option<HashAlgorithm>,
+    /// Writes the chunk table sorted by full file name and the properties
+    /// sorted by key, whatever the order the entries and the properties
+    /// were added in, so that the same content gives the same bytes. The
+    /// commits of an `Archive` also write the data sorted by name; the
+    /// data written by a `PakWriter` stays in the order of the additions
+    pub canonical: bool,
+    /// Compression of the data of the entries added, none if `None`. The
+    /// chunks copied from another archive keep their compression. The
+    /// checksums are computed on the data as stored
+    pub compression: Option<Compression>,
+    /// Width of the offsets of the tables. The `Classic` fragments fail to
+    /// be written beyond 2 GiB, the `Extended` ones are only read by the
+    /// readers knowing `EXTENDED_HEADER`
+    pub offsets: Offsets,
+    /// Algorithm hashing the data of the entries added to write the
+    /// identical data once per fragment, the chunks of the copies pointing
+    /// at the same range; none if `None`. The contents are told apart by
+    /// their digest only, so the algorithm must resist collisions
+    /// (`HashBackend::is_cryptographic`, `Blake3` or `Sha256`): the writers
+    /// fail with `InvalidInput` otherwise. The data of an entry is held in
+    /// memory to be hashed before it is written. The chunks copied from
+    /// another archive are written as is
+    pub dedup: Option<HashAlgorithm>,
+    /// Encryption of the data of the entries added, none if `None`, with
+    /// the key `keys` gives for every entry. The entries encrypted aren't
+    /// deduplicated, their keys may differ. The chunks copied from another
+    /// archive keep their encryption
+    pub encryption: Option<Encryption>,
+    /// Keys of the entries added, required by the `encryption`
+    pub keys: Option<Keys>,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            name_codec: None,
+            slack: 0,
+            checksums: None,
+            canonical: false,
+            compression: None,
+            offsets: Offsets::Classic,
+            dedup: None,
+            encryption: None,
+            keys: None,
+        }
+    }
+}
+
+/// ExistingFiles
+///
+/// Handling of the files already at the path an entry is extracted to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ExistingFiles {
+    /// Replace the file (default)
+    #[default]
+    Overwrite,
+    /// Keep the file and leave the entry out
+    Skip,
+    /// Fail with `ErrorKind::AlreadyExists`
+    Fail,
+}
+
+/// ExtractOptions
+///
+/// Options of `MergeReader::extract_with`.
+///
+/// The entry names never lead out of the destination: they are normalized
+/// into `EntryName`s when the archive is opened, a name escaping the
+/// archive being handled according to `ReadOptions::invalid_names`. The
+/// symbolic links already in the destination are refused by default, so
+/// that an entry can't be written through one.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExtractOptions {
+    /// Handling of the existing files
+    pub existing: ExistingFiles,
+    /// Writes through the symbolic links met under the destination instead
+    /// of failing with `ErrorKind::PermissionDenied`. The links are checked
+    /// before every entry is written, `SandboxDir` also guards against the
+    /// ones created meanwhile
+    pub follow_symlinks: bool,
+    /// Lists the entries which would be written or skipped without
+    /// touching the destination
+    pub dry_run: bool,
+    /// Extracts the entries whose full file name matches the pattern only,
+    /// every one if `None`
+    pub filter: Option<Pattern>,
+    /// Creates the directories of the entries left out by the `filter` too,
+    /// so that the directories of the archive are all there, empty or not
+    pub preserve_empty_dirs: bool,
+}
+
+impl ExtractOptions {
+    /// Creates new `ExtractOptions`, overwriting the existing files.
+    pub fn new() -> Self {
+        ExtractOptions::default()
+    }
+
+    /// Handles the existing files according to `existing`.
+    pub fn existing(mut self, existing: ExistingFiles) -> Self {
+        self.existing = existing;
+        self
+    }
+
+    /// Writes through the symbolic links of the destination, see
+    /// `follow_symlinks`.
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Only lists what would be extracted, see `dry_run`.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Extracts the entries matching `pattern` only.
+    pub fn filter(mut self, pattern: Pattern) -> Self {
+        self.filter = Some(pattern);
+        self
+    }
+
+    /// Creates the directories of the entries left out, see
+    /// `preserve_empty_dirs`.
+    pub fn preserve_empty_dirs(mut self, preserve: bool) -> Self {
+        self.preserve_empty_dirs = preserve;
+        self
+    }
+}
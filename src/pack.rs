@@ -0,0 +1,279 @@
+use codec::NameCodec;
+use error::entry_exists;
+use hash::HashAlgorithm;
+use name::EntryName;
+use options::WriteOptions;
+use progress::{ProgressEvent, Reporting};
+use properties::check_property_key;
+use repack::fragment_path;
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use write::FragmentWriter;
+
+/// Path of the temporary file of the fragment `path`.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp = OsString::from(path);
+    temp.push(".tmp");
+    PathBuf::from(temp)
+}
+
+/// Lists recursively the files of `dir`, sorted by the entry names they
+/// are packed under: their paths relative to `root`, joined with `/`. The
+/// symbolic links to directories aren't followed.
+fn find_files(root: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            find_files(root, &path, files)?;
+        } else if path.is_file() {
+            let relative = path.strip_prefix(root).expect("the walk starts at `root`");
+            let segments: Option<Vec<&str>> = relative.iter().map(|segment| segment.to_str()).collect();
+            match segments {
+                Some(segments) => files.push((segments.join("/"), path.clone())),
+                None => return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("\"{}\" isn't a valid UTF-8 entry name", relative.display())
+                )),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Files of the directory `src_dir` to pack into `dest`, with their entry
+/// names. Fails if `dest` would be written inside `src_dir`.
+pub(crate) fn dir_entries(src_dir: &Path, dest: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let root = fs::canonicalize(src_dir)?;
+    let dest_dir = match dest.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::canonicalize(parent)?,
+        _ => env::current_dir()?,
+    };
+    if dest_dir.starts_with(&root) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("\"{}\" is inside the packed directory \"{}\"", dest.display(), src_dir.display())
+        ));
+    }
+
+    let mut files = Vec::new();
+    find_files(&root, &root, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+/// Adds the `files`, with their entry names, through `add`, reporting the
+/// progress to `progress`: the bytes read from the files are the bytes
+/// written.
+pub(crate) fn add_files<A, F>(files: &[(String, PathBuf)], mut add: A, progress: &mut F) -> io::Result<()>
+    where A: FnMut(&str, &mut Reporting<File>) -> io::Result<u64>,
+          F: FnMut(&ProgressEvent)
+{
+    let mut bytes = 0;
+    for (_, path) in files {
+        bytes += fs::metadata(path)?.len();
+    }
+    progress(&ProgressEvent::Started { entries: files.len(), bytes });
+
+    let mut written = 0;
+    for (full_file_name, path) in files {
+        let file = File::open(path)?;
+        progress(&ProgressEvent::EntryStarted { full_file_name, size: file.metadata()?.len() });
+        let size = {
+            let mut report = |bytes| progress(&ProgressEvent::Written { full_file_name, bytes });
+            add(full_file_name, &mut Reporting { inner: file, report: &mut report })?
+        };
+        written += size;
+        progress(&ProgressEvent::EntryFinished { full_file_name, size });
+    }
+    progress(&ProgressEvent::Finished { entries: files.len(), bytes: written });
+    Ok(())
+}
+
+/// ChainPacker
+///
+/// Packs a stream of entries into a new fragment chain starting at `head`,
+/// for data sets larger than the memory: the data of every entry is written
+/// once, directly into the temporary file of the current fragment
+/// (`<fragment>.tmp`). Once a fragment holds `max_fragment_size` bytes of
+/// data, its tables and its link are written and the next entries go into
+/// the next fragment. `finish` writes the tables of the last fragment,
+/// with the properties, and moves the temporary files to their final
+/// paths, so that an interrupted pack never leaves a partial chain behind.
+///
+/// An entry is never split: a fragment may exceed `max_fragment_size` by
+/// the size of its last entry.
+pub struct ChainPacker {
+    head: PathBuf,
+    max_fragment_size: u64,
+    buffer_size: usize,
+    name_codec: Option<NameCodec>,
+    checksums: Option<HashAlgorithm>,
+    canonical: bool,
+    /// Writer of the current fragment
+    current: Option<FragmentWriter<BufWriter<File>>>,
+    /// Temporary files of the fragments, the current one included
+    temps: Vec<PathBuf>,
+    properties: Vec<(String, String)>,
+    names: HashSet<String>,
+}
+
+impl ChainPacker {
+    /// Creates a new `ChainPacker` writing the chain starting at `head`.
+    pub fn create<P: AsRef<Path>>(head: P, max_fragment_size: u64) -> io::Result<Self> {
+        ChainPacker::create_with(head, max_fragment_size, &WriteOptions::default())
+    }
+
+    /// Creates a new `ChainPacker` with the given write `options`.
+    pub fn create_with<P: AsRef<Path>>(head: P, max_fragment_size: u64, options: &WriteOptions) -> io::Result<Self> {
+        if max_fragment_size == 0 || max_fragment_size > i32::MAX as u64 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid maximum fragment size {}", max_fragment_size)
+            ));
+        }
+        Ok(ChainPacker {
+            head: head.as_ref().to_path_buf(),
+            max_fragment_size,
+            buffer_size: options.buffer_size,
+            name_codec: options.name_codec.clone(),
+            checksums: options.checksums,
+            canonical: options.canonical,
+            current: None,
+            temps: Vec::new(),
+            properties: Vec::new(),
+            names: HashSet::new(),
+        })
+    }
+
+    /// Number of fragments started so far.
+    pub fn fragment_count(&self) -> usize {
+        self.temps.len()
+    }
+
+    /// Sets a property of the archive, written in the last fragment,
+    /// failing with `InvalidInput` if it is reserved, see
+    /// `is_reserved_property`: the packer links the fragments itself.
+    pub fn set_property<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) -> io::Result<()> {
+        let key = key.into();
+        check_property_key(&key)?;
+        self.properties.retain(|(k, _)| *k != key);
+        self.properties.push((key, value.into()));
+        Ok(())
+    }
+
+    /// Starts a new fragment, finishing the current one with a link to it.
+    fn next_fragment(&mut self) -> io::Result<()> {
+        let index = self.temps.len();
+        if let Some(mut writer) = self.current.take() {
+            let link = fragment_path(&self.head, index);
+            let link = link.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            writer.add_property("link", &link);
+            writer.finish()?.into_inner().map_err(Error::from)?.sync_all()?;
+        }
+
+        let temp = temp_path(&fragment_path(&self.head, index));
+        let file = File::create(&temp)?;
+        self.temps.push(temp);
+        let writer = FragmentWriter::with_options(BufWriter::with_capacity(self.buffer_size, file), &WriteOptions {
+            name_codec: self.name_codec.clone(),
+            checksums: self.checksums,
+            canonical: self.canonical,
+            ..WriteOptions::default()
+        })?;
+        self.current = Some(writer);
+        Ok(())
+    }
+
+    /// Adds the entry `full_file_name` with the data read from `data`,
+    /// returning its size.
+    pub fn add<R: Read>(&mut self, full_file_name: &str, data: &mut R) -> io::Result<u64> {
+        let full_file_name = EntryName::new(full_file_name)?.into_string();
+        if self.names.contains(&full_file_name) {
+            return Err(entry_exists(&full_file_name));
+        }
+
+        let full = match self.current {
+            Some(ref writer) => writer.position() >= self.max_fragment_size,
+            None => true,
+        };
+        if full {
+            self.next_fragment()?;
+        }
+
+        let writer = self.current.as_mut().expect("a fragment is started");
+        let size = writer.add(&full_file_name, data)?;
+        if writer.position() > i32::MAX as u64 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("`full_file_name` \"{}\" overflows its fragment", full_file_name)
+            ));
+        }
+        self.names.insert(full_file_name);
+        Ok(size)
+    }
+
+    /// Adds the entry `full_file_name` containing `data`.
+    pub fn add_bytes(&mut self, full_file_name: &str, data: &[u8]) -> io::Result<u64> {
+        self.add(full_file_name, &mut &data[..])
+    }
+
+    /// Packs the files of the directory `src_dir` into a new chain starting
+    /// at `head`, their paths relative to `src_dir` being the entry names,
+    /// see `PakWriter::pack_dir`. Returns the fragments in the order of the
+    /// chain.
+    pub fn pack_dir<P: AsRef<Path>, Q: AsRef<Path>>(src_dir: P, head: Q, max_fragment_size: u64) -> io::Result<Vec<PathBuf>> {
+        ChainPacker::pack_dir_with_progress(src_dir, head, max_fragment_size, |_| {})
+    }
+
+    /// Packs the files of the directory `src_dir` like `pack_dir`,
+    /// reporting the progress to `progress`.
+    pub fn pack_dir_with_progress<P, Q, F>(src_dir: P, head: Q, max_fragment_size: u64, mut progress: F) -> io::Result<Vec<PathBuf>>
+        where P: AsRef<Path>,
+              Q: AsRef<Path>,
+              F: FnMut(&ProgressEvent)
+    {
+        let files = dir_entries(src_dir.as_ref(), head.as_ref())?;
+        let mut packer = ChainPacker::create(head, max_fragment_size)?;
+        add_files(&files, |full_file_name, data| packer.add(full_file_name, data), &mut progress)?;
+        packer.finish()
+    }
+
+    /// Writes the tables of the last fragment and moves the fragments to
+    /// their final paths, returning them in the order of the chain.
+    pub fn finish(mut self) -> io::Result<Vec<PathBuf>> {
+        if self.current.is_none() {
+            self.next_fragment()?;
+        }
+        let mut writer = self.current.take().expect("a fragment is started");
+        for (key, value) in &self.properties {
+            writer.add_property(key, value);
+        }
+        writer.finish()?.into_inner().map_err(Error::from)?.sync_all()?;
+
+        let mut paths = Vec::with_capacity(self.temps.len());
+        for (index, temp) in self.temps.iter().enumerate() {
+            let path = fragment_path(&self.head, index);
+            fs::rename(temp, &path)?;
+            paths.push(path);
+        }
+        self.temps.clear();
+        Ok(paths)
+    }
+}
+
+impl Drop for ChainPacker {
+    /// Removes the temporary files of an unfinished pack.
+    fn drop(&mut self) {
+        self.current.take();
+        for temp in &self.temps {
+            let _ = fs::remove_file(temp);
+        }
+    }
+}
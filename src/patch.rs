@@ -0,0 +1,271 @@
+use access::check_access;
+use archive::temp_path;
+use byteorder_extended::{ReadExt, WriteExt};
+use diff::diff;
+use hash::{Digest, HashAlgorithm};
+use options::WriteOptions;
+use raw::{Property, read_u64, write_u64};
+use read::{MergedChunk, MergeReader};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Seek, Write};
+use std::path::Path;
+use write::PakWriter;
+
+/// Magic bytes starting a patch.
+static PATCH_MAGIC: &[u8; 4] = b"PPAT";
+
+/// Version of the patch format.
+const PATCH_VERSION: u8 = 1;
+
+/// Algorithm of the digests of the base entries.
+const BASE_ALGORITHM: HashAlgorithm = HashAlgorithm::Crc32;
+
+/// PatchEntry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatchEntry {
+    /// Full file name
+    pub full_file_name: String,
+    /// Data of the entry in the patched archive
+    pub data: Vec<u8>,
+}
+
+/// Patch
+///
+/// Delta between two versions of an archive, see `Patch::between`: only
+/// the entries added or modified are stored, with their new data, so that
+/// the new version is rebuilt from the old one by `apply_patch`. The CRC32
+/// digests of the base entries the patch removes or replaces are stored as
+/// well, so that a patch fails to apply to another version. The entries
+/// without a base digest are the added ones, which the base must not have.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Patch {
+    /// Entries of the base removed by the patch, sorted
+    pub removed: Vec<String>,
+    /// Entries added or replaced by the patch, sorted by full file name
+    pub entries: Vec<PatchEntry>,
+    /// Properties of the patched archive, the `link` ones excluded
+    pub properties: Vec<Property>,
+    /// Digests of the base entries removed or replaced
+    pub base: BTreeMap<String, Digest>,
+}
+
+impl Patch {
+    /// Computes the patch turning the archive `old` into `new`. The entries
+    /// are compared like `diff` does. Fails with `Error::AccessDenied` if
+    /// the `AccessPolicy` of `new` denies an entry added or modified.
+    pub fn between<A, B>(old: &MergeReader<A>, new: &MergeReader<B>) -> io::Result<Self>
+    where
+        A: Read + Seek,
+        B: Read + Seek
+    {
+        let diff = diff(old, new)?;
+
+        let mut base = BTreeMap::new();
+        for full_file_name in diff.removed.iter().chain(&diff.modified) {
+            base.insert(full_file_name.clone(), old.chunks[full_file_name].digest(&BASE_ALGORITHM)?);
+        }
+
+        let mut names: Vec<&String> = diff.added.iter().chain(&diff.modified).collect();
+        names.sort();
+        let mut entries = Vec::with_capacity(names.len());
+        for full_file_name in names {
+            entries.push(PatchEntry {
+                full_file_name: full_file_name.clone(),
+                data: new.allowed_chunk(full_file_name)?.data()?,
+            });
+        }
+
+        let mut properties: Vec<Property> = new.properties.iter()
+            .filter(|&(key, _)| key != "link")
+            .map(|(key, value)| Property::new(key.clone(), value.clone()))
+            .collect();
+        properties.sort_by(|a, b| a.key.cmp(&b.key));
+
+        Ok(Patch { removed: diff.removed, entries, properties, base })
+    }
+
+    /// Returns `true` if the patch changes no entry.
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.entries.is_empty()
+    }
+
+    /// Reads a `Patch` from the specified reader.
+    pub fn from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != PATCH_MAGIC || reader.read_u8()? != PATCH_VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, "Corrupted patch header"));
+        }
+
+        let removed_count = reader.read_i32()?;
+        let mut removed = Vec::new();
+        for _ in 0..removed_count {
+            removed.push(reader.read_string()?);
+        }
+
+        let entries_count = reader.read_i32()?;
+        let mut entries = Vec::new();
+        for _ in 0..entries_count {
+            let full_file_name = reader.read_string()?;
+            let size = read_u64(reader)?;
+            let mut data = Vec::new();
+            reader.take(size).read_to_end(&mut data)?;
+            if data.len() as u64 != size {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!("truncated patch entry \"{}\"", full_file_name)
+                ));
+            }
+            entries.push(PatchEntry { full_file_name, data });
+        }
+
+        let properties_count = reader.read_i32()?;
+        let mut properties = Vec::new();
+        for _ in 0..properties_count {
+            properties.push(Property::from(reader)?);
+        }
+
+        let base_count = reader.read_i32()?;
+        let mut base = BTreeMap::new();
+        for _ in 0..base_count {
+            let full_file_name = reader.read_string()?;
+            let mut bytes = vec![0; reader.read_u8()? as usize];
+            reader.read_exact(&mut bytes)?;
+            base.insert(full_file_name, Digest::new(bytes));
+        }
+
+        Ok(Patch { removed, entries, properties, base })
+    }
+
+    /// Writes the `Patch` in the specified writer.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(PATCH_MAGIC)?;
+        writer.write_u8(PATCH_VERSION)?;
+
+        writer.write_i32(self.removed.len() as i32)?;
+        for full_file_name in &self.removed {
+            writer.write_string(full_file_name)?;
+        }
+
+        writer.write_i32(self.entries.len() as i32)?;
+        for entry in &self.entries {
+            writer.write_string(&entry.full_file_name)?;
+            write_u64(writer, entry.data.len() as u64)?;
+            writer.write_all(&entry.data)?;
+        }
+
+        writer.write_i32(self.properties.len() as i32)?;
+        for property in &self.properties {
+            property.write(writer)?;
+        }
+
+        writer.write_i32(self.base.len() as i32)?;
+        for (full_file_name, digest) in &self.base {
+            writer.write_string(full_file_name)?;
+            writer.write_u8(digest.as_bytes().len() as u8)?;
+            writer.write_all(digest.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Loads a `Patch` from the file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Patch::from(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Saves the `Patch` to the file at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+}
+
+/// Writes into the new pak file `out` the archive `base` updated by
+/// `patch`: the entries of `base` the patch doesn't touch are copied in the
+/// order of the fragments, followed by the entries of the patch, and the
+/// properties are the ones of the patch.
+///
+/// Fails without writing anything if an entry removed or replaced by the
+/// patch is missing from `base` or differs from the one the patch was
+/// computed from, if an entry added by the patch already exists in `base`,
+/// or if the `AccessPolicy` of `base` denies an entry to copy. The archive
+/// is written into a temporary file renamed to `out` once complete, so
+/// `out` is left untouched on failure. `out` must not be a fragment of
+/// `base`.
+pub fn apply_patch<R, P>(base: &MergeReader<R>, patch: &Patch, out: P) -> io::Result<()>
+where
+    R: Read + Seek,
+    P: AsRef<Path>
+{
+    for (full_file_name, digest) in &patch.base {
+        let matches = match base.chunks.get(full_file_name) {
+            Some(chunk) => chunk.digest(&BASE_ALGORITHM)? == *digest,
+            None => false,
+        };
+        if !matches {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("the patch doesn't apply: `full_file_name` \"{}\" differs from its base", full_file_name)
+            ));
+        }
+    }
+    for entry in &patch.entries {
+        if !patch.base.contains_key(&entry.full_file_name) && base.chunks.contains_key(&entry.full_file_name) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("the patch doesn't apply: `full_file_name` \"{}\" already exists in its base", entry.full_file_name)
+            ));
+        }
+    }
+
+    let replaced: HashSet<&str> = patch.removed.iter()
+        .map(|full_file_name| full_file_name.as_str())
+        .chain(patch.entries.iter().map(|entry| entry.full_file_name.as_str()))
+        .collect();
+    let mut kept: Vec<_> = base.iter()
+        .filter(|&(full_file_name, _)| !replaced.contains(full_file_name.as_str()))
+        .collect();
+    // sequential reads
+    kept.sort_by_key(|&(full_file_name, chunk)| (chunk.fragment(), chunk.offset(), full_file_name.clone()));
+    for &(full_file_name, _) in &kept {
+        check_access(&base.options.access_policy, full_file_name)?;
+    }
+
+    let out = out.as_ref();
+    let temp = temp_path(out);
+    let written = write_patched(base, patch, kept, &temp).and_then(|()| fs::rename(&temp, out));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+/// Writes into the new pak file `path` the entries `kept` of `base`,
+/// followed by the entries and properties of `patch`.
+fn write_patched<R: Read + Seek>(
+    base: &MergeReader<R>,
+    patch: &Patch,
+    kept: Vec<(&String, &MergedChunk<R>)>,
+    path: &Path
+) -> io::Result<()> {
+    let mut writer = PakWriter::create_with(path, &WriteOptions {
+        buffer_size: base.options.copy_buffer_size,
+        ..WriteOptions::default()
+    })?;
+    for (full_file_name, chunk) in kept {
+        let name = writer.reserve_name(full_file_name)?;
+        writer.writer.add_chunk(&name, chunk)?;
+    }
+    for entry in &patch.entries {
+        writer.add_bytes(&entry.full_file_name, &entry.data)?;
+    }
+    for property in &patch.properties {
+        writer.set_property(&property.key, &property.value)?;
+    }
+    writer.finish()
+}
@@ -0,0 +1,118 @@
+use memory::Charge;
+#[cfg(all(unix, feature = "mmap"))]
+use mmap::Mapping;
+use read::MergeReader;
+use std::fmt;
+use std::io;
+use std::io::{Read, Seek};
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// Copy of the data, charged to the memory account of its reader.
+struct Owned {
+    data: Vec<u8>,
+    _charge: Charge,
+}
+
+/// Storage kept alive by a `PinnedBytes`.
+#[derive(Clone)]
+enum Storage {
+    /// Copy of the data
+    Owned(Arc<Owned>),
+    /// Memory map of the fragment
+    #[cfg(all(unix, feature = "mmap"))]
+    Mapped(Arc<Mapping>),
+}
+
+/// PinnedBytes
+///
+/// Handle on the data of an entry, cheap to clone and `Send`, which stays
+/// valid as long as it is held, independently of the reader. Engines can
+/// keep it across frames instead of copying the data again.
+#[derive(Clone)]
+pub struct PinnedBytes {
+    storage: Storage,
+    start: usize,
+    len: usize,
+}
+
+impl PinnedBytes {
+    fn owned(data: Vec<u8>, charge: Charge) -> Self {
+        PinnedBytes {
+            len: data.len(),
+            storage: Storage::Owned(Arc::new(Owned { data, _charge: charge })),
+            start: 0,
+        }
+    }
+
+    /// Handle on a whole memory map, charged to no reader: the memory is
+    /// owned by the system.
+    #[cfg(all(unix, feature = "mmap"))]
+    pub(crate) fn mapped(mapping: Arc<Mapping>) -> Self {
+        PinnedBytes {
+            len: mapping.as_slice().len(),
+            storage: Storage::Mapped(mapping),
+            start: 0,
+        }
+    }
+
+    /// Pinned data.
+    pub fn as_slice(&self) -> &[u8] {
+        match self.storage {
+            Storage::Owned(ref owned) => &owned.data[self.start..self.start + self.len],
+            #[cfg(all(unix, feature = "mmap"))]
+            Storage::Mapped(ref mapping) => &mapping.as_slice()[self.start..self.start + self.len],
+        }
+    }
+
+    /// Handle on the sub-range `start..start + len` of the data, sharing the
+    /// same storage.
+    pub fn slice(&self, start: usize, len: usize) -> Option<PinnedBytes> {
+        match start.checked_add(len) {
+            Some(end) if end <= self.len => Some(PinnedBytes {
+                storage: self.storage.clone(),
+                start: self.start + start,
+                len,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Deref for PinnedBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl AsRef<[u8]> for PinnedBytes {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl fmt::Debug for PinnedBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PinnedBytes({} bytes)", self.len)
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Pins the data of the entry `full_file_name`, charged to the memory
+    /// usage of the reader until every handle on it is dropped. The data of
+    /// memory-mapped fragments is shared instead of being copied. Fails
+    /// with `Error::AccessDenied` if the `AccessPolicy` denies the entry.
+    pub fn pin(&self, full_file_name: &str) -> io::Result<PinnedBytes> {
+        let chunk = self.allowed_chunk(full_file_name)?;
+        if let Some(ref mapped) = chunk.mapped {
+            return Ok(mapped.clone());
+        }
+        let charge = self.charge(chunk.uncompressed_size() as usize)?;
+        Ok(PinnedBytes::owned(chunk.data()?, charge))
+    }
+}
@@ -0,0 +1,197 @@
+use read::{MergeReader, MergedChunk};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::io;
+use std::io::{Read, Seek};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Function classifying the first bytes of a content.
+type Classify<T> = Arc<dyn Fn(&[u8]) -> Option<T> + Send + Sync>;
+
+/// Probe
+///
+/// A classification rule of a `ContentRegistry`.
+#[derive(Clone)]
+struct Probe<T> {
+    /// Number of leading bytes the probe inspects
+    len: usize,
+    classify: Classify<T>,
+}
+
+/// ContentRegistry
+///
+/// Classifies the entries by their content into the types `T` of the
+/// consumer, so that the formats of a game (SWL, DLM, ELE, audio banks)
+/// are described outside of the crate. The probes are tried in the order
+/// they were registered, and the first match wins: the specific probes
+/// must be registered before the generic ones.
+///
+/// ``` no_run
+/// # use pak::{ContentRegistry, MergeReader};
+/// # fn main() -> std::io::Result<()> {
+/// #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+/// enum Asset { Swf, Map }
+///
+/// let mut registry = ContentRegistry::new();
+/// registry.register_magic(0, b"FWS", Asset::Swf);
+/// registry.register_magic(0, b"CWS", Asset::Swf);
+/// registry.register(1, |data| if data[0] == 0x4d { Some(Asset::Map) } else { None });
+///
+/// let reader = MergeReader::open("maps0.d2p")?;
+/// let kinds = reader.classify_all(&registry)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Only the first bytes of the entries are read, as many as the longest
+/// probe inspects.
+#[derive(Clone)]
+pub struct ContentRegistry<T> {
+    probes: Vec<Probe<T>>,
+}
+
+impl<T: Clone> ContentRegistry<T> {
+    /// Creates a new empty `ContentRegistry`.
+    pub fn new() -> Self {
+        ContentRegistry { probes: Vec::new() }
+    }
+
+    /// Registers a probe inspecting the first `len` bytes of the entries,
+    /// `classify` returning the type of the content, or `None` to try the
+    /// next probes. The entries shorter than `len` are not submitted to it.
+    pub fn register<F>(&mut self, len: usize, classify: F) -> &mut Self
+        where F: Fn(&[u8]) -> Option<T> + Send + Sync + 'static
+    {
+        self.probes.push(Probe { len, classify: Arc::new(classify) });
+        self
+    }
+
+    /// Registers the entries holding the bytes `magic` at `offset` as
+    /// `kind`.
+    pub fn register_magic(&mut self, offset: usize, magic: &[u8], kind: T) -> &mut Self
+        where T: Send + Sync + 'static
+    {
+        let magic = magic.to_vec();
+        self.register(offset + magic.len(), move |data| {
+            if data[offset..].starts_with(&magic) { Some(kind.clone()) } else { None }
+        })
+    }
+
+    /// Number of registered probes.
+    pub fn len(&self) -> usize {
+        self.probes.len()
+    }
+
+    /// Returns `true` if no probe is registered.
+    pub fn is_empty(&self) -> bool {
+        self.probes.is_empty()
+    }
+
+    /// Number of leading bytes needed by the probes.
+    pub fn sniff_len(&self) -> usize {
+        self.probes.iter().map(|probe| probe.len).max().unwrap_or(0)
+    }
+
+    /// Type of the content starting with `data`, by the first matching
+    /// probe. `data` may be the whole content or only its first
+    /// `sniff_len` bytes.
+    pub fn classify(&self, data: &[u8]) -> Option<T> {
+        self.probes.iter()
+            .filter(|probe| data.len() >= probe.len)
+            .filter_map(|probe| (probe.classify)(data))
+            .next()
+    }
+
+    /// Type of the data of `chunk`, reading only its first bytes.
+    pub fn classify_chunk<R>(&self, chunk: &MergedChunk<R>) -> io::Result<Option<T>>
+        where R: Read + Seek
+    {
+        let mut head = Vec::with_capacity(self.sniff_len());
+        chunk.decoded_reader()?.take(self.sniff_len() as u64).read_to_end(&mut head)?;
+        Ok(self.classify(&head))
+    }
+}
+
+impl<T: Clone> Default for ContentRegistry<T> {
+    fn default() -> Self {
+        ContentRegistry::new()
+    }
+}
+
+impl<T> fmt::Debug for ContentRegistry<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ContentRegistry")
+            .field("probes", &self.probes.len())
+            .finish()
+    }
+}
+
+/// KindStats
+///
+/// Entries of a content type, see `MergeReader::kind_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct KindStats {
+    /// Number of entries
+    pub entries: usize,
+    /// Total size of their data
+    pub bytes: u64,
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Type of the entry `full_file_name` according to `registry`, reading
+    /// only its first bytes.
+    pub fn classify_entry<T: Clone>(&self, full_file_name: &str, registry: &ContentRegistry<T>) -> io::Result<Option<T>> {
+        registry.classify_chunk(self.allowed_chunk(full_file_name)?)
+    }
+
+    /// Types of the entries, by full file name, `None` for the entries no
+    /// probe recognizes. The entries denied by the `AccessPolicy` are
+    /// skipped.
+    pub fn classify_all<T: Clone>(&self, registry: &ContentRegistry<T>) -> io::Result<HashMap<String, Option<T>>> {
+        let mut kinds = HashMap::with_capacity(self.chunks.len());
+        for (full_file_name, chunk) in self.chunks.iter().filter(|&(name, _)| self.allows(name)) {
+            kinds.insert(full_file_name.clone(), registry.classify_chunk(chunk)?);
+        }
+        Ok(kinds)
+    }
+
+    /// Number and size of the entries of each type, `None` counting the
+    /// entries no probe recognizes.
+    pub fn kind_stats<T>(&self, registry: &ContentRegistry<T>) -> io::Result<HashMap<Option<T>, KindStats>>
+        where T: Clone + Eq + Hash
+    {
+        let mut stats: HashMap<Option<T>, KindStats> = HashMap::new();
+        for (full_file_name, kind) in self.classify_all(registry)? {
+            let kind_stats = stats.entry(kind).or_default();
+            kind_stats.entries += 1;
+            kind_stats.bytes += self.chunks[&full_file_name].size();
+        }
+        Ok(stats)
+    }
+
+    /// Extracts into `dest` the entries whose type, according to
+    /// `registry`, satisfies `filter`, and returns their names, sorted.
+    pub fn extract_kind<T, F, P>(&self, dest: P, registry: &ContentRegistry<T>, filter: F) -> io::Result<Vec<String>>
+    where
+        T: Clone,
+        F: Fn(Option<&T>) -> bool,
+        P: AsRef<Path>
+    {
+        let mut names: Vec<String> = self.classify_all(registry)?
+            .into_iter()
+            .filter(|(_, kind)| filter(kind.as_ref()))
+            .map(|(full_file_name, _)| full_file_name)
+            .collect();
+        names.sort();
+
+        for full_file_name in &names {
+            self.extract_entry(full_file_name, dest.as_ref())?;
+        }
+        Ok(names)
+    }
+}
@@ -0,0 +1,147 @@
+use std::io;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+/// ProgressEvent
+///
+/// Step of a long operation reported to its progress callback, see
+/// `MergeReader::extract_with_progress` and `PakWriter::pack_dir_with_progress`.
+/// The entries processed in parallel report their events interleaved.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProgressEvent<'a> {
+    /// The operation starts, on `entries` entries of `bytes` bytes in total
+    Started { entries: usize, bytes: u64 },
+    /// The entry `full_file_name`, of `size` bytes, starts
+    EntryStarted { full_file_name: &'a str, size: u64 },
+    /// `bytes` more bytes of the entry `full_file_name` were processed
+    Written { full_file_name: &'a str, bytes: u64 },
+    /// The entry `full_file_name` is done, of `size` bytes
+    EntryFinished { full_file_name: &'a str, size: u64 },
+    /// The operation is done, on `entries` entries of `bytes` bytes
+    Finished { entries: usize, bytes: u64 },
+}
+
+/// ProgressTracker
+///
+/// Totals of an operation, updated with its `ProgressEvent`s, to render a
+/// progress bar and estimate the remaining time.
+#[derive(Clone, Debug)]
+pub struct ProgressTracker {
+    entries: usize,
+    bytes: u64,
+    entries_done: usize,
+    bytes_done: u64,
+    start: Instant,
+}
+
+impl ProgressTracker {
+    /// Creates a new `ProgressTracker`, timing from now.
+    pub fn new() -> Self {
+        ProgressTracker {
+            entries: 0,
+            bytes: 0,
+            entries_done: 0,
+            bytes_done: 0,
+            start: Instant::now(),
+        }
+    }
+
+    /// Updates the totals with `event`. `Started` restarts the timing.
+    pub fn update(&mut self, event: &ProgressEvent) {
+        match *event {
+            ProgressEvent::Started { entries, bytes } => {
+                *self = ProgressTracker { entries, bytes, ..ProgressTracker::new() };
+            }
+            ProgressEvent::EntryStarted { .. } => {}
+            ProgressEvent::Written { bytes, .. } => self.bytes_done += bytes,
+            ProgressEvent::EntryFinished { .. } => self.entries_done += 1,
+            ProgressEvent::Finished { entries, bytes } => {
+                self.entries_done = entries;
+                self.bytes_done = bytes;
+            }
+        }
+    }
+
+    /// Number of entries of the operation.
+    pub fn entries(&self) -> usize {
+        self.entries
+    }
+
+    /// Number of entries done.
+    pub fn entries_done(&self) -> usize {
+        self.entries_done
+    }
+
+    /// Size of the entries of the operation.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Number of bytes processed.
+    pub fn bytes_done(&self) -> u64 {
+        self.bytes_done
+    }
+
+    /// Part of the operation done, between 0 and 1: by bytes, by entries if
+    /// the entries are empty.
+    pub fn fraction(&self) -> f64 {
+        let fraction = match (self.bytes, self.entries) {
+            (0, 0) => 1.0,
+            (0, entries) => self.entries_done as f64 / entries as f64,
+            (bytes, _) => self.bytes_done as f64 / bytes as f64,
+        };
+        fraction.min(1.0)
+    }
+
+    /// Time elapsed since the operation started.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Remaining time estimated from the throughput so far, `None` before
+    /// anything is done.
+    pub fn remaining(&self) -> Option<Duration> {
+        let fraction = self.fraction();
+        if fraction <= 0.0 {
+            return None;
+        }
+        Some(self.elapsed().mul_f64((1.0 - fraction) / fraction))
+    }
+}
+
+impl Default for ProgressTracker {
+    fn default() -> Self {
+        ProgressTracker::new()
+    }
+}
+
+/// Reader or writer calling `report` with the number of bytes of every
+/// read or write.
+pub(crate) struct Reporting<'a, T> {
+    pub(crate) inner: T,
+    pub(crate) report: &'a mut dyn FnMut(u64),
+}
+
+impl<'a, T: Read> Read for Reporting<'a, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if read > 0 {
+            (self.report)(read as u64);
+        }
+        Ok(read)
+    }
+}
+
+impl<'a, T: Write> Write for Reporting<'a, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if written > 0 {
+            (self.report)(written as u64);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,61 @@
+use checksum::CHECKSUM_PREFIX;
+use compression::COMPRESSION_PREFIX;
+use encryption::ENCRYPTION_PREFIX;
+use std::io;
+use std::io::{Error, ErrorKind};
+
+/// Key of the property chaining a fragment to the next one.
+pub(crate) const LINK_PROPERTY: &str = "link";
+
+/// Returns `true` if the property `key` is managed by the crate: the `link`
+/// chaining the fragments, set with `PakWriter::set_link`, and the
+/// `checksum:`, `compression:` and `encryption:` properties describing the
+/// chunks, set by the writers from their `WriteOptions`. The `set_property` and
+/// `remove_property` functions of the writers and editors refuse them.
+pub fn is_reserved_property(key: &str) -> bool {
+    key == LINK_PROPERTY
+        || key.starts_with(CHECKSUM_PREFIX)
+        || key.starts_with(COMPRESSION_PREFIX)
+        || key.starts_with(ENCRYPTION_PREFIX)
+}
+
+/// Fails with `InvalidInput` if the property `key` is reserved, see
+/// `is_reserved_property`.
+pub(crate) fn check_property_key(key: &str) -> io::Result<()> {
+    if key == LINK_PROPERTY {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "the `link` property chains the fragments and can't be set directly, use `PakWriter::set_link`"
+        ));
+    }
+    if is_reserved_property(key) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("the property \"{}\" is managed by the writer and can't be set directly", key)
+        ));
+    }
+    Ok(())
+}
+
+fn malformed(key: &str, value: &str, expected: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("the property \"{}\" is \"{}\", not {}", key, value, expected)
+    )
+}
+
+/// Parses the value of the property `key` as an `i32`, failing with
+/// `InvalidData` if it is malformed.
+pub(crate) fn parse_i32(key: &str, value: &str) -> io::Result<i32> {
+    value.trim().parse().map_err(|_| malformed(key, value, "an integer"))
+}
+
+/// Parses the value of the property `key` as a `bool`: `true` or `1`,
+/// `false` or `0`, failing with `InvalidData` otherwise.
+pub(crate) fn parse_bool(key: &str, value: &str) -> io::Result<bool> {
+    match value.trim() {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(malformed(key, value, "a boolean")),
+    }
+}
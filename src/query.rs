@@ -0,0 +1,91 @@
+use glob::Pattern;
+use read::{EntryMeta, MergeReader};
+#[cfg(feature = "regex")]
+use regex::Regex;
+use std::io;
+use std::io::{Read, Seek};
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Metadata of the entries accepted by `predicate`, sorted by name.
+    fn entries_filtered<F>(&self, mut predicate: F) -> Vec<EntryMeta>
+        where F: FnMut(&str, u64) -> bool
+    {
+        let mut entries: Vec<EntryMeta> = self.iter()
+            .filter(|&(full_file_name, chunk)| predicate(full_file_name, chunk.size()))
+            .map(|(full_file_name, chunk)| chunk.meta(full_file_name))
+            .collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        entries
+    }
+
+    /// Metadata of the entries accepted by `predicate`, given the full file
+    /// name and the metadata of every entry, sorted by name. Only the
+    /// tables are read.
+    pub fn find<F>(&self, mut predicate: F) -> Vec<EntryMeta>
+        where F: FnMut(&str, &EntryMeta) -> bool
+    {
+        let mut entries: Vec<EntryMeta> = self.iter()
+            .map(|(full_file_name, chunk)| chunk.meta(full_file_name))
+            .filter(|meta| predicate(&meta.name, meta))
+            .collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        entries
+    }
+
+    /// Metadata of the `n` largest entries, by decreasing size.
+    pub fn largest(&self, n: usize) -> Vec<EntryMeta> {
+        let mut entries = self.entries_filtered(|_, _| true);
+        entries.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+        entries.truncate(n);
+        entries
+    }
+
+    /// Metadata of the entries strictly larger than `bytes`, sorted by name.
+    pub fn entries_larger_than(&self, bytes: u64) -> Vec<EntryMeta> {
+        self.entries_filtered(|_, size| size > bytes)
+    }
+
+    /// Metadata of the entries whose name matches the glob `pattern`,
+    /// sorted by name.
+    pub fn entries_matching_glob(&self, pattern: &Pattern) -> Vec<EntryMeta> {
+        self.entries_filtered(|full_file_name, _| pattern.matches(full_file_name))
+    }
+
+    /// Metadata of the entries whose name matches `regex`, sorted by name.
+    #[cfg(feature = "regex")]
+    pub fn entries_matching(&self, regex: &Regex) -> Vec<EntryMeta> {
+        self.entries_filtered(|full_file_name, _| regex.is_match(full_file_name))
+    }
+
+    /// Checks that every entry of `names` exists, returning the missing ones
+    /// in the order of `names`. The entries the `AccessPolicy` denies are
+    /// missing, as for `PakFs`.
+    ///
+    /// If `prefetch` is set, the data of the existing entries is read once,
+    /// in the order of the fragments, so that it is in the cache of the OS
+    /// when the loader reads it.
+    pub fn ensure_loaded<S: AsRef<str>>(&self, names: &[S], prefetch: bool) -> io::Result<Vec<String>> {
+        let mut missing = Vec::new();
+        let mut present = Vec::new();
+        for full_file_name in names {
+            match self.lookup(full_file_name.as_ref()) {
+                Some((name, chunk)) if self.allows(name) => present.push(chunk),
+                _ => missing.push(full_file_name.as_ref().to_string()),
+            }
+        }
+
+        if prefetch {
+            present.sort_by_key(|chunk| (chunk.fragment(), chunk.offset()));
+            for chunk in present {
+                for block in chunk.stored_iter(self.options.copy_buffer_size) {
+                    block?;
+                }
+            }
+        }
+
+        Ok(missing)
+    }
+}
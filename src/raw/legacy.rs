@@ -0,0 +1,112 @@
+//! The legacy Pak Protocol (`d2pOld` extension) of the older client
+//! versions, read into the structures of the current format so that such
+//! archives can be extracted and migrated.
+//!
+//! Legacy file format :
+//!
+//! ``` text
+//!     1. Header : From Start 0
+//!         +----------------------+----------------+-----------------------+
+//!         |   header             |    1 byte      |   value expected 1    |
+//!         |   header             |    1 byte      |   value expected 0    |
+//!         +----------------------+----------------+-----------------------+
+//!
+//!     2. Index offset : From End -4
+//!         +----------------------+----------------+-----------------------+
+//!         |   index_offset       |    4 bytes     |   offset of the index |
+//!         +----------------------+----------------+-----------------------+
+//!
+//!     3. Index : From Start index_offset
+//!         +----------------------+----------------+-----------------------+
+//!         |   count              |    4 bytes     |   number of files     |
+//!         +----------------------+----------------+-----------------------+
+//!         for 0 to count
+//!             +---------------------+------------------------------------+
+//!             |   full_file_name    |  2 bytes (length) | string (utf8)  |
+//!             |   offset            |  4 bytes (from the end of header)  |
+//!             |   size              |  4 bytes                           |
+//!             +---------------------+------------------------------------+
+//!
+//!     4. Data : From Start 2
+//! ```
+//!
+//! A legacy archive is a single file: it has neither properties nor links.
+
+use byteorder_extended::ReadExt;
+use raw::{Chunk, Info, Offsets, ParseError, EXTENDED_HEADER, HEADER};
+use read::MergeReader;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Bytes of the legacy header.
+pub const LEGACY_HEADER: [u8; 2] = [1, 0];
+
+/// Version
+///
+/// Version of the format of a fragment, given by its header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Version {
+    /// Legacy Pak Protocol, `LEGACY_HEADER`
+    Legacy,
+    /// Current Pak Protocol, `HEADER` or `EXTENDED_HEADER`
+    Current,
+}
+
+/// Reads the header from the specified reader and returns the version of
+/// the format it announces.
+pub fn read_version<R: Read>(reader: &mut R) -> io::Result<Version> {
+    let mut header = [0; 2];
+    reader.read_exact(&mut header)?;
+    match header {
+        HEADER | EXTENDED_HEADER => Ok(Version::Current),
+        LEGACY_HEADER => Ok(Version::Legacy),
+        _ => Err(ParseError::InvalidHeader.into()),
+    }
+}
+
+/// Reads the index of a legacy fragment from the specified reader, as the
+/// `Info` and the chunks of the current format: the data starts right after
+/// the header and ends at the index, the `Info` declares no properties.
+pub fn read_tables<R: Read + Seek>(reader: &mut R) -> io::Result<(Info, Vec<Chunk>)> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    if len < (LEGACY_HEADER.len() + 4) as u64 {
+        return Err(ParseError::UnexpectedEnd.into());
+    }
+    reader.seek(SeekFrom::End(-4))?;
+    let index_offset = reader.read_i32()? as u32 as u64;
+    if index_offset < LEGACY_HEADER.len() as u64 || index_offset + 4 > len - 4 {
+        return Err(ParseError::InvalidInfo.into());
+    }
+
+    reader.seek(SeekFrom::Start(index_offset))?;
+    let count = reader.read_i32()?;
+    if count < 0 {
+        return Err(ParseError::InvalidInfo.into());
+    }
+    // each entry takes at least 10 bytes
+    let mut chunks = Vec::with_capacity((count as u64).min(len / 10) as usize);
+    for _ in 0..count {
+        chunks.push(Chunk::from(reader, Offsets::Classic)?);
+    }
+
+    let info = Info {
+        offset: LEGACY_HEADER.len() as u64,
+        size: (index_offset - LEGACY_HEADER.len() as u64) as i64,
+        chunks_offset: index_offset + 4,
+        chunks_count: count,
+        properties_offset: index_offset,
+        properties_count: 0,
+        offsets: Offsets::Classic,
+    };
+    Ok((info, chunks))
+}
+
+/// Rewrites the legacy archive `src` as a single-fragment archive of the
+/// current format at `dest`, returning the number of entries. The data is
+/// copied as is.
+pub fn migrate<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dest: Q) -> io::Result<usize> {
+    let reader = MergeReader::<File>::open(src)?;
+    reader.save_subset(|_, _| true, dest)
+}
@@ -0,0 +1,288 @@
+//! The structures of the pak format.
+//!
+//! Parsing a fragment held in memory (`RawFragment::parse`, `Info::parse`...)
+//! only needs `core` and `alloc`; reading from and writing to `std::io`
+//! streams is available with the `std` feature.
+
+#[cfg(not(feature = "std"))]
+use alloc::string::String;
+#[cfg(feature = "std")]
+use byteorder_extended::{ReadExt, WriteExt};
+#[cfg(feature = "std")]
+use error;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+
+#[cfg(feature = "std")]
+pub mod legacy;
+mod parse;
+
+pub use self::parse::{parse_header, Offsets, ParseError, RawFragment, EXTENDED_HEADER, EXTENDED_INFO_LEN, HEADER, INFO_LEN};
+
+#[cfg(feature = "std")]
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Error {
+        match err {
+            ParseError::InvalidHeader => error::Error::InvalidHeader { path: None },
+            cause => error::Error::Corrupt { path: None, offset: None, cause },
+        }.into()
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+/// Reads the pak header from the specified reader, returning the width of
+/// the offsets it announces.
+#[cfg(feature = "std")]
+pub fn read_header<R: Read>(reader: &mut R) -> io::Result<Offsets> {
+    let mut header = [0; 2];
+    reader.read_exact(&mut header)?;
+    parse_header(&header).map_err(Error::from)
+}
+
+/// Writes the pak header in the specified writer.
+#[cfg(feature = "std")]
+pub fn write_header<W: Write>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(&HEADER)
+}
+
+/// Reads a big-endian `u64` from the specified reader.
+#[cfg(feature = "std")]
+pub(crate) fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let high = reader.read_i32()? as u32 as u64;
+    let low = reader.read_i32()? as u32 as u64;
+    Ok(high << 32 | low)
+}
+
+/// Writes a big-endian `u64` in the specified writer.
+#[cfg(feature = "std")]
+pub(crate) fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    writer.write_i32((value >> 32) as u32 as i32)?;
+    writer.write_i32(value as u32 as i32)?;
+    Ok(())
+}
+
+/// Reads an offset or a size of the width of `offsets` from the specified
+/// reader.
+#[cfg(feature = "std")]
+fn read_offset<R: Read>(reader: &mut R, offsets: Offsets) -> io::Result<i64> {
+    match offsets {
+        Offsets::Classic => reader.read_i32().map(i64::from),
+        Offsets::Extended => read_u64(reader).map(|value| value as i64),
+    }
+}
+
+/// Converts a position of a fragment to a signed offset.
+#[cfg(feature = "std")]
+fn offset_value(position: u64) -> io::Result<i64> {
+    if position > i64::MAX as u64 {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("invalid pak offset {}", position)));
+    }
+    Ok(position as i64)
+}
+
+/// Writes an offset or a size in the width of `offsets` in the specified
+/// writer, failing with `InvalidInput` instead of truncating it.
+#[cfg(feature = "std")]
+fn write_offset<W: Write>(writer: &mut W, value: i64, offsets: Offsets) -> io::Result<()> {
+    match offsets {
+        Offsets::Classic => {
+            if value < i32::MIN as i64 || value > i32::MAX as i64 {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{} exceeds the 2 GiB limit of the pak format, write the archive with `Offsets::Extended`", value)
+                ));
+            }
+            writer.write_i32(value as i32)
+        }
+        Offsets::Extended => write_u64(writer, value as u64),
+    }
+}
+
+/// Property
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Property {
+    /// Key
+    pub key: String,
+    /// Value
+    pub value: String,
+}
+
+impl Property {
+    /// Creates a new `Property`.
+    pub fn new(key: String, value: String) -> Self {
+        Property {
+            key,
+            value
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Property {
+    /// Reads a new `Property` from the specified reader.
+    pub fn from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let key = reader.read_string()?;
+        let value = reader.read_string()?;
+        Ok(Property::new(key, value))
+    }
+
+    /// Writes the `Property` in the specified writer.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_string(self.key.as_str())?;
+        writer.write_string(self.value.as_str())?;
+        Ok(())
+    }
+
+    /// Reads properties from the specified reader using offset and count `Info`.
+    pub fn read<R: Read + Seek>(reader: &mut R, info: &Info) -> io::Result<HashMap<String, Self>> {
+        let mut properties: HashMap<String, Property> = HashMap::with_capacity(info.properties_count.max(0) as usize);
+
+        reader.seek(SeekFrom::Start(info.properties_offset))?;
+        for _ in 0..info.properties_count {
+            let prop = Property::from(reader)?;
+            properties.insert(prop.key.clone(), prop);
+        }
+        
+        Ok(properties)
+    }
+}
+
+
+/// Chunk
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Chunk {
+    pub full_file_name: String,
+    pub offset: i64,
+    pub size: i64,
+}
+
+impl Chunk {
+    /// Creates a new `Chunk`.
+    pub fn new(full_file_name: String, offset: i64, size: i64) -> Self {
+        Chunk {
+            full_file_name,
+            offset,
+            size
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Chunk {
+    /// Creates a new `Chunk` from the specified reader, with offsets of the
+    /// width of `offsets`.
+    pub fn from<R: Read>(reader: &mut R, offsets: Offsets) -> io::Result<Self> {
+        let full_file_name = reader.read_string()?;
+        let offset = read_offset(reader, offsets)?;
+        let size = read_offset(reader, offsets)?;
+        Ok(Chunk::new(full_file_name, offset, size))
+    }
+
+    /// Writes the `Chunk` in the specified writer, with offsets of the width
+    /// of `offsets`. Fails with `InvalidInput` if they don't fit.
+    pub fn write<W: Write>(&self, writer: &mut W, offsets: Offsets) -> io::Result<()> {
+        writer.write_string(self.full_file_name.as_str())?;
+        write_offset(writer, self.offset, offsets)?;
+        write_offset(writer, self.size, offsets)?;
+        Ok(())
+    }
+
+    /// Reads chunks from the specified reader using offset and count `Info`,
+    /// in the order of the chunk table, duplicated names included.
+    pub fn read_all<R: Read + Seek>(reader: &mut R, info: &Info) -> io::Result<Vec<Self>> {
+        let mut chunks = Vec::with_capacity(info.chunks_count.max(0) as usize);
+
+        reader.seek(SeekFrom::Start(info.chunks_offset))?;
+        for _ in 0..info.chunks_count {
+            chunks.push(Chunk::from(reader, info.offsets)?);
+        }
+
+        Ok(chunks)
+    }
+
+    /// Reads chunks from the specified reader using offset and count `Info`.
+    pub fn read<R: Read + Seek>(reader: &mut R, info: &Info) -> io::Result<HashMap<String, Self>> {
+        let mut chunks: HashMap<String, Chunk> = HashMap::with_capacity(info.chunks_count.max(0) as usize);
+
+        reader.seek(SeekFrom::Start(info.chunks_offset))?;
+        for _ in 0..info.chunks_count {
+            let chunk = Chunk::from(reader, info.offsets)?;
+            chunks.insert(chunk.full_file_name.clone(), chunk);
+        }
+
+        Ok(chunks)
+    }
+}
+
+/// Info
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Info {
+    /// Offset base when attempting to load data 
+    pub offset: u64,
+    /// Size
+    pub size: i64,
+    /// Offset to start reading chunks
+    pub chunks_offset: u64,
+    /// Number of chunks
+    pub chunks_count: i32,
+    /// Offset to start reading properties
+    pub properties_offset: u64,
+    /// Number of properties
+    pub properties_count: i32,
+    /// Width of the offsets and sizes of the fragment
+    pub offsets: Offsets,
+}
+
+impl Info {
+    /// Bytes reserved between the end of the data and the chunk table.
+    pub fn slack(&self) -> u64 {
+        self.chunks_offset.saturating_sub(self.offset.saturating_add(self.size.max(0) as u64))
+    }
+}
+
+#[cfg(feature = "std")]
+impl Info {
+    /// Reads a new `Info` from the specified reader, of the width announced
+    /// by the header of the fragment, checked with `check`.
+    pub fn from<R: Read + Seek>(reader: &mut R) -> io::Result<Self> {
+        // too short to hold an `Info`, seeking before the start would fail
+        let len = reader.seek(SeekFrom::End(0))?;
+        if len < (HEADER.len() + INFO_LEN) as u64 {
+            return Err(ParseError::UnexpectedEnd.into());
+        }
+        reader.seek(SeekFrom::Start(0))?;
+        let offsets = read_header(reader)?;
+        let info_len = offsets.info_len();
+        if len < (HEADER.len() + info_len) as u64 {
+            return Err(ParseError::UnexpectedEnd.into());
+        }
+        reader.seek(SeekFrom::Start(len - info_len as u64))?;
+
+        let mut trailer = [0; EXTENDED_INFO_LEN];
+        reader.read_exact(&mut trailer[..info_len])?;
+        let info = Info::parse_trailer(&trailer[..info_len], offsets)?;
+        info.check(len)?;
+        Ok(info)
+    }
+
+    /// Writes the `Info` in the specified writer, with offsets of the width
+    /// of `self.offsets`. Fails with `InvalidInput` if they don't fit.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_offset(writer, offset_value(self.offset)?, self.offsets)?;
+        write_offset(writer, self.size, self.offsets)?;
+        write_offset(writer, offset_value(self.chunks_offset)?, self.offsets)?;
+        writer.write_i32(self.chunks_count)?;
+        write_offset(writer, offset_value(self.properties_offset)?, self.offsets)?;
+        writer.write_i32(self.properties_count)?;
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,303 @@
+//! Parsing of the pak format over byte slices, with `core` and `alloc`
+//! only, so that it can be embedded where `std::io` isn't available.
+
+#[cfg(not(feature = "std"))]
+use alloc::string::String;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+use core::fmt;
+use core::str;
+use super::{Chunk, Info, Property};
+
+/// Bytes of the pak header.
+pub const HEADER: [u8; 2] = [2, 1];
+
+/// Bytes of the header of the fragments with 64-bit offsets.
+pub const EXTENDED_HEADER: [u8; 2] = [2, 2];
+
+/// Size of the `Info`, at the end of a fragment.
+pub const INFO_LEN: usize = 24;
+
+/// Size of the `Info` of the fragments with 64-bit offsets.
+pub const EXTENDED_INFO_LEN: usize = 40;
+
+/// Offsets
+///
+/// Width of the offsets and the sizes of the tables of a fragment, given
+/// by its header.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Offsets {
+    /// 32-bit signed offsets and sizes, `HEADER`: the fragment can't exceed
+    /// 2 GiB (default)
+    #[default]
+    Classic,
+    /// 64-bit signed offsets and sizes, `EXTENDED_HEADER`
+    Extended,
+}
+
+impl Offsets {
+    /// Bytes of the header announcing the offsets.
+    pub fn header(self) -> [u8; 2] {
+        match self {
+            Offsets::Classic => HEADER,
+            Offsets::Extended => EXTENDED_HEADER,
+        }
+    }
+
+    /// Size of the `Info`.
+    pub fn info_len(self) -> usize {
+        match self {
+            Offsets::Classic => INFO_LEN,
+            Offsets::Extended => EXTENDED_INFO_LEN,
+        }
+    }
+
+    /// Smallest size of an entry of the chunk table: the length of an empty
+    /// name, an offset and a size.
+    pub fn min_chunk_len(self) -> usize {
+        match self {
+            Offsets::Classic => 10,
+            Offsets::Extended => 18,
+        }
+    }
+
+    /// Largest offset or size the tables can hold.
+    pub fn max(self) -> u64 {
+        match self {
+            Offsets::Classic => i32::MAX as u64,
+            Offsets::Extended => i64::MAX as u64,
+        }
+    }
+}
+
+/// ParseError
+///
+/// Error of the parsing of a fragment held in memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// The header isn't `HEADER`
+    InvalidHeader,
+    /// The data ends before the structure being parsed
+    UnexpectedEnd,
+    /// A string isn't valid UTF-8
+    InvalidUtf8,
+    /// A count or an offset of the `Info` is negative or out of the fragment
+    InvalidInfo,
+    /// The offset or the size of a chunk is negative or out of the fragment
+    InvalidChunk,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ParseError::InvalidHeader => "Corrupted pak header",
+            ParseError::UnexpectedEnd => "unexpected end of the pak data",
+            ParseError::InvalidUtf8 => "invalid UTF-8 string in the pak data",
+            ParseError::InvalidInfo => "invalid pak info",
+            ParseError::InvalidChunk => "invalid pak chunk range",
+        })
+    }
+}
+
+/// Big-endian reader over a byte slice.
+struct SliceReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    fn at(data: &'a [u8], position: usize) -> Result<Self, ParseError> {
+        if position > data.len() {
+            return Err(ParseError::InvalidInfo);
+        }
+        Ok(SliceReader { data, position })
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ParseError> {
+        let end = self.position.checked_add(len).ok_or(ParseError::UnexpectedEnd)?;
+        let bytes = self.data.get(self.position..end).ok_or(ParseError::UnexpectedEnd)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn read_i32(&mut self) -> Result<i32, ParseError> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_i64(&mut self) -> Result<i64, ParseError> {
+        let bytes = self.take(8)?;
+        let mut be = [0; 8];
+        be.copy_from_slice(bytes);
+        Ok(i64::from_be_bytes(be))
+    }
+
+    /// Reads an offset or a size of the width of `offsets`.
+    fn read_offset(&mut self, offsets: Offsets) -> Result<i64, ParseError> {
+        match offsets {
+            Offsets::Classic => self.read_i32().map(i64::from),
+            Offsets::Extended => self.read_i64(),
+        }
+    }
+
+    fn read_string(&mut self) -> Result<String, ParseError> {
+        let len = self.take(2)?;
+        let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+        str::from_utf8(self.take(len)?)
+            .map(String::from)
+            .map_err(|_| ParseError::InvalidUtf8)
+    }
+}
+
+/// Checks the header at the start of `fragment`, returning the width of
+/// the offsets it announces.
+pub fn parse_header(fragment: &[u8]) -> Result<Offsets, ParseError> {
+    match fragment.get(..HEADER.len()) {
+        Some(header) if header == HEADER => Ok(Offsets::Classic),
+        Some(header) if header == EXTENDED_HEADER => Ok(Offsets::Extended),
+        Some(_) => Err(ParseError::InvalidHeader),
+        None => Err(ParseError::UnexpectedEnd),
+    }
+}
+
+/// Checks that a count of the `Info` is positive.
+fn count(count: i32) -> Result<usize, ParseError> {
+    if count < 0 {
+        return Err(ParseError::InvalidInfo);
+    }
+    Ok(count as usize)
+}
+
+impl Info {
+    /// Parses the `offsets.info_len()` bytes of an `Info`.
+    pub fn parse_trailer(trailer: &[u8], offsets: Offsets) -> Result<Self, ParseError> {
+        let mut reader = SliceReader { data: trailer, position: 0 };
+        Ok(Info {
+            offset: reader.read_offset(offsets)? as u64,
+            size: reader.read_offset(offsets)?,
+            chunks_offset: reader.read_offset(offsets)? as u64,
+            chunks_count: reader.read_i32()?,
+            properties_offset: reader.read_offset(offsets)? as u64,
+            properties_count: reader.read_i32()?,
+            offsets,
+        })
+    }
+
+    /// Parses the `Info` at the end of `fragment`, of the width announced by
+    /// its header, checked with `check`.
+    pub fn parse(fragment: &[u8]) -> Result<Self, ParseError> {
+        let offsets = parse_header(fragment)?;
+        if fragment.len() < HEADER.len() + offsets.info_len() {
+            return Err(ParseError::UnexpectedEnd);
+        }
+        let info = Info::parse_trailer(&fragment[fragment.len() - offsets.info_len()..], offsets)?;
+        info.check(fragment.len() as u64)?;
+        Ok(info)
+    }
+
+    /// Checks that the `Info` can describe a fragment of `len` bytes: the
+    /// size and the counts are positive, the data area is within the
+    /// fragment, the tables start in it and their counts fit in the bytes
+    /// after their start, so that a hostile count can't cause a giant
+    /// allocation. Fails with `InvalidInfo` otherwise.
+    pub fn check(&self, len: u64) -> Result<(), ParseError> {
+        let chunks = count(self.chunks_count)? as u64;
+        let properties = count(self.properties_count)? as u64;
+        let data_end = if self.size < 0 { None } else { self.offset.checked_add(self.size as u64) };
+        let valid = data_end.is_some_and(|end| end <= len)
+            && self.chunks_offset <= len
+            && self.properties_offset <= len
+            && chunks.saturating_mul(self.offsets.min_chunk_len() as u64) <= len - self.chunks_offset
+            // each property takes at least 4 bytes
+            && properties.saturating_mul(4) <= len - self.properties_offset;
+        if !valid {
+            return Err(ParseError::InvalidInfo);
+        }
+        Ok(())
+    }
+}
+
+impl Chunk {
+    /// Parses the chunk table of `fragment` described by `info`, in order,
+    /// duplicated names included.
+    pub fn parse_all(fragment: &[u8], info: &Info) -> Result<Vec<Self>, ParseError> {
+        let count = count(info.chunks_count)?;
+        let mut reader = SliceReader::at(fragment, info.chunks_offset as usize)?;
+        let mut chunks = Vec::with_capacity(count.min(fragment.len() / info.offsets.min_chunk_len()));
+        for _ in 0..count {
+            let full_file_name = reader.read_string()?;
+            let offset = reader.read_offset(info.offsets)?;
+            let size = reader.read_offset(info.offsets)?;
+            chunks.push(Chunk { full_file_name, offset, size });
+        }
+        Ok(chunks)
+    }
+
+    /// Checks that the offset and the size of the chunk are positive and
+    /// that its data is within a fragment of `len` bytes described by
+    /// `info`. Fails with `InvalidChunk` otherwise.
+    pub fn check(&self, info: &Info, len: u64) -> Result<(), ParseError> {
+        if self.offset < 0 || self.size < 0 {
+            return Err(ParseError::InvalidChunk);
+        }
+        let end = info.offset.checked_add(self.offset as u64)
+            .and_then(|start| start.checked_add(self.size as u64));
+        match end {
+            Some(end) if end <= len => Ok(()),
+            _ => Err(ParseError::InvalidChunk),
+        }
+    }
+
+    /// Data of the chunk in `fragment`, `None` if it is out of the
+    /// fragment.
+    pub fn data<'a>(&self, fragment: &'a [u8], info: &Info) -> Option<&'a [u8]> {
+        if self.offset < 0 || self.size < 0 {
+            return None;
+        }
+        let start = (info.offset as usize).checked_add(self.offset as usize)?;
+        let end = start.checked_add(self.size as usize)?;
+        fragment.get(start..end)
+    }
+}
+
+impl Property {
+    /// Parses the properties of `fragment` described by `info`, in order.
+    pub fn parse_all(fragment: &[u8], info: &Info) -> Result<Vec<Self>, ParseError> {
+        let count = count(info.properties_count)?;
+        let mut reader = SliceReader::at(fragment, info.properties_offset as usize)?;
+        // each property takes at least 4 bytes
+        let mut properties = Vec::with_capacity(count.min(fragment.len() / 4));
+        for _ in 0..count {
+            let key = reader.read_string()?;
+            let value = reader.read_string()?;
+            properties.push(Property { key, value });
+        }
+        Ok(properties)
+    }
+}
+
+/// RawFragment
+///
+/// The tables of a fragment held in memory.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RawFragment {
+    /// Info
+    pub info: Info,
+    /// Chunks, in the order of the table
+    pub chunks: Vec<Chunk>,
+    /// Properties, in the order of the table
+    pub properties: Vec<Property>,
+}
+
+impl RawFragment {
+    /// Parses the header and the tables of `fragment`.
+    pub fn parse(fragment: &[u8]) -> Result<Self, ParseError> {
+        parse_header(fragment)?;
+        let info = Info::parse(fragment)?;
+        let chunks = Chunk::parse_all(fragment, &info)?;
+        let properties = Property::parse_all(fragment, &info)?;
+        Ok(RawFragment { info, chunks, properties })
+    }
+}
@@ -1,38 +1,87 @@
-use raw::{Chunk, Info, Property, read_header};
+use access::{check_access, AccessLog};
+use cache::ChunkCache;
+use checksum::{take_checksums, Checksum};
+use compression::{take_compressions, ChunkCompression};
+use encryption::{take_encryptions, ChunkEncryption};
+use error::{entry_not_found, Error as PakError};
+use glob::Pattern;
+use memory::MemoryAccount;
+use name::{find_entry, fold_names, EntryName};
+use options::{InvalidNames, ReadLimits, ReadOptions};
+use pin::PinnedBytes;
+use progress::ProgressEvent;
+use properties::{parse_bool, parse_i32, LINK_PROPERTY};
+use raw::{Chunk, Info, Property};
+use raw::legacy::{self, read_version, Version};
+use retry::{RetryPolicy, RetryReader};
+use std::borrow::Cow;
 use std::cell::RefCell;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::collections::hash_map::Iter;
 use std::fs;
 use std::fs::File;
 use std::io;
-use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::io::{BufReader, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use timeout::Deadline;
+use verify::{Finding, Severity};
 
 /// Replaces the file name (with extension) of the `path` to `file_name`.
-fn set_file_name<P: AsRef<Path>>(path: P, file_name: &str) -> Option<PathBuf> {
+pub(crate) fn set_file_name<P: AsRef<Path>>(path: P, file_name: &str) -> Option<PathBuf> {
     path.as_ref()
         .to_str()
         .and_then(|path| {
-            path.rfind('/').and_then(|last_index| {
-                Some(PathBuf::from(
+            path.rfind('/').map(|last_index| {
+                PathBuf::from(
                     &[ 
                         &path[..last_index], 
                         "/", 
                         file_name
                     ].concat()
-                ))
+                )
             }).or(Some(PathBuf::from(file_name)))
         })
 }
 
+/// Identifies the fragment at `path` in a chain: its canonical path if it is
+/// a file, the path itself otherwise (fragments of an `ArchiveSource`).
+fn fragment_key(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// EntryMeta
+///
+/// Metadata of an entry, detached from the reader.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EntryMeta {
+    /// Full file name
+    pub name: String,
+    /// Size of the data
+    pub size: u64,
+    /// Index of the fragment containing the data
+    pub fragment: usize,
+    /// Offset of the data in its fragment
+    pub offset: u64,
+}
 
 /// MergedChunk
 #[derive(Debug)]
 pub struct MergedChunk<R> {
     offset: u64, 
     size: u64,
+    fragment: usize,
     reader: Rc<RefCell<R>>,
+    /// Data of the chunk in the memory map of its fragment, if mapped
+    pub(crate) mapped: Option<PinnedBytes>,
+    /// Checksum stored in the properties of its fragment, if any
+    pub(crate) checksum: Option<Checksum>,
+    /// Compression recorded in the properties of its fragment, if any
+    pub(crate) compression: Option<ChunkCompression>,
+    /// Encryption recorded in the properties of its fragment, if any
+    pub(crate) encryption: Option<ChunkEncryption>,
 }
 
 impl<R> MergedChunk<R>
@@ -40,30 +89,395 @@ where
     R: Read + Seek
 {
     /// Creates a new `MergedChunk`.
-    fn new(
+    pub(crate) fn new(
         offset: u64,
         size: u64,
+        fragment: usize,
         reader: Rc<RefCell<R>>
     ) -> Self {
         MergedChunk {
-            offset: offset,
-            size: size,
-            reader: reader
+            offset,
+            size,
+            fragment,
+            reader,
+            mapped: None,
+            checksum: None,
+            compression: None,
+            encryption: None,
         }
     }
 
-    /// Reads the data.
-    pub fn data(&self) -> io::Result<Vec<u8>> {
-        let mut buffer: Vec<u8> = vec![0; self.size as usize];
-        {
-            let mut reader = self.reader.borrow_mut();
+    /// Offset of the data in its fragment.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Size of the data as stored, compressed if the chunk is.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns `true` if the data is stored compressed, see `Compression`.
+    pub fn is_compressed(&self) -> bool {
+        self.compression.is_some()
+    }
+
+    /// Returns `true` if the data is stored encrypted, see `Encryption`.
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_some()
+    }
+
+    /// Size of the data once decrypted and decompressed, as recorded with
+    /// its compression or its encryption, the stored size if the chunk is
+    /// neither compressed nor encrypted.
+    pub fn uncompressed_size(&self) -> u64 {
+        match self.compression {
+            Some(ref compression) => compression.size,
+            None => self.encryption.as_ref().map_or(self.size, |encryption| encryption.size),
+        }
+    }
+
+    /// Index of the fragment containing the data.
+    pub fn fragment(&self) -> usize {
+        self.fragment
+    }
+
+    /// Identifies the stored data: fragment and offset.
+    pub(crate) fn location(&self) -> (usize, u64) {
+        (&*self.reader as *const RefCell<R> as usize, self.offset)
+    }
+
+    /// Metadata of the chunk, named `full_file_name`.
+    pub fn meta(&self, full_file_name: &str) -> EntryMeta {
+        EntryMeta {
+            name: full_file_name.to_string(),
+            size: self.size,
+            fragment: self.fragment,
+            offset: self.offset,
+        }
+    }
+
+    /// Runs `f` with the shared reader of the fragment.
+    ///
+    /// The reader is borrowed only for the duration of `f`: the borrow is
+    /// released even if `f` panics, and every operation seeks before reading,
+    /// so a failed read never leaves the reader unusable for the next ones.
+    /// A re-entrant access is reported as an error instead of panicking.
+    pub(crate) fn with_reader<T, F>(&self, f: F) -> io::Result<T>
+        where F: FnOnce(&mut R) -> io::Result<T>
+    {
+        let mut reader = self.reader.try_borrow_mut().map_err(|_| Error::new(
+            ErrorKind::WouldBlock,
+            "the fragment reader is already in use"
+        ))?;
+        f(&mut reader)
+    }
+
+    /// Size of the fragment containing the data.
+    pub(crate) fn fragment_len(&self) -> io::Result<u64> {
+        self.with_reader(|reader| reader.seek(SeekFrom::End(0)))
+    }
+
+    /// Copies the data into `writer` without buffering it entirely.
+    pub(crate) fn copy_to<W: Write>(&self, writer: &mut W) -> io::Result<u64> {
+        let copied = self.with_reader(|reader| {
             reader.seek(SeekFrom::Start(self.offset))?;
-            reader.read(&mut buffer)?;
+            io::copy(&mut reader.by_ref().take(self.size), writer)
+        })?;
+        self.check_copied(copied)
+    }
+
+    /// Copies the data into `writer` through a buffer of `buffer_size`
+    /// bytes.
+    pub(crate) fn copy_to_with<W: Write>(&self, writer: &mut W, buffer_size: usize) -> io::Result<u64> {
+        let mut buffer = vec![0; buffer_size.max(1).min(self.size.max(1) as usize)];
+        let copied = self.with_reader(|reader| {
+            reader.seek(SeekFrom::Start(self.offset))?;
+            let mut copied = 0;
+            while copied < self.size {
+                let len = (self.size - copied).min(buffer.len() as u64) as usize;
+                let read = reader.read(&mut buffer[..len])?;
+                if read == 0 {
+                    break;
+                }
+                writer.write_all(&buffer[..read])?;
+                copied += read as u64;
+            }
+            Ok(copied)
+        })?;
+        self.check_copied(copied)
+    }
+
+    /// Reader of the data as stored, decrypted if the chunk is encrypted:
+    /// the encrypted data is then read and decrypted whole.
+    fn decrypted_reader<'a>(&'a self) -> io::Result<Box<dyn Read + 'a>> {
+        match self.encryption {
+            Some(ref encryption) => Ok(Box::new(io::Cursor::new(encryption.decrypt(&self.stored()?)?))),
+            None => Ok(Box::new(self.stored_reader())),
+        }
+    }
+
+    /// Reader of the data, decrypted and decompressed if the chunk is
+    /// encrypted or compressed. The number of bytes read from it must be
+    /// checked with `check_decoded`.
+    pub(crate) fn decoded_reader<'a>(&'a self) -> io::Result<Box<dyn Read + 'a>> {
+        let stored = self.decrypted_reader()?;
+        match self.compression {
+            Some(ref compression) => compression.decoder(stored),
+            None => Ok(stored),
+        }
+    }
+
+    /// Checks the number of bytes read from `decoded_reader`.
+    pub(crate) fn check_decoded(&self, read: u64) -> io::Result<u64> {
+        match (&self.compression, &self.encryption) {
+            (Some(compression), _) => compression.check_size(read),
+            (None, Some(encryption)) => encryption.check_size(read),
+            (None, None) => self.check_copied(read),
+        }
+    }
+
+    /// Writes the data, decrypted and decompressed if the chunk is
+    /// encrypted or compressed, into `writer` through a buffer of
+    /// `buffer_size` bytes, returning the number of bytes written.
+    pub(crate) fn decode_to<W: Write>(&self, writer: &mut W, buffer_size: usize) -> io::Result<u64> {
+        if self.compression.is_none() && self.encryption.is_none() {
+            return self.copy_to_with(writer, buffer_size);
+        }
+        let read = io::copy(&mut self.decoded_reader()?, writer)?;
+        self.check_decoded(read)
+    }
+
+    /// Decrypts and decompresses the stored data `stored` if the chunk is
+    /// encrypted or compressed.
+    pub(crate) fn decode(&self, stored: Vec<u8>) -> io::Result<Vec<u8>> {
+        let stored = match self.encryption {
+            Some(ref encryption) => encryption.decrypt(&stored)?,
+            None => stored,
+        };
+        match self.compression {
+            Some(ref compression) => compression.decode(&stored),
+            None => Ok(stored),
+        }
+    }
+
+    pub(crate) fn check_copied(&self, copied: u64) -> io::Result<u64> {
+        if copied < self.size {
+            return Err(PakError::Truncated { offset: self.offset, expected: self.size, read: copied }.into());
+        }
+        Ok(copied)
+    }
+
+    /// Iterates over the data as stored in blocks of at most `chunk_size`
+    /// bytes (at least 1), without holding the whole data in memory. The
+    /// blocks of a compressed or encrypted chunk are neither decompressed
+    /// nor decrypted, unlike the data of `data` or `read_at`.
+    pub fn stored_iter(&self, chunk_size: usize) -> DataIter<'_, R> {
+        DataIter {
+            chunk: self,
+            position: 0,
+            block_size: chunk_size.max(1) as u64,
         }
+    }
+
+    /// `Read + Seek` view on the data as stored, to stream it into a decoder
+    /// without holding it in memory. The data of a compressed or encrypted
+    /// chunk is neither decompressed nor decrypted, unlike the data of
+    /// `data` or `read_at`.
+    pub fn stored_reader(&self) -> ChunkReader<'_, R> {
+        ChunkReader::new(self)
+    }
+
+    /// Data borrowed from the memory map of the fragment, `None` if the
+    /// fragment isn't mapped (see `MergeReader::open_mmap`) or if the chunk
+    /// is compressed or encrypted.
+    pub fn as_slice(&self) -> Option<&[u8]> {
+        self.mapped.as_ref().map(|mapped| mapped.as_slice())
+    }
+
+    /// Reads at most `len` bytes of the data from `offset`, decompressed if
+    /// the chunk is compressed, without reading the rest of the entry: to
+    /// sniff the type of a file or read an index embedded in a large entry.
+    /// Fewer bytes are returned at the end of the data, none past it. The
+    /// compressed data is decoded from its start up to the end of the range,
+    /// the encrypted data is decrypted whole.
+    pub fn read_at(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        if let Some(data) = self.as_slice() {
+            let start = offset.min(data.len() as u64) as usize;
+            let end = start + len.min(data.len() - start);
+            return Ok(data[start..end].to_vec());
+        }
+
+        let mut buffer = Vec::with_capacity(len.min(self.uncompressed_size().saturating_sub(offset) as usize));
+        if self.compression.is_some() || self.encryption.is_some() {
+            let mut decoder = self.decoded_reader()?;
+            let skipped = io::copy(&mut decoder.by_ref().take(offset), &mut io::sink())?;
+            decoder.take(len as u64).read_to_end(&mut buffer)?;
+            // a short decoded data is corrupted, not the end of the range
+            if offset + (buffer.len() as u64) < self.uncompressed_size().min(offset + len as u64) {
+                self.check_decoded(skipped + buffer.len() as u64)?;
+            }
+        } else {
+            let mut reader = self.stored_reader();
+            reader.seek(SeekFrom::Start(offset))?;
+            reader.take(len as u64).read_to_end(&mut buffer)?;
+        }
+        Ok(buffer)
+    }
+
+    /// Reads the bytes `range` of the data, decompressed if the chunk is
+    /// compressed, see `read_at`. Fails with `InvalidInput` if the range is
+    /// reversed or ends past the data.
+    pub fn read_range(&self, range: Range<u64>) -> io::Result<Vec<u8>> {
+        if range.start > range.end || range.end > self.uncompressed_size() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("range {}..{} out of the {} bytes of the data", range.start, range.end, self.uncompressed_size())
+            ));
+        }
+        self.read_at(range.start, (range.end - range.start) as usize)
+    }
+
+    /// Reads the data, decrypted and decompressed if the chunk is encrypted
+    /// or compressed.
+    pub fn data(&self) -> io::Result<Vec<u8>> {
+        self.decode(self.stored()?)
+    }
+
+    /// Reads the data as stored. The buffer grows with the data read, so
+    /// that the size of a hostile chunk is never allocated upfront.
+    fn stored(&self) -> io::Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        self.with_reader(|reader| {
+            reader.seek(SeekFrom::Start(self.offset))?;
+            reader.take(self.size).read_to_end(&mut buffer)
+        })?;
+        self.check_copied(buffer.len() as u64)?;
         Ok(buffer)
     }
 }
 
+/// DataIter
+///
+/// Iterator over successive blocks of the data of a `MergedChunk` as
+/// stored, returned by `MergedChunk::stored_iter`, each block being read on
+/// demand: only one block is held in memory at a time.
+#[derive(Debug)]
+pub struct DataIter<'a, R: 'a> {
+    chunk: &'a MergedChunk<R>,
+    /// Bytes of data already yielded
+    position: u64,
+    block_size: u64,
+}
+
+impl<'a, R> Iterator for DataIter<'a, R>
+where
+    R: Read + Seek
+{
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<u8>>> {
+        if self.position >= self.chunk.size {
+            return None;
+        }
+
+        let len = (self.chunk.size - self.position).min(self.block_size);
+        let mut buffer = vec![0; len as usize];
+        let offset = self.chunk.offset + self.position;
+        let read = self.chunk.with_reader(|reader| {
+            reader.seek(SeekFrom::Start(offset))?;
+            reader.read_exact(&mut buffer)
+        });
+
+        match read {
+            Ok(()) => {
+                self.position += len;
+                Some(Ok(buffer))
+            }
+            Err(err) => {
+                // a failed read ends the iteration
+                self.position = self.chunk.size;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// ChunkReader
+///
+/// `Read + Seek` view on the data of a `MergedChunk` as stored, returned
+/// by `MergedChunk::stored_reader`: positions are relative to the start of the data,
+/// and the reads stop at its end. Every read seeks the shared fragment
+/// reader first, so several `ChunkReader` can be used in turn.
+#[derive(Debug)]
+pub struct ChunkReader<'a, R: 'a> {
+    chunk: &'a MergedChunk<R>,
+    /// Position in the data
+    position: u64,
+}
+
+impl<'a, R> ChunkReader<'a, R> {
+    /// Creates a new `ChunkReader` at the start of the data of `chunk`.
+    pub(crate) fn new(chunk: &'a MergedChunk<R>) -> Self {
+        ChunkReader { chunk, position: 0 }
+    }
+
+    /// Position in the data.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Size of the data.
+    pub fn len(&self) -> u64 {
+        self.chunk.size
+    }
+
+    /// Returns `true` if the data is empty.
+    pub fn is_empty(&self) -> bool {
+        self.chunk.size == 0
+    }
+}
+
+impl<'a, R: Read + Seek> Read for ChunkReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.chunk.size.saturating_sub(self.position);
+        let len = (buf.len() as u64).min(remaining) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let offset = self.chunk.offset + self.position;
+        let read = self.chunk.with_reader(|reader| {
+            reader.seek(SeekFrom::Start(offset))?;
+            reader.read(&mut buf[..len])
+        })?;
+        if read == 0 {
+            return Err(PakError::Truncated {
+                offset: self.chunk.offset,
+                expected: self.chunk.size,
+                read: self.position,
+            }.into());
+        }
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<'a, R> Seek for ChunkReader<'a, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.chunk.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| Error::new(
+            ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position"
+        ))?;
+        Ok(self.position)
+    }
+}
+
 /// MergeReader
 ///
 /// `MergeReader` merges the fragments and provides only functions
@@ -73,84 +487,290 @@ where
 #[derive(Debug)]
 pub struct MergeReader<R> {
     /// Chunks 
-    chunks: HashMap<String, MergedChunk<R>>,
+    pub(crate) chunks: HashMap<String, MergedChunk<R>>,
+    /// Names of the chunks by folded key, if the lookups are
+    /// case-insensitive
+    pub(crate) folded: Option<HashMap<String, String>>,
     /// Properties
-    properties: HashMap<String, String>,
+    pub(crate) properties: HashMap<String, String>,
+    /// Paths of the fragments in the order they were merged
+    pub(crate) fragments: Vec<PathBuf>,
+    /// Readers of the fragments, in the order of `fragments`
+    pub(crate) readers: Vec<Rc<RefCell<R>>>,
+    /// Entries read, if the recording is enabled
+    pub(crate) access_log: RefCell<Option<AccessLog>>,
+    /// Problems found while merging the fragments
+    pub(crate) warnings: Vec<Finding>,
+    /// Options the archive was opened with
+    pub(crate) options: ReadOptions,
+    /// Bytes held by the reader
+    pub(crate) memory: MemoryAccount,
+    /// Data of the chunks recently read, see `ReadStrategy`
+    pub(crate) cache: RefCell<ChunkCache>,
 }
 
 impl MergeReader<File> {
     pub fn open<P: AsRef<Path>>(loc: P) -> io::Result<Self> {
         MergeReader::merge(
             loc.as_ref(),
-            |path| File::open(path)
+            File::open
         )
     }
 
+    /// Opens the pak file according to `options`.
+    pub fn open_with<P: AsRef<Path>>(loc: P, options: &ReadOptions) -> io::Result<Self> {
+        MergeReader::merge_with_options(
+            loc.as_ref(),
+            File::open,
+            true,
+            options
+        )
+    }
+
+    /// Extracts every entry of the pak file `loc` into `dest`, in parallel
+    /// through a `SharedReader`, overwriting the existing files. See
+    /// `extract_with` to skip them, to refuse the symbolic links or to list
+    /// the entries first.
     pub fn extract<P: AsRef<Path>>(loc: P, dest: P) -> io::Result<()> {
-        let dest = dest.as_ref();
-        let reader = MergeReader::<File>::open(&loc)?;
+        MergeReader::extract_filtered(loc, dest, None)
+    }
+
+    /// Extracts every entry of the pak file `loc` into `dest` like
+    /// `extract`, reporting the progress to `progress`, see
+    /// `SharedReader::extract_with_progress`.
+    pub fn extract_with_progress<P, F>(loc: P, dest: P, progress: F) -> io::Result<()>
+        where P: AsRef<Path>,
+              F: FnMut(&ProgressEvent) + Send
+    {
+        MergeReader::<File>::open(&loc)?.shared()?.extract_with_progress(dest, None, progress)
+    }
 
-        for (full_file_name, chunk) in reader.iter() {
-            // create the path
-            let mut output = PathBuf::from(dest);
-            output.push(full_file_name);
+    /// Extracts the entries of the pak file `loc` matching the glob `filter`
+    /// into `dest`, every entry if `None`, see `extract`.
+    pub fn extract_filtered<P: AsRef<Path>>(loc: P, dest: P, filter: Option<&Pattern>) -> io::Result<()> {
+        let shared = MergeReader::<File>::open(&loc)?.shared()?;
+        match filter {
+            Some(pattern) => shared.extract_matching(dest, pattern),
+            None => shared.extract_all(dest),
+        }
+    }
+}
 
-            // create the directory paths
-            fs::create_dir_all(output.parent().unwrap())?;
+impl MergeReader<RetryReader<File>> {
+    /// Opens the pak file retrying the transient failures of the underlying
+    /// files according to `policy`.
+    pub fn open_with_retry<P: AsRef<Path>>(loc: P, policy: RetryPolicy) -> io::Result<Self> {
+        MergeReader::merge(
+            loc.as_ref(),
+            |path| {
+                let file = policy.run(|| File::open(&path))?;
+                RetryReader::new(file, policy)
+            }
+        )
+    }
+}
 
-            // create the file
-            let mut file = File::create(&output)?;
+/// Error of an archive beyond the limit `field` of its `ReadLimits`.
+pub(crate) fn limit_exceeded(path: &Path, what: String, field: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("\"{}\": {}, see `ReadLimits::{}`", path.display(), what, field)
+    )
+}
 
-            // fill the file with the data
-            file.write_all(chunk.data().unwrap().as_slice())?;
+/// Checks that the chunks and the properties announced by `info` fit in
+/// `limits`, `chunks` and `properties` being read from the previous
+/// fragments.
+fn check_counts(info: &Info, limits: &ReadLimits, chunks: usize, properties: usize, path: &Path) -> io::Result<()> {
+    let announced = |count: i32, read: usize| read.saturating_add(count.max(0) as usize);
+    if let Some(max) = limits.max_chunks {
+        if announced(info.chunks_count, chunks) > max {
+            return Err(limit_exceeded(path, format!("more than {} chunks", max), "max_chunks"));
         }
-
-        Ok(())
     }
+    if let Some(max) = limits.max_properties {
+        if announced(info.properties_count, properties) > max {
+            return Err(limit_exceeded(path, format!("more than {} properties", max), "max_properties"));
+        }
+    }
+    Ok(())
 }
 
 impl<R> MergeReader<R> 
 where 
     R: Read + Seek
 {
-    fn merge<P, F>(initial: P, make_reader: F) -> io::Result<Self> 
+    pub(crate) fn merge<P, F>(initial: P, make_reader: F) -> io::Result<Self> 
+        where P: Into<PathBuf>,
+              F: Fn(PathBuf) -> io::Result<R>
+    {
+        MergeReader::merge_with(initial, make_reader, true)
+    }
+
+    /// Reads the fragment `initial` and, if `follow_links` is set, every
+    /// fragment it links to.
+    pub(crate) fn merge_with<P, F>(initial: P, make_reader: F, follow_links: bool) -> io::Result<Self> 
+        where P: Into<PathBuf>,
+              F: Fn(PathBuf) -> io::Result<R>
+    {
+        MergeReader::merge_with_options(initial, make_reader, follow_links, &ReadOptions::default())
+    }
+
+    /// Reads the fragments like `merge_with`, according to `options`.
+    pub(crate) fn merge_with_options<P, F>(
+        initial: P,
+        make_reader: F,
+        follow_links: bool,
+        options: &ReadOptions
+    ) -> io::Result<Self>
         where P: Into<PathBuf>,
               F: Fn(PathBuf) -> io::Result<R>
     {
         let mut merge = MergeReader { 
             chunks: HashMap::new(),
-            properties: HashMap::new()
+            folded: None,
+            properties: HashMap::new(),
+            fragments: Vec::new(),
+            readers: Vec::new(),
+            access_log: RefCell::new(None),
+            warnings: Vec::new(),
+            options: options.clone(),
+            memory: MemoryAccount::default(),
+            cache: RefCell::new(ChunkCache::default()),
         };
 
         let mut links = VecDeque::new();
 
         let initial = initial.into();
         links.push_back(initial.clone());
+        // fragments opened or about to be, a link back to one is a cycle
+        let mut visited = HashSet::new();
+        visited.insert(fragment_key(&initial));
 
+        let limits = &options.limits;
+        let (mut total_chunks, mut total_properties) = (0, 0);
+        let deadline = Deadline::start(format!("open \"{}\"", initial.display()), options.timeouts.open);
         while {
-            let mut reader = make_reader(links.pop_front().unwrap())?;
-
-            read_header(&mut reader)?;
-            let info = Info::from(&mut reader)?;
-            let mut chunks = Chunk::read(&mut reader, &info)?;
-            let mut properties = Property::read(&mut reader, &info)?;
-
-            let reader = Rc::new(RefCell::new(reader));
-
-            for (full_file_name, chunk) in chunks.drain() {
-                merge.chunks.insert(
-                    full_file_name,
-                    MergedChunk::new(
-                        info.offset + chunk.offset as u64,
-                        chunk.size as u64,
-                        reader.clone()
-                    )
+            deadline.check()?;
+            let path = links.pop_front().unwrap();
+            if let Some(max) = limits.max_fragments {
+                if merge.fragments.len() >= max {
+                    return Err(limit_exceeded(&path, format!("more than {} fragments", max), "max_fragments"));
+                }
+            }
+            let reader = match (make_reader(path.clone()), merge.fragments.last()) {
+                (Err(ref err), Some(linked_from)) if err.kind() == ErrorKind::NotFound => {
+                    return Err(PakError::MissingFragment { path, linked_from: linked_from.clone() }.into());
+                }
+                (reader, _) => reader?,
+            };
+            let fragment = merge.fragments.len();
+
+            // the tables are read through a buffer, the data is always read
+            // after a seek so the reader can be unwrapped afterwards
+            let mut buffered = BufReader::with_capacity(options.table_buffer_size.max(1), reader);
+            let version = read_version(&mut buffered).map_err(|err| PakError::in_fragment(err, &path, Some(0)))?;
+            let (info, legacy_chunks) = match version {
+                Version::Current => {
+                    let info = Info::from(&mut buffered).map_err(|err| PakError::in_fragment(err, &path, None))?;
+                    (info, None)
+                }
+                Version::Legacy => {
+                    let (info, chunks) = legacy::read_tables(&mut buffered)
+                        .map_err(|err| PakError::in_fragment(err, &path, None))?;
+                    (info, Some(chunks))
+                }
+            };
+            // before the tables of the current format are read
+            check_counts(&info, limits, total_chunks, total_properties, &path)?;
+            let (chunks, mut properties) = match legacy_chunks {
+                Some(chunks) => (chunks, HashMap::new()),
+                None => {
+                    let chunks = Chunk::read_all(&mut buffered, &info)
+                        .map_err(|err| PakError::in_fragment(err, &path, Some(info.chunks_offset)))?;
+                    let properties = Property::read(&mut buffered, &info)
+                        .map_err(|err| PakError::in_fragment(err, &path, Some(info.properties_offset)))?;
+                    (chunks, properties)
+                }
+            };
+            total_chunks += chunks.len();
+            total_properties += properties.len();
+            if limits.check_chunks {
+                let len = buffered.seek(SeekFrom::End(0))?;
+                for chunk in &chunks {
+                    chunk.check(&info, len).map_err(|cause| Error::from(PakError::Corrupt {
+                        path: Some(path.clone()),
+                        offset: Some(info.chunks_offset),
+                        cause,
+                    }))?;
+                }
+            }
+            merge.fragments.push(path.clone());
+            let mut checksums = take_checksums(&mut properties, &mut merge.warnings);
+            let mut compressions = take_compressions(&mut properties, &mut merge.warnings);
+            let mut encryptions = take_encryptions(&mut properties, &mut merge.warnings);
+
+            let reader = Rc::new(RefCell::new(buffered.into_inner()));
+            merge.readers.push(reader.clone());
+
+            let mut names = HashSet::with_capacity(chunks.len());
+            for (index, chunk) in chunks.into_iter().enumerate() {
+                let decoded = match options.name_codec {
+                    Some(ref codec) => codec.decode(&chunk.full_file_name)
+                        .and_then(|name| EntryName::new(&name)),
+                    None => EntryName::new(&chunk.full_file_name),
+                };
+                let full_file_name = match decoded {
+                    Ok(name) => name.into_string(),
+                    Err(err) => match options.invalid_names {
+                        InvalidNames::Reject => return Err(err),
+                        InvalidNames::Skip => {
+                            merge.warnings.push(Finding::new(Severity::Warning, format!("skipped: {}", err))
+                                .entry(chunk.full_file_name.as_str()));
+                            continue;
+                        }
+                        InvalidNames::Synthesize => {
+                            let name = format!("unnamed/{}-{}", fragment, index);
+                            merge.warnings.push(Finding::new(Severity::Warning, format!("renamed \"{}\": {}", name, err))
+                                .entry(chunk.full_file_name.as_str()));
+                            name
+                        }
+                    },
+                };
+                if !names.insert(full_file_name.clone()) {
+                    merge.warnings.push(Finding::new(Severity::Warning, "duplicated in the fragment, the last one is kept")
+                        .entry(full_file_name.as_str()));
+                }
+                let mut merged = MergedChunk::new(
+                    info.offset + chunk.offset as u64,
+                    chunk.size as u64,
+                    fragment,
+                    reader.clone()
                 );
+                merged.checksum = checksums.remove(&chunk.full_file_name);
+                merged.compression = compressions.remove(&chunk.full_file_name);
+                merged.encryption = encryptions.remove(&chunk.full_file_name)
+                    .map(|encryption| encryption.of(&full_file_name, options.keys.as_ref()));
+                if let Some(max) = limits.max_entry_size {
+                    if merged.size() > max || merged.uncompressed_size() > max {
+                        return Err(limit_exceeded(
+                            &path,
+                            format!("the entry \"{}\" is larger than {} bytes", full_file_name, max),
+                            "max_entry_size"
+                        ));
+                    }
+                }
+                merge.chunks.insert(full_file_name, merged);
             }
 
             for (key, property) in properties.drain() {
-                if key.eq("link") {
-                    links.push_back(set_file_name(&initial, &property.value).unwrap());
+                if follow_links && key == LINK_PROPERTY {
+                    let invalid = || Error::from(PakError::InvalidLink { link: property.value.clone() });
+                    let next = set_file_name(&initial, &property.value).ok_or_else(invalid)?;
+                    if !visited.insert(fragment_key(&next)) {
+                        return Err(invalid());
+                    }
+                    links.push_back(next);
                 }
                 merge.properties.insert(property.key, property.value);
             }
@@ -158,19 +778,163 @@ where
             !links.is_empty()
         } {}
 
+        merge.index_folded();
+        merge.account_index()?;
         Ok(merge)
     }
 
+    /// Adds the fragments of `other` after the ones of the reader, its
+    /// entries and properties overriding the ones of the reader.
+    pub(crate) fn absorb(&mut self, other: MergeReader<R>) -> io::Result<()> {
+        let base = self.fragments.len();
+        for (full_file_name, mut chunk) in other.chunks {
+            chunk.fragment += base;
+            self.chunks.insert(full_file_name, chunk);
+        }
+        self.properties.extend(other.properties);
+        self.fragments.extend(other.fragments);
+        self.readers.extend(other.readers);
+        self.warnings.extend(other.warnings);
+        self.cache.get_mut().clear();
+        self.index_folded();
+        self.account_index()
+    }
+
+    /// Builds the index of the case-insensitive lookups, if they are
+    /// enabled. Called once the chunks are merged.
+    pub(crate) fn index_folded(&mut self) {
+        self.folded = if self.options.case_insensitive {
+            Some(fold_names(self.chunks.keys()))
+        } else {
+            None
+        };
+    }
+
+    /// Name and chunk of the entry `full_file_name`, looked up by its
+    /// normalized name, ignoring its case if `ReadOptions::case_insensitive`
+    /// is set.
+    pub(crate) fn lookup(&self, full_file_name: &str) -> Option<(&String, &MergedChunk<R>)> {
+        find_entry(&self.chunks, self.folded.as_ref(), full_file_name)
+    }
+
+    /// Chunk of the entry `full_file_name`, see `lookup`.
+    pub(crate) fn chunk(&self, full_file_name: &str) -> Option<&MergedChunk<R>> {
+        self.lookup(full_file_name).map(|(_, chunk)| chunk)
+    }
+
+    /// Name and chunk of the entry `full_file_name`, if the `AccessPolicy`
+    /// allows reading it. The policy is asked about the name as stored,
+    /// whatever the case of `full_file_name`.
+    pub(crate) fn allowed_entry(&self, full_file_name: &str) -> io::Result<(&String, &MergedChunk<R>)> {
+        let (name, chunk) = self.lookup(full_file_name).ok_or_else(|| entry_not_found(full_file_name))?;
+        check_access(&self.options.access_policy, name)?;
+        Ok((name, chunk))
+    }
+
+    /// Chunk of the entry `full_file_name`, if the `AccessPolicy` allows
+    /// reading it.
+    pub(crate) fn allowed_chunk(&self, full_file_name: &str) -> io::Result<&MergedChunk<R>> {
+        self.allowed_entry(full_file_name).map(|(_, chunk)| chunk)
+    }
+
+    /// Name of the entry `full_file_name` as stored in the archive: its
+    /// normalized form, with the case of the archive if the lookups are
+    /// case-insensitive. `None` if there is no such entry.
+    pub fn stored_name(&self, full_file_name: &str) -> Option<&str> {
+        self.lookup(full_file_name).map(|(name, _)| name.as_str())
+    }
+
+    /// Returns `true` if the `AccessPolicy` allows reading the entry
+    /// `full_file_name`.
+    pub(crate) fn allows(&self, full_file_name: &str) -> bool {
+        self.options.access_policy.as_ref().is_none_or(|policy| policy.allows(full_file_name))
+    }
+
+    /// Reads the entry `full_file_name`, decompressed and decrypted. Fails
+    /// with `Error::EntryNotFound` if the archive doesn't have it and with
+    /// `Error::AccessDenied` if the `AccessPolicy` denies it. The read is
+    /// recorded in the `AccessLog`, and bounded by the `read` timeout.
     pub fn read_file(&self, full_file_name: &str) -> io::Result<Vec<u8>> {
-         self.chunks.get(full_file_name).map(|chunk| chunk.data()).unwrap_or(
-            Err(Error::new(
-                ErrorKind::InvalidInput, 
-                format!("`full_file_name` \"{}\" can't be read", full_file_name)
-            ))
-        )
+        let (name, chunk) = self.allowed_entry(full_file_name)?;
+        if let Some(ref mut log) = *self.access_log.borrow_mut() {
+            log.record(name);
+        }
+        if self.options.timeouts.read.is_none() {
+            if self.options.read_strategy.is_cached() && chunk.as_slice().is_none() {
+                return chunk.decode(self.cached_stored(chunk)?);
+            }
+            return chunk.data();
+        }
+
+        let deadline = Deadline::start(format!("read \"{}\"", full_file_name), self.options.timeouts.read);
+        let mut buffer = Vec::new();
+        deadline.reader(chunk.decoded_reader()?).read_to_end(&mut buffer)?;
+        chunk.check_decoded(buffer.len() as u64)?;
+        Ok(buffer)
     }
 
-    pub fn iter(&self) -> Iter<String, MergedChunk<R>> {
+    /// Reads the entry `full_file_name`, borrowing the data when the backend
+    /// allows it (memory-mapped fragments) and copying it otherwise, so that
+    /// generic code gets zero-copy reads where possible.
+    pub fn read_file_cow(&self, full_file_name: &str) -> io::Result<Cow<'_, [u8]>> {
+        let (name, chunk) = self.allowed_entry(full_file_name)?;
+        if let Some(data) = chunk.as_slice() {
+            if let Some(ref mut log) = *self.access_log.borrow_mut() {
+                log.record(name);
+            }
+            return Ok(Cow::Borrowed(data));
+        }
+        self.read_file(full_file_name).map(Cow::Owned)
+    }
+
+    /// Problems found while opening the archive (skipped or renamed entries,
+    /// duplicated names), also reported by `verify`.
+    pub fn warnings(&self) -> &[Finding] {
+        &self.warnings
+    }
+
+    /// Properties of the archive, the ones of the later fragments overriding
+    /// the ones of the earlier. The `link` properties chaining the fragments
+    /// are kept, the `checksum:`, `compression:` and `encryption:` ones
+    /// describe the chunks and are not.
+    pub fn properties(&self) -> &HashMap<String, String> {
+        &self.properties
+    }
+
+    /// Value of the property `key`.
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(|value| value.as_str())
+    }
+
+    /// Value of the property `key` as an `i32`, failing with `InvalidData`
+    /// if it isn't one.
+    pub fn property_i32(&self, key: &str) -> io::Result<Option<i32>> {
+        self.property(key).map(|value| parse_i32(key, value)).transpose()
+    }
+
+    /// Value of the property `key` as a `bool` (`true` or `1`, `false` or
+    /// `0`), failing with `InvalidData` if it isn't one.
+    pub fn property_bool(&self, key: &str) -> io::Result<Option<bool>> {
+        self.property(key).map(|value| parse_bool(key, value)).transpose()
+    }
+
+    pub fn iter(&self) -> Iter<'_, String, MergedChunk<R>> {
         self.chunks.iter()
     }
+
+    /// Extracts every entry in memory, without touching the disk: the map
+    /// associates the relative path each entry would be extracted to with
+    /// its data. The entries denied by the `AccessPolicy` are skipped.
+    pub fn extract_to_memfs(&self) -> io::Result<BTreeMap<PathBuf, Vec<u8>>> {
+        let mut files = BTreeMap::new();
+        for (full_file_name, chunk) in self.iter().filter(|&(full_file_name, _)| self.allows(full_file_name)) {
+            files.insert(EntryName::new(full_file_name)?.to_path(""), chunk.data()?);
+        }
+        Ok(files)
+    }
+
+    /// Paths of the fragments, the first one being the initial pak file.
+    pub fn fragments(&self) -> &[PathBuf] {
+        &self.fragments
+    }
 }
\ No newline at end of file
@@ -0,0 +1,234 @@
+use read::{MergedChunk, MergeReader};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Error, Read, Seek};
+use std::path::{Path, PathBuf};
+use write::FragmentWriter;
+
+/// Entries of `reader` accepted by `predicate` and allowed by the
+/// `AccessPolicy`, in the order of their data in the fragments so that they
+/// are read sequentially.
+pub(crate) fn selected<R, F>(reader: &MergeReader<R>, mut predicate: F) -> Vec<(&String, &MergedChunk<R>)>
+where
+    R: Read + Seek,
+    F: FnMut(&str, &MergedChunk<R>) -> bool
+{
+    let mut entries: Vec<_> = reader.iter()
+        .filter(|&(full_file_name, chunk)| reader.allows(full_file_name) && predicate(full_file_name, chunk))
+        .collect();
+    entries.sort_by_key(|&(full_file_name, chunk)| (chunk.fragment(), chunk.offset(), full_file_name.clone()));
+    entries
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Writes a new single-fragment archive at `dest` containing only the
+    /// entries accepted by `predicate`, returning their count.
+    ///
+    /// The data is copied as is from the fragments, without being decoded or
+    /// buffered entirely. The properties are kept, except the `link` ones.
+    pub fn save_subset<F, P>(&self, predicate: F, dest: P) -> io::Result<usize>
+        where F: FnMut(&str, &MergedChunk<R>) -> bool,
+              P: AsRef<Path>
+    {
+        let entries = selected(self, predicate);
+        let mut writer = FragmentWriter::new(BufWriter::with_capacity(self.options.copy_buffer_size, File::create(dest)?))?;
+
+        for &(full_file_name, chunk) in &entries {
+            writer.add_chunk(full_file_name, chunk)?;
+        }
+
+        let mut properties: Vec<_> = self.properties.iter()
+            .filter(|&(key, _)| key != "link")
+            .collect();
+        properties.sort();
+        for (key, value) in properties {
+            writer.add_property(key, value);
+        }
+
+        writer.finish()?.into_inner().map_err(Error::from)?.sync_all()?;
+        Ok(entries.len())
+    }
+}
+
+/// ShardGrouping
+///
+/// Order in which the entries are distributed into the fragments.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ShardGrouping {
+    /// Keep the order of the data in the source fragments
+    Layout,
+    /// Sort by the first `depth` directories, then by name, so that the
+    /// entries of a directory end up in the same fragments
+    Prefix(usize),
+    /// Entries listed first in the given order (e.g. most accessed first),
+    /// then the others by name
+    Access(Vec<String>),
+}
+
+/// ShardPolicy
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShardPolicy {
+    /// Maximum size of the data of a fragment. A single entry larger than
+    /// that gets a fragment of its own.
+    pub max_fragment_size: u64,
+    /// Order of the entries
+    pub grouping: ShardGrouping,
+    /// Depth of the directories kept whole: when the entries of such a
+    /// directory follow each other and fit in a fragment, a new fragment is
+    /// started rather than splitting them. 0 splits anywhere.
+    pub keep_directories: usize,
+}
+
+impl ShardPolicy {
+    /// Creates a new `ShardPolicy`, splitting anywhere.
+    pub fn new(max_fragment_size: u64, grouping: ShardGrouping) -> Self {
+        ShardPolicy { max_fragment_size, grouping, keep_directories: 0 }
+    }
+
+    /// Keeps the directories of depth `depth` whole when they fit, see
+    /// `keep_directories`.
+    pub fn keep_directories(mut self, depth: usize) -> Self {
+        self.keep_directories = depth;
+        self
+    }
+
+    /// Distributes `entries`, in order, into fragments.
+    fn split<'a, T>(&self, entries: Vec<(&'a String, T)>, size_of: impl Fn(&T) -> u64) -> Vec<Vec<(&'a String, T)>> {
+        let mut fragments = vec![Vec::new()];
+        let mut size = 0;
+        let mut entries = entries.into_iter().peekable();
+        while let Some(entry) = entries.next() {
+            // the entries of the directory of `entry` which follow it
+            let mut group = vec![entry];
+            if self.keep_directories > 0 {
+                let directory = prefix(group[0].0, self.keep_directories);
+                if directory.len() < group[0].0.len() {
+                    while let Some(next) = entries.next_if(|next| prefix(next.0, self.keep_directories) == directory) {
+                        group.push(next);
+                    }
+                }
+            }
+
+            let group_size: u64 = group.iter().map(|entry| size_of(&entry.1)).sum();
+            if size > 0 && size + group_size > self.max_fragment_size && group_size <= self.max_fragment_size {
+                fragments.push(Vec::new());
+                size = 0;
+            }
+            // a group too large for a fragment is split like single entries
+            for entry in group {
+                let entry_size = size_of(&entry.1);
+                if size > 0 && size + entry_size > self.max_fragment_size {
+                    fragments.push(Vec::new());
+                    size = 0;
+                }
+                size += entry_size;
+                fragments.last_mut().unwrap().push(entry);
+            }
+        }
+        fragments
+    }
+}
+
+/// Path of the fragment `index` of the chain starting at `head`:
+/// `name.d2p`, `name1.d2p`, `name2.d2p`...
+pub(crate) fn fragment_path(head: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        return head.to_path_buf();
+    }
+    let stem = head.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    let file_name = match head.extension() {
+        Some(extension) => format!("{}{}.{}", stem, index, extension.to_string_lossy()),
+        None => format!("{}{}", stem, index),
+    };
+    head.with_file_name(file_name)
+}
+
+/// First `depth` directories of `full_file_name`.
+fn prefix(full_file_name: &str, depth: usize) -> &str {
+    let end = full_file_name.match_indices('/')
+        .nth(depth.saturating_sub(1))
+        .map_or(full_file_name.len(), |(i, _)| i);
+    if depth == 0 { "" } else { &full_file_name[..end] }
+}
+
+/// Writes the fragments of a chain starting at `head`, one per group of
+/// `fragments`, linking each one to the next, through buffers of
+/// `buffer_size` bytes. The properties are written in the first fragment.
+pub(crate) fn write_chain<R>(
+    head: &Path,
+    fragments: &[Vec<(&String, &MergedChunk<R>)>],
+    properties: &[(&String, &String)],
+    buffer_size: usize
+) -> io::Result<Vec<PathBuf>>
+where
+    R: Read + Seek
+{
+    let mut paths = Vec::with_capacity(fragments.len());
+
+    for (i, entries) in fragments.iter().enumerate() {
+        let path = fragment_path(head, i);
+        let mut writer = FragmentWriter::new(BufWriter::with_capacity(buffer_size, File::create(&path)?))?;
+
+        for &(full_file_name, chunk) in entries {
+            writer.add_chunk(full_file_name, chunk)?;
+        }
+        if i == 0 {
+            for &(key, value) in properties {
+                writer.add_property(key, value);
+            }
+        }
+        if i + 1 < fragments.len() {
+            let next = fragment_path(head, i + 1);
+            let link = next.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            writer.add_property("link", &link);
+        }
+
+        writer.finish()?.into_inner().map_err(Error::from)?.sync_all()?;
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Redistributes the entries into a new fragment chain starting at
+    /// `head`, returning the paths of the written fragments.
+    ///
+    /// The `link` properties are rewritten to chain the new fragments, the
+    /// other properties are kept in the first one. The new chain must not
+    /// overwrite a fragment of the archive being read.
+    pub fn reshard<P: AsRef<Path>>(&self, head: P, policy: &ShardPolicy) -> io::Result<Vec<PathBuf>> {
+        let mut entries = selected(self, |_, _| true);
+        match policy.grouping {
+            ShardGrouping::Layout => {}
+            ShardGrouping::Prefix(depth) => {
+                entries.sort_by(|a, b| prefix(a.0, depth).cmp(prefix(b.0, depth)).then_with(|| a.0.cmp(b.0)));
+            }
+            ShardGrouping::Access(ref order) => {
+                let ranks: HashMap<&str, usize> = order.iter()
+                    .enumerate()
+                    .map(|(rank, name)| (name.as_str(), rank))
+                    .collect();
+                entries.sort_by_key(|&(full_file_name, _)| {
+                    (ranks.get(full_file_name.as_str()).cloned().unwrap_or(usize::MAX), full_file_name.clone())
+                });
+            }
+        }
+
+        let fragments = policy.split(entries, |chunk| chunk.size());
+
+        let mut properties: Vec<_> = self.properties.iter()
+            .filter(|&(key, _)| key != "link")
+            .collect();
+        properties.sort();
+
+        write_chain(head.as_ref(), &fragments, &properties, self.options.copy_buffer_size)
+    }
+}
@@ -0,0 +1,249 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+use std::thread;
+use std::time::Duration;
+use timeout::TimedOut;
+
+/// Returns `true` if the error is likely to disappear by retrying the
+/// operation (network shares, flaky external drives, ...). An exceeded
+/// `Timeouts` isn't transient: retrying would exceed it again. Neither is
+/// `WouldBlock`, reported for a fragment reader already in use by the same
+/// thread, which a retry finds in use again.
+pub fn is_transient(err: &Error) -> bool {
+    if TimedOut::from_error(err).is_some() {
+        return false;
+    }
+    matches!(
+        err.kind(),
+        ErrorKind::Interrupted
+            | ErrorKind::TimedOut
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::BrokenPipe
+    )
+}
+
+/// ErrorClass
+///
+/// Coarse classification of an error returned while reading a pak file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The underlying device failed, retrying later may succeed
+    Transient,
+    /// The pak file itself is malformed, retrying won't help
+    Corruption,
+    /// Any other error (missing file, permissions, ...)
+    Other,
+}
+
+/// Classifies the specified error.
+pub fn classify(err: &Error) -> ErrorClass {
+    if is_transient(err) || RetryExhausted::from_error(err).is_some() {
+        return ErrorClass::Transient;
+    }
+    match err.kind() {
+        ErrorKind::InvalidInput
+        | ErrorKind::InvalidData
+        | ErrorKind::UnexpectedEof => ErrorClass::Corruption,
+        _ => ErrorClass::Other,
+    }
+}
+
+/// RetryExhausted
+///
+/// Error payload returned when every attempt of a `RetryPolicy` failed
+/// with a transient error. The wrapping `io::Error` keeps the kind of the
+/// last failure.
+#[derive(Debug)]
+pub struct RetryExhausted {
+    attempts: u32,
+    last: Error,
+}
+
+impl RetryExhausted {
+    /// Number of attempts made before giving up.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Last error encountered.
+    pub fn last_error(&self) -> &Error {
+        &self.last
+    }
+
+    /// Returns the `RetryExhausted` payload of the error if any.
+    pub fn from_error(err: &Error) -> Option<&RetryExhausted> {
+        err.get_ref().and_then(|inner| inner.downcast_ref::<RetryExhausted>())
+    }
+
+    fn into_error(self) -> Error {
+        Error::new(self.last.kind(), self)
+    }
+}
+
+impl fmt::Display for RetryExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "gave up after {} attempts: {}", self.attempts, self.last)
+    }
+}
+
+impl error::Error for RetryExhausted {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.last)
+    }
+}
+
+/// RetryPolicy
+///
+/// Retries transient failures with an exponential backoff. Structural
+/// errors (corrupted header, invalid data, ...) are never retried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, the first one included
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay between two attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(4, Duration::from_millis(50), Duration::from_secs(2))
+    }
+}
+
+impl RetryPolicy {
+    /// Creates a new `RetryPolicy`.
+    pub fn new(max_attempts: u32, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            initial_backoff,
+            max_backoff,
+        }
+    }
+
+    /// Creates a `RetryPolicy` making a single attempt.
+    pub fn none() -> Self {
+        RetryPolicy::new(1, Duration::from_millis(0), Duration::from_millis(0))
+    }
+
+    /// Delay to wait after the failed `attempt` (starting at 1).
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt - 1).unwrap_or(u32::MAX);
+        self.initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+
+    /// Runs `f` until it succeeds, fails with a non transient error or the
+    /// attempts are exhausted.
+    pub fn run<T, F>(&self, mut f: F) -> io::Result<T>
+        where F: FnMut() -> io::Result<T>
+    {
+        let mut attempt = 1;
+        loop {
+            match f() {
+                Err(ref err) if is_transient(err) && attempt < self.max_attempts => {
+                    thread::sleep(self.backoff(attempt));
+                    attempt += 1;
+                }
+                Err(err) => {
+                    return if is_transient(&err) && self.max_attempts > 1 {
+                        Err(RetryExhausted { attempts: attempt, last: err }.into_error())
+                    } else {
+                        Err(err)
+                    };
+                }
+                ok => return ok,
+            }
+        }
+    }
+}
+
+/// RetryReader
+///
+/// Wraps a reader and retries its operations according to a `RetryPolicy`.
+/// The position is restored before every new attempt since a failed read
+/// leaves it undefined.
+#[derive(Debug)]
+pub struct RetryReader<R> {
+    inner: R,
+    policy: RetryPolicy,
+    position: u64,
+}
+
+impl<R> RetryReader<R>
+where
+    R: Read + Seek
+{
+    /// Creates a new `RetryReader`.
+    pub fn new(mut inner: R, policy: RetryPolicy) -> io::Result<Self> {
+        let position = inner.stream_position()?;
+        Ok(RetryReader {
+            inner,
+            policy,
+            position,
+        })
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Unwraps this `RetryReader`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R> Read for RetryReader<R>
+where
+    R: Read + Seek
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let inner = &mut self.inner;
+        let position = self.position;
+        let mut dirty = false;
+
+        let read = self.policy.run(|| {
+            if dirty {
+                inner.seek(SeekFrom::Start(position))?;
+            }
+            dirty = true;
+            inner.read(buf)
+        })?;
+
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R> Seek for RetryReader<R>
+where
+    R: Read + Seek
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let inner = &mut self.inner;
+        let pos = match pos {
+            SeekFrom::Current(delta) => {
+                let target = self.position as i64 + delta;
+                if target < 0 {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "invalid seek to a negative position"
+                    ));
+                }
+                SeekFrom::Start(target as u64)
+            }
+            pos => pos,
+        };
+
+        self.position = self.policy.run(|| inner.seek(pos))?;
+        Ok(self.position)
+    }
+}
@@ -0,0 +1,132 @@
+use name::EntryName;
+use read::MergeReader;
+use std::ffi::CString;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Error, ErrorKind, Read, Seek};
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::Path;
+
+/// Opens `path` relatively to the directory `dir` with `openat2`, the
+/// resolution of the path, symbolic links included, staying beneath `dir`.
+fn open_beneath(dir: &File, path: &str, flags: libc::c_int) -> io::Result<File> {
+    let c_path = CString::new(path).map_err(|_| Error::new(
+        ErrorKind::InvalidInput,
+        format!("\"{}\" contains a NUL byte", path)
+    ))?;
+    // `open_how` can't be built field by field, it may grow
+    let mut how: libc::open_how = unsafe { mem::zeroed() };
+    how.flags = (flags | libc::O_CLOEXEC) as u64;
+    if flags & libc::O_CREAT != 0 {
+        how.mode = 0o666;
+    }
+    how.resolve = libc::RESOLVE_BENEATH | libc::RESOLVE_NO_MAGICLINKS;
+
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            dir.as_raw_fd(),
+            c_path.as_ptr(),
+            &how as *const libc::open_how,
+            mem::size_of::<libc::open_how>()
+        )
+    };
+    if fd < 0 {
+        let err = Error::last_os_error();
+        return Err(match err.raw_os_error() {
+            Some(libc::ENOSYS) => Error::new(
+                ErrorKind::Unsupported,
+                "the sandboxed extraction requires `openat2` (Linux 5.6 or later)"
+            ),
+            Some(libc::EXDEV) => Error::new(
+                ErrorKind::PermissionDenied,
+                format!("\"{}\" resolves outside of the extraction directory", path)
+            ),
+            _ => err,
+        });
+    }
+    Ok(unsafe { File::from_raw_fd(fd as libc::c_int) })
+}
+
+/// Creates the directory `name` in the directory `dir`, if missing.
+fn mkdir_in(dir: &File, name: &str) -> io::Result<()> {
+    let c_name = CString::new(name).map_err(|_| Error::new(
+        ErrorKind::InvalidInput,
+        format!("\"{}\" contains a NUL byte", name)
+    ))?;
+    if unsafe { libc::mkdirat(dir.as_raw_fd(), c_name.as_ptr(), 0o777) } < 0 {
+        let err = Error::last_os_error();
+        if err.kind() != ErrorKind::AlreadyExists {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// SandboxDir
+///
+/// Extraction directory for untrusted archives, on Linux 5.6 or later: the
+/// files are created through a handle on the directory with `openat2` and
+/// `RESOLVE_BENEATH`, so the kernel refuses any path resolving outside of
+/// it, through `..` or through a symbolic link planted in the directory,
+/// instead of relying on the normalization of the names only. A path
+/// escaping the directory fails with `ErrorKind::PermissionDenied`, an
+/// older kernel with `ErrorKind::Unsupported`.
+#[derive(Debug)]
+pub struct SandboxDir {
+    dir: File,
+}
+
+impl SandboxDir {
+    /// Opens the directory `path`, creating it if missing.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        fs::create_dir_all(&path)?;
+        let dir = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_DIRECTORY | libc::O_CLOEXEC)
+            .open(path)?;
+        Ok(SandboxDir { dir })
+    }
+
+    /// Creates (or truncates) the file of the entry `full_file_name`,
+    /// creating its directories, beneath the directory.
+    pub fn create_file(&self, full_file_name: &str) -> io::Result<File> {
+        let full_file_name = EntryName::new(full_file_name)?;
+        let full_file_name = full_file_name.as_str();
+        if let Some(end) = full_file_name.rfind('/') {
+            let mut parent = self.dir.try_clone()?;
+            for segment in full_file_name[..end].split('/') {
+                mkdir_in(&parent, segment)?;
+                parent = open_beneath(&parent, segment, libc::O_PATH | libc::O_DIRECTORY)?;
+            }
+        }
+        open_beneath(&self.dir, full_file_name, libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC)
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Extracts every entry into `dest` through a `SandboxDir`, so that no
+    /// entry is written outside of `dest` whatever its name and whatever
+    /// `dest` already contains. The entries denied by the `AccessPolicy`
+    /// are skipped.
+    pub fn extract_sandboxed<P: AsRef<Path>>(&self, dest: P) -> io::Result<()> {
+        let sandbox = SandboxDir::open(dest)?;
+        let mut entries: Vec<_> = self.iter()
+            .filter(|&(full_file_name, _)| self.allows(full_file_name))
+            .collect();
+        // sequential reads
+        entries.sort_by_key(|&(full_file_name, chunk)| (chunk.fragment(), chunk.offset(), full_file_name.clone()));
+
+        for (full_file_name, chunk) in entries {
+            let mut file = sandbox.create_file(full_file_name)?;
+            chunk.decode_to(&mut file, self.options.copy_buffer_size)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,325 @@
+use read::{MergedChunk, MergeReader};
+#[cfg(feature = "regex")]
+use regex::Regex;
+#[cfg(feature = "regex")]
+use regex::bytes::Regex as BytesRegex;
+use std::collections::HashMap;
+use std::io;
+use std::io::{Read, Seek};
+use std::str;
+
+/// Number of leading bytes inspected to detect a text chunk.
+const SNIFF_LEN: usize = 4096;
+
+/// Bytes of a block kept with the next one by `search_regex`, the longest
+/// match found whole.
+#[cfg(feature = "regex")]
+const REGEX_OVERLAP: usize = 4096;
+
+/// TextKind
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextKind {
+    /// XML (or HTML) document
+    Xml,
+    /// JSON document
+    Json,
+    /// Any other text
+    Plain,
+}
+
+/// Detects if `data` looks like text, and which kind of text.
+///
+/// Only the first bytes are inspected: they must be valid UTF-8 (a
+/// sequence cut at the end of the sample is tolerated) without control
+/// characters other than whitespaces.
+pub fn detect_text(data: &[u8]) -> Option<TextKind> {
+    let sample = &data[..data.len().min(SNIFF_LEN)];
+    let sample = match str::from_utf8(sample) {
+        Ok(sample) => sample,
+        Err(err) if err.error_len().is_none() && data.len() > SNIFF_LEN => {
+            str::from_utf8(&sample[..err.valid_up_to()]).unwrap_or("")
+        }
+        Err(_) => return None,
+    };
+
+    if sample.chars().any(|c| c.is_control() && !c.is_whitespace()) {
+        return None;
+    }
+
+    let trimmed = sample.trim_start_matches('\u{feff}').trim_start();
+    if trimmed.starts_with('<') {
+        Some(TextKind::Xml)
+    } else if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        Some(TextKind::Json)
+    } else {
+        Some(TextKind::Plain)
+    }
+}
+
+/// TextMatch
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TextMatch {
+    /// Full file name of the entry
+    pub full_file_name: String,
+    /// Byte offset of the match in the entry
+    pub offset: u64,
+    /// Byte length of the match
+    pub len: u64,
+}
+
+/// Splits `text` into lowercase words with their byte offsets.
+fn tokenize(text: &str) -> Vec<(String, u64)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+
+    for (i, c) in text.char_indices().chain(Some((text.len(), ' '))) {
+        if c.is_alphanumeric() || c == '_' {
+            if start.is_none() {
+                start = Some(i);
+            }
+        } else if let Some(begin) = start.take() {
+            tokens.push((text[begin..i].to_lowercase(), begin as u64));
+        }
+    }
+
+    tokens
+}
+
+/// TextIndex
+///
+/// Inverted index of the words contained in the text chunks of an archive.
+#[derive(Clone, Debug, Default)]
+pub struct TextIndex {
+    names: Vec<String>,
+    kinds: Vec<TextKind>,
+    words: HashMap<String, Vec<(usize, u64)>>,
+}
+
+impl TextIndex {
+    /// Finds the occurrences of the whole `word` (case insensitive).
+    pub fn find_word(&self, word: &str) -> Vec<TextMatch> {
+        let len = word.len() as u64;
+        self.words.get(&word.to_lowercase()).map_or(Vec::new(), |occurrences| {
+            occurrences.iter().map(|&(entry, offset)| TextMatch {
+                full_file_name: self.names[entry].clone(),
+                offset,
+                len,
+            }).collect()
+        })
+    }
+
+    /// Iterates over the indexed entries and their kind of text.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, TextKind)> {
+        self.names.iter().map(|name| name.as_str()).zip(self.kinds.iter().cloned())
+    }
+
+    /// Number of distinct words.
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Returns `true` if no word was indexed.
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+/// Ranges of the non-overlapping occurrences of `needle` in `haystack`.
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Vec<(usize, usize)> {
+    let mut found = Vec::new();
+    let mut start = 0;
+    while start + needle.len() <= haystack.len() {
+        if &haystack[start..start + needle.len()] == needle {
+            found.push((start, start + needle.len()));
+            start += needle.len();
+        } else {
+            start += 1;
+        }
+    }
+    found
+}
+
+/// Scans the decoded data of `chunk` block by block, each block being
+/// searched with the last `overlap` bytes of the previous one, so that the
+/// data is never held whole. `find` returns the ranges of the matches in
+/// the bytes given, in order and not overlapping.
+fn scan_chunk<R, F>(
+    full_file_name: &str,
+    chunk: &MergedChunk<R>,
+    block_size: usize,
+    overlap: usize,
+    find: &mut F,
+    matches: &mut Vec<TextMatch>
+) -> io::Result<()>
+where
+    R: Read + Seek,
+    F: FnMut(&[u8]) -> Vec<(usize, usize)>
+{
+    let mut decoded = chunk.decoded_reader()?;
+    let mut window = Vec::with_capacity(overlap + block_size);
+    let mut block = vec![0; block_size.max(1)];
+    // offset of the window in the data, end of the last match
+    let mut position = 0;
+    let mut next = 0;
+    loop {
+        let read = decoded.read(&mut block)?;
+        window.extend_from_slice(&block[..read]);
+        // the matches starting in the overlap are left to the next window,
+        // which sees their end
+        let limit = if read == 0 { window.len() } else { window.len().saturating_sub(overlap) };
+        for (start, end) in find(&window) {
+            if start >= limit {
+                break;
+            }
+            if position + start as u64 >= next {
+                matches.push(TextMatch {
+                    full_file_name: full_file_name.to_string(),
+                    offset: position + start as u64,
+                    len: (end - start) as u64,
+                });
+                next = position + end as u64;
+            }
+        }
+        if read == 0 {
+            break;
+        }
+        window.drain(..limit);
+        position += limit as u64;
+    }
+    chunk.check_decoded(position + window.len() as u64)?;
+    Ok(())
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Full file names of the entries the `AccessPolicy` allows reading,
+    /// sorted.
+    fn searched_names(&self) -> Vec<&String> {
+        let mut names: Vec<&String> = self.chunks.keys()
+            .filter(|full_file_name| self.allows(full_file_name))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Calls `find` with the data of every entry the `AccessPolicy` allows
+    /// reading, sorted by full file name, see `scan_chunk`.
+    fn scan_entries<F>(&self, overlap: usize, mut find: F) -> io::Result<Vec<TextMatch>>
+        where F: FnMut(&[u8]) -> Vec<(usize, usize)>
+    {
+        let names = self.searched_names();
+
+        let mut matches = Vec::new();
+        for full_file_name in names {
+            scan_chunk(
+                full_file_name,
+                &self.chunks[full_file_name],
+                self.options.copy_buffer_size,
+                overlap,
+                &mut find,
+                &mut matches
+            )?;
+        }
+        Ok(matches)
+    }
+
+    /// Calls `f` with every text entry the `AccessPolicy` allows reading,
+    /// sorted by full file name.
+    fn for_each_text<F>(&self, mut f: F) -> io::Result<()>
+        where F: FnMut(&str, TextKind, &str)
+    {
+        let names = self.searched_names();
+
+        for full_file_name in names {
+            let data = self.chunks[full_file_name].data()?;
+            if let Some(kind) = detect_text(&data) {
+                if let Ok(text) = str::from_utf8(&data) {
+                    f(full_file_name, kind, text);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds every occurrence of `needle` in the text entries.
+    pub fn search_text(&self, needle: &str) -> io::Result<Vec<TextMatch>> {
+        let mut matches = Vec::new();
+        if needle.is_empty() {
+            return Ok(matches);
+        }
+
+        self.for_each_text(|full_file_name, _, text| {
+            for (offset, _) in text.match_indices(needle) {
+                matches.push(TextMatch {
+                    full_file_name: full_file_name.to_string(),
+                    offset: offset as u64,
+                    len: needle.len() as u64,
+                });
+            }
+        })?;
+
+        Ok(matches)
+    }
+
+    /// Finds every match of `pattern` in the text entries.
+    #[cfg(feature = "regex")]
+    pub fn search_text_regex(&self, pattern: &Regex) -> io::Result<Vec<TextMatch>> {
+        let mut matches = Vec::new();
+
+        self.for_each_text(|full_file_name, _, text| {
+            for found in pattern.find_iter(text) {
+                matches.push(TextMatch {
+                    full_file_name: full_file_name.to_string(),
+                    offset: found.start() as u64,
+                    len: (found.end() - found.start()) as u64,
+                });
+            }
+        })?;
+
+        Ok(matches)
+    }
+
+    /// Finds every occurrence of `needle` in the data of the entries, text
+    /// or binary, sorted by full file name and offset. The data is
+    /// decompressed and scanned block by block, without extracting it.
+    pub fn search_bytes(&self, needle: &[u8]) -> io::Result<Vec<TextMatch>> {
+        if needle.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.scan_entries(needle.len() - 1, |window| find_bytes(window, needle))
+    }
+
+    /// Finds every match of `pattern` in the data of the entries, text or
+    /// binary, sorted by full file name and offset, scanning it like
+    /// `search_bytes`.
+    ///
+    /// The blocks are searched on their own, with the last 4 KiB of the
+    /// previous one: a match longer than that may be cut, and `^` matches at
+    /// the start of every block.
+    #[cfg(feature = "regex")]
+    pub fn search_regex(&self, pattern: &BytesRegex) -> io::Result<Vec<TextMatch>> {
+        self.scan_entries(REGEX_OVERLAP, |window| {
+            pattern.find_iter(window).map(|found| (found.start(), found.end())).collect()
+        })
+    }
+
+    /// Builds the inverted index of the words of the text entries.
+    pub fn build_text_index(&self) -> io::Result<TextIndex> {
+        let mut index = TextIndex::default();
+
+        self.for_each_text(|full_file_name, kind, text| {
+            let entry = index.names.len();
+            index.names.push(full_file_name.to_string());
+            index.kinds.push(kind);
+
+            for (word, offset) in tokenize(text) {
+                index.words.entry(word).or_insert_with(Vec::new).push((entry, offset));
+            }
+        })?;
+
+        Ok(index)
+    }
+}
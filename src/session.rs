@@ -0,0 +1,161 @@
+use archive::{Archive, Staged};
+use history::History;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Operation recorded by a `Session`.
+#[derive(Clone, Debug)]
+enum Operation {
+    Add(String, Vec<u8>),
+    Replace(String, Vec<u8>),
+    Insert(String, Vec<u8>),
+    InsertFile(String, PathBuf),
+    Remove(String),
+    SetProperty(String, String),
+    RemoveProperty(String),
+}
+
+/// Staged state of an `Archive`, restored on rollback.
+struct Snapshot {
+    staged: BTreeMap<String, Staged>,
+    properties: HashMap<String, String>,
+    properties_changed: bool,
+    history: History,
+}
+
+/// Session
+///
+/// Records edit operations on an `Archive` without touching it. On
+/// `commit`, the operations are applied and validated, then the archive is
+/// committed in one atomic rewrite; if anything fails the archive is rolled
+/// back to its state before the session. Dropping a `Session` without
+/// committing it discards the operations.
+pub struct Session<'a> {
+    archive: &'a mut Archive,
+    operations: Vec<Operation>,
+}
+
+impl<'a> Session<'a> {
+    /// Records the addition of a new entry.
+    pub fn add<S: Into<String>>(&mut self, full_file_name: S, data: Vec<u8>) -> &mut Self {
+        self.operations.push(Operation::Add(full_file_name.into(), data));
+        self
+    }
+
+    /// Records the replacement of the data of an existing entry.
+    pub fn replace<S: Into<String>>(&mut self, full_file_name: S, data: Vec<u8>) -> &mut Self {
+        self.operations.push(Operation::Replace(full_file_name.into(), data));
+        self
+    }
+
+    /// Records the addition or the replacement of an entry.
+    pub fn insert<S: Into<String>>(&mut self, full_file_name: S, data: Vec<u8>) -> &mut Self {
+        self.operations.push(Operation::Insert(full_file_name.into(), data));
+        self
+    }
+
+    /// Records the addition or the replacement of an entry with the content
+    /// of the file at `path`.
+    pub fn insert_file<S: Into<String>, P: AsRef<Path>>(&mut self, full_file_name: S, path: P) -> &mut Self {
+        self.operations.push(Operation::InsertFile(full_file_name.into(), path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Records the removal of an entry.
+    pub fn remove<S: Into<String>>(&mut self, full_file_name: S) -> &mut Self {
+        self.operations.push(Operation::Remove(full_file_name.into()));
+        self
+    }
+
+    /// Records the modification of the property `key`, which fails to
+    /// apply if it is reserved, see `is_reserved_property`.
+    pub fn set_property<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) -> &mut Self {
+        self.operations.push(Operation::SetProperty(key.into(), value.into()));
+        self
+    }
+
+    /// Records the removal of the property `key`.
+    pub fn remove_property<K: Into<String>>(&mut self, key: K) -> &mut Self {
+        self.operations.push(Operation::RemoveProperty(key.into()));
+        self
+    }
+
+    /// Number of recorded operations.
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Returns `true` if no operation was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            staged: self.archive.staged.clone(),
+            properties: self.archive.properties.clone(),
+            properties_changed: self.archive.properties_changed,
+            history: self.archive.history.clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.archive.staged = snapshot.staged;
+        self.archive.properties = snapshot.properties;
+        self.archive.properties_changed = snapshot.properties_changed;
+        self.archive.history = snapshot.history;
+    }
+
+    /// Stages the operations in the archive and validates the result.
+    fn apply(&mut self) -> io::Result<()> {
+        for operation in self.operations.iter().cloned() {
+            match operation {
+                Operation::Add(name, data) => self.archive.add(name, data)?,
+                Operation::Replace(name, data) => self.archive.replace(&name, data)?,
+                Operation::Insert(name, data) => self.archive.insert(name, data)?,
+                Operation::InsertFile(name, path) => self.archive.insert_file(name, path)?,
+                Operation::Remove(name) => self.archive.remove(&name)?,
+                Operation::SetProperty(key, value) => self.archive.set_property(key, value)?,
+                Operation::RemoveProperty(key) => {
+                    self.archive.remove_property(&key)?;
+                }
+            }
+        }
+        self.archive.validate()
+    }
+
+    /// Checks that the operations can be applied and produce a valid
+    /// archive, without applying them.
+    pub fn validate(&mut self) -> io::Result<()> {
+        let snapshot = self.snapshot();
+        let result = self.apply();
+        self.restore(snapshot);
+        result
+    }
+
+    /// Applies the operations and commits the archive, along with the changes
+    /// staged before the session. On error, the archive file and its staged
+    /// changes are left as they were before the session.
+    pub fn commit(mut self) -> io::Result<()> {
+        let snapshot = self.snapshot();
+        let result = self.apply().and_then(|_| self.archive.commit());
+        if result.is_err() {
+            self.restore(snapshot);
+        }
+        result
+    }
+
+    /// Discards the operations.
+    pub fn rollback(self) {}
+}
+
+impl Archive {
+    /// Starts an edit `Session`.
+    pub fn session(&mut self) -> Session<'_> {
+        Session {
+            archive: self,
+            operations: Vec::new(),
+        }
+    }
+}
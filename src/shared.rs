@@ -0,0 +1,395 @@
+use access::{check_access, AccessPolicy};
+use compression::ChunkCompression;
+use encryption::ChunkEncryption;
+use error::{entry_not_found, Error as PakError};
+use glob::Pattern;
+use name::{find_entry, EntryName};
+use progress::{ProgressEvent, Reporting};
+use read::MergeReader;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Fragment opened once and read with positional reads.
+#[derive(Debug)]
+struct SharedFragment {
+    path: PathBuf,
+    file: File,
+}
+
+impl SharedFragment {
+    /// Reads the `size` bytes at `offset`, failing with `Error::Truncated`
+    /// if the fragment ends before them, so that the size of a hostile
+    /// chunk is never allocated.
+    fn read_stored(&self, offset: u64, size: u64) -> io::Result<Vec<u8>> {
+        let len = self.file.metadata()?.len();
+        if offset.checked_add(size).is_none_or(|end| end > len) {
+            return Err(PakError::Truncated { offset, expected: size, read: len.saturating_sub(offset) }.into());
+        }
+        let mut buffer = vec![0; size as usize];
+        self.read_exact_at(&mut buffer, offset)?;
+        Ok(buffer)
+    }
+
+    /// Fills `buf` with the bytes at `offset`.
+    #[cfg(unix)]
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        use std::os::unix::fs::FileExt;
+        self.file.read_exact_at(buf, offset)
+    }
+
+    /// Fills `buf` with the bytes at `offset`.
+    #[cfg(windows)]
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        use std::os::windows::fs::FileExt;
+        while !buf.is_empty() {
+            match self.file.seek_read(buf, offset) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "failed to fill whole buffer")),
+                Ok(read) => {
+                    buf = &mut buf[read..];
+                    offset += read as u64;
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills `buf` with the bytes at `offset`, through a handle of its own
+    /// since the platform has no positional reads.
+    #[cfg(not(any(unix, windows)))]
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        use std::io::SeekFrom;
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(buf)
+    }
+}
+
+/// Location of the data of an entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct SharedChunk {
+    offset: u64,
+    size: u64,
+    fragment: usize,
+    compression: Option<ChunkCompression>,
+    encryption: Option<ChunkEncryption>,
+}
+
+impl SharedChunk {
+    /// Size of the data once decrypted and decompressed.
+    fn uncompressed_size(&self) -> u64 {
+        match self.compression {
+            Some(ref compression) => compression.size,
+            None => self.encryption.as_ref().map_or(self.size, |encryption| encryption.size),
+        }
+    }
+
+    /// Decrypts and decompresses the stored data `stored` if the chunk is
+    /// encrypted or compressed.
+    fn decode(&self, stored: Vec<u8>) -> io::Result<Vec<u8>> {
+        let stored = match self.encryption {
+            Some(ref encryption) => encryption.decrypt(&stored)?,
+            None => stored,
+        };
+        match self.compression {
+            Some(ref compression) => compression.decode(&stored),
+            None => Ok(stored),
+        }
+    }
+}
+
+/// SharedReader
+///
+/// Read-only view on a merged archive which is `Send + Sync`: every
+/// fragment is opened once and read with positional reads (`read_at`), so
+/// threads read entries concurrently without locking.
+///
+/// It is built from a `MergeReader` by reopening its fragments, which must
+/// be files on the disk.
+#[derive(Debug)]
+pub struct SharedReader {
+    chunks: HashMap<String, SharedChunk>,
+    folded: Option<HashMap<String, String>>,
+    fragments: Vec<SharedFragment>,
+    copy_buffer_size: usize,
+    access_policy: Option<AccessPolicy>,
+}
+
+impl SharedReader {
+    /// Creates a `SharedReader` without any entry.
+    pub(crate) fn empty() -> Self {
+        SharedReader {
+            chunks: HashMap::new(),
+            folded: None,
+            fragments: Vec::new(),
+            copy_buffer_size: 1,
+            access_policy: None,
+        }
+    }
+
+    /// Opens the pak file `loc` (and the fragments it links to).
+    pub fn open<P: AsRef<Path>>(loc: P) -> io::Result<Self> {
+        MergeReader::open(loc)?.shared()
+    }
+
+    /// Returns `true` if the archive contains the entry `full_file_name`.
+    pub fn contains(&self, full_file_name: &str) -> bool {
+        find_entry(&self.chunks, self.folded.as_ref(), full_file_name).is_some()
+    }
+
+    /// Full file names of the entries, sorted.
+    pub fn file_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.chunks.keys().map(|name| name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Returns `true` if the archive contains no entry.
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Paths of the fragments, the first one being the initial pak file.
+    pub fn fragments(&self) -> Vec<&Path> {
+        self.fragments.iter().map(|fragment| fragment.path.as_path()).collect()
+    }
+
+    fn chunk(&self, full_file_name: &str) -> io::Result<&SharedChunk> {
+        let (name, chunk) = find_entry(&self.chunks, self.folded.as_ref(), full_file_name)
+            .ok_or_else(|| entry_not_found(full_file_name))?;
+        check_access(&self.access_policy, name)?;
+        Ok(chunk)
+    }
+
+    /// Full file names of the entries the `AccessPolicy` allows reading,
+    /// sorted.
+    fn allowed_names(&self) -> Vec<&str> {
+        let mut names = self.file_names();
+        if let Some(ref policy) = self.access_policy {
+            names.retain(|name| policy.allows(name));
+        }
+        names
+    }
+
+    /// Reads the entry `full_file_name`.
+    pub fn read_file(&self, full_file_name: &str) -> io::Result<Vec<u8>> {
+        let chunk = self.chunk(full_file_name)?;
+        let stored = self.fragments[chunk.fragment].read_stored(chunk.offset, chunk.size)?;
+        chunk.decode(stored)
+    }
+
+    /// Opens a new handle on the fragment of the entry `full_file_name`,
+    /// positioned at its data, returning it with the size of the entry.
+    /// Fails with `Unsupported` if the entry is compressed or
+    /// encrypted.
+    #[cfg(feature = "async")]
+    pub(crate) fn open_entry(&self, full_file_name: &str) -> io::Result<(File, u64)> {
+        let chunk = self.chunk(full_file_name)?;
+        if chunk.compression.is_some() || chunk.encryption.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("`full_file_name` \"{}\" is compressed or encrypted and can't be streamed, read it instead", full_file_name)
+            ));
+        }
+        let mut file = File::open(&self.fragments[chunk.fragment].path)?;
+        file.seek(io::SeekFrom::Start(chunk.offset))?;
+        Ok((file, chunk.size))
+    }
+
+    /// Extracts the entry `full_file_name` into `dest`, returning the path
+    /// of the extracted file. The data is copied by blocks of the copy
+    /// buffer size of the `ReadOptions`.
+    pub fn extract_entry<P: AsRef<Path>>(&self, full_file_name: &str, dest: P) -> io::Result<PathBuf> {
+        self.extract_entry_reporting(full_file_name, dest.as_ref(), &mut |_| {})
+    }
+
+    /// Extracts the entry `full_file_name` into `dest` like `extract_entry`,
+    /// calling `report` with the number of bytes of every write.
+    fn extract_entry_reporting(&self, full_file_name: &str, dest: &Path, report: &mut dyn FnMut(u64)) -> io::Result<PathBuf> {
+        let chunk = self.chunk(full_file_name)?;
+        let output = EntryName::new(full_file_name)?.output_path(dest);
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = Reporting { inner: File::create(&output)?, report };
+        let fragment = &self.fragments[chunk.fragment];
+        if chunk.compression.is_some() || chunk.encryption.is_some() {
+            let stored = fragment.read_stored(chunk.offset, chunk.size)?;
+            let stored = match chunk.encryption {
+                Some(ref encryption) => encryption.decrypt(&stored)?,
+                None => stored,
+            };
+            match chunk.compression {
+                Some(ref compression) => {
+                    let written = io::copy(&mut compression.decoder(&stored[..])?, &mut file)?;
+                    compression.check_size(written)?;
+                }
+                None => file.write_all(&stored)?,
+            }
+            return Ok(output);
+        }
+        let mut buffer = vec![0; self.copy_buffer_size.max(1).min(chunk.size.max(1) as usize)];
+        let mut copied = 0;
+        while copied < chunk.size {
+            let len = (chunk.size - copied).min(buffer.len() as u64) as usize;
+            fragment.read_exact_at(&mut buffer[..len], chunk.offset + copied)?;
+            file.write_all(&buffer[..len])?;
+            copied += len as u64;
+        }
+        Ok(output)
+    }
+
+    /// Extracts every entry into `dest` on one thread per available core,
+    /// skipping the entries denied by the `AccessPolicy`.
+    pub fn extract_all<P: AsRef<Path>>(&self, dest: P) -> io::Result<()> {
+        let threads = thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1);
+        self.extract_all_with(dest, threads)
+    }
+
+    /// Extracts every entry into `dest` on `threads` threads. The first
+    /// failure stops the extraction and is returned.
+    pub fn extract_all_with<P: AsRef<Path>>(&self, dest: P, threads: usize) -> io::Result<()> {
+        self.extract_names(dest.as_ref(), &self.allowed_names(), threads, None)
+    }
+
+    /// Extracts the entries whose full file name matches the glob `pattern`
+    /// into `dest`, on one thread per available core.
+    pub fn extract_matching<P: AsRef<Path>>(&self, dest: P, pattern: &Pattern) -> io::Result<()> {
+        let threads = thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1);
+        self.extract_names(dest.as_ref(), &self.matching_names(Some(pattern)), threads, None)
+    }
+
+    /// Extracts the entries whose full file name matches the glob `filter`
+    /// (every entry if `None`) into `dest`, on one thread per available
+    /// core, reporting the progress to `progress`: `Started` with the
+    /// totals, the events of every entry, then `Finished` if every entry
+    /// was extracted.
+    pub fn extract_with_progress<P, F>(&self, dest: P, filter: Option<&Pattern>, progress: F) -> io::Result<()>
+        where P: AsRef<Path>,
+              F: FnMut(&ProgressEvent) + Send
+    {
+        let threads = thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1);
+        let names = self.matching_names(filter);
+        let bytes = names.iter().map(|name| self.chunks[*name].uncompressed_size()).sum();
+
+        // the entries are extracted in parallel, their events one at a time
+        let progress = Mutex::new(progress);
+        let report = |event: &ProgressEvent| (progress.lock().unwrap_or_else(|poison| poison.into_inner()))(event);
+        report(&ProgressEvent::Started { entries: names.len(), bytes });
+        self.extract_names(dest.as_ref(), &names, threads, Some(&report))?;
+        report(&ProgressEvent::Finished { entries: names.len(), bytes });
+        Ok(())
+    }
+
+    /// Full file names of the entries the `AccessPolicy` allows reading
+    /// matching the glob `filter`, every one if `None`, sorted.
+    fn matching_names(&self, filter: Option<&Pattern>) -> Vec<&str> {
+        self.allowed_names().into_iter()
+            .filter(|name| filter.is_none_or(|pattern| pattern.matches(name)))
+            .collect()
+    }
+
+    /// Extracts the entry `full_file_name` into `dest`, reporting its events
+    /// to `report` if specified.
+    fn extract_reported(
+        &self,
+        full_file_name: &str,
+        dest: &Path,
+        report: Option<&(dyn Fn(&ProgressEvent) + Sync)>
+    ) -> io::Result<PathBuf> {
+        let report = match report {
+            Some(report) => report,
+            None => return self.extract_entry(full_file_name, dest),
+        };
+        let size = self.chunk(full_file_name)?.uncompressed_size();
+        report(&ProgressEvent::EntryStarted { full_file_name, size });
+        let output = self.extract_entry_reporting(full_file_name, dest, &mut |bytes| {
+            report(&ProgressEvent::Written { full_file_name, bytes })
+        })?;
+        report(&ProgressEvent::EntryFinished { full_file_name, size });
+        Ok(output)
+    }
+
+    /// Extracts the entries `names` into `dest` on `threads` threads,
+    /// reporting their events to `report` if specified.
+    fn extract_names(
+        &self,
+        dest: &Path,
+        names: &[&str],
+        threads: usize,
+        report: Option<&(dyn Fn(&ProgressEvent) + Sync)>
+    ) -> io::Result<()> {
+        let next = AtomicUsize::new(0);
+        let failure = Mutex::new(None);
+
+        thread::scope(|scope| {
+            for _ in 0..threads.max(1).min(names.len()) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= names.len() {
+                        break;
+                    }
+                    if let Err(err) = self.extract_reported(names[index], dest, report) {
+                        // the remaining entries are skipped
+                        next.store(names.len(), Ordering::Relaxed);
+                        failure.lock().unwrap_or_else(|poison| poison.into_inner()).get_or_insert(err);
+                        break;
+                    }
+                });
+            }
+        });
+
+        match failure.into_inner().unwrap_or_else(|poison| poison.into_inner()) {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Builds a `SharedReader` on the same entries, reopening the fragments.
+    pub fn shared(&self) -> io::Result<SharedReader> {
+        let mut fragments = Vec::with_capacity(self.fragments.len());
+        for path in &self.fragments {
+            fragments.push(SharedFragment {
+                file: File::open(path)?,
+                path: path.clone(),
+            });
+        }
+
+        let chunks = self.iter()
+            .map(|(full_file_name, chunk)| (full_file_name.clone(), SharedChunk {
+                offset: chunk.offset(),
+                size: chunk.size(),
+                fragment: chunk.fragment(),
+                compression: chunk.compression.clone(),
+                encryption: chunk.encryption.clone(),
+            }))
+            .collect();
+
+        Ok(SharedReader {
+            chunks,
+            folded: self.folded.clone(),
+            fragments,
+            copy_buffer_size: self.options.copy_buffer_size,
+            access_policy: self.options.access_policy.clone(),
+        })
+    }
+}
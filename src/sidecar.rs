@@ -0,0 +1,391 @@
+use byteorder_extended::{ReadExt, WriteExt};
+use cache::ChunkCache;
+use compression::take_compressions;
+use encryption::take_encryptions;
+use hash::{Digest, HashAlgorithm, HashIndex};
+use raw::{Property, read_u64, write_u64};
+use memory::MemoryAccount;
+use options::ReadOptions;
+use read::{MergedChunk, MergeReader, set_file_name};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::UNIX_EPOCH;
+
+/// Magic bytes starting a sidecar index.
+static SIDECAR_MAGIC: &[u8; 4] = b"PIDX";
+
+/// Version of the sidecar index format.
+const SIDECAR_VERSION: u8 = 1;
+
+/// FragmentStamp
+///
+/// Identifies the state of a fragment when the sidecar was written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FragmentStamp {
+    /// File name of the fragment
+    pub file_name: String,
+    /// Size in bytes
+    pub size: u64,
+    /// Modification time in milliseconds since the Unix epoch
+    pub modified: u64,
+}
+
+impl FragmentStamp {
+    /// Reads the current `FragmentStamp` of the fragment at `path`.
+    pub fn of<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let metadata = fs::metadata(path)?;
+        let modified = metadata.modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() * 1000 + elapsed.subsec_millis() as u64)
+            .unwrap_or(0);
+        let file_name = path.file_name()
+            .and_then(|file_name| file_name.to_str())
+            .ok_or_else(|| Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid fragment path \"{}\"", path.display())
+            ))?;
+
+        Ok(FragmentStamp {
+            file_name: file_name.to_string(),
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// SidecarEntry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SidecarEntry {
+    /// Full file name
+    pub full_file_name: String,
+    /// Index of the fragment containing the data
+    pub fragment: usize,
+    /// Offset of the data in its fragment
+    pub offset: u64,
+    /// Size of the data
+    pub size: u64,
+    /// Digest of the data, if the sidecar was written with hashes
+    pub digest: Option<Digest>,
+}
+
+/// Sidecar
+///
+/// Compact index of a whole fragment chain, stored next to the archive so
+/// that reopening it doesn't need to parse any table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sidecar {
+    /// Fragments in the order they are merged
+    pub fragments: Vec<FragmentStamp>,
+    /// Entries sorted by full file name
+    pub entries: Vec<SidecarEntry>,
+    /// Properties of the whole chain
+    pub properties: Vec<Property>,
+    /// Algorithm of the entry digests, if any
+    pub algorithm: Option<HashAlgorithm>,
+}
+
+fn algorithm_to_u8(algorithm: Option<HashAlgorithm>) -> u8 {
+    match algorithm {
+        None => 0,
+        Some(HashAlgorithm::Crc32) => 1,
+        Some(HashAlgorithm::Fnv64) => 2,
+        #[cfg(feature = "xxh3")]
+        Some(HashAlgorithm::Xxh3) => 3,
+        #[cfg(feature = "blake3")]
+        Some(HashAlgorithm::Blake3) => 4,
+        #[cfg(feature = "sha256")]
+        Some(HashAlgorithm::Sha256) => 5,
+    }
+}
+
+fn algorithm_from_u8(value: u8) -> io::Result<Option<HashAlgorithm>> {
+    match value {
+        0 => Ok(None),
+        1 => Ok(Some(HashAlgorithm::Crc32)),
+        2 => Ok(Some(HashAlgorithm::Fnv64)),
+        #[cfg(feature = "xxh3")]
+        3 => Ok(Some(HashAlgorithm::Xxh3)),
+        #[cfg(feature = "blake3")]
+        4 => Ok(Some(HashAlgorithm::Blake3)),
+        #[cfg(feature = "sha256")]
+        5 => Ok(Some(HashAlgorithm::Sha256)),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("unknown sidecar hash algorithm {}", value)
+        )),
+    }
+}
+
+impl Sidecar {
+    /// Default location of the sidecar of the archive `loc`: `<loc>.idx`.
+    pub fn path_for<P: AsRef<Path>>(loc: P) -> PathBuf {
+        let mut path = OsString::from(loc.as_ref());
+        path.push(".idx");
+        PathBuf::from(path)
+    }
+
+    /// Reads a `Sidecar` from the specified reader.
+    pub fn from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != SIDECAR_MAGIC || reader.read_u8()? != SIDECAR_VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, "Corrupted sidecar header"));
+        }
+        let algorithm = algorithm_from_u8(reader.read_u8()?)?;
+
+        let fragments_count = reader.read_i32()?;
+        let mut fragments = Vec::new();
+        for _ in 0..fragments_count {
+            let file_name = reader.read_string()?;
+            let size = read_u64(reader)?;
+            let modified = read_u64(reader)?;
+            fragments.push(FragmentStamp { file_name, size, modified });
+        }
+
+        let entries_count = reader.read_i32()?;
+        let mut entries = Vec::new();
+        for _ in 0..entries_count {
+            let full_file_name = reader.read_string()?;
+            let fragment = reader.read_i32()? as usize;
+            let offset = read_u64(reader)?;
+            let size = read_u64(reader)?;
+            let digest = match reader.read_u8()? {
+                0 => None,
+                len => {
+                    let mut bytes = vec![0; len as usize];
+                    reader.read_exact(&mut bytes)?;
+                    Some(Digest::new(bytes))
+                }
+            };
+            if fragment >= fragments.len() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("sidecar entry \"{}\" refers to an unknown fragment", full_file_name)
+                ));
+            }
+            entries.push(SidecarEntry { full_file_name, fragment, offset, size, digest });
+        }
+
+        let properties_count = reader.read_i32()?;
+        let mut properties = Vec::new();
+        for _ in 0..properties_count {
+            properties.push(Property::from(reader)?);
+        }
+
+        Ok(Sidecar { fragments, entries, properties, algorithm })
+    }
+
+    /// Writes the `Sidecar` in the specified writer.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(SIDECAR_MAGIC)?;
+        writer.write_u8(SIDECAR_VERSION)?;
+        writer.write_u8(algorithm_to_u8(self.algorithm))?;
+
+        writer.write_i32(self.fragments.len() as i32)?;
+        for fragment in &self.fragments {
+            writer.write_string(&fragment.file_name)?;
+            write_u64(writer, fragment.size)?;
+            write_u64(writer, fragment.modified)?;
+        }
+
+        writer.write_i32(self.entries.len() as i32)?;
+        for entry in &self.entries {
+            writer.write_string(&entry.full_file_name)?;
+            writer.write_i32(entry.fragment as i32)?;
+            write_u64(writer, entry.offset)?;
+            write_u64(writer, entry.size)?;
+            match entry.digest {
+                Some(ref digest) => {
+                    writer.write_u8(digest.as_bytes().len() as u8)?;
+                    writer.write_all(digest.as_bytes())?;
+                }
+                None => writer.write_u8(0)?,
+            }
+        }
+
+        writer.write_i32(self.properties.len() as i32)?;
+        for property in &self.properties {
+            property.write(writer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Loads a `Sidecar` from the file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Sidecar::from(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Saves the `Sidecar` to the file at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Paths of the fragments, resolved relatively to the archive `loc`.
+    fn fragment_paths(&self, loc: &Path) -> io::Result<Vec<PathBuf>> {
+        self.fragments.iter().enumerate().map(|(i, fragment)| {
+            if i == 0 {
+                Ok(loc.to_path_buf())
+            } else {
+                set_file_name(loc, &fragment.file_name).ok_or_else(|| Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid fragment \"{}\"", fragment.file_name)
+                ))
+            }
+        }).collect()
+    }
+
+    /// Returns `true` if no fragment of the archive `loc` changed since the
+    /// sidecar was written.
+    pub fn is_fresh<P: AsRef<Path>>(&self, loc: P) -> bool {
+        match self.fragment_paths(loc.as_ref()) {
+            Ok(paths) => !paths.is_empty() && paths.iter().zip(&self.fragments).all(|(path, stamp)| {
+                FragmentStamp::of(path).is_ok_and(|current| {
+                    current.size == stamp.size && current.modified == stamp.modified
+                })
+            }),
+            Err(_) => false,
+        }
+    }
+
+    /// Builds a `HashIndex` from the stored digests, if any.
+    pub fn hash_index(&self) -> Option<HashIndex> {
+        self.algorithm.map(|algorithm| {
+            let mut names: HashMap<Digest, Vec<String>> = HashMap::new();
+            for entry in &self.entries {
+                if let Some(ref digest) = entry.digest {
+                    names.entry(digest.clone())
+                        .or_default()
+                        .push(entry.full_file_name.clone());
+                }
+            }
+            HashIndex::new(algorithm, names)
+        })
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Builds the `Sidecar` of the archive, computing the entry digests with
+    /// `algorithm` if specified.
+    pub fn sidecar(&self, algorithm: Option<HashAlgorithm>) -> io::Result<Sidecar> {
+        let fragments = self.fragments.iter()
+            .map(FragmentStamp::of)
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut entries = Vec::with_capacity(self.chunks.len());
+        for (full_file_name, chunk) in self.iter() {
+            let digest = match algorithm {
+                Some(algorithm) => Some(algorithm.digest(&chunk.data()?)),
+                None => None,
+            };
+            entries.push(SidecarEntry {
+                full_file_name: full_file_name.clone(),
+                fragment: chunk.fragment(),
+                offset: chunk.offset(),
+                size: chunk.size(),
+                digest,
+            });
+        }
+        entries.sort_by(|a, b| a.full_file_name.cmp(&b.full_file_name));
+
+        let mut properties: Vec<Property> = self.properties.iter()
+            .map(|(key, value)| Property::new(key.clone(), value.clone()))
+            .collect();
+        // the compressions and the encryptions are recorded by full file name
+        properties.extend(self.iter().filter_map(|(full_file_name, chunk)| {
+            chunk.compression.as_ref().map(|compression| compression.to_property(full_file_name))
+        }));
+        properties.extend(self.iter().filter_map(|(full_file_name, chunk)| {
+            chunk.encryption.as_ref().map(|encryption| encryption.to_property(full_file_name))
+        }));
+        properties.sort_by(|a, b| a.key.cmp(&b.key));
+
+        Ok(Sidecar { fragments, entries, properties, algorithm })
+    }
+}
+
+impl MergeReader<File> {
+    /// Writes the sidecar index of the archive at its default location.
+    pub fn write_sidecar(&self, algorithm: Option<HashAlgorithm>) -> io::Result<()> {
+        self.sidecar(algorithm)?.save(Sidecar::path_for(&self.fragments[0]))
+    }
+
+    /// Opens the pak file using its sidecar index when it is up to date.
+    ///
+    /// When the sidecar is missing or stale, the tables are parsed and a new
+    /// sidecar is written; failing to write it is not an error.
+    pub fn open_with_sidecar<P: AsRef<Path>>(loc: P) -> io::Result<Self> {
+        let loc = loc.as_ref();
+
+        if let Ok(sidecar) = Sidecar::load(Sidecar::path_for(loc)) {
+            if sidecar.is_fresh(loc) {
+                return MergeReader::from_sidecar(loc, &sidecar);
+            }
+        }
+
+        let reader = MergeReader::open(loc)?;
+        let _ = reader.write_sidecar(None);
+        Ok(reader)
+    }
+
+    /// Opens the fragments of the archive `loc` described by `sidecar`
+    /// without reading their tables.
+    pub fn from_sidecar<P: AsRef<Path>>(loc: P, sidecar: &Sidecar) -> io::Result<Self> {
+        let fragments = sidecar.fragment_paths(loc.as_ref())?;
+        let readers = fragments.iter()
+            .map(|path| File::open(path).map(|file| Rc::new(RefCell::new(file))))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut properties: HashMap<String, Property> = sidecar.properties.iter()
+            .map(|property| (property.key.clone(), property.clone()))
+            .collect();
+        let mut warnings = Vec::new();
+        let mut compressions = take_compressions(&mut properties, &mut warnings);
+        let mut encryptions = take_encryptions(&mut properties, &mut warnings);
+
+        let chunks = sidecar.entries.iter().map(|entry| {
+            let mut chunk = MergedChunk::new(
+                entry.offset,
+                entry.size,
+                entry.fragment,
+                readers[entry.fragment].clone()
+            );
+            chunk.compression = compressions.remove(&entry.full_file_name);
+            chunk.encryption = encryptions.remove(&entry.full_file_name)
+                .map(|encryption| encryption.of(&entry.full_file_name, None));
+            (entry.full_file_name.clone(), chunk)
+        }).collect();
+
+        let properties = properties.into_iter()
+            .map(|(key, property)| (key, property.value))
+            .collect();
+
+        let mut reader = MergeReader {
+            chunks,
+            folded: None,
+            properties,
+            fragments,
+            readers,
+            access_log: RefCell::new(None),
+            warnings,
+            options: ReadOptions::default(),
+            memory: MemoryAccount::default(),
+            cache: RefCell::new(ChunkCache::default()),
+        };
+        reader.account_index()?;
+        Ok(reader)
+    }
+}
@@ -0,0 +1,58 @@
+use archive::{Archive, Staged};
+use raw::Info;
+use std::fs;
+use std::fs::File;
+use std::io;
+
+impl Archive {
+    /// Bytes still reserved between the data and the tables of the
+    /// committed file, 0 if it doesn't exist yet. The slack is reserved by
+    /// the `slack` of the `WriteOptions`.
+    pub fn remaining_slack(&self) -> io::Result<u64> {
+        if self.reader.is_none() {
+            return Ok(0);
+        }
+        Ok(Info::from(&mut File::open(self.path())?)?.slack())
+    }
+
+    /// Size of the data of the staged entries if they can be written in the
+    /// slack: only new entries, fitting in `slack`.
+    fn staged_size(&self, slack: u64) -> io::Result<Option<u64>> {
+        let mut size = 0;
+        for (full_file_name, staged) in &self.staged {
+            if self.committed(full_file_name) {
+                return Ok(None);
+            }
+            size += match *staged {
+                Staged::Bytes(ref data) => data.len() as u64,
+                Staged::File(ref source) => fs::metadata(source)?.len(),
+                Staged::Removed => return Ok(None),
+            };
+        }
+        Ok(if size <= slack { Some(size) } else { None })
+    }
+
+    /// Applies the staged changes like `commit`, writing the data of the new
+    /// entries into the slack of the committed file and rewriting its tables
+    /// in place, instead of rewriting the whole file. Returns `true` if the
+    /// changes were written in place, `false` if they didn't fit (or
+    /// replace or remove entries) and were committed by `commit`.
+    ///
+    /// Unlike `commit`, an interruption while the tables are rewritten
+    /// leaves the file corrupted.
+    pub fn commit_in_place(&mut self) -> io::Result<bool> {
+        if !self.has_changes() {
+            return Ok(true);
+        }
+        if self.reader.is_none() {
+            return self.commit().map(|_| false);
+        }
+        let info = Info::from(&mut File::open(self.path())?)?;
+        if self.staged_size(info.slack())?.is_none() {
+            return self.commit().map(|_| false);
+        }
+        // the data fits in the slack: the tables don't move
+        self.patch(false)?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,89 @@
+use archive::Archive;
+use shared::SharedReader;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Snapshot
+///
+/// Read-only view on the committed content of an `Archive`, pinned to the
+/// trailer it had when the snapshot was taken: the staged changes aren't
+/// visible and a later `commit` doesn't affect it. Readers switch over to
+/// the new content by taking a new snapshot once `generation` changed.
+///
+/// The snapshot keeps its own handle on the committed file, which the
+/// system keeps alive after `commit` replaces it. `commit_in_place` only
+/// writes past the data the snapshot refers to. `Snapshot` is cheap to
+/// clone and `Send + Sync`.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    path: PathBuf,
+    generation: u64,
+    reader: Arc<SharedReader>,
+    properties: Arc<HashMap<String, String>>,
+}
+
+impl Snapshot {
+    /// Path of the archive.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of commits of the archive before the snapshot was taken.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns `true` if the snapshot contains the entry `full_file_name`.
+    pub fn contains(&self, full_file_name: &str) -> bool {
+        self.reader.contains(full_file_name)
+    }
+
+    /// Full file names of the entries, sorted.
+    pub fn file_names(&self) -> Vec<&str> {
+        self.reader.file_names()
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.reader.len()
+    }
+
+    /// Returns `true` if the snapshot contains no entry.
+    pub fn is_empty(&self) -> bool {
+        self.reader.is_empty()
+    }
+
+    /// Reads the entry `full_file_name`.
+    pub fn read_file(&self, full_file_name: &str) -> io::Result<Vec<u8>> {
+        self.reader.read_file(full_file_name)
+    }
+
+    /// Value of the property `key`.
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(|value| value.as_str())
+    }
+
+    /// Reader of the snapshot, to extract its entries.
+    pub fn reader(&self) -> &SharedReader {
+        &self.reader
+    }
+}
+
+impl Archive {
+    /// Takes a read-only snapshot of the committed content, see `Snapshot`.
+    /// A new archive not committed yet gives an empty snapshot.
+    pub fn snapshot(&self) -> io::Result<Snapshot> {
+        let (reader, properties) = match self.reader {
+            Some(ref reader) => (reader.shared()?, reader.properties.clone()),
+            None => (SharedReader::empty(), HashMap::new()),
+        };
+        Ok(Snapshot {
+            path: self.path().to_path_buf(),
+            generation: self.generation(),
+            reader: Arc::new(reader),
+            properties: Arc::new(properties),
+        })
+    }
+}
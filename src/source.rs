@@ -0,0 +1,129 @@
+use hash::{Digest, HashAlgorithm, HashBackend, HashState};
+use std::collections::BTreeMap;
+use std::collections::btree_map::Iter;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::{Error, ErrorKind, Read};
+use std::path::Path;
+
+/// Reader computing the digest of the data read through it.
+pub(crate) struct HashingReader<R> {
+    inner: R,
+    state: Box<dyn HashState>,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub(crate) fn new(inner: R, algorithm: HashAlgorithm) -> Self {
+        HashingReader { inner, state: algorithm.start() }
+    }
+
+    /// Digest of the data read so far.
+    pub(crate) fn finish(self) -> Digest {
+        self.state.finish()
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.state.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// SourceManifest
+///
+/// Digests of the source files of the entries, computed while packing by
+/// `Archive::commit_with_digests`, or checked by `Archive::commit_verified`
+/// to detect sources modified since the manifest was generated.
+///
+/// The text format is the one of `sha256sum` and similar tools: one line per
+/// entry, the digest in hexadecimal, two spaces, then the full file name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceManifest {
+    algorithm: HashAlgorithm,
+    digests: BTreeMap<String, Digest>,
+}
+
+impl SourceManifest {
+    /// Creates a new empty `SourceManifest`.
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        SourceManifest { algorithm, digests: BTreeMap::new() }
+    }
+
+    /// Algorithm of the digests.
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Sets the expected digest of the entry `full_file_name`.
+    pub fn insert<S: Into<String>>(&mut self, full_file_name: S, digest: Digest) {
+        self.digests.insert(full_file_name.into(), digest);
+    }
+
+    /// Digest of the entry `full_file_name`.
+    pub fn get(&self, full_file_name: &str) -> Option<&Digest> {
+        self.digests.get(full_file_name)
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.digests.len()
+    }
+
+    /// Returns `true` if the manifest contains no entry.
+    pub fn is_empty(&self) -> bool {
+        self.digests.is_empty()
+    }
+
+    /// Iterates over the entries and their digests.
+    pub fn iter(&self) -> Iter<'_, String, Digest> {
+        self.digests.iter()
+    }
+
+    /// Entries of `actual` missing from the manifest or with a different
+    /// digest, sorted.
+    pub fn mismatches(&self, actual: &SourceManifest) -> Vec<String> {
+        actual.digests.iter()
+            .filter(|&(full_file_name, digest)| self.digests.get(full_file_name) != Some(digest))
+            .map(|(full_file_name, _)| full_file_name.clone())
+            .collect()
+    }
+
+    /// Parses the text format, the digests being computed with `algorithm`.
+    pub fn from_text(text: &str, algorithm: HashAlgorithm) -> io::Result<Self> {
+        let mut manifest = SourceManifest::new(algorithm);
+        for line in text.lines().filter(|line| !line.is_empty()) {
+            let mut parts = line.splitn(2, "  ");
+            match (parts.next().and_then(Digest::from_hex), parts.next()) {
+                (Some(digest), Some(full_file_name)) => manifest.insert(full_file_name, digest),
+                _ => return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("invalid manifest line \"{}\"", line)
+                )),
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Loads the manifest at `path`, the digests being computed with
+    /// `algorithm`.
+    pub fn load<P: AsRef<Path>>(path: P, algorithm: HashAlgorithm) -> io::Result<Self> {
+        SourceManifest::from_text(&fs::read_to_string(path)?, algorithm)
+    }
+
+    /// Saves the manifest at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for SourceManifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (full_file_name, digest) in &self.digests {
+            writeln!(f, "{}  {}", digest, full_file_name)?;
+        }
+        Ok(())
+    }
+}
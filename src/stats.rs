@@ -0,0 +1,183 @@
+use raw::Info;
+use raw::legacy::{self, read_version, Version};
+use read::{EntryMeta, MergeReader};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+/// Number of entries listed by `ArchiveStats::largest`.
+const LARGEST_ENTRIES: usize = 10;
+
+/// FragmentStats
+///
+/// Figures of a fragment of an archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FragmentStats {
+    /// Path of the fragment
+    pub path: PathBuf,
+    /// Size of the fragment file
+    pub size: u64,
+    /// Number of chunks, as declared by its `Info`
+    pub chunks: usize,
+    /// Number of entries of the archive read from the fragment, the other
+    /// chunks being overridden by later fragments or skipped
+    pub entries: usize,
+    /// Size of the data area, as declared by its `Info`
+    pub data_size: u64,
+    /// Bytes of the data area no entry refers to
+    pub wasted: u64,
+}
+
+/// WastedRegion
+///
+/// Bytes of the data area of a fragment no entry refers to: data of an
+/// overridden or removed entry, or a gap left by an edit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WastedRegion {
+    /// Index of the fragment
+    pub fragment: usize,
+    /// Offset of the region in the fragment
+    pub offset: u64,
+    /// Size of the region
+    pub size: u64,
+}
+
+/// ArchiveStats
+///
+/// Figures of an archive returned by `MergeReader::stats`, computed from the
+/// tables only: no data is read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ArchiveStats {
+    /// Fragments, in the order they were merged
+    pub fragments: Vec<FragmentStats>,
+    /// Number of entries
+    pub entries: usize,
+    /// Bytes of data stored for the entries, the data shared by several
+    /// entries being counted once
+    pub payload: u64,
+    /// Size of the data of the entries once decompressed
+    pub uncompressed: u64,
+    /// Entries pointing at the data of another entry
+    pub shared_entries: usize,
+    /// Largest entries by stored size, at most 10, largest first
+    pub largest: Vec<EntryMeta>,
+    /// Regions of the data areas no entry refers to, by fragment and offset
+    pub wasted: Vec<WastedRegion>,
+    /// Entries with the same stored size as another one, without reading
+    /// their data: the candidates of `MergeReader::duplicates`
+    pub duplicate_candidates: usize,
+    /// Bytes which would be saved if every candidate was a duplicate, an
+    /// upper bound of `DuplicateReport::potential_savings`
+    pub duplicate_bytes_estimate: u64,
+}
+
+impl ArchiveStats {
+    /// Total size of the fragment files.
+    pub fn total_size(&self) -> u64 {
+        self.fragments.iter().map(|fragment| fragment.size).sum()
+    }
+
+    /// Bytes of the data areas no entry refers to.
+    pub fn wasted_bytes(&self) -> u64 {
+        self.wasted.iter().map(|region| region.size).sum()
+    }
+}
+
+/// Reads the `Info` of the fragment read by `reader`, the tables of the
+/// legacy fragments.
+fn read_info<R: Read + Seek>(reader: &mut R) -> io::Result<Info> {
+    reader.seek(SeekFrom::Start(0))?;
+    match read_version(reader)? {
+        Version::Current => Info::from(reader),
+        Version::Legacy => legacy::read_tables(reader).map(|(info, _)| info),
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Computes the figures of the archive: fragments, payload, largest
+    /// entries, wasted regions and duplicate candidates. Only the `Info` of
+    /// every fragment is read again.
+    pub fn stats(&self) -> io::Result<ArchiveStats> {
+        // stored ranges of the data, the entries sharing them counted once
+        let mut ranges: Vec<Vec<(u64, u64)>> = vec![Vec::new(); self.fragments.len()];
+        let mut entries = vec![0; self.fragments.len()];
+        let mut used = HashSet::new();
+        let mut by_size: HashMap<u64, usize> = HashMap::new();
+        let mut stats = ArchiveStats {
+            fragments: Vec::with_capacity(self.fragments.len()),
+            entries: self.chunks.len(),
+            payload: 0,
+            uncompressed: 0,
+            shared_entries: 0,
+            largest: Vec::new(),
+            wasted: Vec::new(),
+            duplicate_candidates: 0,
+            duplicate_bytes_estimate: 0,
+        };
+        for chunk in self.chunks.values() {
+            entries[chunk.fragment()] += 1;
+            stats.uncompressed += chunk.uncompressed_size();
+            if !used.insert((chunk.fragment(), chunk.offset(), chunk.size())) {
+                stats.shared_entries += 1;
+                continue;
+            }
+            stats.payload += chunk.size();
+            ranges[chunk.fragment()].push((chunk.offset(), chunk.offset() + chunk.size()));
+            *by_size.entry(chunk.size()).or_insert(0) += 1;
+        }
+
+        for (&size, &copies) in by_size.iter().filter(|&(&size, &copies)| size > 0 && copies > 1) {
+            stats.duplicate_candidates += copies;
+            stats.duplicate_bytes_estimate += size * (copies as u64 - 1);
+        }
+
+        let mut largest: Vec<EntryMeta> = self.iter()
+            .map(|(full_file_name, chunk)| chunk.meta(full_file_name))
+            .collect();
+        largest.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+        largest.truncate(LARGEST_ENTRIES);
+        stats.largest = largest;
+
+        for (fragment, path) in self.fragments.iter().enumerate() {
+            let (info, size) = {
+                let mut reader = self.readers[fragment].try_borrow_mut().map_err(|_| Error::new(
+                    ErrorKind::WouldBlock,
+                    "the fragment reader is already in use"
+                ))?;
+                (read_info(&mut *reader)?, reader.seek(SeekFrom::End(0))?)
+            };
+            let start = info.offset;
+            let data_size = info.size.max(0) as u64;
+
+            let ranges = &mut ranges[fragment];
+            ranges.sort();
+            let mut position = start;
+            let mut wasted = 0;
+            for &(offset, end) in ranges.iter().chain(Some(&(start + data_size, start + data_size))) {
+                if offset > position {
+                    stats.wasted.push(WastedRegion { fragment, offset: position, size: offset - position });
+                    wasted += offset - position;
+                }
+                position = position.max(end);
+            }
+
+            stats.fragments.push(FragmentStats {
+                path: path.clone(),
+                size,
+                chunks: info.chunks_count.max(0) as usize,
+                entries: entries[fragment],
+                data_size,
+                wasted,
+            });
+        }
+
+        Ok(stats)
+    }
+}
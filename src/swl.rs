@@ -0,0 +1,117 @@
+//! Reading and writing of the `.swl` wrappers of the graphics archives.
+//!
+//! A SWL file wraps a SWF movie with the names of the classes it exports,
+//! so that the client can tell which library to load for a class without
+//! parsing the movie.
+//!
+//! SWL file format :
+//!
+//! ``` text
+//!     1. Header : From Start 0
+//!         +----------------------+----------------+-----------------------+
+//!         |   header             |    1 byte      |   value expected 76   |
+//!         |   version            |    1 byte      |   _                   |
+//!         |   frame_rate         |    4 bytes     |   _                   |
+//!         |   classes_count      |    4 bytes     |   _                   |
+//!         +----------------------+----------------+-----------------------+
+//!         for 0 to classes_count
+//!             +---------------------+------------------------------------+
+//!             |   class             |  2 bytes (length) | string (utf8)  |
+//!             +---------------------+------------------------------------+
+//!
+//!     2. SWF : After the classes, up to the end of the file
+//! ```
+
+use byteorder_extended::{ReadExt, WriteExt};
+use read::MergeReader;
+use std::io;
+use std::io::{Error, ErrorKind, Read, Seek, Write};
+
+/// Header of a SWL file.
+const SWL_HEADER: u8 = 76;
+
+/// Swl
+///
+/// A SWF movie with the classes it exports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Swl {
+    /// Version of the SWL format
+    pub version: u8,
+    /// Frame rate of the movie
+    pub frame_rate: u32,
+    /// Fully qualified names of the classes exported by the movie
+    pub classes: Vec<String>,
+    /// The SWF movie
+    pub swf: Vec<u8>,
+}
+
+impl Swl {
+    /// Reads a `Swl` from the specified reader, the SWF up to the end.
+    pub fn from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        if reader.read_u8()? != SWL_HEADER {
+            return Err(Error::new(ErrorKind::InvalidData, "Corrupted swl header"));
+        }
+        let version = reader.read_u8()?;
+        let frame_rate = reader.read_i32()? as u32;
+        let classes_count = reader.read_i32()?;
+        let mut classes = Vec::new();
+        for _ in 0..classes_count {
+            classes.push(reader.read_string()?);
+        }
+        let mut swf = Vec::new();
+        reader.read_to_end(&mut swf)?;
+        Ok(Swl { version, frame_rate, classes, swf })
+    }
+
+    /// Reads a `Swl` from the content of a `.swl` file, as returned by
+    /// `MergeReader::read_file`.
+    pub fn from_bytes(mut data: &[u8]) -> io::Result<Self> {
+        Swl::from(&mut data)
+    }
+
+    /// Writes the `Swl` in the specified writer.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u8(SWL_HEADER)?;
+        writer.write_u8(self.version)?;
+        writer.write_i32(self.frame_rate as i32)?;
+        writer.write_i32(self.classes.len() as i32)?;
+        for class in &self.classes {
+            writer.write_string(class)?;
+        }
+        writer.write_all(&self.swf)
+    }
+
+    /// Returns `true` if the movie exports the class `name`.
+    pub fn exports(&self, name: &str) -> bool {
+        self.classes.iter().any(|class| class == name)
+    }
+
+    /// Returns `true` if the movie is compressed: its signature is `CWS`
+    /// (zlib) or `ZWS` (LZMA) instead of `FWS`.
+    pub fn is_compressed(&self) -> bool {
+        self.swf.starts_with(b"CWS") || self.swf.starts_with(b"ZWS")
+    }
+
+    /// Version of the SWF format of the movie, `None` if it doesn't start
+    /// with a SWF signature.
+    pub fn swf_version(&self) -> Option<u8> {
+        match self.swf.get(..4) {
+            Some(header) if &header[..3] == b"FWS" || &header[..3] == b"CWS" || &header[..3] == b"ZWS" => Some(header[3]),
+            _ => None,
+        }
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Reads the entry `full_file_name` as a `.swl` file, see
+    /// `Swl::from_bytes`.
+    pub fn read_swl(&self, full_file_name: &str) -> io::Result<Swl> {
+        Swl::from_bytes(&self.read_file(full_file_name)?).map_err(|err| Error::new(
+            err.kind(),
+            format!("can't decode `full_file_name` \"{}\" as a swl file: {}", full_file_name, err)
+        ))
+    }
+}
@@ -0,0 +1,258 @@
+//! Synthetic archive builders for tests.
+//!
+//! Available with the `testkit` feature, so that tests (of this crate and of
+//! downstream crates) don't depend on real game files.
+
+use raw::{Chunk, Info, Offsets, Property};
+use read::MergeReader;
+use repack::fragment_path;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::{Cursor, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Corruption
+///
+/// Defect injected in a generated fragment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Corruption {
+    /// Invalid header bytes
+    Header,
+    /// Overrides the offset of a chunk in the table
+    ChunkOffset(String, i64),
+    /// Overrides the size of a chunk in the table
+    ChunkSize(String, i64),
+    /// Overrides the number of chunks in the `Info`
+    ChunksCount(i32),
+    /// Overrides the number of properties in the `Info`
+    PropertiesCount(i32),
+    /// Keeps only the first bytes of the fragment
+    Truncate(usize),
+}
+
+/// Generates `size` pseudo-random bytes, the same ones for the same `seed`.
+pub fn pseudo_random(seed: u64, size: usize) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    (0..size).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as u8
+    }).collect()
+}
+
+/// FragmentBuilder
+///
+/// Builds the bytes of a single pak fragment.
+#[derive(Clone, Debug, Default)]
+pub struct FragmentBuilder {
+    entries: Vec<(String, Vec<u8>)>,
+    properties: Vec<(String, String)>,
+    corruptions: Vec<Corruption>,
+    offsets: Offsets,
+}
+
+impl FragmentBuilder {
+    /// Creates a new empty `FragmentBuilder`.
+    pub fn new() -> Self {
+        FragmentBuilder::default()
+    }
+
+    /// Adds an entry.
+    pub fn entry<S: Into<String>>(mut self, full_file_name: S, data: Vec<u8>) -> Self {
+        self.entries.push((full_file_name.into(), data));
+        self
+    }
+
+    /// Adds `count` entries `entries/<i>.bin` of `size` pseudo-random bytes.
+    pub fn entries(mut self, count: usize, size: usize) -> Self {
+        let first = self.entries.len();
+        for i in first..first + count {
+            self.entries.push((format!("entries/{}.bin", i), pseudo_random(i as u64, size)));
+        }
+        self
+    }
+
+    /// Adds a property.
+    pub fn property<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.properties.push((key.into(), value.into()));
+        self
+    }
+
+    /// Sets the width of the offsets of the tables, `Classic` by default.
+    pub fn offsets(mut self, offsets: Offsets) -> Self {
+        self.offsets = offsets;
+        self
+    }
+
+    /// Injects a defect.
+    pub fn corrupt(mut self, corruption: Corruption) -> Self {
+        self.corruptions.push(corruption);
+        self
+    }
+
+    /// Builds the bytes of the fragment.
+    pub fn build(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&self.offsets.header());
+        let offset = buffer.len() as u64;
+
+        let mut chunks = Vec::with_capacity(self.entries.len());
+        for (full_file_name, data) in &self.entries {
+            chunks.push(Chunk::new(
+                full_file_name.clone(),
+                buffer.len() as i64 - offset as i64,
+                data.len() as i64
+            ));
+            buffer.extend_from_slice(data);
+        }
+        let size = buffer.len() as i64 - offset as i64;
+
+        let mut info = Info {
+            offset,
+            size,
+            chunks_offset: buffer.len() as u64,
+            chunks_count: chunks.len() as i32,
+            properties_offset: 0,
+            properties_count: self.properties.len() as i32,
+            offsets: self.offsets,
+        };
+
+        for corruption in &self.corruptions {
+            match *corruption {
+                Corruption::ChunkOffset(ref name, value) => {
+                    chunks.iter_mut().filter(|chunk| &chunk.full_file_name == name).for_each(|chunk| chunk.offset = value);
+                }
+                Corruption::ChunkSize(ref name, value) => {
+                    chunks.iter_mut().filter(|chunk| &chunk.full_file_name == name).for_each(|chunk| chunk.size = value);
+                }
+                _ => {}
+            }
+        }
+
+        for chunk in &chunks {
+            chunk.write(&mut buffer, self.offsets).unwrap();
+        }
+        info.properties_offset = buffer.len() as u64;
+        for (key, value) in &self.properties {
+            Property::new(key.clone(), value.clone()).write(&mut buffer).unwrap();
+        }
+
+        for corruption in &self.corruptions {
+            match *corruption {
+                Corruption::ChunksCount(value) => info.chunks_count = value,
+                Corruption::PropertiesCount(value) => info.properties_count = value,
+                _ => {}
+            }
+        }
+        info.write(&mut buffer).unwrap();
+
+        for corruption in &self.corruptions {
+            match *corruption {
+                Corruption::Header => {
+                    buffer[0] = 0xFF;
+                    buffer[1] = 0xFF;
+                }
+                Corruption::Truncate(len) => buffer.truncate(len),
+                _ => {}
+            }
+        }
+
+        buffer
+    }
+
+    /// Writes the fragment at `path`.
+    pub fn write_to<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.build())
+    }
+
+    /// Opens the fragment in memory.
+    pub fn reader(&self) -> io::Result<MergeReader<Cursor<Vec<u8>>>> {
+        ChainBuilder::new().fragment(self.clone()).reader()
+    }
+}
+
+/// ChainBuilder
+///
+/// Builds a chain of fragments, each one linking to the next. The fragments
+/// are named like the ones written by `MergeReader::reshard`: `name.d2p`,
+/// `name1.d2p`, `name2.d2p`...
+#[derive(Clone, Debug, Default)]
+pub struct ChainBuilder {
+    fragments: Vec<FragmentBuilder>,
+    missing: Vec<usize>,
+}
+
+impl ChainBuilder {
+    /// Creates a new empty `ChainBuilder`.
+    pub fn new() -> Self {
+        ChainBuilder::default()
+    }
+
+    /// Appends a fragment.
+    pub fn fragment(mut self, fragment: FragmentBuilder) -> Self {
+        self.fragments.push(fragment);
+        self
+    }
+
+    /// Appends `count` fragments of `entries` entries of `size` bytes each.
+    pub fn fragments(mut self, count: usize, entries: usize, size: usize) -> Self {
+        for _ in 0..count {
+            let first = self.fragments.len() * entries;
+            let mut fragment = FragmentBuilder::new();
+            for i in first..first + entries {
+                fragment = fragment.entry(format!("entries/{}.bin", i), pseudo_random(i as u64, size));
+            }
+            self.fragments.push(fragment);
+        }
+        self
+    }
+
+    /// Doesn't write the fragment `index`, leaving a dangling link.
+    pub fn missing(mut self, index: usize) -> Self {
+        self.missing.push(index);
+        self
+    }
+
+    /// Builds the fragments, linked together, with their path relative to
+    /// `head`.
+    pub fn build<P: AsRef<Path>>(&self, head: P) -> Vec<(PathBuf, Vec<u8>)> {
+        let head = head.as_ref();
+        self.fragments.iter().enumerate()
+            .filter(|&(i, _)| !self.missing.contains(&i))
+            .map(|(i, fragment)| {
+                let mut fragment = fragment.clone();
+                if i + 1 < self.fragments.len() {
+                    let next = fragment_path(head, i + 1);
+                    let link = next.file_name().unwrap().to_string_lossy().into_owned();
+                    fragment = fragment.property("link", link);
+                }
+                (fragment_path(head, i), fragment.build())
+            })
+            .collect()
+    }
+
+    /// Writes the fragments, `head` being the path of the first one.
+    pub fn write_to<P: AsRef<Path>>(&self, head: P) -> io::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for (path, bytes) in self.build(head) {
+            fs::write(&path, bytes)?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    /// Opens the chain in memory.
+    pub fn reader(&self) -> io::Result<MergeReader<Cursor<Vec<u8>>>> {
+        let head = PathBuf::from("fixture.d2p");
+        let fragments: HashMap<PathBuf, Vec<u8>> = self.build(&head).into_iter().collect();
+
+        MergeReader::merge(head, |path| {
+            fragments.get(&path).cloned().map(Cursor::new).ok_or_else(|| Error::new(
+                ErrorKind::NotFound,
+                format!("missing fragment \"{}\"", path.display())
+            ))
+        })
+    }
+}
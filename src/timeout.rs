@@ -0,0 +1,110 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::{Error, ErrorKind, Read};
+use std::time::{Duration, Instant};
+
+/// Maximum size of a read between two checks of a deadline.
+const MAX_READ: usize = 64 * 1024;
+
+/// TimedOut
+///
+/// Error payload returned when an operation exceeds its timeout. The
+/// wrapping `io::Error` is of kind `ErrorKind::TimedOut`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimedOut {
+    operation: String,
+    timeout: Duration,
+}
+
+impl TimedOut {
+    /// Operation which timed out, e.g. `open "maps.d2p"`.
+    pub fn operation(&self) -> &str {
+        &self.operation
+    }
+
+    /// Timeout exceeded.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Returns the `TimedOut` payload of the error if any.
+    pub fn from_error(err: &Error) -> Option<&TimedOut> {
+        err.get_ref().and_then(|inner| inner.downcast_ref::<TimedOut>())
+    }
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} timed out after {:?}", self.operation, self.timeout)
+    }
+}
+
+impl error::Error for TimedOut {}
+
+/// Timeouts
+///
+/// Timeouts of the operations of a `MergeReader`, none by default. They are
+/// checked between the reads of the fragments, which are made in blocks of
+/// at most 64 KiB: a single blocked system call isn't interrupted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Timeouts {
+    /// Timeout of the opening of the archive: reading the tables of every
+    /// fragment
+    pub open: Option<Duration>,
+    /// Timeout of the read of an entry (`read_file`, `pin`...)
+    pub read: Option<Duration>,
+    /// Timeout of the extraction of an entry to the disk
+    pub extract: Option<Duration>,
+}
+
+/// Deadline of an operation.
+#[derive(Clone, Debug)]
+pub(crate) struct Deadline {
+    operation: String,
+    timeout: Option<Duration>,
+    /// Start of the operation, only read with a timeout: there is no clock
+    /// on `wasm32-unknown-unknown`
+    start: Option<Instant>,
+}
+
+impl Deadline {
+    /// Starts the `operation`, which must end before `timeout`.
+    pub(crate) fn start<S: Into<String>>(operation: S, timeout: Option<Duration>) -> Self {
+        Deadline {
+            operation: operation.into(),
+            start: timeout.map(|_| Instant::now()),
+            timeout,
+        }
+    }
+
+    /// Fails with a `TimedOut` error if the timeout is exceeded.
+    pub(crate) fn check(&self) -> io::Result<()> {
+        match (self.timeout, self.start) {
+            (Some(timeout), Some(start)) if start.elapsed() > timeout => Err(Error::new(
+                ErrorKind::TimedOut,
+                TimedOut { operation: self.operation.clone(), timeout }
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Wraps `reader`, checking the deadline before every read.
+    pub(crate) fn reader<R: Read>(&self, reader: R) -> DeadlineReader<'_, R> {
+        DeadlineReader { inner: reader, deadline: self }
+    }
+}
+
+/// Reader checking a `Deadline` before every read, of at most 64 KiB.
+pub(crate) struct DeadlineReader<'a, R> {
+    inner: R,
+    deadline: &'a Deadline,
+}
+
+impl<'a, R: Read> Read for DeadlineReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.deadline.check()?;
+        let len = buf.len().min(MAX_READ);
+        self.inner.read(&mut buf[..len])
+    }
+}
@@ -0,0 +1,163 @@
+use options::WriteOptions;
+use read::MergeReader;
+use repack::selected;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Error, Read, Seek, Write};
+use std::path::Path;
+use write::{FragmentWriter, PakWriter};
+
+/// Transformed
+///
+/// Outcome of the transform of an entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Transformed {
+    /// The entry keeps its name
+    Keep,
+    /// The entry is renamed, e.g. `file.swl` into `file.swf`
+    Rename(String),
+    /// The entry is left out, nothing must have been written
+    Skip,
+}
+
+/// Function rewriting the data of an entry, from the reader of the
+/// original data into the writer of the new one.
+pub type RewriteFn = Box<dyn FnOnce(&mut dyn Read, &mut dyn Write) -> io::Result<()>>;
+
+/// Transform
+///
+/// Transform of an entry decided from its name only by
+/// `PakWriter::from_reader_with`, the data being read during the copy.
+pub enum Transform {
+    /// The entry is copied as is
+    Keep,
+    /// The entry is copied as is under a new name, unless it is encrypted:
+    /// its encryption authenticates its name
+    Rename(String),
+    /// The entry is left out
+    Skip,
+    /// The data of the entry is streamed through the function, under a new
+    /// name if specified, e.g. to unwrap a SWL into a SWF
+    Rewrite(Option<String>, RewriteFn),
+}
+
+impl fmt::Debug for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Transform::Keep => f.write_str("Keep"),
+            Transform::Rename(ref name) => f.debug_tuple("Rename").field(name).finish(),
+            Transform::Skip => f.write_str("Skip"),
+            Transform::Rewrite(ref name, _) => f.debug_tuple("Rewrite").field(name).field(&"..").finish(),
+        }
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Streams every entry through `transform` into a new single-fragment
+    /// archive at `dest`, returning the number of written entries.
+    ///
+    /// `transform` reads the data of the entry from the reader and writes the
+    /// new data into the writer (compressing, decrypting, converting...), the
+    /// chunk table and the `Info` are written accordingly. The entries are
+    /// processed in the order of their data, and the properties are kept
+    /// except the `link` ones. `dest` must not be a fragment of the archive.
+    pub fn transform<P, F>(&self, dest: P, mut transform: F) -> io::Result<usize>
+        where P: AsRef<Path>,
+              F: FnMut(&str, &mut dyn Read, &mut dyn Write) -> io::Result<Transformed>
+    {
+        let mut writer = FragmentWriter::new(BufWriter::with_capacity(self.options.copy_buffer_size, File::create(dest)?))?;
+        let mut count = 0;
+
+        for (full_file_name, chunk) in selected(self, |_, _| true) {
+            let added = writer.add_with(|data| {
+                let mut reader = chunk.decoded_reader()?;
+                Ok(match transform(full_file_name, &mut reader, data)? {
+                    Transformed::Keep => Some(full_file_name.clone()),
+                    Transformed::Rename(name) => Some(name),
+                    Transformed::Skip => None,
+                })
+            })?;
+            if added.is_some() {
+                count += 1;
+            }
+        }
+
+        let mut properties: Vec<_> = self.properties.iter()
+            .filter(|&(key, _)| key != "link")
+            .collect();
+        properties.sort();
+        for (key, value) in properties {
+            writer.add_property(key, value);
+        }
+
+        writer.finish()?.into_inner().map_err(Error::from)?.sync_all()?;
+        Ok(count)
+    }
+}
+
+/// Streams every entry of the pak file `src` through `transform` into a new
+/// archive at `dest`, see `MergeReader::transform`.
+pub fn transform<P, Q, F>(src: P, dest: Q, transform: F) -> io::Result<usize>
+    where P: AsRef<Path>,
+          Q: AsRef<Path>,
+          F: FnMut(&str, &mut dyn Read, &mut dyn Write) -> io::Result<Transformed>
+{
+    MergeReader::open(src)?.transform(dest, transform)
+}
+
+impl PakWriter {
+    /// Creates a new `PakWriter` at `dest` containing the entries of
+    /// `reader` transformed in one pass: `transform` decides from the name
+    /// of every entry whether it is kept, renamed, left out or rewritten,
+    /// and the data is then copied (or streamed through the rewrite) in the
+    /// order of the fragments. The properties are kept except the `link`
+    /// ones.
+    ///
+    /// More entries can be added before `finish`. `dest` must not be a
+    /// fragment of the archive.
+    pub fn from_reader_with<R, P, F>(reader: &MergeReader<R>, dest: P, mut transform: F) -> io::Result<Self>
+        where R: Read + Seek,
+              P: AsRef<Path>,
+              F: FnMut(&str) -> Transform
+    {
+        let mut writer = PakWriter::create_with(dest, &WriteOptions {
+            buffer_size: reader.options.copy_buffer_size,
+            ..WriteOptions::default()
+        })?;
+
+        for (full_file_name, chunk) in selected(reader, |_, _| true) {
+            match transform(full_file_name) {
+                Transform::Keep => {
+                    let name = writer.reserve_name(full_file_name)?;
+                    writer.writer.add_chunk(&name, chunk)?;
+                }
+                Transform::Rename(new_name) => {
+                    let name = writer.reserve_name(&new_name)?;
+                    writer.writer.add_chunk(&name, chunk)?;
+                }
+                Transform::Skip => {}
+                Transform::Rewrite(new_name, rewrite) => {
+                    let name = writer.reserve_name(new_name.as_ref().unwrap_or(full_file_name))?;
+                    writer.writer.add_with(|data| {
+                        rewrite(&mut chunk.decoded_reader()?, data)?;
+                        Ok(Some(name))
+                    })?;
+                }
+            }
+        }
+
+        let mut properties: Vec<_> = reader.properties.iter()
+            .filter(|&(key, _)| key != "link")
+            .collect();
+        properties.sort();
+        for (key, value) in properties {
+            writer.writer.add_property(key, value);
+        }
+
+        Ok(writer)
+    }
+}
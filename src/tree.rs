@@ -0,0 +1,154 @@
+use read::{MergeReader, MergedChunk};
+use std::io::{Read, Seek};
+use std::rc::Rc;
+
+/// Dir
+///
+/// Directory of the tree of the entries, the full file names being split
+/// on `/`. The names are sorted once by `MergeReader::root`, then the
+/// directories are computed on demand from ranges of the sorted names: a
+/// browser walks the archive without building the whole tree.
+pub struct Dir<'a, R: 'a> {
+    entries: Rc<Vec<(&'a str, &'a MergedChunk<R>)>>,
+    /// Range of the entries within the directory, at any depth
+    start: usize,
+    end: usize,
+    /// Length of the path of the directory, its trailing `/` included
+    prefix_len: usize,
+}
+
+/// Node
+///
+/// Item of a `Dir`.
+pub enum Node<'a, R: 'a> {
+    /// Subdirectory
+    Dir(Dir<'a, R>),
+    /// Entry, with its full file name
+    File(&'a str, &'a MergedChunk<R>),
+}
+
+impl<'a, R> Clone for Dir<'a, R> {
+    fn clone(&self) -> Self {
+        Dir {
+            entries: self.entries.clone(),
+            start: self.start,
+            end: self.end,
+            prefix_len: self.prefix_len,
+        }
+    }
+}
+
+impl<'a, R> Dir<'a, R> {
+    /// Path of the directory, without trailing `/`, empty for the root.
+    pub fn path(&self) -> &'a str {
+        match self.prefix_len {
+            0 => "",
+            len => &self.entries[self.start].0[..len - 1],
+        }
+    }
+
+    /// Last component of the path, empty for the root.
+    pub fn name(&self) -> &'a str {
+        let path = self.path();
+        &path[path.rfind('/').map_or(0, |slash| slash + 1)..]
+    }
+
+    /// Number of entries in the directory and its subdirectories.
+    pub fn entry_count(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Subdirectories, sorted by name.
+    pub fn dirs(&self) -> Vec<Dir<'a, R>> {
+        let mut dirs = Vec::new();
+        let mut index = self.start;
+        while index < self.end {
+            let name = self.entries[index].0;
+            match name[self.prefix_len..].find('/') {
+                Some(slash) => {
+                    let dir = self.range(&name[..self.prefix_len + slash + 1]);
+                    // the content of the subdirectory is skipped at once
+                    index = dir.end;
+                    dirs.push(dir);
+                }
+                None => index += 1,
+            }
+        }
+        dirs
+    }
+
+    /// Entries directly in the directory, sorted by name.
+    pub fn files(&self) -> Vec<(&'a str, &'a MergedChunk<R>)> {
+        let mut files = Vec::new();
+        let mut index = self.start;
+        while index < self.end {
+            let name = self.entries[index].0;
+            match name[self.prefix_len..].find('/') {
+                Some(slash) => index = self.range(&name[..self.prefix_len + slash + 1]).end,
+                None => {
+                    files.push(self.entries[index]);
+                    index += 1;
+                }
+            }
+        }
+        files
+    }
+
+    /// Looks up `path`, relative to the directory: an entry, else a
+    /// directory. An empty path is the directory itself.
+    pub fn get(&self, path: &str) -> Option<Node<'a, R>> {
+        let path = path.trim_end_matches('/');
+        if path.is_empty() {
+            return Some(Node::Dir(self.clone()));
+        }
+
+        let prefix = match self.prefix_len {
+            0 => "",
+            len => &self.entries[self.start].0[..len],
+        };
+        let full_file_name = format!("{}{}", prefix, path);
+        let entries = &self.entries[self.start..self.end];
+        if let Ok(index) = entries.binary_search_by(|entry| entry.0.cmp(&full_file_name)) {
+            let (name, chunk) = entries[index];
+            return Some(Node::File(name, chunk));
+        }
+        let dir = self.range(&format!("{}/", full_file_name));
+        if dir.start < dir.end {
+            Some(Node::Dir(dir))
+        } else {
+            None
+        }
+    }
+
+    /// Subdirectory `dir`, its full path with its trailing `/`.
+    fn range(&self, dir: &str) -> Dir<'a, R> {
+        let entries = &self.entries[self.start..self.end];
+        let start = entries.partition_point(|entry| entry.0 < dir);
+        let len = entries[start..].partition_point(|entry| entry.0.starts_with(dir));
+        Dir {
+            entries: self.entries.clone(),
+            start: self.start + start,
+            end: self.start + start + len,
+            prefix_len: dir.len(),
+        }
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Root of the tree of the entries, see `Dir`.
+    pub fn root(&self) -> Dir<'_, R> {
+        let mut entries: Vec<_> = self.iter()
+            .map(|(full_file_name, chunk)| (full_file_name.as_str(), chunk))
+            .collect();
+        entries.sort_by_key(|entry| entry.0);
+        Dir {
+            end: entries.len(),
+            entries: Rc::new(entries),
+            start: 0,
+            prefix_len: 0,
+        }
+    }
+}
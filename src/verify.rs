@@ -0,0 +1,273 @@
+use read::{MergedChunk, MergeReader};
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek};
+
+/// Severity
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Severity {
+    /// Informational, nothing to fix
+    Info,
+    /// The archive is readable but something looks wrong
+    Warning,
+    /// The archive (or a part of it) can't be read correctly
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// Finding
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Finding {
+    /// Severity
+    pub severity: Severity,
+    /// Full file name of the entry concerned, if any
+    pub entry: Option<String>,
+    /// Description of the problem
+    pub message: String,
+    /// Suggested fix, if any
+    pub suggestion: Option<String>,
+}
+
+impl Finding {
+    /// Creates a new `Finding`.
+    pub fn new<S: Into<String>>(severity: Severity, message: S) -> Self {
+        Finding {
+            severity,
+            entry: None,
+            message: message.into(),
+            suggestion: None,
+        }
+    }
+
+    /// Sets the entry concerned by the `Finding`.
+    pub fn entry<S: Into<String>>(mut self, entry: S) -> Self {
+        self.entry = Some(entry.into());
+        self
+    }
+
+    /// Sets the suggested fix of the `Finding`.
+    pub fn suggestion<S: Into<String>>(mut self, suggestion: S) -> Self {
+        self.suggestion = Some(suggestion.into());
+        self
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.severity)?;
+        if let Some(ref entry) = self.entry {
+            write!(f, "\"{}\": ", entry)?;
+        }
+        f.write_str(&self.message)?;
+        if let Some(ref suggestion) = self.suggestion {
+            write!(f, " ({})", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Report
+///
+/// Findings of an archive verification.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Report {
+    /// Findings in the order they were found
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    /// Creates a new empty `Report`.
+    pub fn new() -> Self {
+        Report::default()
+    }
+
+    /// Adds a `Finding` to the `Report`.
+    pub fn push(&mut self, finding: Finding) {
+        self.findings.push(finding);
+    }
+
+    /// Returns `true` if the report contains no error.
+    pub fn is_ok(&self) -> bool {
+        self.findings.iter().all(|finding| finding.severity < Severity::Error)
+    }
+
+    /// Iterates over the findings with a severity of at least `min`.
+    pub fn at_least(&self, min: Severity) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(move |finding| finding.severity >= min)
+    }
+
+    /// Iterates over the findings concerning the entry `full_file_name`.
+    pub fn for_entry<'a>(&'a self, full_file_name: &'a str) -> impl Iterator<Item = &'a Finding> {
+        self.findings.iter().filter(move |finding| {
+            finding.entry.as_ref().is_some_and(|entry| entry == full_file_name)
+        })
+    }
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Verifies that every chunk of the archive can be read.
+    pub fn verify(&self) -> Report {
+        let mut report = Report::new();
+        for warning in &self.warnings {
+            report.push(warning.clone());
+        }
+
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (full_file_name, chunk) in entries {
+            let fragment_len = match chunk.fragment_len() {
+                Ok(fragment_len) => fragment_len,
+                Err(err) => {
+                    report.push(Finding::new(Severity::Error, format!("can't read the fragment: {}", err))
+                        .entry(full_file_name.as_str()));
+                    continue;
+                }
+            };
+
+            let end = chunk.offset().checked_add(chunk.size());
+            if end.is_none_or(|end| end > fragment_len) {
+                report.push(Finding::new(
+                    Severity::Error,
+                    format!(
+                        "data range {}+{} exceeds the fragment size {}",
+                        chunk.offset(), chunk.size(), fragment_len
+                    ))
+                    .entry(full_file_name.as_str())
+                    .suggestion("repack the archive from an intact copy"));
+            } else if chunk.size() == 0 {
+                report.push(Finding::new(Severity::Info, "empty entry")
+                    .entry(full_file_name.as_str()));
+            }
+        }
+
+        report
+    }
+}
+
+/// VerifyOptions
+///
+/// Options of `MergeReader::verify_with`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VerifyOptions {
+    /// Reads the data of every entry, not only the chunk table
+    pub read_data: bool,
+    /// Size of the blocks in which the data is read
+    pub block_size: usize,
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        VerifyOptions {
+            read_data: true,
+            block_size: 1 << 20,
+        }
+    }
+}
+
+/// Advises the OS that the range of `file` won't be read again, so its
+/// pages can be evicted from the page cache.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn drop_cache(file: &File, offset: u64, len: u64) {
+    use std::os::unix::io::AsRawFd;
+
+    // only a hint: a failure doesn't affect the verification
+    unsafe {
+        libc::posix_fadvise(
+            file.as_raw_fd(),
+            offset as libc::off_t,
+            len as libc::off_t,
+            libc::POSIX_FADV_DONTNEED
+        );
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+fn drop_cache(_file: &File, _offset: u64, _len: u64) {}
+
+/// Reads the data of `chunk` in blocks, calling `after_read` with the
+/// offset and the length of every block read.
+fn read_data<R, F>(chunk: &MergedChunk<R>, options: &VerifyOptions, after_read: &mut F) -> io::Result<()>
+where
+    R: Read + Seek,
+    F: FnMut(&MergedChunk<R>, u64, u64)
+{
+    let mut offset = chunk.offset();
+    for block in chunk.stored_iter(options.block_size) {
+        let len = block?.len() as u64;
+        after_read(chunk, offset, len);
+        offset += len;
+    }
+    Ok(())
+}
+
+impl<R> MergeReader<R>
+where
+    R: Read + Seek
+{
+    /// Verifies the archive like `verify`, reading the data too if
+    /// `options.read_data` is set.
+    pub fn verify_with(&self, options: &VerifyOptions) -> Report {
+        self.verify_data(options, |_, _, _| {})
+    }
+
+    fn verify_data<F>(&self, options: &VerifyOptions, mut after_read: F) -> Report
+        where F: FnMut(&MergedChunk<R>, u64, u64)
+    {
+        let mut report = self.verify();
+        if !options.read_data {
+            return report;
+        }
+
+        let mut entries: Vec<_> = self.iter()
+            .filter(|&(full_file_name, _)| {
+                report.for_entry(full_file_name).all(|finding| finding.severity < Severity::Error)
+            })
+            .collect();
+        // sequential reads
+        entries.sort_by_key(|&(full_file_name, chunk)| (chunk.fragment(), chunk.offset(), full_file_name.clone()));
+
+        for (full_file_name, chunk) in entries {
+            if let Err(err) = read_data(chunk, options, &mut after_read) {
+                report.push(Finding::new(Severity::Error, format!("can't read the data: {}", err))
+                    .entry(full_file_name.as_str())
+                    .suggestion("repack the archive from an intact copy"));
+            }
+        }
+
+        report
+    }
+}
+
+impl MergeReader<File> {
+    /// Verifies the archive like `verify_with`, advising the OS to drop the
+    /// data read from the page cache (`POSIX_FADV_DONTNEED`), so that a
+    /// bulk verification doesn't evict the hot data of other processes.
+    ///
+    /// On the platforms without `posix_fadvise`, it is the same as
+    /// `verify_with`.
+    pub fn verify_uncached(&self, options: &VerifyOptions) -> Report {
+        self.verify_data(options, |chunk, offset, len| {
+            let _ = chunk.with_reader(|file| {
+                drop_cache(file, offset, len);
+                Ok(())
+            });
+        })
+    }
+}
@@ -0,0 +1,338 @@
+use error::entry_not_found;
+use mem::MemArchive;
+use name::{lookup_key, EntryName};
+use offline::Local;
+use read::{MergedChunk, MergeReader};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Error, ErrorKind, Read, Seek};
+use std::path::PathBuf;
+
+/// FsEntry
+///
+/// Item of `PakFs::list_dir`, named relatively to the directory listed.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FsEntry {
+    /// Subdirectory
+    Dir(String),
+    /// File, with the size of its data
+    File(String, u64),
+}
+
+impl FsEntry {
+    /// Name of the file or of the subdirectory.
+    pub fn name(&self) -> &str {
+        match *self {
+            FsEntry::Dir(ref name) | FsEntry::File(ref name, _) => name,
+        }
+    }
+
+    /// Returns `true` for a subdirectory.
+    pub fn is_dir(&self) -> bool {
+        match *self {
+            FsEntry::Dir(_) => true,
+            FsEntry::File(..) => false,
+        }
+    }
+}
+
+/// PakFs
+///
+/// Read-only file system over entries, so that an engine or a mod loader
+/// reads the content of the archives without caring where it comes from:
+/// a `MergeReader`, a `MemArchive`, a `LooseDir` of files on the disk, or
+/// an `Overlay` layering them.
+///
+/// The paths are full file names, normalized like `EntryName`: `dir\file`,
+/// `/dir/file` and `./dir/file` are the same file. The root directory is
+/// the empty path.
+///
+/// With the `offline` feature, only the file systems of the crate implement
+/// it.
+pub trait PakFs: Local {
+    /// Reads the file `path`.
+    fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+
+    /// Returns `true` if the file `path` exists.
+    fn exists(&self, path: &str) -> bool;
+
+    /// Files and subdirectories of the directory `path`, sorted by name.
+    /// Fails with `NotFound` if there is no such directory.
+    fn list_dir(&self, path: &str) -> io::Result<Vec<FsEntry>>;
+
+    /// Opens the file `path` for reading. Reads the whole file by default,
+    /// the implementations able to stream it don't.
+    fn open<'a>(&'a self, path: &str) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(io::Cursor::new(self.read(path)?)))
+    }
+}
+
+/// Prefix of the full file names of the entries of the directory `path`,
+/// with its trailing `/`, empty for the root.
+fn dir_prefix(path: &str) -> io::Result<String> {
+    if path.split(&['/', '\\'][..]).all(|segment| segment.is_empty() || segment == ".") {
+        return Ok(String::new());
+    }
+    Ok(format!("{}/", EntryName::new(path)?))
+}
+
+fn dir_not_found(path: &str) -> Error {
+    Error::new(ErrorKind::NotFound, format!("no directory \"{}\"", path))
+}
+
+/// Lists the directory `path` among `files`, full file names with their
+/// size, failing with `NotFound` if none is in it. The root always exists.
+fn list_files<'a, I>(files: I, path: &str) -> io::Result<Vec<FsEntry>>
+    where I: Iterator<Item = (&'a str, u64)>
+{
+    let prefix = dir_prefix(path)?;
+    let mut entries = BTreeMap::new();
+    for (full_file_name, size) in files {
+        if !full_file_name.starts_with(&*prefix) {
+            continue;
+        }
+        let rest = &full_file_name[prefix.len()..];
+        let entry = match rest.find('/') {
+            Some(slash) => FsEntry::Dir(rest[..slash].to_string()),
+            None => FsEntry::File(rest.to_string(), size),
+        };
+        entries.insert(entry.name().to_string(), entry);
+    }
+    if entries.is_empty() && !prefix.is_empty() {
+        return Err(dir_not_found(path));
+    }
+    Ok(entries.into_values().collect())
+}
+
+/// Reader of a chunk checking the size of the data once it is read.
+struct CheckedReader<'a, R: 'a> {
+    inner: Box<dyn Read + 'a>,
+    chunk: &'a MergedChunk<R>,
+    read: u64,
+}
+
+impl<'a, R> Read for CheckedReader<'a, R>
+where
+    R: Read + Seek
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if read == 0 && !buf.is_empty() {
+            self.chunk.check_decoded(self.read)?;
+        }
+        self.read += read as u64;
+        Ok(read)
+    }
+}
+
+/// The entries the `AccessPolicy` denies don't exist.
+impl<R> PakFs for MergeReader<R>
+where
+    R: Read + Seek
+{
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.read_file(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.allowed_chunk(path).is_ok()
+    }
+
+    fn list_dir(&self, path: &str) -> io::Result<Vec<FsEntry>> {
+        let files = self.iter()
+            .filter(|&(full_file_name, _)| self.allows(full_file_name))
+            .map(|(full_file_name, chunk)| (full_file_name.as_str(), chunk.uncompressed_size()));
+        list_files(files, path)
+    }
+
+    fn open<'a>(&'a self, path: &str) -> io::Result<Box<dyn Read + 'a>> {
+        let chunk = self.allowed_chunk(path)?;
+        Ok(Box::new(CheckedReader {
+            inner: chunk.decoded_reader()?,
+            chunk,
+            read: 0,
+        }))
+    }
+}
+
+impl PakFs for MemArchive {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.read_file(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.contains(path)
+    }
+
+    fn list_dir(&self, path: &str) -> io::Result<Vec<FsEntry>> {
+        let names = self.file_names();
+        let files = names.iter()
+            .map(|full_file_name| (full_file_name.as_str(), self.get(full_file_name).map_or(0, |data| data.len() as u64)));
+        list_files(files, path)
+    }
+
+    fn open<'a>(&'a self, path: &str) -> io::Result<Box<dyn Read + 'a>> {
+        let data = self.get(path).ok_or_else(|| entry_not_found(path))?;
+        Ok(Box::new(data))
+    }
+}
+
+/// LooseDir
+///
+/// Directory of loose files on the disk seen as a `PakFs`: the file `path`
+/// is the file `EntryName::to_path` gives in the directory, so a path can't
+/// escape it. The names which aren't valid UTF-8 aren't listed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LooseDir {
+    root: PathBuf,
+}
+
+impl LooseDir {
+    /// Creates a new `LooseDir` of the files of `root`.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        LooseDir { root: root.into() }
+    }
+
+    /// Directory of the files.
+    pub fn root(&self) -> &PathBuf {
+        &self.root
+    }
+
+    /// Path of the file `path` on the disk.
+    fn file_path(&self, path: &str) -> io::Result<PathBuf> {
+        Ok(EntryName::new(path)?.to_path(&self.root))
+    }
+}
+
+impl PakFs for LooseDir {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.file_path(path)?)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.file_path(path).is_ok_and(|file| file.is_file())
+    }
+
+    fn list_dir(&self, path: &str) -> io::Result<Vec<FsEntry>> {
+        let dir = match dir_prefix(path)?.as_str() {
+            "" => self.root.clone(),
+            prefix => EntryName::new(prefix)?.to_path(&self.root),
+        };
+        if !dir.is_dir() {
+            return Err(dir_not_found(path));
+        }
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                entries.push(FsEntry::Dir(name));
+            } else if metadata.is_file() {
+                entries.push(FsEntry::File(name, metadata.len()));
+            }
+        }
+        entries.sort_by(|a, b| a.name().cmp(b.name()));
+        Ok(entries)
+    }
+
+    fn open<'a>(&'a self, path: &str) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(File::open(self.file_path(path)?)?))
+    }
+}
+
+/// Overlay
+///
+/// Layers of `PakFs`, each layer shadowing the files of the layers added
+/// before it: the base archives first, then the patches, then a `LooseDir`
+/// of mod files. The directories are the union of the directories of the
+/// layers.
+#[derive(Default)]
+pub struct Overlay {
+    layers: Vec<Box<dyn PakFs>>,
+}
+
+impl Overlay {
+    /// Creates a new `Overlay` without any layer.
+    pub fn new() -> Self {
+        Overlay::default()
+    }
+
+    /// Adds `layer` on top of the layers.
+    pub fn push<F: PakFs + 'static>(&mut self, layer: F) -> &mut Self {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    /// Number of layers.
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Returns `true` if there is no layer.
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Index of the layer providing the file `path`, the topmost one
+    /// containing it.
+    pub fn which(&self, path: &str) -> Option<usize> {
+        self.layers.iter().rposition(|layer| layer.exists(path))
+    }
+
+    /// Layer providing the file `path`.
+    fn layer(&self, path: &str) -> io::Result<&dyn PakFs> {
+        match self.which(path) {
+            Some(index) => Ok(&*self.layers[index]),
+            None => Err(entry_not_found(&lookup_key(path))),
+        }
+    }
+}
+
+impl PakFs for Overlay {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.layer(path)?.read(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.which(path).is_some()
+    }
+
+    fn list_dir(&self, path: &str) -> io::Result<Vec<FsEntry>> {
+        let mut entries = BTreeMap::new();
+        let mut found = false;
+        for layer in &self.layers {
+            match layer.list_dir(path) {
+                Err(ref err) if err.kind() == ErrorKind::NotFound => continue,
+                listed => {
+                    found = true;
+                    for entry in listed? {
+                        entries.insert(entry.name().to_string(), entry);
+                    }
+                }
+            }
+        }
+        if !found && !dir_prefix(path)?.is_empty() {
+            return Err(dir_not_found(path));
+        }
+        Ok(entries.into_values().collect())
+    }
+
+    fn open<'a>(&'a self, path: &str) -> io::Result<Box<dyn Read + 'a>> {
+        self.layer(path)?.open(path)
+    }
+}
+
+impl fmt::Debug for Overlay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Overlay")
+            .field("layers", &self.layers.len())
+            .finish()
+    }
+}
@@ -0,0 +1,74 @@
+use catalog::read_links;
+use glob::Pattern;
+use read::MergeReader;
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Files matching the glob `pattern`, sorted. The wildcards are only
+/// allowed in the file name.
+fn glob_files(pattern: &str) -> io::Result<Vec<PathBuf>> {
+    let (dir, file_name) = match pattern.rfind('/') {
+        Some(slash) => (&pattern[..slash + 1], &pattern[slash + 1..]),
+        None => ("", pattern),
+    };
+    if dir.contains(&['*', '?'][..]) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("\"{}\": the wildcards are only allowed in the file name", pattern)
+        ));
+    }
+
+    let file_name = Pattern::new(file_name);
+    let mut files = Vec::new();
+    for entry in fs::read_dir(if dir.is_empty() { "." } else { dir })? {
+        let entry = entry?;
+        let matches = entry.file_name().to_str().is_some_and(|name| file_name.matches(name));
+        if matches && entry.file_type()?.is_file() {
+            files.push(Path::new(dir).join(entry.file_name()));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+impl MergeReader<File> {
+    /// Opens the pak files matching the glob `pattern` (the wildcards being
+    /// only allowed in the file name, `content/maps?.d2p`), when the name
+    /// of the head of a chain isn't known in advance.
+    ///
+    /// The files linked to by another matching file are fragments of its
+    /// chain; the other ones are heads, opened with the fragments they link
+    /// to. Independent heads are merged in the order of their paths, the
+    /// entries of a later head overriding the ones of an earlier head.
+    pub fn open_chain_glob(pattern: &str) -> io::Result<Self> {
+        let files = glob_files(pattern)?;
+        if files.is_empty() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("no pak file matches \"{}\"", pattern)
+            ));
+        }
+
+        let mut linked = HashSet::new();
+        for file in &files {
+            linked.extend(read_links(file)?);
+        }
+        let mut heads = files.iter().filter(|file| !linked.contains(*file));
+
+        let mut merge = match heads.next() {
+            Some(head) => MergeReader::open(head)?,
+            None => return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("every pak file matching \"{}\" is linked to by another one", pattern)
+            )),
+        };
+        for head in heads {
+            merge.absorb(MergeReader::open(head)?)?;
+        }
+        Ok(merge)
+    }
+}
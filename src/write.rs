@@ -0,0 +1,607 @@
+use chain::{check_link, link_value};
+use checksum::Checksum;
+use codec::NameCodec;
+use compression::{ChunkCompression, Compression};
+use encryption::{ChunkEncryption, Encryption};
+use error::entry_exists;
+use hash::{Digest, HashAlgorithm, HashBackend, HashState};
+use key::{KeyProvider, Keys};
+use name::{lookup_key, EntryName};
+use options::WriteOptions;
+use pack::{add_files, dir_entries};
+use progress::ProgressEvent;
+use properties::{check_property_key, LINK_PROPERTY};
+use raw::{Chunk, Info, Offsets, Property};
+use read::MergedChunk;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Error, ErrorKind, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// Size of the pak header.
+const HEADER_LEN: u64 = 2;
+
+/// Writer counting the bytes written into `inner`, and computing their
+/// checksum if enabled.
+struct CountingWriter<'a, W: 'a> {
+    inner: &'a mut W,
+    count: u64,
+    state: Option<Box<dyn HashState>>,
+}
+
+impl<'a, W: Write> Write for CountingWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        if let Some(ref mut state) = self.state {
+            state.update(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Data of a chunk encoded by `encode`.
+pub(crate) struct Encoded {
+    /// Data as stored
+    pub(crate) stored: Vec<u8>,
+    /// Size of the data read
+    pub(crate) read: u64,
+    pub(crate) compression: Option<ChunkCompression>,
+    pub(crate) encryption: Option<ChunkEncryption>,
+}
+
+/// Reads `data` and encodes it in memory as the writers store it:
+/// compressed with `compression` if specified, then encrypted with
+/// `encryption` and the key `keys` give for `full_file_name`.
+pub(crate) fn encode<R: Read>(
+    full_file_name: &str,
+    data: &mut R,
+    compression: Option<Compression>,
+    encryption: Option<Encryption>,
+    keys: Option<&Keys>,
+) -> io::Result<Encoded> {
+    let key = match (encryption, keys) {
+        (None, _) => None,
+        (Some(_), Some(keys)) => Some(keys.key_for(full_file_name)?),
+        (Some(_), None) => return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`WriteOptions::encryption` requires `WriteOptions::keys`"
+        )),
+    };
+    let mut plain = Vec::new();
+    let compressed = match compression {
+        Some(compression) => Some(ChunkCompression {
+            name: compression.name().to_string(),
+            size: compression.compress(data, &mut plain)?,
+        }),
+        None => {
+            data.read_to_end(&mut plain)?;
+            None
+        }
+    };
+    let read = compressed.as_ref().map_or(plain.len() as u64, |compressed| compressed.size);
+    let (stored, encrypted) = match (encryption, key) {
+        (Some(encryption), Some(key)) => (
+            encryption.encrypt(&key, full_file_name, &plain)?,
+            Some(ChunkEncryption::new(encryption, plain.len() as u64)),
+        ),
+        _ => (plain, None),
+    };
+    Ok(Encoded { stored, read, compression: compressed, encryption: encrypted })
+}
+
+/// FragmentWriter
+///
+/// Writes a single pak fragment: the header, then the data of the chunks as
+/// they are added, and finally the chunk table, the properties and the
+/// `Info` on `finish`. The names of the chunks are normalized.
+pub(crate) struct FragmentWriter<W> {
+    writer: W,
+    /// Bytes of data written so far
+    position: u64,
+    chunks: Vec<Chunk>,
+    /// Checksums of the chunks, by index
+    checksums: Vec<Option<Checksum>>,
+    /// Compressions of the chunks, by index
+    compressions: Vec<Option<ChunkCompression>>,
+    /// Encryptions of the chunks, by index
+    encryptions: Vec<Option<ChunkEncryption>>,
+    properties: Vec<Property>,
+    /// Encoder of the names in the chunk table
+    name_codec: Option<NameCodec>,
+    /// Bytes reserved before the tables
+    slack: u64,
+    /// Algorithm of the checksums of the chunks, if enabled
+    algorithm: Option<HashAlgorithm>,
+    /// Sorts the tables on `finish`
+    canonical: bool,
+    /// Compression of the data of the chunks added, if enabled
+    compression: Option<Compression>,
+    /// Width of the offsets of the tables, announced by the header
+    offsets: Offsets,
+    /// Algorithm hashing the data of the chunks added, if deduplicated
+    dedup: Option<HashAlgorithm>,
+    /// Index of the first chunk of every content written, by size and
+    /// digest
+    contents: HashMap<(u64, Digest), usize>,
+    /// Encryption of the data of the chunks added, if enabled
+    encryption: Option<Encryption>,
+    /// Keys of the entries, for the encryption
+    keys: Option<Keys>,
+}
+
+impl<W> FragmentWriter<W>
+where
+    W: Write
+{
+    /// Creates a new `FragmentWriter`, writing the header.
+    pub(crate) fn new(writer: W) -> io::Result<Self> {
+        FragmentWriter::with_offsets(writer, Offsets::Classic)
+    }
+
+    /// Creates a new `FragmentWriter` applying the `options`, see
+    /// `set_options`, and writing the header of their `offsets`.
+    pub(crate) fn with_options(writer: W, options: &WriteOptions) -> io::Result<Self> {
+        let mut writer = FragmentWriter::with_offsets(writer, options.offsets)?;
+        writer.set_options(options)?;
+        Ok(writer)
+    }
+
+    /// Creates a new `FragmentWriter` with offsets of the width of
+    /// `offsets`, writing the header announcing them.
+    fn with_offsets(mut writer: W, offsets: Offsets) -> io::Result<Self> {
+        writer.write_all(&offsets.header())?;
+        Ok(FragmentWriter {
+            writer,
+            position: 0,
+            chunks: Vec::new(),
+            checksums: Vec::new(),
+            compressions: Vec::new(),
+            encryptions: Vec::new(),
+            properties: Vec::new(),
+            name_codec: None,
+            slack: 0,
+            algorithm: None,
+            canonical: false,
+            compression: None,
+            offsets,
+            dedup: None,
+            contents: HashMap::new(),
+            encryption: None,
+            keys: None,
+        })
+    }
+
+    /// Applies the `name_codec`, the `slack`, the `checksums`, the
+    /// `canonical` mode, the `compression`, the `dedup`, the `encryption`
+    /// and the `keys` of `options`, the `offsets` being fixed by the header
+    /// already written. Fails with `InvalidInput` if the `dedup` algorithm
+    /// doesn't resist collisions: a collision would point an entry at the
+    /// data of another.
+    fn set_options(&mut self, options: &WriteOptions) -> io::Result<()> {
+        if let Some(algorithm) = options.dedup {
+            if !algorithm.is_cryptographic() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("`WriteOptions::dedup` requires an algorithm resisting collisions, not {}", algorithm)
+                ));
+            }
+        }
+        self.name_codec = options.name_codec.clone();
+        self.slack = options.slack;
+        self.algorithm = options.checksums;
+        self.canonical = options.canonical;
+        self.compression = options.compression;
+        self.dedup = options.dedup;
+        self.encryption = options.encryption;
+        self.keys = options.keys.clone();
+        Ok(())
+    }
+
+    /// Writer of the data of a new chunk.
+    fn data_writer(&mut self) -> CountingWriter<'_, W> {
+        CountingWriter {
+            inner: &mut self.writer,
+            count: 0,
+            state: self.algorithm.map(|algorithm| algorithm.start()),
+        }
+    }
+
+    /// Records the chunk `full_file_name` whose data was just written, of
+    /// `size` bytes as stored. Fails with `InvalidInput` if the data of the
+    /// fragment exceeds the capacity of its offsets.
+    fn push_chunk(
+        &mut self,
+        full_file_name: EntryName,
+        size: u64,
+        state: Option<Box<dyn HashState>>,
+        compression: Option<ChunkCompression>,
+        encryption: Option<ChunkEncryption>
+    ) -> io::Result<()> {
+        let end = HEADER_LEN + self.position + size;
+        if end > self.offsets.max() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "\"{}\" ends at {} bytes, beyond the capacity of the pak fragment, write it with `Offsets::Extended`",
+                    full_file_name, end
+                )
+            ));
+        }
+        self.chunks.push(Chunk::new(
+            full_file_name.into_string(),
+            self.position as i64,
+            size as i64
+        ));
+        self.checksums.push(self.algorithm.and_then(|algorithm| {
+            state.map(|state| Checksum { algorithm, digest: state.finish() })
+        }));
+        self.compressions.push(compression);
+        self.encryptions.push(encryption);
+        self.position += size;
+        Ok(())
+    }
+
+    /// Records the chunk `full_file_name` pointing at the data of the chunk
+    /// `index`, along with its checksum, compression and encryption.
+    fn push_shared(&mut self, full_file_name: EntryName, index: usize) {
+        let (offset, size) = (self.chunks[index].offset, self.chunks[index].size);
+        self.chunks.push(Chunk::new(full_file_name.into_string(), offset, size));
+        let checksum = self.checksums[index].clone();
+        self.checksums.push(checksum);
+        let compression = self.compressions[index].clone();
+        self.compressions.push(compression);
+        let encryption = self.encryptions[index].clone();
+        self.encryptions.push(encryption);
+    }
+
+    /// Bytes of data written so far.
+    pub(crate) fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Adds a chunk named `full_file_name` with the data read from `data`,
+    /// compressed if enabled, returning the size of the data read. If the
+    /// deduplication is enabled, data already written is pointed at
+    /// instead of being written again, unless it is encrypted.
+    pub(crate) fn add<R: Read>(&mut self, full_file_name: &str, data: &mut R) -> io::Result<u64> {
+        let full_file_name = EntryName::new(full_file_name)?;
+        let algorithm = match self.dedup {
+            Some(algorithm) if self.encryption.is_none() => algorithm,
+            _ => return self.write_chunk(full_file_name, data),
+        };
+
+        // the data is hashed before being written, to be written once
+        let mut buffer = Vec::new();
+        data.read_to_end(&mut buffer)?;
+        let content = (buffer.len() as u64, algorithm.digest(&buffer));
+        if let Some(&index) = self.contents.get(&content) {
+            self.push_shared(full_file_name, index);
+            return Ok(content.0);
+        }
+        let read = self.write_chunk(full_file_name, &mut &buffer[..])?;
+        self.contents.insert(content, self.chunks.len() - 1);
+        Ok(read)
+    }
+
+    /// Writes the data read from `data` as a new chunk, compressed and
+    /// encrypted if enabled, returning the size of the data read.
+    fn write_chunk<R: Read>(&mut self, full_file_name: EntryName, data: &mut R) -> io::Result<u64> {
+        if let Some(encryption) = self.encryption {
+            return self.write_encrypted(full_file_name, data, encryption);
+        }
+        let compression = self.compression;
+        let mut writer = self.data_writer();
+        let compressed = match compression {
+            Some(compression) => Some(ChunkCompression {
+                name: compression.name().to_string(),
+                size: compression.compress(data, &mut writer)?,
+            }),
+            None => {
+                io::copy(data, &mut writer)?;
+                None
+            }
+        };
+        let CountingWriter { count: size, state, .. } = writer;
+        let read = compressed.as_ref().map_or(size, |compressed| compressed.size);
+        self.push_chunk(full_file_name, size, state, compressed, None)?;
+        Ok(read)
+    }
+
+    /// Writes the data read from `data` as a new chunk, compressed if
+    /// enabled then encrypted with the key of `full_file_name`, returning
+    /// the size of the data read. The data is held in memory to be
+    /// encrypted at once.
+    fn write_encrypted<R: Read>(&mut self, full_file_name: EntryName, data: &mut R, encryption: Encryption) -> io::Result<u64> {
+        let encoded = encode(full_file_name.as_str(), data, self.compression, Some(encryption), self.keys.as_ref())?;
+        let mut writer = self.data_writer();
+        writer.write_all(&encoded.stored)?;
+        let CountingWriter { count: size, state, .. } = writer;
+        self.push_chunk(full_file_name, size, state, encoded.compression, encoded.encryption)?;
+        Ok(encoded.read)
+    }
+
+    /// Adds a chunk named `full_file_name` with the data of `chunk`, copied
+    /// as is from its fragment along with its compression and encryption.
+    /// Fails with `InvalidInput` if the chunk is encrypted under another
+    /// name, which its encryption authenticates.
+    pub(crate) fn add_chunk<R>(&mut self, full_file_name: &str, chunk: &MergedChunk<R>) -> io::Result<u64>
+        where R: Read + Seek
+    {
+        let full_file_name = EntryName::new(full_file_name)?;
+        if let Some(ref encryption) = chunk.encryption {
+            if encryption.entry != full_file_name.as_str() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("the encrypted entry \"{}\" can't be copied as \"{}\"", encryption.entry, full_file_name.as_str())
+                ));
+            }
+        }
+        let mut writer = self.data_writer();
+        chunk.copy_to(&mut writer)?;
+        let CountingWriter { count: size, state, .. } = writer;
+        let encryption = chunk.encryption.as_ref().map(ChunkEncryption::stored);
+        self.push_chunk(full_file_name, size, state, chunk.compression.clone(), encryption)?;
+        Ok(size)
+    }
+
+    /// Adds a chunk whose data is written by `f`, as is, and named by the
+    /// name it returns. If `f` returns `None` the chunk isn't added, which
+    /// is only valid if no data was written. Returns the size of the added
+    /// chunk.
+    pub(crate) fn add_with<F>(&mut self, f: F) -> io::Result<Option<u64>>
+        where F: FnOnce(&mut dyn Write) -> io::Result<Option<String>>
+    {
+        let mut writer = self.data_writer();
+        let full_file_name = f(&mut writer)?;
+        let CountingWriter { count: size, state, .. } = writer;
+
+        let full_file_name = match full_file_name {
+            Some(full_file_name) => EntryName::new(&full_file_name)?,
+            None if size == 0 => return Ok(None),
+            None => return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "a chunk can't be dropped once its data is written"
+            )),
+        };
+        self.push_chunk(full_file_name, size, state, None, None)?;
+        Ok(Some(size))
+    }
+
+    /// Adds a chunk named `full_file_name` containing `data`.
+    pub(crate) fn add_bytes(&mut self, full_file_name: &str, data: &[u8]) -> io::Result<u64> {
+        self.add(full_file_name, &mut &data[..])
+    }
+
+    /// Adds a property.
+    pub(crate) fn add_property(&mut self, key: &str, value: &str) {
+        self.properties.push(Property::new(key.to_string(), value.to_string()));
+    }
+
+    /// Sets a property, replacing the previous value of `key`.
+    pub(crate) fn set_property(&mut self, key: &str, value: &str) {
+        self.properties.retain(|property| property.key != key);
+        self.add_property(key, value);
+    }
+
+    /// Removes the property `key`, returning its last value.
+    pub(crate) fn remove_property(&mut self, key: &str) -> Option<String> {
+        let value = self.property(key).map(str::to_string);
+        self.properties.retain(|property| property.key != key);
+        value
+    }
+
+    /// Value of the property `key`, the last one if it was added several
+    /// times.
+    pub(crate) fn property(&self, key: &str) -> Option<&str> {
+        self.properties.iter().rev()
+            .find(|property| property.key == key)
+            .map(|property| property.value.as_str())
+    }
+
+    /// Writes the chunk table, the properties and the `Info`, returning the
+    /// underlying writer.
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        io::copy(&mut io::repeat(0).take(self.slack), &mut self.writer)?;
+        let chunks_offset = HEADER_LEN + self.position + self.slack;
+        let mut order: Vec<usize> = (0..self.chunks.len()).collect();
+        if self.canonical {
+            order.sort_by(|&a, &b| self.chunks[a].full_file_name.cmp(&self.chunks[b].full_file_name));
+            self.properties.sort_by(|a, b| (&a.key, &a.value).cmp(&(&b.key, &b.value)));
+        }
+
+        let mut tables = Vec::new();
+        // checksums, compressions and encryptions of the chunks
+        let mut chunk_properties = Vec::new();
+        for i in order {
+            let chunk = &self.chunks[i];
+            let stored = match self.name_codec {
+                Some(ref codec) => Chunk::new(
+                    codec.encode(&chunk.full_file_name)?,
+                    chunk.offset,
+                    chunk.size
+                ),
+                None => chunk.clone(),
+            };
+            stored.write(&mut tables, self.offsets)?;
+            if let Some(ref checksum) = self.checksums[i] {
+                chunk_properties.push(checksum.to_property(&stored.full_file_name));
+            }
+            if let Some(ref compression) = self.compressions[i] {
+                chunk_properties.push(compression.to_property(&stored.full_file_name));
+            }
+            if let Some(ref encryption) = self.encryptions[i] {
+                chunk_properties.push(encryption.to_property(&stored.full_file_name));
+            }
+        }
+        let properties_offset = chunks_offset + tables.len() as u64;
+        for property in self.properties.iter().chain(&chunk_properties) {
+            property.write(&mut tables)?;
+        }
+        self.writer.write_all(&tables)?;
+
+        Info {
+            offset: HEADER_LEN,
+            size: self.position as i64,
+            chunks_offset,
+            chunks_count: self.chunks.len() as i32,
+            properties_offset,
+            properties_count: (self.properties.len() + chunk_properties.len()) as i32,
+            offsets: self.offsets,
+        }.write(&mut self.writer)?;
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// PakWriter
+///
+/// Writes a new single-fragment pak file. The data of the entries is
+/// written as they are added, the chunk table, the properties and the
+/// `Info` on `finish`: the entries are never held in memory.
+///
+/// ``` no_run
+/// # use pak::PakWriter;
+/// # fn main() -> std::io::Result<()> {
+/// let mut writer = PakWriter::create("assets.d2p")?;
+/// writer.add_file("sprites/hero.swf", "build/hero.swf")?;
+/// writer.add_bytes("config.xml", b"<config/>")?;
+/// writer.set_property("version", "2.0")?;
+/// writer.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The names of the entries are normalized, and adding the same name twice
+/// fails. Dropping the writer without calling `finish` leaves an invalid
+/// file.
+pub struct PakWriter {
+    pub(crate) path: PathBuf,
+    pub(crate) writer: FragmentWriter<BufWriter<File>>,
+    /// Normalized names of the entries added so far
+    pub(crate) names: HashSet<String>,
+}
+
+impl PakWriter {
+    /// Creates a new `PakWriter` writing the pak file at `path`.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        PakWriter::create_with(path, &WriteOptions::default())
+    }
+
+    /// Creates a new `PakWriter` with the given write `options`.
+    pub fn create_with<P: AsRef<Path>>(path: P, options: &WriteOptions) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let writer = FragmentWriter::with_options(BufWriter::with_capacity(options.buffer_size, File::create(&path)?), options)?;
+        Ok(PakWriter {
+            path,
+            writer,
+            names: HashSet::new(),
+        })
+    }
+
+    /// Path of the pak file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds the entry `full_file_name` with the data read from `data`,
+    /// returning its size.
+    pub fn add_reader<R: Read>(&mut self, full_file_name: &str, data: &mut R) -> io::Result<u64> {
+        let full_file_name = self.reserve_name(full_file_name)?;
+        self.writer.add(&full_file_name, data)
+    }
+
+    /// Adds the entry `full_file_name` containing `data`.
+    pub fn add_bytes(&mut self, full_file_name: &str, data: &[u8]) -> io::Result<u64> {
+        self.add_reader(full_file_name, &mut &data[..])
+    }
+
+    /// Adds the entry `full_file_name` with the content of the file at
+    /// `path`.
+    pub fn add_file<P: AsRef<Path>>(&mut self, full_file_name: &str, path: P) -> io::Result<u64> {
+        let mut file = File::open(path)?;
+        self.add_reader(full_file_name, &mut file)
+    }
+
+    /// Packs the files of the directory `src_dir` into the new pak file
+    /// `dest_pak`, the reverse of `MergeReader::extract`: their paths
+    /// relative to `src_dir`, joined with `/`, are the entry names. The
+    /// symbolic links to files are packed as the files, the ones to
+    /// directories are skipped. See `ChainPacker::pack_dir` to split the
+    /// archive into fragments.
+    pub fn pack_dir<P: AsRef<Path>, Q: AsRef<Path>>(src_dir: P, dest_pak: Q) -> io::Result<()> {
+        PakWriter::pack_dir_with_progress(src_dir, dest_pak, |_| {})
+    }
+
+    /// Packs the files of the directory `src_dir` like `pack_dir`,
+    /// reporting the progress to `progress`.
+    pub fn pack_dir_with_progress<P, Q, F>(src_dir: P, dest_pak: Q, mut progress: F) -> io::Result<()>
+        where P: AsRef<Path>,
+              Q: AsRef<Path>,
+              F: FnMut(&ProgressEvent)
+    {
+        let files = dir_entries(src_dir.as_ref(), dest_pak.as_ref())?;
+        let mut writer = PakWriter::create(dest_pak)?;
+        add_files(&files, |full_file_name, data| writer.add_reader(full_file_name, data), &mut progress)?;
+        writer.finish()
+    }
+
+    /// Returns `true` if the entry `full_file_name` was added.
+    pub fn contains(&self, full_file_name: &str) -> bool {
+        self.names.contains(&*lookup_key(full_file_name))
+    }
+
+    /// Sets the property `key`, failing with `InvalidInput` if it is
+    /// reserved, see `is_reserved_property`: the `link` property is set
+    /// with `set_link`, which checks the fragment it names.
+    pub fn set_property<K: AsRef<str>, V: AsRef<str>>(&mut self, key: K, value: V) -> io::Result<()> {
+        check_property_key(key.as_ref())?;
+        self.writer.set_property(key.as_ref(), value.as_ref());
+        Ok(())
+    }
+
+    /// Removes the property `key`, returning its value, failing with
+    /// `InvalidInput` if it is reserved.
+    pub fn remove_property(&mut self, key: &str) -> io::Result<Option<String>> {
+        check_property_key(key)?;
+        Ok(self.writer.remove_property(key))
+    }
+
+    /// Chains the pak file to the fragment `next`, which must be in the same
+    /// directory, setting the `link` property to its file name. The chain
+    /// starting at `next` is checked by `finish`: the fragments of a chain
+    /// are written from the last one.
+    pub fn set_link<P: AsRef<Path>>(&mut self, next: P) -> io::Result<()> {
+        let link = link_value(&self.path, next)?;
+        self.writer.set_property(LINK_PROPERTY, &link);
+        Ok(())
+    }
+
+    /// Reserves the normalized name of a new entry, failing if it was
+    /// already added.
+    pub(crate) fn reserve_name(&mut self, full_file_name: &str) -> io::Result<String> {
+        let full_file_name = EntryName::new(full_file_name)?.into_string();
+        if !self.names.insert(full_file_name.clone()) {
+            return Err(entry_exists(&full_file_name));
+        }
+        Ok(full_file_name)
+    }
+
+    /// Writes the tables and flushes the pak file to the disk.
+    ///
+    /// Fails without writing the tables if the `link` property doesn't name
+    /// an existing fragment in the same directory, if the chain starting at
+    /// that fragment is incomplete, or if it leads back to the pak file.
+    pub fn finish(self) -> io::Result<()> {
+        if let Some(link) = self.writer.property("link") {
+            check_link(&self.path, link)?;
+        }
+        self.writer.finish()?.into_inner().map_err(Error::from)?.sync_all()
+    }
+}
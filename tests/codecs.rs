@@ -0,0 +1,170 @@
+//! Round-trips of the entries compressed, encrypted and deduplicated by the
+//! writers.
+
+extern crate pak;
+
+mod common;
+
+use common::temp_dir;
+use pak::{HashAlgorithm, PakWriter, WriteOptions};
+use std::fs;
+use std::io::ErrorKind;
+#[cfg(any(feature = "zlib", feature = "zstd", feature = "crypto"))]
+use std::path::Path;
+
+/// Entries of the archives written: compressible and incompressible data.
+#[cfg(any(feature = "zlib", feature = "zstd", feature = "crypto"))]
+fn entries() -> Vec<(&'static str, Vec<u8>)> {
+    use pak::testkit::pseudo_random;
+
+    vec![
+        ("text/lines.txt", b"line of text\n".iter().cycle().take(20_000).cloned().collect()),
+        ("bin/random.bin", pseudo_random(1, 5_000)),
+        ("bin/empty.bin", Vec::new()),
+    ]
+}
+
+/// Writes `entries()` at `path` with `options`.
+#[cfg(any(feature = "zlib", feature = "zstd", feature = "crypto"))]
+fn write(path: &Path, options: &WriteOptions) {
+    let mut writer = PakWriter::create_with(path, options).unwrap();
+    for (full_file_name, data) in entries() {
+        writer.add_bytes(full_file_name, &data).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+#[cfg(any(feature = "zlib", feature = "zstd"))]
+#[test]
+fn compressed_entries_round_trip() {
+    use pak::{Compression, MergeReader};
+
+    let dir = temp_dir("compression");
+    let compressions = [
+        #[cfg(feature = "zlib")]
+        Compression::Zlib,
+        #[cfg(feature = "zstd")]
+        Compression::Zstd,
+    ];
+
+    for &compression in &compressions {
+        let path = dir.join(format!("{}.d2p", compression));
+        write(&path, &WriteOptions { compression: Some(compression), ..WriteOptions::default() });
+
+        let reader = MergeReader::open(&path).unwrap();
+        for (full_file_name, data) in entries() {
+            assert_eq!(reader.read_file(full_file_name).unwrap(), data, "{}: {}", compression, full_file_name);
+            let entry = reader.entry(full_file_name).unwrap();
+            assert!(entry.chunk().is_compressed());
+            assert_eq!(entry.chunk().uncompressed_size(), data.len() as u64);
+        }
+        let lines = reader.entry("text/lines.txt").unwrap();
+        assert!(lines.chunk().size() < 20_000, "{}", compression);
+        assert_eq!(lines.chunk().read_at(13, 4).unwrap(), b"line");
+        assert!(reader.verify().is_ok());
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "crypto")]
+#[test]
+fn encrypted_entries_round_trip() {
+    use pak::{Encryption, Key, Keys, MergeReader, ReadOptions, StaticKey, Transform};
+
+    let dir = temp_dir("encryption");
+    let path = dir.join("encrypted.d2p");
+    let keys = Keys::new(StaticKey::new(Key::new([7; 32])));
+    write(&path, &WriteOptions {
+        encryption: Some(Encryption::XChaCha20Poly1305),
+        keys: Some(keys.clone()),
+        ..WriteOptions::default()
+    });
+    let stored = fs::read(&path).unwrap();
+    assert!(!stored.windows(12).any(|window| window == b"line of text"));
+
+    let with_keys = ReadOptions { keys: Some(keys), ..ReadOptions::default() };
+    let reader = MergeReader::open_with(&path, &with_keys).unwrap();
+    for (full_file_name, data) in entries() {
+        assert_eq!(reader.read_file(full_file_name).unwrap(), data, "{}", full_file_name);
+        assert!(reader.entry(full_file_name).unwrap().chunk().is_encrypted());
+    }
+
+    let without_keys = MergeReader::open(&path).unwrap();
+    assert_eq!(without_keys.read_file("bin/random.bin").unwrap_err().kind(), ErrorKind::PermissionDenied);
+    let wrong_key = ReadOptions { keys: Some(Keys::new(StaticKey::new(Key::new([8; 32])))), ..ReadOptions::default() };
+    let wrong = MergeReader::open_with(&path, &wrong_key).unwrap();
+    assert_eq!(wrong.read_file("bin/random.bin").unwrap_err().kind(), ErrorKind::InvalidData);
+
+    // the name is authenticated with the data
+    let renamed = PakWriter::from_reader_with(&reader, dir.join("renamed.d2p"), |full_file_name| {
+        Transform::Rename(format!("moved/{}", full_file_name))
+    });
+    assert_eq!(renamed.err().map(|err| err.kind()), Some(ErrorKind::InvalidInput));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "crypto")]
+#[test]
+fn committed_entries_keep_their_encryption() {
+    use pak::{Archive, Encryption, Key, Keys, MergeReader, ReadOptions, StaticKey};
+
+    let dir = temp_dir("commit-encrypted");
+    let path = dir.join("encrypted.d2p");
+    let keys = Keys::new(StaticKey::new(Key::new([7; 32])));
+    let mut writer = PakWriter::create_with(&path, &WriteOptions {
+        encryption: Some(Encryption::XChaCha20Poly1305),
+        keys: Some(keys.clone()),
+        ..WriteOptions::default()
+    }).unwrap();
+    writer.add_bytes("secret.txt", b"secret").unwrap();
+    writer.finish().unwrap();
+
+    let with_keys = ReadOptions { keys: Some(keys), ..ReadOptions::default() };
+    let mut archive = Archive::open_rw_with(&path, &with_keys).unwrap();
+    archive.add("plain.txt", b"plain".to_vec()).unwrap();
+    archive.commit().unwrap();
+
+    let without_keys = MergeReader::open(&path).unwrap();
+    assert!(without_keys.read_file("secret.txt").is_err());
+    assert_eq!(without_keys.read_file("plain.txt").unwrap(), b"plain");
+    let reader = MergeReader::open_with(&path, &with_keys).unwrap();
+    assert_eq!(reader.read_file("secret.txt").unwrap(), b"secret");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "blake3")]
+#[test]
+fn deduplicated_entries_round_trip() {
+    use pak::testkit::pseudo_random;
+    use pak::MergeReader;
+
+    let dir = temp_dir("dedup");
+    let (plain, dedup) = (dir.join("plain.d2p"), dir.join("dedup.d2p"));
+    let data = pseudo_random(2, 10_000);
+    for &(ref path, algorithm) in &[(&plain, None), (&dedup, Some(HashAlgorithm::Blake3))] {
+        let mut writer = PakWriter::create_with(path, &WriteOptions { dedup: algorithm, ..WriteOptions::default() }).unwrap();
+        writer.add_bytes("a.bin", &data).unwrap();
+        writer.add_bytes("copy/a.bin", &data).unwrap();
+        writer.add_bytes("b.bin", &pseudo_random(3, 10_000)).unwrap();
+        writer.finish().unwrap();
+    }
+    assert!(fs::metadata(&dedup).unwrap().len() + 10_000 <= fs::metadata(&plain).unwrap().len());
+
+    let reader = MergeReader::open(&dedup).unwrap();
+    assert_eq!(reader.read_file("a.bin").unwrap(), data);
+    assert_eq!(reader.read_file("copy/a.bin").unwrap(), data);
+    assert_eq!(reader.read_file("b.bin").unwrap(), pseudo_random(3, 10_000));
+    let (a, copy) = (reader.entry("a.bin").unwrap(), reader.entry("copy/a.bin").unwrap());
+    assert_eq!(a.chunk().offset(), copy.chunk().offset());
+    assert!(reader.verify().is_ok());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedup_requires_an_algorithm_resisting_collisions() {
+    let dir = temp_dir("weak-dedup");
+    let options = WriteOptions { dedup: Some(HashAlgorithm::Fnv64), ..WriteOptions::default() };
+    let err = PakWriter::create_with(dir.join("weak.d2p"), &options).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,14 @@
+//! Helpers shared by the integration tests.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+/// Empty temporary directory of the test `name`.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("pak-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
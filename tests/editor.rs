@@ -0,0 +1,126 @@
+//! Round-trips of the edits of `Archive` and `PakEditor`, and of the
+//! `Rollback` of the appended commits.
+
+extern crate pak;
+
+mod common;
+
+use common::temp_dir;
+use pak::testkit::{pseudo_random, FragmentBuilder};
+use pak::{Archive, MergeReader, PakEditor};
+use std::fs;
+
+#[test]
+fn archive_commit_round_trip() {
+    let dir = temp_dir("archive");
+    let path = dir.join("archive.d2p");
+    let mut archive = Archive::create(&path);
+    for i in 0..3u8 {
+        archive.add(format!("entries/{}.bin", i), vec![i; 100]).unwrap();
+    }
+    archive.set_property("build", "1").unwrap();
+    archive.commit().unwrap();
+
+    let mut archive = Archive::open_rw(&path).unwrap();
+    archive.replace("entries/0.bin", b"replaced".to_vec()).unwrap();
+    archive.remove("entries/1.bin").unwrap();
+    archive.add("new/a.txt", b"added".to_vec()).unwrap();
+    archive.set_property("build", "2").unwrap();
+    archive.commit().unwrap();
+    assert!(!archive.has_changes());
+    assert_eq!(archive.generation(), 1);
+
+    let reader = MergeReader::open(&path).unwrap();
+    assert_eq!(reader.read_file("entries/0.bin").unwrap(), b"replaced");
+    assert!(reader.read_file("entries/1.bin").is_err());
+    assert_eq!(reader.read_file("entries/2.bin").unwrap(), vec![2; 100]);
+    assert_eq!(reader.read_file("new/a.txt").unwrap(), b"added");
+    assert_eq!(reader.property("build"), Some("2"));
+    // no temporary file is left behind
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn editor_commit_round_trip() {
+    let dir = temp_dir("commit");
+    let path = dir.join("patched.d2p");
+    FragmentBuilder::new().entries(5, 100).write_to(&path).unwrap();
+
+    let mut editor = PakEditor::open(&path).unwrap();
+    editor.replace("entries/1.bin", b"new".to_vec()).unwrap();
+    editor.remove("entries/2.bin").unwrap();
+    editor.add("d/9.bin", vec![9; 1_000]).unwrap();
+    editor.set_property("k", "v").unwrap();
+    editor.commit().unwrap();
+    assert_eq!(editor.read_file("d/9.bin").unwrap(), vec![9; 1_000]);
+
+    let reader = MergeReader::open(&path).unwrap();
+    assert_eq!(reader.read_file("entries/0.bin").unwrap(), pseudo_random(0, 100));
+    assert_eq!(reader.read_file("entries/1.bin").unwrap(), b"new");
+    assert!(reader.read_file("entries/2.bin").is_err());
+    assert_eq!(reader.read_file("d/9.bin").unwrap(), vec![9; 1_000]);
+    assert_eq!(reader.property("k"), Some("v"));
+    assert!(reader.verify().is_ok());
+    // the data of the replaced and removed entries is wasted
+    assert!(pak::health(&path).unwrap().wasted >= 200);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn appended_commits_roll_back() {
+    let dir = temp_dir("append");
+    let path = dir.join("appended.d2p");
+    FragmentBuilder::new().entries(3, 100).property("build", "1").write_to(&path).unwrap();
+    let v1 = fs::read(&path).unwrap();
+
+    let mut editor = PakEditor::open(&path).unwrap();
+    editor.add("new/a.txt", b"appended".to_vec()).unwrap();
+    editor.replace("entries/1.bin", vec![9; 5]).unwrap();
+    editor.set_property("build", "2").unwrap();
+    let rollback = editor.commit_append().unwrap();
+    let v2 = fs::read(&path).unwrap();
+    assert_eq!(v2[..v1.len()], v1[..]);
+    assert_eq!(rollback.previous_len(), v1.len() as u64);
+
+    let reader = MergeReader::open(&path).unwrap();
+    assert_eq!(reader.read_file("new/a.txt").unwrap(), b"appended");
+    assert_eq!(reader.read_file("entries/1.bin").unwrap(), vec![9; 5]);
+    assert_eq!(reader.read_file("entries/2.bin").unwrap(), pseudo_random(2, 100));
+    assert_eq!(reader.property("build"), Some("2"));
+    assert!(reader.verify().is_ok());
+    drop(reader);
+
+    editor.add("new/b.txt", b"b".to_vec()).unwrap();
+    let second = editor.commit_append().unwrap();
+    assert_eq!(second.previous_len(), v2.len() as u64);
+    rollback.apply().unwrap();
+    assert_eq!(fs::read(&path).unwrap(), v1);
+    assert_eq!(MergeReader::open(&path).unwrap().property("build"), Some("1"));
+    // the later version is gone with the earlier one
+    assert!(second.apply().is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "zlib")]
+#[test]
+fn editor_compresses_the_new_entries() {
+    use pak::{Compression, WriteOptions};
+
+    let dir = temp_dir("compressed");
+    let path = dir.join("compressed.d2p");
+    FragmentBuilder::new().entries(2, 100).write_to(&path).unwrap();
+
+    let mut editor = PakEditor::open(&path).unwrap();
+    editor.set_write_options(WriteOptions { compression: Some(Compression::Zlib), ..WriteOptions::default() });
+    editor.add("text.txt", vec![b'a'; 10_000]).unwrap();
+    editor.commit().unwrap();
+
+    let reader = MergeReader::open(&path).unwrap();
+    let text = reader.entry("text.txt").unwrap();
+    assert!(text.chunk().is_compressed());
+    assert!(text.chunk().size() < 10_000);
+    assert_eq!(reader.read_file("text.txt").unwrap(), vec![b'a'; 10_000]);
+    assert_eq!(reader.read_file("entries/1.bin").unwrap(), pseudo_random(1, 100));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,215 @@
+//! Round-trips of the formats around the archives: the legacy archives,
+//! and the `.d2o`, `.d2i`, `.dlm` and `.swl` files they hold.
+
+extern crate pak;
+
+mod common;
+
+use common::temp_dir;
+use pak::d2i::{D2iFile, I18nText};
+use pak::d2o::D2oFile;
+use pak::raw::HEADER;
+use pak::swl::Swl;
+use pak::testkit::pseudo_random;
+use pak::MergeReader;
+use std::fs;
+use std::io::Cursor;
+
+/// Appends the big-endian `value`.
+fn int(buffer: &mut Vec<u8>, value: i32) {
+    buffer.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Appends `text` prefixed by its big-endian 16-bit length.
+fn utf(buffer: &mut Vec<u8>, text: &str) {
+    buffer.extend_from_slice(&(text.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(text.as_bytes());
+}
+
+#[test]
+fn legacy_archives_are_migrated() {
+    let dir = temp_dir("legacy");
+    let entries = vec![("a/h.txt", b"hello".to_vec()), ("x.bin", pseudo_random(1, 300))];
+    let mut legacy = vec![1, 0];
+    let mut index = Vec::new();
+    int(&mut index, entries.len() as i32);
+    for &(full_file_name, ref data) in &entries {
+        utf(&mut index, full_file_name);
+        int(&mut index, legacy.len() as i32 - 2);
+        int(&mut index, data.len() as i32);
+        legacy.extend_from_slice(data);
+    }
+    let index_offset = legacy.len() as i32;
+    legacy.extend_from_slice(&index);
+    int(&mut legacy, index_offset);
+    let (old, new) = (dir.join("old.d2pOld"), dir.join("new.d2p"));
+    fs::write(&old, &legacy).unwrap();
+
+    let reader = MergeReader::open(&old).unwrap();
+    for &(full_file_name, ref data) in &entries {
+        assert_eq!(&reader.read_file(full_file_name).unwrap(), data);
+    }
+    assert_eq!(pak::raw::legacy::migrate(&old, &new).unwrap(), entries.len());
+    assert_eq!(fs::read(&new).unwrap()[..2], HEADER);
+    let migrated = MergeReader::open(&new).unwrap();
+    assert!(pak::diff(&reader, &migrated).unwrap().is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn d2o_objects_are_read() {
+    let mut d2o = b"D2O".to_vec();
+    int(&mut d2o, 0);
+    let mut offsets = Vec::new();
+    for &(id, name) in &[(7, "seven"), (8, "eight")] {
+        offsets.push((id, d2o.len() as i32));
+        int(&mut d2o, 1);
+        int(&mut d2o, id);
+        utf(&mut d2o, name);
+    }
+    let index_offset = d2o.len() as i32;
+    d2o[3..7].copy_from_slice(&index_offset.to_be_bytes());
+    int(&mut d2o, offsets.len() as i32 * 8);
+    for (id, offset) in offsets {
+        int(&mut d2o, id);
+        int(&mut d2o, offset);
+    }
+    int(&mut d2o, 1);
+    int(&mut d2o, 1);
+    utf(&mut d2o, "Item");
+    utf(&mut d2o, "items");
+    int(&mut d2o, 2);
+    utf(&mut d2o, "id");
+    int(&mut d2o, -1);
+    utf(&mut d2o, "name");
+    int(&mut d2o, -3);
+
+    let file = D2oFile::new(Cursor::new(d2o)).unwrap();
+    assert_eq!(file.len(), 2);
+    assert_eq!(file.class(1).unwrap().fields.len(), 2);
+    let seven = file.object(7).unwrap().unwrap();
+    assert_eq!(seven.get("id").unwrap().as_int(), Some(7));
+    assert_eq!(seven.get("name").unwrap().as_str(), Some("seven"));
+    assert_eq!(file.object(8).unwrap().unwrap().get("name").unwrap().as_str(), Some("eight"));
+    assert!(file.object(9).unwrap().is_none());
+    assert_eq!(file.objects().count(), 2);
+    assert!(D2oFile::new(Cursor::new(b"XYZ0000".to_vec())).is_err());
+}
+
+#[test]
+fn d2i_round_trip() {
+    let mut texts = D2iFile::new();
+    texts.set_text(1, "Épée");
+    texts.set_entry(2, I18nText { text: "Bâton".into(), undiacritical: Some("baton".into()) });
+    texts.set_text(3, "arc");
+    texts.set_named("ui.ok", "OK");
+    let mut bytes = Vec::new();
+    texts.write(&mut bytes).unwrap();
+
+    let read = D2iFile::from(&mut Cursor::new(&bytes)).unwrap();
+    assert_eq!(read.texts(), texts.texts());
+    assert_eq!(read.named_texts(), texts.named_texts());
+    assert_eq!(read.text(1), Some("Épée"));
+    assert_eq!(read.entry(2).unwrap().undiacritical.as_deref(), Some("baton"));
+    assert_eq!(read.named("ui.ok"), Some("OK"));
+    assert_eq!(read.sort_order(), vec![3, 2, 1]);
+    let mut again = Vec::new();
+    read.write(&mut again).unwrap();
+    assert_eq!(again, bytes);
+}
+
+#[test]
+fn swl_round_trip() {
+    let swl = Swl {
+        version: 1,
+        frame_rate: 50,
+        classes: vec!["Gfx_1".into(), "pkg.Gfx_2".into()],
+        swf: b"CWS\x0arest".to_vec(),
+    };
+    let mut bytes = Vec::new();
+    swl.write(&mut bytes).unwrap();
+
+    let read = Swl::from_bytes(&bytes).unwrap();
+    assert_eq!(read, swl);
+    assert!(read.exports("pkg.Gfx_2"));
+    assert!(read.is_compressed());
+    assert_eq!(read.swf_version(), Some(10));
+    assert!(Swl::from_bytes(b"X").is_err());
+}
+
+/// Appends the data of a map of the sub-area 12 with a background, a layer
+/// of one cell holding a graphical and a sound element, then the cells.
+#[cfg(feature = "zlib")]
+fn map_body(buffer: &mut Vec<u8>) {
+    buffer.extend_from_slice(&5u32.to_be_bytes());
+    buffer.push(0);
+    for &value in &[12, 1, 2, 3, 4, 0] {
+        int(buffer, value);
+    }
+    buffer.extend_from_slice(&0xff11_2233u32.to_be_bytes());
+    int(buffer, 0);
+    buffer.extend_from_slice(&100u16.to_be_bytes());
+    buffer.extend_from_slice(&[0; 4]);
+    int(buffer, 0);
+    buffer.extend_from_slice(&[0, 1]);
+    int(buffer, 9);
+    buffer.push(1);
+    buffer.extend_from_slice(&[0, 0, 0, 7, 0, 1, 0, 2, 0, 0, 3, 232, 3, 232, 1, 2, 3, 255]);
+    buffer.push(0);
+    int(buffer, 560);
+    int(buffer, 0);
+    buffer.extend_from_slice(&[1, 0, 0, 1, 0, 3, 0, 2]);
+    buffer.push(2);
+    buffer.extend_from_slice(&44u32.to_be_bytes());
+    buffer.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 5, 0, 6, 1]);
+    int(buffer, 0);
+    buffer.push(33);
+    buffer.extend_from_slice(&[0, 0, 0, 1, 0, 50, 0, 0, 0, 2, 0, 0, 0, 3, 0, 4, 0, 5]);
+    for i in 0..560 {
+        if i == 0 {
+            buffer.push(0x80);
+            continue;
+        }
+        buffer.push(1);
+        buffer.extend_from_slice(&(if i == 1 { 9u16 } else { 0 }).to_be_bytes());
+        buffer.extend_from_slice(&[0, 0, 0]);
+        if i != 1 {
+            buffer.push(7);
+        }
+    }
+}
+
+#[cfg(feature = "zlib")]
+#[test]
+fn dlm_maps_are_read() {
+    use pak::dlm::{Element, Map};
+
+    let mut plain = vec![77, 11];
+    plain.extend_from_slice(&1000u32.to_be_bytes());
+    plain.extend_from_slice(&[0, 0]);
+    int(&mut plain, 0);
+    map_body(&mut plain);
+    let map = Map::from_bytes(&plain).unwrap();
+    assert_eq!((map.id, map.sub_area_id, map.preset_id, map.background_color), (1000, 12, 9, 0xff11_2233));
+    assert_eq!(map.backgrounds[0].fixture_id, 7);
+    assert_eq!(map.layers[0].cells[0].cell_id, 3);
+    match map.layers[0].cells[0].elements[0] {
+        Element::Graphical(ref graphical) => assert_eq!(graphical.element_id, 44),
+        ref element => panic!("unexpected {:?}", element),
+    }
+    assert_eq!(map.cells.len(), 560);
+    assert!(map.cells[2].mov && map.cells[2].los && map.cells[2].linked_zone == 7);
+
+    // the encrypted data is xored with the key
+    let key = b"k3y";
+    let mut body = Vec::new();
+    map_body(&mut body);
+    let mut encrypted = vec![77, 11];
+    encrypted.extend_from_slice(&1000u32.to_be_bytes());
+    encrypted.extend_from_slice(&[1, 1]);
+    int(&mut encrypted, body.len() as i32);
+    encrypted.extend(body.iter().zip(key.iter().cycle()).map(|(byte, key)| byte ^ key));
+    assert!(Map::from_bytes(&encrypted).is_err());
+    assert_eq!(Map::from_bytes_with_key(&encrypted, key).unwrap(), map);
+    assert!(Map::from_bytes(b"nope").is_err());
+}
@@ -0,0 +1,92 @@
+//! Golden-file round-trip harness.
+//!
+//! Opt-in: ignored by default, run with `cargo test --test golden --
+//! --ignored` and `PAK_GOLDEN_DIR` set to a game content directory (e.g. the
+//! `content` folder of a local install). Every archive chain found there is
+//! opened, repacked into a temporary directory, reopened and compared entry
+//! by entry. The discrepancies of all the archives are reported together.
+
+#![cfg(feature = "std")]
+
+extern crate pak;
+
+use pak::{Catalog, HashAlgorithm, MergeReader};
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::path::Path;
+
+/// Compares the entries of `original` and `repacked`, returning the
+/// discrepancies.
+fn compare<A: Read + Seek, B: Read + Seek>(original: &MergeReader<A>, repacked: &MergeReader<B>) -> Vec<String> {
+    let mut discrepancies = Vec::new();
+
+    let mut names: Vec<&String> = original.iter().map(|(name, _)| name).collect();
+    names.sort();
+    for name in names {
+        let expected = original.read_file(name);
+        let actual = repacked.read_file(name);
+        match (expected, actual) {
+            (Ok(expected), Ok(actual)) => {
+                if HashAlgorithm::Fnv64.digest(&expected) != HashAlgorithm::Fnv64.digest(&actual) {
+                    discrepancies.push(format!("\"{}\": content differs", name));
+                }
+            }
+            (Err(err), _) => discrepancies.push(format!("\"{}\": unreadable in the original: {}", name, err)),
+            (_, Err(err)) => discrepancies.push(format!("\"{}\": unreadable once repacked: {}", name, err)),
+        }
+    }
+
+    let extra = repacked.iter()
+        .filter(|&(name, _)| original.read_file(name).is_err())
+        .count();
+    if extra > 0 {
+        discrepancies.push(format!("{} unexpected entries once repacked", extra));
+    }
+
+    discrepancies
+}
+
+fn round_trip(archive: &Path, temp: &Path) -> Vec<String> {
+    let original = match MergeReader::<File>::open(archive) {
+        Ok(original) => original,
+        Err(err) => return vec![format!("can't be opened: {}", err)],
+    };
+
+    let dest = temp.join(archive.file_name().unwrap());
+    if let Err(err) = original.save_subset(|_, _| true, &dest) {
+        return vec![format!("can't be repacked: {}", err)];
+    }
+
+    let discrepancies = match MergeReader::<File>::open(&dest) {
+        Ok(repacked) => compare(&original, &repacked),
+        Err(err) => vec![format!("can't be reopened once repacked: {}", err)],
+    };
+    let _ = fs::remove_file(&dest);
+    discrepancies
+}
+
+#[test]
+#[ignore]
+fn golden_round_trip() {
+    let dir = env::var_os("PAK_GOLDEN_DIR").expect("PAK_GOLDEN_DIR must be set to run the golden-file round-trip");
+
+    let catalog = Catalog::open_dir(&dir).expect("can't read PAK_GOLDEN_DIR");
+    let temp = env::temp_dir().join(format!("pak-golden-{}", std::process::id()));
+    fs::create_dir_all(&temp).unwrap();
+
+    let mut report = Vec::new();
+    let mut count = 0;
+    for archive in catalog.archives() {
+        count += 1;
+        for discrepancy in round_trip(archive, &temp) {
+            report.push(format!("{}: {}", archive.display(), discrepancy));
+        }
+    }
+    let _ = fs::remove_dir_all(&temp);
+
+    println!("{} archives round-tripped", count);
+    assert!(report.is_empty(), "{} discrepancies:\n{}", report.len(), report.join("\n"));
+}
@@ -0,0 +1,54 @@
+//! Hostile fragments: link cycles and sizes out of bounds are refused
+//! without following or allocating them.
+
+extern crate pak;
+
+mod common;
+
+use common::temp_dir;
+use pak::testkit::{Corruption, FragmentBuilder};
+use pak::MergeReader;
+use std::fs;
+use std::io::ErrorKind;
+
+#[test]
+fn link_cycles_are_refused() {
+    let dir = temp_dir("cycle");
+    FragmentBuilder::new().entries(1, 10).property("link", "self.d2p").write_to(dir.join("self.d2p")).unwrap();
+    FragmentBuilder::new().entries(1, 10).property("link", "b.d2p").write_to(dir.join("a.d2p")).unwrap();
+    FragmentBuilder::new().entries(1, 10).property("link", "a.d2p").write_to(dir.join("b.d2p")).unwrap();
+
+    for name in &["self.d2p", "a.d2p"] {
+        let err = MergeReader::open(dir.join(name)).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput, "{}", name);
+        match pak::Error::from_io(&err) {
+            Some(&pak::Error::InvalidLink { .. }) => {}
+            error => panic!("{}: unexpected {:?}", name, error),
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn counts_out_of_bounds_are_refused() {
+    for corruption in &[Corruption::ChunksCount(i32::MAX), Corruption::PropertiesCount(-1), Corruption::Truncate(10)] {
+        assert!(FragmentBuilder::new().entries(2, 10).corrupt(corruption.clone()).reader().is_err());
+    }
+}
+
+#[test]
+fn sizes_past_the_end_are_not_allocated() {
+    let huge = i64::from(i32::MAX);
+    for corruption in &[
+        Corruption::ChunkSize("entries/1.bin".into(), -1),
+        Corruption::ChunkSize("entries/1.bin".into(), huge),
+        Corruption::ChunkOffset("entries/1.bin".into(), huge),
+    ] {
+        let fragment = FragmentBuilder::new().entries(2, 10).corrupt(corruption.clone());
+        if let Ok(reader) = fragment.reader() {
+            assert!(reader.read_file("entries/1.bin").is_err());
+            assert!(!reader.verify().is_ok());
+            assert_eq!(reader.read_file("entries/0.bin").unwrap().len(), 10);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Round-trips of the archives through the tar and zip formats.
+
+#![cfg(any(feature = "tar", feature = "zip"))]
+
+extern crate pak;
+
+mod common;
+
+use common::temp_dir;
+use pak::testkit::{pseudo_random, FragmentBuilder};
+use pak::MergeReader;
+use std::fs;
+use std::io::Cursor;
+
+/// Archive of nested entries, one of them too long for a ustar header.
+fn fragment() -> FragmentBuilder {
+    let long_name = format!("{}/long.bin", "directory".repeat(20));
+    FragmentBuilder::new()
+        .entries(3, 1_000)
+        .entry("a/b/c.txt", b"abc".to_vec())
+        .entry("empty.bin", Vec::new())
+        .entry(long_name, pseudo_random(9, 700))
+}
+
+#[cfg(feature = "tar")]
+#[test]
+fn tar_round_trip() {
+    let dir = temp_dir("tar");
+    let reader = fragment().reader().unwrap();
+    let count = reader.entries().count();
+    let mut tar = Vec::new();
+    pak::interop::to_tar(&reader, &mut tar).unwrap();
+    assert_eq!(tar.len() % 512, 0);
+
+    let path = dir.join("from-tar.d2p");
+    assert_eq!(pak::interop::from_tar(Cursor::new(tar), &path).unwrap(), count);
+    assert!(pak::diff(&reader, &MergeReader::open(&path).unwrap()).unwrap().is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "zip")]
+#[test]
+fn zip_round_trip() {
+    use std::io::ErrorKind;
+
+    let dir = temp_dir("zip");
+    let reader = fragment().reader().unwrap();
+    let count = reader.entries().count();
+    let mut zip = Cursor::new(Vec::new());
+    pak::interop::to_zip(&reader, &mut zip).unwrap();
+
+    let path = dir.join("from-zip.d2p");
+    zip.set_position(0);
+    assert_eq!(pak::interop::from_zip(zip, &path).unwrap(), count);
+    assert!(pak::diff(&reader, &MergeReader::open(&path).unwrap()).unwrap().is_empty());
+
+    // nothing is created from an invalid zip
+    let invalid = dir.join("invalid.d2p");
+    assert!(pak::interop::from_zip(Cursor::new(b"PK\x05\x06 not a zip".to_vec()), &invalid).is_err());
+    // a central directory claimed larger than the input
+    let mut end = b"PK\x05\x06".to_vec();
+    end.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0, 0xff, 0xff, 0xff, 0x7f, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(pak::interop::from_zip(Cursor::new(end), &invalid).unwrap_err().kind(), ErrorKind::InvalidData);
+    assert!(!invalid.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
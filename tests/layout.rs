@@ -0,0 +1,76 @@
+//! Round-trips of the layouts of the fragments.
+
+extern crate pak;
+
+mod common;
+
+use common::temp_dir;
+use pak::raw::{Offsets, RawFragment, EXTENDED_HEADER};
+use pak::testkit::{pseudo_random, FragmentBuilder};
+use pak::{Archive, MergeReader, PakWriter, WriteOptions};
+use std::fs;
+
+#[test]
+fn extended_offsets_round_trip() {
+    let dir = temp_dir("extended");
+    let path = dir.join("extended.d2p");
+    let mut writer = PakWriter::create_with(&path, &WriteOptions { offsets: Offsets::Extended, ..WriteOptions::default() }).unwrap();
+    writer.add_bytes("a.bin", &pseudo_random(1, 1_000)).unwrap();
+    writer.add_bytes("b/c.bin", b"bc").unwrap();
+    writer.set_property("version", "3").unwrap();
+    writer.finish().unwrap();
+
+    let bytes = fs::read(&path).unwrap();
+    assert_eq!(bytes[..2], EXTENDED_HEADER);
+    assert_eq!(RawFragment::parse(&bytes).unwrap().info.offsets, Offsets::Extended);
+    let reader = MergeReader::open(&path).unwrap();
+    assert_eq!(reader.read_file("a.bin").unwrap(), pseudo_random(1, 1_000));
+    assert_eq!(reader.read_file("b/c.bin").unwrap(), b"bc");
+    assert_eq!(reader.property("version"), Some("3"));
+    assert!(reader.verify().is_ok());
+
+    // a commit keeps the width of the offsets of the write options
+    let mut archive = Archive::open_rw(&path).unwrap();
+    archive.set_write_options(WriteOptions { offsets: Offsets::Extended, ..WriteOptions::default() });
+    archive.insert("d.bin", vec![3; 10]).unwrap();
+    archive.commit().unwrap();
+    assert_eq!(fs::read(&path).unwrap()[..2], EXTENDED_HEADER);
+    assert_eq!(MergeReader::open(&path).unwrap().read_file("d.bin").unwrap(), vec![3; 10]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn extended_fixtures_are_read() {
+    let fragment = FragmentBuilder::new().offsets(Offsets::Extended).entries(3, 100);
+    let reader = fragment.reader().unwrap();
+    for i in 0..3 {
+        assert_eq!(reader.read_file(&format!("entries/{}.bin", i)).unwrap(), pseudo_random(i, 100));
+    }
+}
+
+#[test]
+fn slack_round_trip() {
+    let dir = temp_dir("slack");
+    let path = dir.join("slack.d2p");
+    let mut writer = PakWriter::create_with(&path, &WriteOptions { slack: 100, ..WriteOptions::default() }).unwrap();
+    writer.add_bytes("a.bin", b"aaaa").unwrap();
+    writer.set_property("k", "v").unwrap();
+    writer.finish().unwrap();
+
+    let mut archive = Archive::open_rw(&path).unwrap();
+    assert_eq!(archive.remaining_slack().unwrap(), 100);
+    archive.insert("b.bin", vec![7; 60]).unwrap();
+    assert!(archive.commit_in_place().unwrap());
+    assert_eq!(archive.remaining_slack().unwrap(), 40);
+    let reader = MergeReader::open(&path).unwrap();
+    assert_eq!(reader.read_file("a.bin").unwrap(), b"aaaa");
+    assert_eq!(reader.read_file("b.bin").unwrap(), vec![7; 60]);
+    assert_eq!(reader.property("k"), Some("v"));
+    assert!(reader.verify().is_ok());
+
+    // the slack exhausted, the archive is rewritten
+    archive.insert("c.bin", vec![1; 50]).unwrap();
+    assert!(!archive.commit_in_place().unwrap());
+    assert_eq!(MergeReader::open(&path).unwrap().read_file("c.bin").unwrap(), vec![1; 50]);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,72 @@
+//! Guarantees of the name normalization, checked on generated names.
+
+#![cfg(feature = "std")]
+
+extern crate pak;
+
+use pak::EntryName;
+
+const ALPHABET: &[&str] = &["a", "b", "é", ".", "..", "/", "\\", "//", "x.xml", " "];
+
+/// Generates `count` names made of the pieces of `ALPHABET`.
+fn names(count: usize) -> Vec<String> {
+    let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+    (0..count).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let len = (state % 8) as usize;
+        (0..len).map(|i| ALPHABET[((state >> (i * 4)) % ALPHABET.len() as u64) as usize]).collect()
+    }).collect()
+}
+
+#[test]
+fn normalization_is_idempotent() {
+    for name in names(10_000) {
+        if let Ok(normalized) = EntryName::new(&name) {
+            let again = EntryName::new(normalized.as_str()).unwrap();
+            assert_eq!(normalized, again, "\"{}\"", name);
+        }
+    }
+}
+
+#[test]
+fn normalized_names_are_canonical() {
+    for name in names(10_000) {
+        if let Ok(normalized) = EntryName::new(&name) {
+            let normalized = normalized.as_str();
+            assert!(!normalized.is_empty(), "\"{}\"", name);
+            assert!(!normalized.starts_with('/') && !normalized.ends_with('/'), "\"{}\"", name);
+            assert!(!normalized.contains('\\'), "\"{}\"", name);
+            assert!(normalized.split('/').all(|segment| !segment.is_empty() && segment != "." && segment != ".."), "\"{}\"", name);
+        }
+    }
+}
+
+#[test]
+fn separators_are_equivalent() {
+    for name in names(10_000) {
+        let slashes = EntryName::new(&name.replace('\\', "/")).ok();
+        let backslashes = EntryName::new(&name.replace('/', "\\")).ok();
+        assert_eq!(slashes, backslashes, "\"{}\"", name);
+    }
+}
+
+#[test]
+fn extraction_paths_stay_inside_dest() {
+    let dest = std::path::Path::new("dest");
+    for name in names(10_000) {
+        if let Ok(normalized) = EntryName::new(&name) {
+            assert!(normalized.to_path(dest).starts_with(dest), "\"{}\"", name);
+        }
+    }
+}
+
+#[test]
+fn escaping_names_are_rejected() {
+    assert!(EntryName::new("../a").is_err());
+    assert!(EntryName::new("a/../../b").is_err());
+    assert!(EntryName::new("").is_err());
+    assert!(EntryName::new("/./").is_err());
+    assert_eq!(EntryName::new("\\a/./b//c/../d.xml").unwrap().as_str(), "a/b/d.xml");
+}
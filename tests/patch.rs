@@ -0,0 +1,88 @@
+//! Round-trips of the patch files between two versions of an archive.
+
+extern crate pak;
+
+mod common;
+
+use common::temp_dir;
+use pak::testkit::{pseudo_random, FragmentBuilder};
+use pak::{apply_patch, MergeReader, Patch};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Writes the two versions of the archive into `dir`.
+fn versions(dir: &Path) -> (PathBuf, PathBuf) {
+    let (old, new) = (dir.join("old.d2p"), dir.join("new.d2p"));
+    FragmentBuilder::new()
+        .entry("same.bin", pseudo_random(1, 100))
+        .entry("modified.bin", b"abc".to_vec())
+        .entry("removed.bin", b"gone".to_vec())
+        .property("version", "1")
+        .write_to(&old)
+        .unwrap();
+    FragmentBuilder::new()
+        .entry("same.bin", pseudo_random(1, 100))
+        .entry("modified.bin", b"abd".to_vec())
+        .entry("added.bin", pseudo_random(2, 1_000))
+        .property("version", "2")
+        .write_to(&new)
+        .unwrap();
+    (old, new)
+}
+
+#[test]
+fn patch_round_trip() {
+    let dir = temp_dir("round-trip");
+    let (old, new) = versions(&dir);
+    let (old, new) = (MergeReader::open(&old).unwrap(), MergeReader::open(&new).unwrap());
+
+    let path = dir.join("update.patch");
+    Patch::between(&old, &new).unwrap().save(&path).unwrap();
+    let patch = Patch::load(&path).unwrap();
+    assert_eq!(patch.removed, vec!["removed.bin"]);
+    assert_eq!(patch.entries.len(), 2);
+
+    let patched = dir.join("patched.d2p");
+    apply_patch(&old, &patch, &patched).unwrap();
+    let patched = MergeReader::open(&patched).unwrap();
+    assert!(pak::diff(&patched, &new).unwrap().is_empty());
+    assert_eq!(patched.property("version"), Some("2"));
+    // no temporary file is left behind
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 4);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn patch_requires_its_base() {
+    let dir = temp_dir("base");
+    let (old, new) = versions(&dir);
+    let (old, new) = (MergeReader::open(&old).unwrap(), MergeReader::open(&new).unwrap());
+    let patch = Patch::between(&old, &new).unwrap();
+
+    let out = dir.join("out.d2p");
+    assert!(apply_patch(&new, &patch, &out).is_err());
+    assert!(!out.exists());
+    assert!(Patch::between(&old, &old).unwrap().is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn patch_doesnt_overwrite_existing_entries() {
+    let dir = temp_dir("added");
+    let (old, new) = versions(&dir);
+    let (old, new) = (MergeReader::open(&old).unwrap(), MergeReader::open(&new).unwrap());
+    let patch = Patch::between(&old, &new).unwrap();
+
+    // the base of the patch, with the entry it adds
+    let base = FragmentBuilder::new()
+        .entry("same.bin", pseudo_random(1, 100))
+        .entry("modified.bin", b"abc".to_vec())
+        .entry("removed.bin", b"gone".to_vec())
+        .entry("added.bin", b"already there".to_vec())
+        .reader()
+        .unwrap();
+    let out = dir.join("out.d2p");
+    assert!(apply_patch(&base, &patch, &out).is_err());
+    assert!(!out.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}